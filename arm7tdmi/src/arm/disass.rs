@@ -1,11 +1,13 @@
 use std::fmt;
 
-use crate::bit::BitIndex;
+use bit::BitIndex;
 
 use super::{ArmDecodeHelper, ArmFormat, ArmInstruction};
 
 use super::{AluOpCode, ArmCond, ArmHalfwordTransferType};
-use crate::arm7tdmi::*;
+use crate::psr::RegPSR;
+use crate::registers_consts::REG_PC;
+use crate::*;
 
 impl fmt::Display for ArmCond {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
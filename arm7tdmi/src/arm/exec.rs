@@ -717,7 +717,7 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
     /// ARM Software Interrupt
     /// Execution Time: 2S+1N
     pub fn exec_arm_swi(&mut self, insn: u32) -> CpuAction {
-        self.software_interrupt(self.pc - 4, insn.swi_comment()); // Implies 2S + 1N
-        CpuAction::PipelineFlushed
+        // The bios takes the function number from bits 16..24 in arm state
+        self.software_interrupt(self.pc - 4, (insn.swi_comment() >> 16) & 0xff) // Implies 2S + 1N
     }
 }
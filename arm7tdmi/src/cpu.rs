@@ -122,6 +122,11 @@ pub struct Arm7tdmiCore<I: MemoryInterface> {
     /// Hardware breakpoints for use by gdb
     breakpoints: Vec<Addr>,
 
+    /// When set, software interrupts don't enter the bios, the host picks them up with
+    /// `take_pending_swi` and emulates the bios function instead
+    pub hle_swi: bool,
+    pub(crate) pending_swi: Option<u32>,
+
     /// Deprecated in-house debugger state
     #[cfg(feature = "debugger")]
     pub dbg: DebuggerState,
@@ -142,6 +147,9 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
 
             breakpoints: Vec::new(),
 
+            hle_swi: false,
+            pending_swi: None,
+
             #[cfg(feature = "debugger")]
             dbg: DebuggerState::default(),
         }
@@ -166,6 +174,9 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
 
             breakpoints: Vec::new(), // TODO include breakpoints in saved state
 
+            hle_swi: false,
+            pending_swi: None,
+
             // savestate does not keep debugger related information, so just reinitialize to default
             #[cfg(feature = "debugger")]
             dbg: DebuggerState::default(),
@@ -194,6 +205,12 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
         self.next_fetch_access = state.next_fetch_access;
     }
 
+    /// The bios function number of the last software interrupt, when `hle_swi` is set
+    #[inline]
+    pub fn take_pending_swi(&mut self) -> Option<u32> {
+        self.pending_swi.take()
+    }
+
     pub fn set_memory_interface(&mut self, i: Shared<I>) {
         self.bus = i;
     }
@@ -414,9 +431,9 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
     /// 2S + 1N
     #[inline(always)]
     pub fn reload_pipeline16(&mut self) {
-        self.pipeline[0] = self.load_16(self.pc, NonSeq) as u32;
+        self.pipeline[0] = self.fetch_16(self.pc, NonSeq) as u32;
         self.advance_thumb();
-        self.pipeline[1] = self.load_16(self.pc, Seq) as u32;
+        self.pipeline[1] = self.fetch_16(self.pc, Seq) as u32;
         self.advance_thumb();
         self.next_fetch_access = Seq;
     }
@@ -424,9 +441,9 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
     /// 2S + 1N
     #[inline(always)]
     pub fn reload_pipeline32(&mut self) {
-        self.pipeline[0] = self.load_32(self.pc, NonSeq);
+        self.pipeline[0] = self.fetch_32(self.pc, NonSeq);
         self.advance_arm();
-        self.pipeline[1] = self.load_32(self.pc, Seq);
+        self.pipeline[1] = self.fetch_32(self.pc, Seq);
         self.advance_arm();
         self.next_fetch_access = Seq;
     }
@@ -459,7 +476,7 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
             CpuState::ARM => {
                 let pc = self.pc & !3;
 
-                let fetched_now = self.load_32(pc, self.next_fetch_access);
+                let fetched_now = self.fetch_32(pc, self.next_fetch_access);
                 let insn = self.pipeline[0];
                 self.pipeline[0] = self.pipeline[1];
                 self.pipeline[1] = fetched_now;
@@ -481,7 +498,7 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
             CpuState::THUMB => {
                 let pc = self.pc & !1;
 
-                let fetched_now = self.load_16(pc, self.next_fetch_access);
+                let fetched_now = self.fetch_16(pc, self.next_fetch_access);
                 let insn = self.pipeline[0];
                 self.pipeline[0] = self.pipeline[1];
                 self.pipeline[1] = fetched_now as u32;
@@ -531,13 +548,13 @@ impl<I: MemoryInterface> fmt::Debug for Arm7tdmiCore<I> {
 }
 
 #[cfg(feature = "debugger")]
-impl<I: MemoryInterface> fmt::Display for Core<I> {
+impl<I: MemoryInterface> fmt::Display for Arm7tdmiCore<I> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "ARM7TDMI Core Status:")?;
         writeln!(f, "\tCPSR: {}", self.cpsr)?;
         writeln!(f, "\tGeneral Purpose Registers:")?;
         let reg_normal_style = Style::new().bold();
-        let reg_dirty_style = Colour::Black.bold().on(Colour::Yellow);
+        let reg_dirty_style = ansi_term::Colour::Black.bold().on(ansi_term::Colour::Yellow);
         let gpr = self.copy_registers();
         for i in 0..15 {
             let mut reg_name = reg_string(i).to_string();
@@ -1,6 +1,6 @@
-use super::memory::MemoryInterface;
+use super::memory::{MemoryAccess, MemoryInterface};
 use super::Arm7tdmiCore;
-use super::{CpuMode, CpuState};
+use super::{CpuAction, CpuMode, CpuState};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[allow(dead_code)]
@@ -33,7 +33,7 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
         #[cfg(feature = "debugger")]
         {
             if self.dbg.trace_exceptions {
-                trace!("exception {:?} lr={:x} new_mode={:?}", e, lr, new_mode);
+                log::trace!("exception {:?} lr={:x} new_mode={:?}", e, lr, new_mode);
             }
         }
 
@@ -65,8 +65,15 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
         }
     }
 
+    /// `number` is the bios function number from the comment field
     #[inline]
-    pub fn software_interrupt(&mut self, lr: u32, _cmt: u32) {
-        self.exception(Exception::SoftwareInterrupt, lr);
+    pub fn software_interrupt(&mut self, lr: u32, number: u32) -> CpuAction {
+        if self.hle_swi {
+            self.pending_swi = Some(number);
+            CpuAction::AdvancePC(MemoryAccess::Seq)
+        } else {
+            self.exception(Exception::SoftwareInterrupt, lr);
+            CpuAction::PipelineFlushed
+        }
     }
 }
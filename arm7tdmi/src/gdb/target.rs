@@ -6,13 +6,13 @@ use gdbstub::target::ext::base::singlethread::{
 use gdbstub::target::ext::base::singlethread::{SingleThreadResumeOps, SingleThreadSingleStepOps};
 use gdbstub::target::ext::base::BaseOps;
 use gdbstub::target::ext::breakpoints::BreakpointsOps;
-use gdbstub::target::{self, Target, TargetError, TargetResult};
+use gdbstub::target::{self, Target, TargetResult};
 
-use crate::memory::{DebugRead, MemoryInterface};
+use crate::memory::{DebugRead, DebugWrite, MemoryInterface};
 use crate::registers_consts::*;
 use crate::Arm7tdmiCore;
 
-pub trait MemoryGdbInterface: MemoryInterface + DebugRead {
+pub trait MemoryGdbInterface: MemoryInterface + DebugRead + DebugWrite {
     fn memory_map_xml(&self, offset: u64, length: usize, buf: &mut [u8]) -> usize;
 }
 
@@ -66,9 +66,9 @@ impl<I: MemoryGdbInterface> SingleThreadBase for Arm7tdmiCore<I> {
         Ok(())
     }
 
-    fn write_addrs(&mut self, _start_addr: u32, _data: &[u8]) -> TargetResult<(), Self> {
-        // todo!("implement DebugWrite bus extention")
-        Err(TargetError::NonFatal)
+    fn write_addrs(&mut self, start_addr: u32, data: &[u8]) -> TargetResult<(), Self> {
+        self.bus.debug_write_bytes(start_addr, data);
+        Ok(())
     }
 
     // most targets will want to support at resumption as well...
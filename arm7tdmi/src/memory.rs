@@ -78,6 +78,17 @@ pub trait MemoryInterface {
     /// Write a word
     fn store_32(&mut self, addr: u32, value: u32, access: MemoryAccess);
 
+    /// Fetch a THUMB opcode, a regular read unless the bus times opcode fetches differently
+    #[inline]
+    fn fetch_16(&mut self, addr: u32, access: MemoryAccess) -> u16 {
+        self.load_16(addr, access)
+    }
+    /// Fetch an ARM opcode, a regular read unless the bus times opcode fetches differently
+    #[inline]
+    fn fetch_32(&mut self, addr: u32, access: MemoryAccess) -> u32 {
+        self.load_32(addr, access)
+    }
+
     fn idle_cycle(&mut self);
 }
 
@@ -111,6 +122,16 @@ impl<I: MemoryInterface> MemoryInterface for Arm7tdmiCore<I> {
         self.bus.store_32(addr & !3, value, access);
     }
 
+    #[inline]
+    fn fetch_16(&mut self, addr: u32, access: MemoryAccess) -> u16 {
+        self.bus.fetch_16(addr & !1, access)
+    }
+
+    #[inline]
+    fn fetch_32(&mut self, addr: u32, access: MemoryAccess) -> u32 {
+        self.bus.fetch_32(addr & !3, access)
+    }
+
     #[inline]
     fn idle_cycle(&mut self) {
         self.bus.idle_cycle();
@@ -241,6 +262,31 @@ pub trait DebugRead: BusIO {
     }
 }
 
+/// Helper trait for writing memory as if we were an all-powerfull debugger.
+///
+/// Unlike `BusIO` writes these don't cost cycles and don't trigger any hardware,
+/// and they also reach memory that is read-only to the cpu.
+pub trait DebugWrite: DebugRead {
+    fn debug_write_32(&mut self, addr: Addr, value: u32) {
+        self.debug_write_16(addr, (value & 0xffff) as u16);
+        self.debug_write_16(addr + 2, (value >> 16) as u16);
+    }
+
+    fn debug_write_16(&mut self, addr: Addr, value: u16) {
+        self.debug_write_8(addr, (value & 0xff) as u8);
+        self.debug_write_8(addr + 1, (value >> 8) as u8);
+    }
+
+    fn debug_write_8(&mut self, addr: Addr, value: u8);
+
+    fn debug_write_bytes(&mut self, start_addr: Addr, bytes: &[u8]) {
+        bytes
+            .iter()
+            .enumerate()
+            .for_each(|(idx, byte)| self.debug_write_8(start_addr + (idx as Addr), *byte));
+    }
+}
+
 /// The caller is assumed to handle out of bound accesses,
 /// For performance reasons, this impl trusts that 'addr' is within the array range.
 impl BusIO for Box<[u8]> {
@@ -263,3 +309,10 @@ impl DebugRead for Box<[u8]> {
         self[addr as usize]
     }
 }
+
+impl DebugWrite for Box<[u8]> {
+    #[inline]
+    fn debug_write_8(&mut self, addr: Addr, value: u8) {
+        self[addr as usize] = value;
+    }
+}
@@ -1,9 +1,9 @@
 use std::fmt;
 
-use crate::bit::BitIndex;
+use bit::BitIndex;
 
 use super::*;
-use crate::arm7tdmi::*;
+use crate::*;
 
 use super::ThumbDecodeHelper;
 
@@ -1,5 +1,4 @@
 use crate::{
-    exception::Exception,
     memory::{MemoryAccess, MemoryInterface},
     registers_consts::*,
     Arm7tdmiCore, CpuAction,
@@ -533,9 +532,8 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
 
     /// Format 17
     /// Execution Time: 2S+1N
-    pub(in super::super) fn exec_thumb_swi(&mut self, _insn: u16) -> CpuAction {
-        self.exception(Exception::SoftwareInterrupt, self.pc - 2); // implies pipeline reload
-        CpuAction::PipelineFlushed
+    pub(in super::super) fn exec_thumb_swi(&mut self, insn: u16) -> CpuAction {
+        self.software_interrupt(self.pc - 2, (insn & 0xff) as u32)
     }
 
     /// Format 18
@@ -1,6 +1,6 @@
 use super::SysBus;
 use arm7tdmi::{
-    memory::{Addr, BusIO, DebugRead},
+    memory::{Addr, BusIO, DebugRead, DebugWrite},
     Arm7tdmiCore,
 };
 
@@ -11,7 +11,7 @@ use rustboyadvance_utils::WeakPointer;
 pub struct Bios {
     /// Underlying memory
     rom: Box<[u8]>,
-    /// Last read value
+    /// Last opcode fetched from the bios, what protected reads see
     last_opcode: u32,
     /// Arm pointer - used only to read the PC register
     arm_core: WeakPointer<Arm7tdmiCore<SysBus>>,
@@ -35,6 +35,16 @@ impl Bios {
         self.arm_core.pc < 0x4000
     }
 
+    /// Called on opcode fetches from the bios, the bus holds the whole word
+    #[inline]
+    pub(super) fn latch_fetch(&mut self, addr: Addr) {
+        self.last_opcode = self.rom.read_32(addr & !3);
+    }
+
+    pub(crate) fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
     #[inline]
     pub(crate) fn len(&self) -> usize {
         self.rom.len()
@@ -46,10 +56,7 @@ impl BusIO for Bios {
     #[inline]
     fn read_32(&mut self, addr: Addr) -> u32 {
         if self.read_allowed() {
-            let value = self.rom.read_32(addr);
-            // 32-bit read from bios is most probably an opcode fetch
-            self.last_opcode = value;
-            value
+            self.rom.read_32(addr)
         } else {
             self.last_opcode
         }
@@ -83,3 +90,9 @@ impl DebugRead for Bios {
         self.rom[addr as usize]
     }
 }
+
+impl DebugWrite for Bios {
+    fn debug_write_8(&mut self, addr: Addr, value: u8) {
+        self.rom[addr as usize] = value;
+    }
+}
@@ -10,12 +10,17 @@ use serde::ser::{Serialize, SerializeStruct, Serializer};
 use super::BackupMemoryInterface;
 use rustboyadvance_utils::write_bin_file;
 
+/// The saves of games with a clock end with the state of the RTC, the same as mGBA does
+pub const RTC_BLOCK_SIZE: usize = 16;
+
 #[derive(Debug)]
 pub struct BackupFile {
     size: usize,
     path: Option<PathBuf>,
     file: Option<File>,
     buffer: Vec<u8>,
+    /// Kept after the backup memory in the file
+    rtc_block: Option<[u8; RTC_BLOCK_SIZE]>,
 }
 
 impl Clone for BackupFile {
@@ -73,6 +78,7 @@ impl BackupFile {
     pub fn new(size: usize, path: Option<PathBuf>) -> BackupFile {
         // TODO handle errors without unwrap
         let mut file: Option<File> = None;
+        let mut rtc_block = None;
         let buffer = if let Some(path) = &path {
             if !path.is_file() {
                 write_bin_file(path, &vec![0xff; size]).unwrap();
@@ -86,6 +92,11 @@ impl BackupFile {
 
             let mut buffer = Vec::new();
             _file.read_to_end(&mut buffer).unwrap();
+            if buffer.len() == size + RTC_BLOCK_SIZE {
+                let mut block = [0; RTC_BLOCK_SIZE];
+                block.copy_from_slice(&buffer[size..]);
+                rtc_block = Some(block);
+            }
             buffer.resize(size, 0xff);
 
             file = Some(_file);
@@ -100,6 +111,7 @@ impl BackupFile {
             path,
             file,
             buffer,
+            rtc_block,
         }
     }
 
@@ -111,11 +123,46 @@ impl BackupFile {
         &mut self.buffer
     }
 
+    /// Replace the whole contents, resizing to fit
+    pub fn replace(&mut self, bytes: &[u8]) {
+        self.size = bytes.len();
+        self.buffer = bytes.to_vec();
+        self.flush();
+    }
+
+    pub fn rtc_block(&self) -> Option<&[u8; RTC_BLOCK_SIZE]> {
+        self.rtc_block.as_ref()
+    }
+
+    pub fn set_rtc_block(&mut self, block: [u8; RTC_BLOCK_SIZE]) {
+        self.rtc_block = Some(block);
+        self.write_rtc_block();
+    }
+
+    /// The file is never shrunk, padded saves and saves of a larger size keep their tail
     pub fn flush(&mut self) {
         if let Some(file) = &mut self.file {
             file.seek(SeekFrom::Start(0)).unwrap();
             file.write_all(&self.buffer).unwrap();
         }
+        self.write_rtc_block();
+    }
+
+    /// The block goes right after the backup memory, which only holds data of ours when the file
+    /// is exactly that long or already ends with a block.
+    fn write_rtc_block(&mut self) {
+        if let (Some(file), Some(block)) = (&mut self.file, &self.rtc_block) {
+            let len = file.metadata().unwrap().len() as usize;
+            if len == self.size || len == self.size + RTC_BLOCK_SIZE {
+                file.seek(SeekFrom::Start(self.size as u64)).unwrap();
+                file.write_all(block).unwrap();
+            } else {
+                warn!(
+                    "the save file is {} bytes instead of {}, not writing the RTC state to it",
+                    len, self.size
+                );
+            }
+        }
     }
 }
 
@@ -138,3 +185,48 @@ impl BackupMemoryInterface for BackupFile {
         self.flush();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    fn temp_save(name: &str, bytes: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "rustboyadvance-{}-{}.sav",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_rtc_block() {
+        let block = [0x42; RTC_BLOCK_SIZE];
+
+        let path = temp_save("exact", &[0; 0x100]);
+        let mut file = BackupFile::new(0x100, Some(path.clone()));
+        file.set_rtc_block(block);
+        file.write(0, 1);
+        file.flush();
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(bytes.len(), 0x100 + RTC_BLOCK_SIZE);
+        assert_eq!(bytes[0], 1);
+        assert_eq!(bytes[0x100..], block);
+        assert_eq!(
+            BackupFile::new(0x100, Some(path.clone())).rtc_block(),
+            Some(&block)
+        );
+        fs::remove_file(&path).unwrap();
+
+        // a padded save keeps its size and its padding
+        let path = temp_save("padded", &[0xaa; 0x200]);
+        let mut file = BackupFile::new(0x100, Some(path.clone()));
+        file.set_rtc_block(block);
+        file.flush();
+        assert_eq!(fs::read(&path).unwrap(), vec![0xaa; 0x200]);
+        fs::remove_file(&path).unwrap();
+    }
+}
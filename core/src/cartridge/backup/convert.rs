@@ -0,0 +1,530 @@
+//! Importing and exporting saves in the layouts of other emulators and devices.
+//!
+//! Raw saves (mGBA, VBA, flash carts) only differ from ours in their size: some are padded, and
+//! mGBA appends the RTC state to them. GameShark SP and Action Replay saves (`.sps`, `.gsv`) wrap the
+//! save in a container, and store EEPROM with the bytes of every 64 bit word reversed. VBA
+//! savestates (`.sgm`) hold the backup memory among the rest of the state of the emulator.
+use std::convert::TryInto;
+use std::fmt;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+use super::RTC_BLOCK_SIZE;
+
+const SAVE_SIZES: &[usize] = &[0x200, 0x2000, 0x8000, 0x10000, 0x20000];
+const EEPROM_512_SIZE: usize = 0x200;
+const EEPROM_8K_SIZE: usize = 0x2000;
+
+const SHARKPORT_MAGIC: &[u8] = b"SharkPortSave";
+const SHARKPORT_MAGIC_OFFSET: usize = 4;
+const SHARKPORT_PLATFORM_GBA: u32 = 0x000f_0000;
+/// The payload starts with the identity of the game
+const SHARKPORT_GAME_ID_SIZE: usize = 0x1c;
+const SHARKPORT_NOTES: &str = "Exported from RustBoyAdvance-NG";
+
+const GSV_MAGIC: &[u8] = b"ADVSAVEG";
+const GSV_MAGIC_OFFSET: usize = 0xc;
+const GSV_PAYLOAD_OFFSET: usize = 0x430;
+
+/// The vba-m savestates are gzipped
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+/// Way more than a savestate holds, so a corrupted file can't inflate without bounds
+const SGM_MAX_SIZE: u64 = 4 * 1024 * 1024;
+/// The savestate starts with its version and the title of the game
+const SGM_TITLE_OFFSET: usize = 4;
+/// VBA keeps the whole EEPROM and flash in the savestate, whatever their size
+const SGM_EEPROM_SIZE: usize = 0x2000;
+const SGM_FLASH_SIZE: usize = 0x20000;
+/// flashState, flashReadState, flashSize and flashBank
+const SGM_FLASH_HEADER_SIZE: usize = 16;
+
+#[derive(Debug)]
+pub enum SaveConvertError {
+    IO(io::Error),
+    UnknownSaveType,
+    UnsupportedFormat(&'static str),
+    BadContainer(&'static str),
+    ChecksumMismatch,
+    /// The save is bigger than the backup memory of the game, and not just padded
+    TooBig {
+        size: usize,
+        expected: usize,
+    },
+}
+
+impl fmt::Display for SaveConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveConvertError::IO(e) => write!(f, "save io error: {}", e),
+            SaveConvertError::UnknownSaveType => write!(
+                f,
+                "the save type of the game is unknown, set it with the save type option"
+            ),
+            SaveConvertError::UnsupportedFormat(what) => write!(f, "unsupported save: {}", what),
+            SaveConvertError::BadContainer(what) => write!(f, "bad save container: {}", what),
+            SaveConvertError::ChecksumMismatch => write!(f, "the save is corrupted"),
+            SaveConvertError::TooBig { size, expected } => write!(
+                f,
+                "the save has {:#x} bytes but the game only has {:#x}, is the save type right?",
+                size, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SaveConvertError {}
+
+impl From<io::Error> for SaveConvertError {
+    fn from(err: io::Error) -> SaveConvertError {
+        SaveConvertError::IO(err)
+    }
+}
+
+pub type SaveConvertResult<T> = Result<T, SaveConvertError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveFormat {
+    /// The contents of the backup memory, the layout of mGBA, VBA and most flash carts
+    Raw,
+    /// GameShark SP / Action Replay `.sps`
+    SharkPort,
+    /// Action Replay `.gsv`, import only
+    Gsv,
+    /// VBA savestate `.sgm`, import only
+    Sgm,
+}
+
+impl SaveFormat {
+    /// Tell the format from the contents of a save
+    pub fn detect(bytes: &[u8]) -> SaveConvertResult<SaveFormat> {
+        let has_magic =
+            |offset: usize, magic: &[u8]| bytes.get(offset..offset + magic.len()) == Some(magic);
+        if has_magic(SHARKPORT_MAGIC_OFFSET, SHARKPORT_MAGIC) {
+            Ok(SaveFormat::SharkPort)
+        } else if has_magic(GSV_MAGIC_OFFSET, GSV_MAGIC) {
+            Ok(SaveFormat::Gsv)
+        } else if has_magic(0, GZIP_MAGIC) && !is_save_size(bytes.len()) {
+            // A raw save may start with the magic too
+            Ok(SaveFormat::Sgm)
+        } else {
+            Ok(SaveFormat::Raw)
+        }
+    }
+
+    /// Tell the format of a save file, trusting the `.sgm` extension over the contents
+    pub fn detect_file(path: &Path, bytes: &[u8]) -> SaveConvertResult<SaveFormat> {
+        match SaveFormat::from_path(path) {
+            Ok(SaveFormat::Sgm) => Ok(SaveFormat::Sgm),
+            _ => SaveFormat::detect(bytes),
+        }
+    }
+
+    /// The format to export to for a file name
+    pub fn from_path(path: &Path) -> SaveConvertResult<SaveFormat> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) => extension.to_lowercase().parse(),
+            None => Ok(SaveFormat::Raw),
+        }
+    }
+}
+
+impl fmt::Display for SaveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SaveFormat::Raw => "raw",
+            SaveFormat::SharkPort => "sps",
+            SaveFormat::Gsv => "gsv",
+            SaveFormat::Sgm => "sgm",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for SaveFormat {
+    type Err = SaveConvertError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" | "sav" | "srm" | "sa1" | "bin" => Ok(SaveFormat::Raw),
+            "sps" | "xps" => Ok(SaveFormat::SharkPort),
+            "gsv" => Ok(SaveFormat::Gsv),
+            "sgm" => Ok(SaveFormat::Sgm),
+            _ => Err(SaveConvertError::UnsupportedFormat(
+                "unknown save file extension",
+            )),
+        }
+    }
+}
+
+/// Convert between our EEPROM layout and the one of the GameShark and Action Replay, which
+/// store every 64 bit word with its bytes reversed. The conversion is its own inverse.
+pub fn swap_eeprom_byte_order(data: &mut [u8]) {
+    data.chunks_mut(8).for_each(|word| word.reverse());
+}
+
+fn is_save_size(size: usize) -> bool {
+    SAVE_SIZES
+        .iter()
+        .any(|&save_size| size == save_size || size == save_size + RTC_BLOCK_SIZE)
+}
+
+fn is_padding(bytes: &[u8]) -> bool {
+    bytes.iter().all(|&b| b == 0xff) || bytes.iter().all(|&b| b == 0)
+}
+
+/// A save in the layout of our backup memory, with the RTC block mGBA appends to it
+pub(in super::super) type ImportedSave = (Vec<u8>, Option<[u8; RTC_BLOCK_SIZE]>);
+
+/// Resize the save to the backup memory, `None` for an EEPROM of unknown size
+fn fit(mut data: Vec<u8>, expected_size: Option<usize>) -> SaveConvertResult<ImportedSave> {
+    let mut rtc_block = None;
+    if data.len() > RTC_BLOCK_SIZE && SAVE_SIZES.contains(&(data.len() - RTC_BLOCK_SIZE)) {
+        let mut block = [0; RTC_BLOCK_SIZE];
+        block.copy_from_slice(&data[data.len() - RTC_BLOCK_SIZE..]);
+        rtc_block = Some(block);
+        data.truncate(data.len() - RTC_BLOCK_SIZE);
+    }
+    let size = expected_size.unwrap_or_else(|| {
+        if data.len() <= EEPROM_512_SIZE || is_padding(&data[EEPROM_512_SIZE..]) {
+            EEPROM_512_SIZE
+        } else {
+            EEPROM_8K_SIZE
+        }
+    });
+    if data.len() > size && !is_padding(&data[size..]) {
+        return Err(SaveConvertError::TooBig {
+            size: data.len(),
+            expected: size,
+        });
+    }
+    data.resize(size, 0xff);
+    Ok((data, rtc_block))
+}
+
+/// Reads the length prefixed fields of a container
+struct Fields<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Fields<'a> {
+    fn take(&mut self, len: usize) -> SaveConvertResult<&'a [u8]> {
+        let field = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or(SaveConvertError::BadContainer("truncated"))?;
+        self.pos += len;
+        Ok(field)
+    }
+
+    fn u32(&mut self) -> SaveConvertResult<u32> {
+        let mut value = [0; 4];
+        value.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(value))
+    }
+
+    fn field(&mut self) -> SaveConvertResult<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+}
+
+/// The identity of the game at the start of a SharkPort payload
+fn sharkport_game_id(rom: &[u8]) -> [u8; SHARKPORT_GAME_ID_SIZE] {
+    let mut id = [0; SHARKPORT_GAME_ID_SIZE];
+    if rom.len() >= 0xc0 {
+        // Title and game code
+        id[..0x10].copy_from_slice(&rom[0xa0..0xb0]);
+        id[0x12] = rom[0xbd];
+        id[0x13] = rom[0xb0];
+    }
+    id[0x14] = 1;
+    id
+}
+
+fn sharkport_checksum(payload: &[u8]) -> u32 {
+    // The bytes are sign extended
+    payload.iter().fold(0u32, |sum, &byte| {
+        sum.wrapping_add(((byte as i8 as i32) as u32).wrapping_shl(sum % 24))
+    })
+}
+
+fn unpack_sharkport(bytes: &[u8], rom: &[u8]) -> SaveConvertResult<Vec<u8>> {
+    let mut fields = Fields { bytes, pos: 0 };
+    if fields.field()? != SHARKPORT_MAGIC {
+        return Err(SaveConvertError::BadContainer("not a SharkPort save"));
+    }
+    if fields.u32()? != SHARKPORT_PLATFORM_GBA {
+        return Err(SaveConvertError::BadContainer("not a GBA save"));
+    }
+    // Title, date and notes
+    for _ in 0..3 {
+        fields.field()?;
+    }
+    let payload = fields.field()?;
+    if payload.len() < SHARKPORT_GAME_ID_SIZE {
+        return Err(SaveConvertError::BadContainer("truncated"));
+    }
+    if fields.u32()? != sharkport_checksum(payload) {
+        return Err(SaveConvertError::ChecksumMismatch);
+    }
+    let (game_id, data) = payload.split_at(SHARKPORT_GAME_ID_SIZE);
+    if game_id[..0x10] != sharkport_game_id(rom)[..0x10] {
+        warn!("the save was made for another game");
+    }
+    Ok(data.to_vec())
+}
+
+fn pack_sharkport(data: &[u8], rom: &[u8]) -> Vec<u8> {
+    let mut payload = sharkport_game_id(rom).to_vec();
+    payload.extend_from_slice(data);
+    let title = rom.get(0xa0..0xac).unwrap_or_default();
+    let title = &title[..title.iter().position(|&b| b == 0).unwrap_or(title.len())];
+    let date = chrono::Local::now()
+        .format("%m/%d/%Y %I:%M:%S %p")
+        .to_string();
+
+    let mut bytes = Vec::with_capacity(payload.len() + 0x100);
+    push_field(&mut bytes, SHARKPORT_MAGIC);
+    bytes.extend_from_slice(&SHARKPORT_PLATFORM_GBA.to_le_bytes());
+    push_field(&mut bytes, title);
+    push_field(&mut bytes, date.as_bytes());
+    push_field(&mut bytes, SHARKPORT_NOTES.as_bytes());
+    push_field(&mut bytes, &payload);
+    bytes.extend_from_slice(&sharkport_checksum(&payload).to_le_bytes());
+    bytes
+}
+
+fn push_field(bytes: &mut Vec<u8>, field: &[u8]) {
+    bytes.extend_from_slice(&(field.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(field);
+}
+
+fn unpack_gsv(bytes: &[u8]) -> SaveConvertResult<Vec<u8>> {
+    bytes
+        .get(GSV_PAYLOAD_OFFSET..)
+        .map(<[u8]>::to_vec)
+        .ok_or(SaveConvertError::BadContainer("truncated"))
+}
+
+/// Find the backup memory in a VBA-M savestate. It has no offsets to it, so look for where the
+/// EEPROM is followed by the flash:
+/// eepromSize: u32, eepromData: [u8; 0x2000], flashState: u32, flashReadState: u32,
+/// flashSize: u32, flashBank: u32, flashSaveMemory: [u8; 0x20000].
+/// The SRAM is kept in flashSaveMemory as well.
+fn unpack_sgm(
+    bytes: &[u8],
+    eeprom: bool,
+    expected_size: Option<usize>,
+    rom: &[u8],
+) -> SaveConvertResult<Vec<u8>> {
+    let mut state = Vec::new();
+    flate2::read::GzDecoder::new(bytes)
+        .take(SGM_MAX_SIZE)
+        .read_to_end(&mut state)
+        .map_err(|_| SaveConvertError::BadContainer("not a gzipped VBA savestate"))?;
+
+    let u32_at = |pos: usize| {
+        state
+            .get(pos..pos + 4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+    };
+    let flash_header = |eeprom_pos: usize| eeprom_pos + 4 + SGM_EEPROM_SIZE;
+    let eeprom_pos = (0..state.len())
+        .find(|&pos| {
+            let flash_pos = flash_header(pos);
+            matches!(u32_at(pos), Some(0x200) | Some(0x2000))
+                && matches!(u32_at(flash_pos), Some(flash_state) if flash_state < 0x10)
+                && matches!(u32_at(flash_pos + 4), Some(read_state) if read_state < 0x10)
+                && matches!(u32_at(flash_pos + 8), Some(0x10000) | Some(0x20000))
+                && matches!(u32_at(flash_pos + 12), Some(0) | Some(1))
+                && state.len() >= flash_pos + SGM_FLASH_HEADER_SIZE + SGM_FLASH_SIZE
+        })
+        .ok_or(SaveConvertError::BadContainer(
+            "no backup memory in the VBA savestate",
+        ))?;
+
+    if state.get(SGM_TITLE_OFFSET..SGM_TITLE_OFFSET + 0x10) != rom.get(0xa0..0xb0) {
+        warn!("the save was made for another game");
+    }
+    let (start, size) = if eeprom {
+        (eeprom_pos + 4, u32_at(eeprom_pos).unwrap() as usize)
+    } else {
+        let flash_pos = flash_header(eeprom_pos);
+        let flash_size = u32_at(flash_pos + 8).unwrap() as usize;
+        (
+            flash_pos + SGM_FLASH_HEADER_SIZE,
+            expected_size.unwrap_or(flash_size).min(SGM_FLASH_SIZE),
+        )
+    };
+    Ok(state[start..start + size].to_vec())
+}
+
+/// Extract a save in any format into the layout of our backup memory.
+/// `expected_size` is the size of the backup memory, `None` for an EEPROM of unknown size.
+pub(in super::super) fn import(
+    bytes: &[u8],
+    format: SaveFormat,
+    eeprom: bool,
+    expected_size: Option<usize>,
+    rom: &[u8],
+) -> SaveConvertResult<ImportedSave> {
+    info!("Importing a {} save", format);
+    let mut data = match format {
+        SaveFormat::Raw => bytes.to_vec(),
+        SaveFormat::SharkPort => unpack_sharkport(bytes, rom)?,
+        SaveFormat::Gsv => unpack_gsv(bytes)?,
+        SaveFormat::Sgm => unpack_sgm(bytes, eeprom, expected_size, rom)?,
+    };
+    // VBA keeps the EEPROM in our order
+    if eeprom && (format == SaveFormat::SharkPort || format == SaveFormat::Gsv) {
+        swap_eeprom_byte_order(&mut data);
+    }
+    fit(data, expected_size)
+}
+
+/// Pack the contents of our backup memory in another format
+pub(in super::super) fn export(
+    data: &[u8],
+    eeprom: bool,
+    format: SaveFormat,
+    rom: &[u8],
+) -> SaveConvertResult<Vec<u8>> {
+    match format {
+        SaveFormat::Raw => Ok(data.to_vec()),
+        SaveFormat::SharkPort => {
+            let mut data = data.to_vec();
+            if eeprom {
+                swap_eeprom_byte_order(&mut data);
+            }
+            Ok(pack_sharkport(&data, rom))
+        }
+        SaveFormat::Gsv => Err(SaveConvertError::UnsupportedFormat("exporting .gsv saves")),
+        SaveFormat::Sgm => Err(SaveConvertError::UnsupportedFormat(
+            "exporting VBA .sgm savestates",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit() {
+        let mut data = vec![0x12; 0x8000];
+        // mGBA rtc
+        data.extend_from_slice(&[0x34; RTC_BLOCK_SIZE]);
+        assert_eq!(
+            fit(data, Some(0x8000)).unwrap(),
+            (vec![0x12; 0x8000], Some([0x34; RTC_BLOCK_SIZE]))
+        );
+
+        // padded eeprom
+        let mut data = vec![0x12; 0x200];
+        data.resize(0x10000, 0xff);
+        assert_eq!(fit(data.clone(), None).unwrap(), (vec![0x12; 0x200], None));
+        assert_eq!(fit(data, Some(0x2000)).unwrap().0.len(), 0x2000);
+
+        let data = vec![0x12; 0x2000];
+        assert_eq!(fit(data.clone(), None).unwrap().0.len(), 0x2000);
+        assert!(fit(data, Some(0x200)).is_err());
+
+        // a short sram dump
+        assert_eq!(
+            fit(vec![0; 0x10], Some(0x8000)).unwrap().0[0x10..],
+            [0xff; 0x7ff0][..]
+        );
+    }
+
+    #[test]
+    fn test_sharkport_roundtrip() {
+        let mut rom = vec![0; 0xc0];
+        rom[0xa0..0xb0].copy_from_slice(b"POKEMON EMERBPEE");
+        let data: Vec<u8> = (0..0x200).map(|i| i as u8).collect();
+
+        let packed = export(&data, true, SaveFormat::SharkPort, &rom).unwrap();
+        assert_eq!(SaveFormat::detect(&packed).unwrap(), SaveFormat::SharkPort);
+        // The eeprom words are reversed in the container
+        let payload = packed.len() - 4 - data.len();
+        assert_eq!(packed[payload..payload + 8], [7, 6, 5, 4, 3, 2, 1, 0]);
+        assert_eq!(
+            import(&packed, SaveFormat::SharkPort, true, Some(0x200), &rom)
+                .unwrap()
+                .0,
+            data
+        );
+
+        let mut corrupted = packed.clone();
+        corrupted[payload] ^= 1;
+        assert!(import(&corrupted, SaveFormat::SharkPort, true, Some(0x200), &rom).is_err());
+    }
+
+    #[test]
+    fn test_gsv() {
+        let mut gsv = vec![0; GSV_PAYLOAD_OFFSET];
+        gsv[GSV_MAGIC_OFFSET..GSV_MAGIC_OFFSET + 8].copy_from_slice(GSV_MAGIC);
+        gsv.extend_from_slice(&[0x55; 0x8000]);
+        assert_eq!(SaveFormat::detect(&gsv).unwrap(), SaveFormat::Gsv);
+        assert_eq!(
+            import(&gsv, SaveFormat::Gsv, false, Some(0x8000), &[]).unwrap(),
+            (vec![0x55; 0x8000], None)
+        );
+    }
+
+    fn make_sgm(rom: &[u8], eeprom: &[u8], flash: &[u8]) -> Vec<u8> {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut state = 10u32.to_le_bytes().to_vec();
+        state.extend_from_slice(&rom[0xa0..0xb0]);
+        // registers and memories
+        state.extend_from_slice(&[0x20; 0x1000]);
+        state.extend_from_slice(&(eeprom.len() as u32).to_le_bytes());
+        state.extend_from_slice(eeprom);
+        state.resize(state.len() + SGM_EEPROM_SIZE - eeprom.len(), 0xff);
+        for word in &[0, 0, 0x10000, 0] {
+            state.extend_from_slice(&u32::to_le_bytes(*word));
+        }
+        state.extend_from_slice(flash);
+        state.resize(state.len() + SGM_FLASH_SIZE - flash.len(), 0xff);
+        // sound, cheats and the rest
+        state.extend_from_slice(&[0x30; 0x100]);
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&state).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_sgm() {
+        let mut rom = vec![0; 0xc0];
+        rom[0xa0..0xb0].copy_from_slice(b"POKEMON EMERBPEE");
+        let eeprom: Vec<u8> = (0..0x200).map(|i| i as u8).collect();
+        let sgm = make_sgm(&rom, &eeprom, &[0x55; 0x8000]);
+        assert_eq!(SaveFormat::detect(&sgm).unwrap(), SaveFormat::Sgm);
+
+        let (data, _) = import(&sgm, SaveFormat::Sgm, false, Some(0x8000), &rom).unwrap();
+        assert_eq!(data, vec![0x55; 0x8000]);
+        let (data, _) = import(&sgm, SaveFormat::Sgm, true, None, &rom).unwrap();
+        assert_eq!(data, eeprom);
+
+        assert!(import(&[0x1f, 0x8b, 0], SaveFormat::Sgm, false, None, &rom).is_err());
+    }
+
+    #[test]
+    fn test_detect_gzip_magic() {
+        let mut data = vec![0x12; 0x8000];
+        data[..2].copy_from_slice(GZIP_MAGIC);
+        assert_eq!(SaveFormat::detect(&data).unwrap(), SaveFormat::Raw);
+        assert_eq!(
+            SaveFormat::detect_file(Path::new("game.sav"), &data).unwrap(),
+            SaveFormat::Raw
+        );
+        assert_eq!(
+            SaveFormat::detect_file(Path::new("game.sgm"), &data).unwrap(),
+            SaveFormat::Sgm
+        );
+    }
+}
@@ -21,6 +21,14 @@ enum EepromAddressBits {
 }
 
 impl EepromType {
+    fn from_size(size: usize) -> Option<EepromType> {
+        match size {
+            0x0200 => Some(EepromType::Eeprom512),
+            0x2000 => Some(EepromType::Eeprom8k),
+            _ => None,
+        }
+    }
+
     fn size(&self) -> usize {
         match self {
             EepromType::Eeprom512 => 0x0200,
@@ -290,9 +298,10 @@ impl EepromController {
         if let Some(path) = &path {
             if let Ok(metadata) = fs::metadata(&path) {
                 let human_size = bytesize::ByteSize::b(metadata.len());
-                let assumed_type = match metadata.len() {
-                    512 => EepromType::Eeprom512,
-                    8192 => EepromType::Eeprom8k,
+                // the save may end with the rtc block
+                let assumed_type = match metadata.len() as usize {
+                    0x200 | 0x210 => EepromType::Eeprom512,
+                    0x2000 | 0x2010 => EepromType::Eeprom8k,
                     _ => panic!("invalid file size ({}) for eeprom save", human_size),
                 };
                 detect = false;
@@ -318,6 +327,34 @@ impl EepromController {
         }
     }
 
+    /// The size of the chip, `None` until the game reveals it
+    pub fn size(&self) -> Option<usize> {
+        if self.detect {
+            None
+        } else {
+            Some(self.chip.borrow().memory.bytes().len())
+        }
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        self.chip.borrow().memory.bytes().to_vec()
+    }
+
+    pub(crate) fn memory_mut(&mut self) -> &mut BackupFile {
+        &mut self.chip.get_mut().memory
+    }
+
+    /// Replace the contents, the chip takes the size of `bytes`.
+    /// While the size isn't detected it will still be corrected by the game.
+    pub fn load(&mut self, bytes: &[u8]) {
+        let eeprom_type = EepromType::from_size(bytes.len())
+            .unwrap_or_else(|| panic!("invalid eeprom size {:#x}", bytes.len()));
+        let mut chip = self.chip.borrow_mut();
+        chip.addr_bits = eeprom_type.bits();
+        chip.memory.replace(bytes);
+        chip.reset();
+    }
+
     pub fn write_half(&mut self, address: u32, value: u16) {
         assert!(!self.detect);
         self.chip.borrow_mut().clock_data_in(address, value as u8);
@@ -85,6 +85,14 @@ impl Flash {
         }
     }
 
+    pub(crate) fn memory(&self) -> &BackupFile {
+        &self.memory
+    }
+
+    pub(crate) fn memory_mut(&mut self) -> &mut BackupFile {
+        &mut self.memory
+    }
+
     fn reset_sequence(&mut self) {
         self.wrseq = FlashWriteSequence::Initial;
     }
@@ -161,6 +169,18 @@ impl Flash {
         }
     }
 
+    /// Read from the selected bank, bypassing the command interface
+    pub fn debug_read(&self, addr: u32) -> u8 {
+        self.memory
+            .read(self.flash_offset((addr & 0xffff) as usize))
+    }
+
+    /// Write to the selected bank, bypassing the command interface
+    pub fn debug_write(&mut self, addr: u32, value: u8) {
+        let offset = self.flash_offset((addr & 0xffff) as usize);
+        self.memory.write(offset, value);
+    }
+
     pub fn write(&mut self, addr: u32, value: u8) {
         trace!("[FLASH] write {:#x}={:#x}", addr, value);
         match self.wrseq {
@@ -2,7 +2,8 @@ use std::fmt;
 use std::str::FromStr;
 
 mod backup_file;
-pub use backup_file::BackupFile;
+pub use backup_file::{BackupFile, RTC_BLOCK_SIZE};
+pub mod convert;
 pub mod eeprom;
 pub mod flash;
 
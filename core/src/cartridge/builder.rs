@@ -1,3 +1,4 @@
+use std::fs;
 use std::path::{Path, PathBuf};
 
 use memmem::{Searcher, TwoWaySearcher};
@@ -9,19 +10,24 @@ use super::backup::eeprom::*;
 use super::backup::flash::*;
 use super::backup::{BackupFile, BackupType};
 use super::gpio::Gpio;
+use super::gyro::Gyro;
 use super::header;
+use super::rtc::{Rtc, RtcClock};
+use super::rumble::Rumble;
+use super::solar_sensor::SolarSensor;
+use super::tilt::TiltSensor;
 use super::BackupMedia;
 use super::Cartridge;
 
 use super::loader::{load_from_bytes, load_from_file, LoadRom};
+use super::patch::{apply_patch, find_sibling_patch};
 
-#[derive(Debug)]
-#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum GpioDeviceType {
     Rtc,
     SolarSensor,
     Gyro,
-    None,
+    Rumble,
 }
 
 #[derive(Debug)]
@@ -29,8 +35,14 @@ pub struct GamepakBuilder {
     path: Option<PathBuf>,
     bytes: Option<Box<[u8]>>,
     save_path: Option<PathBuf>,
+    patch_path: Option<PathBuf>,
     save_type: BackupType,
-    gpio_device: GpioDeviceType,
+    /// Some cartridges have several devices on the gpio port, like Boktai's RTC and solar sensor
+    gpio_devices: Vec<GpioDeviceType>,
+    /// Left unset, the RTC follows the host clock shifted by the time set in the save file
+    rtc_clock: Option<RtcClock>,
+    /// The tilt sensor sits in the backup memory region instead of the gpio port
+    tilt: bool,
     create_backup_file: bool,
 }
 
@@ -40,8 +52,11 @@ impl GamepakBuilder {
             save_type: BackupType::AutoDetect,
             path: None,
             save_path: None,
+            patch_path: None,
             bytes: None,
-            gpio_device: GpioDeviceType::None,
+            gpio_devices: Vec::new(),
+            rtc_clock: None,
+            tilt: false,
             create_backup_file: true,
         }
     }
@@ -66,6 +81,13 @@ impl GamepakBuilder {
         self
     }
 
+    /// Apply an IPS, UPS or BPS patch to the rom.
+    /// Without one, a patch next to the rom file with the same name is used if there is one.
+    pub fn patch(mut self, path: &Path) -> Self {
+        self.patch_path = Some(path.to_path_buf());
+        self
+    }
+
     pub fn save_type(mut self, save_type: BackupType) -> Self {
         self.save_type = save_type;
         self
@@ -97,7 +119,33 @@ impl GamepakBuilder {
     }
 
     pub fn with_rtc(mut self) -> Self {
-        self.gpio_device = GpioDeviceType::Rtc;
+        add_gpio_device(&mut self.gpio_devices, GpioDeviceType::Rtc);
+        self
+    }
+
+    /// Where the RTC takes the time from, this overrides the time set in the save file
+    pub fn rtc_clock(mut self, clock: RtcClock) -> Self {
+        self.rtc_clock = Some(clock);
+        self
+    }
+
+    pub fn with_solar_sensor(mut self) -> Self {
+        add_gpio_device(&mut self.gpio_devices, GpioDeviceType::SolarSensor);
+        self
+    }
+
+    pub fn with_gyro(mut self) -> Self {
+        add_gpio_device(&mut self.gpio_devices, GpioDeviceType::Gyro);
+        self
+    }
+
+    pub fn with_rumble(mut self) -> Self {
+        add_gpio_device(&mut self.gpio_devices, GpioDeviceType::Rumble);
+        self
+    }
+
+    pub fn with_tilt(mut self) -> Self {
+        self.tilt = true;
         self
     }
 
@@ -120,6 +168,18 @@ impl GamepakBuilder {
             ))
         }?;
 
+        let rom_path = &self.path;
+        let patch_path = self
+            .patch_path
+            .take()
+            .or_else(|| rom_path.as_deref().and_then(find_sibling_patch));
+        let bytes = if let Some(patch_path) = patch_path {
+            info!("Applying patch {}", patch_path.display());
+            apply_patch(&bytes, &fs::read(&patch_path)?)?
+        } else {
+            bytes
+        };
+
         let header = header::parse(&bytes)?;
         info!("Loaded ROM: {:?}", header);
 
@@ -134,7 +194,8 @@ impl GamepakBuilder {
         }
 
         let mut save_type = self.save_type;
-        let mut gpio_device = self.gpio_device;
+        let mut gpio_devices = self.gpio_devices;
+        let mut tilt = self.tilt;
 
         if let Some(overrides) = overrides::get_game_overrides(&header.game_code) {
             info!(
@@ -152,17 +213,18 @@ impl GamepakBuilder {
             }
 
             if overrides.force_rtc() {
-                match gpio_device {
-                    GpioDeviceType::None => gpio_device = GpioDeviceType::Rtc,
-                    GpioDeviceType::Rtc => {}
-                    _ => {
-                        warn!(
-                            "Can't use RTC due to forced gpio device type {:?}",
-                            gpio_device
-                        );
-                    }
-                }
+                add_gpio_device(&mut gpio_devices, GpioDeviceType::Rtc);
+            }
+            if overrides.solar_sensor() {
+                add_gpio_device(&mut gpio_devices, GpioDeviceType::SolarSensor);
             }
+            if overrides.gyro() {
+                add_gpio_device(&mut gpio_devices, GpioDeviceType::Gyro);
+            }
+            if overrides.rumble() {
+                add_gpio_device(&mut gpio_devices, GpioDeviceType::Rumble);
+            }
+            tilt |= overrides.tilt();
         }
 
         if save_type == BackupType::AutoDetect {
@@ -174,27 +236,66 @@ impl GamepakBuilder {
             }
         }
 
+        let gpio = create_gpio(&gpio_devices, self.rtc_clock);
         let backup = create_backup(save_type, self.save_path);
-
-        let gpio = match gpio_device {
-            GpioDeviceType::None => None,
-            GpioDeviceType::Rtc => {
-                info!("Emulating RTC!");
-                Some(Gpio::new_rtc())
-            }
-            _ => unimplemented!("Gpio device {:?} not implemented", gpio_device),
+        let tilt = if tilt {
+            info!("Emulating the tilt sensor!");
+            Some(TiltSensor::new())
+        } else {
+            None
         };
 
         let size = bytes.len();
-        Ok(Cartridge {
+        let mut cartridge = Cartridge {
             header,
             gpio,
             bytes: bytes.into_boxed_slice(),
             size,
             backup,
+            tilt,
             symbols,
-        })
+        };
+        cartridge.load_rtc_block();
+        Ok(cartridge)
+    }
+}
+
+fn add_gpio_device(devices: &mut Vec<GpioDeviceType>, device: GpioDeviceType) {
+    if !devices.contains(&device) {
+        devices.push(device);
+    }
+}
+
+fn create_gpio(devices: &[GpioDeviceType], rtc_clock: Option<RtcClock>) -> Option<Gpio> {
+    if devices.is_empty() {
+        return None;
+    }
+    let mut gpio = Gpio::new_none();
+    for device in devices {
+        match device {
+            GpioDeviceType::Rtc => {
+                info!("Emulating RTC!");
+                let mut rtc = Rtc::new();
+                if let Some(clock) = rtc_clock {
+                    rtc.set_clock(clock);
+                }
+                gpio.rtc = Some(rtc);
+            }
+            GpioDeviceType::SolarSensor => {
+                info!("Emulating the solar sensor!");
+                gpio.solar_sensor = Some(SolarSensor::new());
+            }
+            GpioDeviceType::Gyro => {
+                info!("Emulating the gyro sensor!");
+                gpio.gyro = Some(Gyro::new());
+            }
+            GpioDeviceType::Rumble => {
+                info!("Emulating the rumble motor!");
+                gpio.rumble = Some(Rumble::new());
+            }
+        }
     }
+    Some(gpio)
 }
 
 const BACKUP_FILE_EXT: &str = "sav";
@@ -1,4 +1,7 @@
+use super::gyro::Gyro;
 use super::rtc::Rtc;
+use super::rumble::Rumble;
+use super::solar_sensor::SolarSensor;
 use super::{GPIO_PORT_CONTROL, GPIO_PORT_DATA, GPIO_PORT_DIRECTION};
 
 use bit::BitIndex;
@@ -28,6 +31,9 @@ pub trait GpioDevice: Sized {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Gpio {
     pub(crate) rtc: Option<Rtc>,
+    pub(crate) solar_sensor: Option<SolarSensor>,
+    pub(crate) gyro: Option<Gyro>,
+    pub(crate) rumble: Option<Rumble>,
     direction: GpioState,
     control: GpioPortControl,
 }
@@ -36,14 +42,9 @@ impl Gpio {
     pub fn new_none() -> Self {
         Gpio {
             rtc: None,
-            direction: [GpioDirection::Out; 4],
-            control: GpioPortControl::WriteOnly,
-        }
-    }
-
-    pub fn new_rtc() -> Self {
-        Gpio {
-            rtc: Some(Rtc::new()),
+            solar_sensor: None,
+            gyro: None,
+            rumble: None,
             direction: [GpioDirection::Out; 4],
             control: GpioPortControl::WriteOnly,
         }
@@ -56,11 +57,21 @@ impl Gpio {
     pub fn read(&self, addr: u32) -> u16 {
         match addr {
             GPIO_PORT_DATA => {
+                // The devices share the port, each drives its own pins
+                let mut data = 0;
                 if let Some(rtc) = &self.rtc {
-                    rtc.read(&self.direction)
-                } else {
-                    0
+                    data |= rtc.read(&self.direction);
                 }
+                if let Some(solar_sensor) = &self.solar_sensor {
+                    data |= solar_sensor.read(&self.direction);
+                }
+                if let Some(gyro) = &self.gyro {
+                    data |= gyro.read(&self.direction);
+                }
+                if let Some(rumble) = &self.rumble {
+                    data |= rumble.read(&self.direction);
+                }
+                data
             }
             GPIO_PORT_DIRECTION => {
                 let mut direction = 0u16;
@@ -80,6 +91,15 @@ impl Gpio {
                 if let Some(rtc) = &mut self.rtc {
                     rtc.write(&self.direction, value);
                 }
+                if let Some(solar_sensor) = &mut self.solar_sensor {
+                    solar_sensor.write(&self.direction, value);
+                }
+                if let Some(gyro) = &mut self.gyro {
+                    gyro.write(&self.direction, value);
+                }
+                if let Some(rumble) = &mut self.rumble {
+                    rumble.write(&self.direction, value);
+                }
             }
             GPIO_PORT_DIRECTION => {
                 for i in 0..4 {
@@ -0,0 +1,108 @@
+use bit::BitIndex;
+use serde::{Deserialize, Serialize};
+
+use super::gpio::{GpioDevice, GpioState};
+
+/// The sample when the cartridge doesn't turn
+const GYRO_CENTER: i32 = 0x6c0;
+
+#[derive(Clone, Copy, Debug)]
+enum Pin {
+    /// Samples the rotation rate
+    Start = 0,
+    /// Shifts out a bit of the sample on falling edges
+    Clock = 1,
+    /// The sample, msb first
+    Data = 2,
+}
+
+impl Pin {
+    #[inline]
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// The gyro sensor of WarioWare: Twisted, measuring how fast the cartridge turns around the axis
+/// that faces the player.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Gyro {
+    rotation: i16,
+    sample: u16,
+    clock: bool,
+    data: bool,
+}
+
+impl Gyro {
+    pub fn new() -> Self {
+        Gyro {
+            rotation: 0,
+            sample: 0,
+            clock: false,
+            data: false,
+        }
+    }
+
+    /// How fast the cartridge turns, positive is clockwise.
+    /// The full range of an analog stick axis maps to the full range of the sensor.
+    pub fn set_rotation(&mut self, rotation: i16) {
+        self.rotation = rotation;
+    }
+}
+
+impl GpioDevice for Gyro {
+    fn write(&mut self, _gpio_state: &GpioState, data: u16) {
+        if data.bit(Pin::Start.index()) {
+            self.sample = (GYRO_CENTER + (self.rotation as i32 >> 5)) as u16;
+        }
+        let clock = data.bit(Pin::Clock.index());
+        if self.clock && !clock {
+            self.data = self.sample.bit(15);
+            self.sample <<= 1;
+        }
+        self.clock = clock;
+    }
+
+    fn read(&self, _gpio_state: &GpioState) -> u16 {
+        let mut result = 0;
+        result.set_bit(Pin::Data.index(), self.data);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::gpio::GpioDirection;
+    use super::*;
+
+    const GPIO_STATE: GpioState = [
+        GpioDirection::Out,
+        GpioDirection::Out,
+        GpioDirection::In,
+        GpioDirection::Out,
+    ];
+
+    fn read_sample(gyro: &mut Gyro) -> u16 {
+        gyro.write(&GPIO_STATE, 0b0011);
+        gyro.write(&GPIO_STATE, 0b0010);
+        let mut sample = 0;
+        for _ in 0..16 {
+            gyro.write(&GPIO_STATE, 0b0000);
+            sample = sample << 1 | gyro.read(&GPIO_STATE).bit(Pin::Data.index()) as u16;
+            gyro.write(&GPIO_STATE, 0b0010);
+        }
+        sample
+    }
+
+    #[test]
+    fn test_sample() {
+        let mut gyro = Gyro::new();
+        assert_eq!(read_sample(&mut gyro), GYRO_CENTER as u16);
+        gyro.set_rotation(i16::MAX);
+        let clockwise = read_sample(&mut gyro);
+        gyro.set_rotation(i16::MIN);
+        let counterclockwise = read_sample(&mut gyro);
+        assert!(counterclockwise < GYRO_CENTER as u16 && GYRO_CENTER as u16 <= clockwise);
+        assert_eq!(clockwise - counterclockwise, 0x7ff);
+    }
+}
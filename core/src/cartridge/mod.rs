@@ -2,23 +2,36 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
-use arm7tdmi::memory::{Addr, BusIO, DebugRead};
+use arm7tdmi::memory::{Addr, BusIO, DebugRead, DebugWrite};
 
 pub mod header;
 use header::CartridgeHeader;
 
 mod backup;
+pub use backup::convert::{
+    swap_eeprom_byte_order, SaveConvertError, SaveConvertResult, SaveFormat,
+};
 use backup::eeprom::EepromController;
 use backup::flash::Flash;
 pub use backup::BackupType;
 use backup::{BackupFile, BackupMemoryInterface};
 
 mod gpio;
+mod gyro;
 mod rtc;
+mod rumble;
+mod solar_sensor;
+mod tilt;
 use gpio::Gpio;
+pub use rtc::RtcClock;
+use rumble::Rumble;
+pub use rumble::{DynRumbleInterface, RumbleInterface};
+pub use solar_sensor::SOLAR_LIGHT_LEVELS;
+use tilt::{is_tilt_access, TiltSensor};
 
 mod builder;
 mod loader;
+mod patch;
 pub use builder::GamepakBuilder;
 
 pub const GPIO_PORT_DATA: u32 = 0xC4;
@@ -33,6 +46,17 @@ pub enum BackupMedia {
     Undetected,
 }
 
+impl BackupMedia {
+    fn file_mut(&mut self) -> Option<&mut BackupFile> {
+        match self {
+            BackupMedia::Sram(memory) => Some(memory),
+            BackupMedia::Flash(flash) => Some(flash.memory_mut()),
+            BackupMedia::Eeprom(eeprom) => Some(eeprom.memory_mut()),
+            BackupMedia::Undetected => None,
+        }
+    }
+}
+
 pub type SymbolTable = HashMap<String, u32>;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -45,6 +69,7 @@ pub struct Cartridge {
     gpio: Option<Gpio>,
     symbols: Option<SymbolTable>, // TODO move it somewhere else
     pub(crate) backup: BackupMedia,
+    tilt: Option<TiltSensor>,
 }
 
 impl Cartridge {
@@ -55,6 +80,105 @@ impl Cartridge {
         &self.gpio
     }
 
+    pub fn has_rtc(&self) -> bool {
+        self.gpio.as_ref().map_or(false, |gpio| gpio.rtc.is_some())
+    }
+
+    pub fn rtc_clock(&self) -> Option<RtcClock> {
+        self.gpio
+            .as_ref()
+            .and_then(|gpio| gpio.rtc.as_ref())
+            .map(|rtc| rtc.clock())
+    }
+
+    /// Set where the RTC takes the time from, if the cartridge has one
+    pub fn set_rtc_clock(&mut self, clock: RtcClock) {
+        if let Some(rtc) = self.gpio.as_mut().and_then(|gpio| gpio.rtc.as_mut()) {
+            rtc.set_clock(clock);
+        }
+    }
+
+    /// Restore the RTC from the block at the end of the save file
+    pub(crate) fn load_rtc_block(&mut self) {
+        let rtc = self.gpio.as_mut().and_then(|gpio| gpio.rtc.as_mut());
+        let block = self.backup.file_mut().and_then(|file| file.rtc_block());
+        if let (Some(rtc), Some(block)) = (rtc, block) {
+            rtc.load_block(block);
+        }
+    }
+
+    /// Keep the RTC at the end of the save file, like other emulators do. Deterministic clocks
+    /// are left out of it, so they don't change the time of later runs.
+    pub(crate) fn save_rtc_block(&mut self) {
+        let rtc = self.gpio.as_ref().and_then(|gpio| gpio.rtc.as_ref());
+        let file = self.backup.file_mut();
+        if let (Some(rtc), Some(file)) = (rtc, file) {
+            if !rtc.clock().is_deterministic() {
+                file.set_rtc_block(rtc.to_block());
+            }
+        }
+    }
+
+    pub(crate) fn update_rtc_cycles(&mut self, cycles: usize) {
+        if let Some(rtc) = self.gpio.as_mut().and_then(|gpio| gpio.rtc.as_mut()) {
+            rtc.set_cycles(cycles);
+        }
+    }
+
+    pub fn has_solar_sensor(&self) -> bool {
+        self.gpio
+            .as_ref()
+            .map_or(false, |gpio| gpio.solar_sensor.is_some())
+    }
+
+    /// Set how bright the light on the solar sensor is, if the cartridge has one
+    pub fn set_solar_light(&mut self, light: u8) {
+        if let Some(solar_sensor) = self
+            .gpio
+            .as_mut()
+            .and_then(|gpio| gpio.solar_sensor.as_mut())
+        {
+            solar_sensor.set_light(light);
+        }
+    }
+
+    pub fn has_gyro(&self) -> bool {
+        self.gpio.as_ref().map_or(false, |gpio| gpio.gyro.is_some())
+    }
+
+    /// Set how fast the cartridge turns, if it has a gyro sensor
+    pub fn set_gyro_rotation(&mut self, rotation: i16) {
+        if let Some(gyro) = self.gpio.as_mut().and_then(|gpio| gpio.gyro.as_mut()) {
+            gyro.set_rotation(rotation);
+        }
+    }
+
+    pub fn has_rumble(&self) -> bool {
+        self.gpio
+            .as_ref()
+            .map_or(false, |gpio| gpio.rumble.is_some())
+    }
+
+    fn rumble(&self) -> Option<&Rumble> {
+        self.gpio.as_ref().and_then(|gpio| gpio.rumble.as_ref())
+    }
+
+    /// Whether the game has the rumble motor turned on
+    pub fn is_rumbling(&self) -> bool {
+        self.rumble().map_or(false, |rumble| rumble.is_on())
+    }
+
+    pub fn has_tilt(&self) -> bool {
+        self.tilt.is_some()
+    }
+
+    /// Set how far the cartridge is tilted, if it has a tilt sensor
+    pub fn set_tilt(&mut self, x: i16, y: i16) {
+        if let Some(tilt) = &mut self.tilt {
+            tilt.set_tilt(x, y);
+        }
+    }
+
     pub fn set_rom_bytes(&mut self, bytes: Box<[u8]>) {
         self.size = bytes.len();
         self.bytes = bytes;
@@ -64,6 +188,53 @@ impl Cartridge {
         &self.bytes
     }
 
+    /// Replace the save with one in any of the `SaveFormat`s
+    pub fn import_save(&mut self, bytes: &[u8]) -> SaveConvertResult<()> {
+        self.import_save_as(bytes, SaveFormat::detect(bytes)?)
+    }
+
+    /// Replace the save with one in the given format, see `SaveFormat::detect_file`
+    pub fn import_save_as(&mut self, bytes: &[u8], format: SaveFormat) -> SaveConvertResult<()> {
+        let rom = &self.bytes;
+        let rtc_block = match &mut self.backup {
+            BackupMedia::Sram(memory) => {
+                let (data, rtc_block) =
+                    backup::convert::import(bytes, format, false, Some(memory.bytes().len()), rom)?;
+                memory.replace(&data);
+                rtc_block
+            }
+            BackupMedia::Flash(flash) => {
+                let memory = flash.memory_mut();
+                let (data, rtc_block) =
+                    backup::convert::import(bytes, format, false, Some(memory.bytes().len()), rom)?;
+                memory.replace(&data);
+                rtc_block
+            }
+            BackupMedia::Eeprom(eeprom) => {
+                let (data, rtc_block) =
+                    backup::convert::import(bytes, format, true, eeprom.size(), rom)?;
+                eeprom.load(&data);
+                rtc_block
+            }
+            BackupMedia::Undetected => return Err(SaveConvertError::UnknownSaveType),
+        };
+        if let (Some(block), Some(file)) = (rtc_block, self.backup.file_mut()) {
+            file.set_rtc_block(block);
+            self.load_rtc_block();
+        }
+        Ok(())
+    }
+
+    pub fn export_save(&self, format: SaveFormat) -> SaveConvertResult<Vec<u8>> {
+        let (data, eeprom) = match &self.backup {
+            BackupMedia::Sram(memory) => (memory.bytes().to_vec(), false),
+            BackupMedia::Flash(flash) => (flash.memory().bytes().to_vec(), false),
+            BackupMedia::Eeprom(eeprom) => (eeprom.bytes(), true),
+            BackupMedia::Undetected => return Err(SaveConvertError::UnknownSaveType),
+        };
+        backup::convert::export(&data, eeprom, format, &self.bytes)
+    }
+
     // 'Clones' the cartridge without the ROM buffer
     pub fn thin_copy(&self) -> Cartridge {
         Cartridge {
@@ -73,12 +244,14 @@ impl Cartridge {
             gpio: self.gpio.clone(),
             symbols: self.symbols.clone(),
             backup: self.backup.clone(),
+            tilt: self.tilt.clone(),
         }
     }
 
     pub fn update_from(&mut self, other: Cartridge) {
         self.header = other.header;
         self.gpio = other.gpio;
+        self.tilt = other.tilt;
         self.symbols = other.symbols;
         self.backup = other.backup;
     }
@@ -113,6 +286,9 @@ impl BusIO for Cartridge {
     fn read_8(&mut self, addr: Addr) -> u8 {
         let offset = (addr & 0x01ff_ffff) as usize;
         match addr & 0xff000000 {
+            SRAM_LO | SRAM_HI if self.tilt.is_some() && is_tilt_access(addr) => {
+                self.tilt.as_ref().unwrap().read(addr)
+            }
             SRAM_LO | SRAM_HI => match &self.backup {
                 BackupMedia::Sram(memory) => memory.read((addr & 0x7FFF) as usize),
                 BackupMedia::Flash(flash) => flash.read(addr),
@@ -150,6 +326,9 @@ impl BusIO for Cartridge {
 
     fn write_8(&mut self, addr: u32, value: u8) {
         match addr & 0xff000000 {
+            SRAM_LO | SRAM_HI if self.tilt.is_some() && is_tilt_access(addr) => {
+                self.tilt.as_mut().unwrap().write(addr, value)
+            }
             SRAM_LO | SRAM_HI => match &mut self.backup {
                 BackupMedia::Flash(flash) => flash.write(addr, value),
                 BackupMedia::Sram(memory) => memory.write((addr & 0x7FFF) as usize, value),
@@ -163,6 +342,9 @@ impl BusIO for Cartridge {
         if is_gpio_access(addr) {
             if let Some(gpio) = &mut self.gpio {
                 gpio.write(addr & 0x1ff_ffff, value);
+                if gpio.rtc.as_mut().map_or(false, |rtc| rtc.take_changed()) {
+                    self.save_rtc_block();
+                }
                 return;
             }
         }
@@ -181,10 +363,32 @@ impl BusIO for Cartridge {
 impl DebugRead for Cartridge {
     fn debug_read_8(&mut self, addr: Addr) -> u8 {
         let offset = (addr & 0x01ff_ffff) as usize;
-        if offset >= self.size {
-            self.read_unused(addr)
-        } else {
-            self.bytes[offset]
+        match addr & 0xff000000 {
+            SRAM_LO | SRAM_HI if self.tilt.is_some() && is_tilt_access(addr) => {
+                self.tilt.as_ref().unwrap().read(addr)
+            }
+            SRAM_LO | SRAM_HI => match &self.backup {
+                BackupMedia::Sram(memory) => memory.read((addr & 0x7FFF) as usize),
+                BackupMedia::Flash(flash) => flash.debug_read(addr),
+                _ => 0,
+            },
+            _ if offset >= self.size => self.read_unused(addr),
+            _ => self.bytes[offset],
+        }
+    }
+}
+
+impl DebugWrite for Cartridge {
+    fn debug_write_8(&mut self, addr: Addr, value: u8) {
+        let offset = (addr & 0x01ff_ffff) as usize;
+        match addr & 0xff000000 {
+            SRAM_LO | SRAM_HI => match &mut self.backup {
+                BackupMedia::Sram(memory) => memory.write((addr & 0x7FFF) as usize, value),
+                BackupMedia::Flash(flash) => flash.debug_write(addr, value),
+                _ => {}
+            },
+            _ if offset < self.size => self.bytes[offset] = value,
+            _ => {}
         }
     }
 }
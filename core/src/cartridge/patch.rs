@@ -0,0 +1,385 @@
+//! Soft patching of ROMs with IPS, UPS and BPS patches.
+//!
+//! The format is told from the magic at the start of the patch. UPS and BPS patches carry the
+//! CRC32 of the ROM they were made for and of the result, both are checked.
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+
+use super::super::{GBAError, GBAResult};
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: usize = 0x45_4f_46;
+const UPS_MAGIC: &[u8] = b"UPS1";
+const BPS_MAGIC: &[u8] = b"BPS1";
+/// UPS and BPS end with the source, target and patch CRC32s
+const FOOTER_SIZE: usize = 12;
+/// The largest rom the gamepak bus can address
+const MAX_ROM_SIZE: usize = 32 * 1024 * 1024;
+
+pub const PATCH_EXTENSIONS: &[&str] = &["ips", "ups", "bps"];
+
+/// Find a patch next to the rom with the same name, e.g. `game.ips` for `game.gba`
+pub fn find_sibling_patch(rom_path: &Path) -> Option<PathBuf> {
+    PATCH_EXTENSIONS
+        .iter()
+        .map(|extension| rom_path.with_extension(extension))
+        .find(|path| path.is_file())
+}
+
+fn patch_error(message: &str) -> GBAError {
+    GBAError::CartridgeLoadError(format!("patch error: {}", message))
+}
+
+/// Apply the patch, returning the patched rom
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> GBAResult<Vec<u8>> {
+    if patch.starts_with(IPS_MAGIC) {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(UPS_MAGIC) {
+        apply_ups(rom, patch)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(rom, patch)
+    } else {
+        Err(patch_error("not an IPS, UPS or BPS patch"))
+    }
+}
+
+/// Reads the fields of a patch, every read past the end is an error
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Reader<'a> {
+        Reader { data, pos }
+    }
+
+    fn bytes(&mut self, len: usize) -> GBAResult<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| patch_error("truncated patch"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> GBAResult<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn be(&mut self, len: usize) -> GBAResult<usize> {
+        Ok(self
+            .bytes(len)?
+            .iter()
+            .fold(0, |value, byte| value << 8 | *byte as usize))
+    }
+
+    /// The variable length numbers of UPS and BPS
+    fn varint(&mut self) -> GBAResult<usize> {
+        let mut value = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let byte = self.u8()?;
+            let too_large = || patch_error("number too large");
+            value = ((byte & 0x7f) as usize)
+                .checked_mul(shift)
+                .and_then(|digit| value.checked_add(digit))
+                .ok_or_else(too_large)?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_mul(0x80).ok_or_else(too_large)?;
+            value = value.checked_add(shift).ok_or_else(too_large)?;
+        }
+    }
+}
+
+/// The target size is read from the patch, check it before allocating the target
+fn check_target_size(target_size: usize) -> GBAResult<()> {
+    if target_size > MAX_ROM_SIZE {
+        Err(patch_error("the patched rom would be larger than 32MiB"))
+    } else {
+        Ok(())
+    }
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> GBAResult<Vec<u8>> {
+    let mut target = rom.to_vec();
+    let mut reader = Reader::new(patch, IPS_MAGIC.len());
+    loop {
+        let offset = reader.be(3)?;
+        if offset == IPS_EOF {
+            break;
+        }
+        let (len, fill) = match reader.be(2)? {
+            // Run length encoded record
+            0 => (reader.be(2)?, Some(reader.u8()?)),
+            len => (len, None),
+        };
+        if target.len() < offset + len {
+            target.resize(offset + len, 0);
+        }
+        match fill {
+            Some(value) => target[offset..offset + len]
+                .iter_mut()
+                .for_each(|b| *b = value),
+            None => target[offset..offset + len].copy_from_slice(reader.bytes(len)?),
+        }
+    }
+    // Some patches end with the size to truncate to
+    if let Ok(size) = reader.be(3) {
+        target.truncate(size);
+    }
+    Ok(target)
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    crc32fast::hash(data)
+}
+
+/// Checks the patch CRC and returns the source and target CRCs
+fn check_footer(patch: &[u8]) -> GBAResult<(u32, u32)> {
+    if patch.len() < FOOTER_SIZE + 4 {
+        return Err(patch_error("truncated patch"));
+    }
+    let footer = &patch[patch.len() - FOOTER_SIZE..];
+    let read =
+        |i: usize| u32::from_le_bytes([footer[i], footer[i + 1], footer[i + 2], footer[i + 3]]);
+    if crc32(&patch[..patch.len() - 4]) != read(8) {
+        return Err(patch_error("the patch is corrupted (checksum mismatch)"));
+    }
+    Ok((read(0), read(4)))
+}
+
+fn check_source(rom: &[u8], expected_crc: u32) -> GBAResult<()> {
+    let crc = crc32(rom);
+    if crc != expected_crc {
+        return Err(patch_error(&format!(
+            "the patch is for another rom (crc32 {:08x}, expected {:08x})",
+            crc, expected_crc
+        )));
+    }
+    Ok(())
+}
+
+fn check_target(target: &[u8], expected_crc: u32) -> GBAResult<()> {
+    if crc32(target) != expected_crc {
+        return Err(patch_error("the patched rom has the wrong checksum"));
+    }
+    Ok(())
+}
+
+fn apply_ups(rom: &[u8], patch: &[u8]) -> GBAResult<Vec<u8>> {
+    let (source_crc, target_crc) = check_footer(patch)?;
+    check_source(rom, source_crc)?;
+
+    let mut reader = Reader::new(&patch[..patch.len() - FOOTER_SIZE], UPS_MAGIC.len());
+    let source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    if source_size != rom.len() {
+        return Err(patch_error("the patch is for a rom of another size"));
+    }
+    check_target_size(target_size)?;
+
+    let mut target = vec![0; target_size];
+    let common = rom.len().min(target_size);
+    target[..common].copy_from_slice(&rom[..common]);
+    let mut pos = 0usize;
+    while reader.pos < reader.data.len() {
+        pos = pos
+            .checked_add(reader.varint()?)
+            .ok_or_else(|| patch_error("the patch writes out of bounds"))?;
+        // Bytes are xored with the source up to and including a terminating zero
+        loop {
+            let xor = reader.u8()?;
+            if let Some(byte) = target.get_mut(pos) {
+                *byte ^= xor;
+            }
+            pos += 1;
+            if xor == 0 {
+                break;
+            }
+        }
+    }
+
+    check_target(&target, target_crc)?;
+    Ok(target)
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> GBAResult<Vec<u8>> {
+    let (source_crc, target_crc) = check_footer(patch)?;
+    check_source(rom, source_crc)?;
+
+    let mut reader = Reader::new(&patch[..patch.len() - FOOTER_SIZE], BPS_MAGIC.len());
+    let source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    let metadata_size = reader.varint()?;
+    reader.bytes(metadata_size)?;
+    if source_size != rom.len() {
+        return Err(patch_error("the patch is for a rom of another size"));
+    }
+    check_target_size(target_size)?;
+
+    let out_of_bounds = || patch_error("the patch reads or writes out of bounds");
+    let mut target: Vec<u8> = Vec::with_capacity(target_size);
+    let mut source_offset = 0isize;
+    let mut target_offset = 0isize;
+    while reader.pos < reader.data.len() {
+        let action = reader.varint()?;
+        let len = (action >> 2) + 1;
+        if target.len() + len > target_size {
+            return Err(out_of_bounds());
+        }
+        match action & 3 {
+            // SourceRead, from the same position in the source
+            0 => {
+                let pos = target.len();
+                let bytes = rom.get(pos..pos + len).ok_or_else(out_of_bounds)?;
+                target.extend_from_slice(bytes);
+            }
+            // TargetRead, from the patch
+            1 => target.extend_from_slice(reader.bytes(len)?),
+            // SourceCopy and TargetCopy move their offset by a signed amount first
+            command => {
+                let data = reader.varint()?;
+                let delta = (data >> 1) as isize * if data & 1 != 0 { -1 } else { 1 };
+                if command == 2 {
+                    source_offset += delta;
+                    let start = usize::try_from(source_offset).map_err(|_| out_of_bounds())?;
+                    let bytes = rom.get(start..start + len).ok_or_else(out_of_bounds)?;
+                    target.extend_from_slice(bytes);
+                    source_offset += len as isize;
+                } else {
+                    target_offset += delta;
+                    let start = usize::try_from(target_offset).map_err(|_| out_of_bounds())?;
+                    if start >= target.len() {
+                        return Err(out_of_bounds());
+                    }
+                    // The copy may overlap what it writes, so it goes byte by byte
+                    for i in start..start + len {
+                        let byte = target[i];
+                        target.push(byte);
+                    }
+                    target_offset += len as isize;
+                }
+            }
+        }
+    }
+    if target.len() != target_size {
+        return Err(patch_error("the patched rom has the wrong size"));
+    }
+
+    check_target(&target, target_crc)?;
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(mut value: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let x = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(0x80 | x);
+                return bytes;
+            }
+            bytes.push(x);
+            value -= 1;
+        }
+    }
+
+    fn with_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        let crc = crc32(&patch);
+        patch.extend_from_slice(&crc.to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn test_varint() {
+        for &value in &[0, 1, 0x7f, 0x80, 0x4000, 0x123456] {
+            let bytes = varint(value);
+            assert_eq!(Reader::new(&bytes, 0).varint().unwrap(), value);
+        }
+        // a corrupt patch that never ends the number
+        assert!(Reader::new(&[0x7f; 16], 0).varint().is_err());
+        assert!(Reader::new(&[0; 16], 0).varint().is_err());
+    }
+
+    #[test]
+    fn test_ips() {
+        let rom = [0u8; 8];
+        let mut patch = b"PATCH".to_vec();
+        // 2 bytes at 1
+        patch.extend_from_slice(&[0, 0, 1, 0, 2, 0xaa, 0xbb]);
+        // rle, 3 times 0xcc at 8, past the end of the rom
+        patch.extend_from_slice(&[0, 0, 8, 0, 0, 0, 3, 0xcc]);
+        patch.extend_from_slice(b"EOF");
+        assert_eq!(
+            apply_patch(&rom, &patch).unwrap(),
+            [0, 0xaa, 0xbb, 0, 0, 0, 0, 0, 0xcc, 0xcc, 0xcc]
+        );
+
+        // truncated to 4 bytes
+        patch.extend_from_slice(&[0, 0, 4]);
+        assert_eq!(apply_patch(&rom, &patch).unwrap(), [0, 0xaa, 0xbb, 0]);
+    }
+
+    #[test]
+    fn test_ups() {
+        let source = [1u8, 2, 3, 4];
+        let target = [1u8, 2, 7, 4, 9];
+        let mut patch = b"UPS1".to_vec();
+        patch.extend(varint(source.len()));
+        patch.extend(varint(target.len()));
+        // skip 2, xor 3 ^ 7, terminate
+        patch.extend(varint(2));
+        patch.extend_from_slice(&[3 ^ 7, 0]);
+        // skip 0 from after the terminator at 3, xor the new byte
+        patch.extend(varint(0));
+        patch.extend_from_slice(&[9, 0]);
+        let patch = with_footer(patch, &source, &target);
+        assert_eq!(apply_patch(&source, &patch).unwrap(), target);
+
+        assert!(apply_patch(&[1, 2, 3, 5], &patch).is_err());
+        let mut corrupted = patch.clone();
+        corrupted[6] ^= 1;
+        assert!(apply_patch(&source, &corrupted).is_err());
+
+        // the target size is checked before anything is allocated
+        let mut huge = b"UPS1".to_vec();
+        huge.extend(varint(source.len()));
+        huge.extend(varint(usize::MAX >> 8));
+        let huge = with_footer(huge, &source, &target);
+        assert!(apply_patch(&source, &huge).is_err());
+    }
+
+    #[test]
+    fn test_bps() {
+        let source = b"abcdefgh";
+        let target = b"abcXYXYXYabcd";
+        let mut patch = b"BPS1".to_vec();
+        patch.extend(varint(source.len()));
+        patch.extend(varint(target.len()));
+        patch.extend(varint(0));
+        // SourceRead 3
+        patch.extend(varint((3 - 1) << 2));
+        // TargetRead 2
+        patch.extend(varint((2 - 1) << 2 | 1));
+        patch.extend_from_slice(b"XY");
+        // TargetCopy 4 from 3, overlapping what it writes
+        patch.extend(varint((4 - 1) << 2 | 3));
+        patch.extend(varint(3 << 1));
+        // SourceCopy 4 from 0
+        patch.extend(varint((4 - 1) << 2 | 2));
+        patch.extend(varint(0));
+        let patch = with_footer(patch, source, target);
+        assert_eq!(apply_patch(source, &patch).unwrap(), target);
+
+        assert!(apply_patch(b"abcdefgX", &patch).is_err());
+    }
+}
@@ -1,12 +1,16 @@
 use bit::BitIndex;
 use bit_reverse::LookupReverse;
 use chrono::prelude::*;
+use chrono::Duration;
 use serde::{Deserialize, Serialize};
 
 use num::FromPrimitive;
 
 use std::cmp;
+use std::fmt;
+use std::str::FromStr;
 
+use super::backup::RTC_BLOCK_SIZE;
 use super::gpio::{GpioDevice, GpioDirection, GpioState};
 
 fn num2bcd(mut num: u8) -> u8 {
@@ -24,6 +28,134 @@ fn num2bcd(mut num: u8) -> u8 {
     bcd
 }
 
+fn bcd2num(bcd: u8) -> u8 {
+    (bcd >> 4) * 10 + (bcd & 0xf)
+}
+
+/// The gba runs 2^24 cycles per second
+const CYCLES_PER_SECOND: usize = 1 << 24;
+
+const DATE_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// Bound on the offset from the host time, plenty for the 100 years the RTC counts
+const MAX_OFFSET: i64 = 200 * 366 * 24 * 60 * 60;
+
+fn epoch() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(1970, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+}
+
+fn to_seconds(date_time: NaiveDateTime) -> i64 {
+    date_time.signed_duration_since(epoch()).num_seconds()
+}
+
+fn from_seconds(seconds: i64) -> NaiveDateTime {
+    epoch() + Duration::seconds(seconds)
+}
+
+/// Where the RTC takes the time from. The times are local times in seconds since 1970-01-01.
+#[derive(SmartDefault, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum RtcClock {
+    /// The local time of the host
+    #[default]
+    RealTime,
+    /// The local time of the host, shifted by some seconds
+    Offset(i64),
+    /// Always the same time
+    Frozen(i64),
+    /// Starts at the time on power-on and advances with the emulated cycles, so the game sees the
+    /// same times on every run
+    Emulated(i64),
+}
+
+impl RtcClock {
+    fn now(&self, cycles: usize) -> NaiveDateTime {
+        match *self {
+            RtcClock::RealTime => Local::now().naive_local(),
+            RtcClock::Offset(offset) => Local::now().naive_local() + Duration::seconds(offset),
+            RtcClock::Frozen(time) => from_seconds(time),
+            RtcClock::Emulated(start) => from_seconds(start + (cycles / CYCLES_PER_SECOND) as i64),
+        }
+    }
+
+    /// Whether the game sees the same times on every run
+    pub fn is_deterministic(&self) -> bool {
+        match self {
+            RtcClock::Frozen(_) | RtcClock::Emulated(_) => true,
+            RtcClock::RealTime | RtcClock::Offset(_) => false,
+        }
+    }
+
+    /// An emulated clock showing the current time after `cycles`, for clocks that follow the host
+    pub(crate) fn to_deterministic(self, cycles: usize) -> RtcClock {
+        if self.is_deterministic() {
+            self
+        } else {
+            let elapsed = (cycles / CYCLES_PER_SECOND) as i64;
+            RtcClock::Emulated(to_seconds(self.now(cycles)) - elapsed)
+        }
+    }
+}
+
+impl fmt::Display for RtcClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            RtcClock::RealTime => write!(f, "realtime"),
+            RtcClock::Offset(offset) => write!(f, "offset:{}", offset),
+            RtcClock::Frozen(time) => {
+                write!(f, "frozen:{}", from_seconds(time).format(DATE_TIME_FORMAT))
+            }
+            RtcClock::Emulated(start) => {
+                write!(
+                    f,
+                    "emulated:{}",
+                    from_seconds(start).format(DATE_TIME_FORMAT)
+                )
+            }
+        }
+    }
+}
+
+/// Parses `realtime`, `offset:<seconds>`, `frozen:<date time>` and `emulated:<date time>`, where
+/// the date time looks like `2004-09-01T12:00:00`
+impl FromStr for RtcClock {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_date_time = |date_time: &str| {
+            let date_time = NaiveDateTime::parse_from_str(date_time, DATE_TIME_FORMAT)
+                .map_err(|e| format!("{} is not a valid date time: {}", date_time, e))?;
+            if (2000..=2099).contains(&date_time.year()) {
+                Ok(to_seconds(date_time))
+            } else {
+                Err(String::from("the RTC only counts the years 2000 to 2099"))
+            }
+        };
+        let mut parts = s.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some("realtime"), None) => Ok(RtcClock::RealTime),
+            (Some("offset"), Some(offset)) => offset
+                .parse::<i64>()
+                .ok()
+                .filter(|seconds| seconds.abs() <= MAX_OFFSET)
+                .map(RtcClock::Offset)
+                .ok_or_else(|| {
+                    format!(
+                        "{} is not a valid offset, it is in seconds and at most {}",
+                        offset, MAX_OFFSET
+                    )
+                }),
+            (Some("frozen"), Some(date_time)) => parse_date_time(date_time).map(RtcClock::Frozen),
+            (Some("emulated"), Some(date_time)) => {
+                parse_date_time(date_time).map(RtcClock::Emulated)
+            }
+            _ => Err(format!("{} is not a valid RTC clock", s)),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Copy, Debug)]
 enum Port {
     /// Serial Clock
@@ -194,6 +326,16 @@ pub struct Rtc {
     status: registers::StatusRegister,
     serial_buffer: SerialBuffer,
     internal_buffer: [u8; 8],
+    clock: RtcClock,
+    /// A clock set by the user wins over the time set in the save file
+    #[serde(skip)]
+    clock_chosen: bool,
+    /// The scheduler timestamp, for the emulated clock
+    #[serde(skip)]
+    cycles: usize,
+    /// Whether the game changed the state kept in the save file
+    #[serde(skip)]
+    changed: bool,
 }
 
 impl Rtc {
@@ -206,6 +348,92 @@ impl Rtc {
             status: registers::StatusRegister(0x82),
             serial_buffer: SerialBuffer::new(),
             internal_buffer: [0; 8],
+            clock: RtcClock::RealTime,
+            clock_chosen: false,
+            cycles: 0,
+            changed: false,
+        }
+    }
+
+    pub fn clock(&self) -> RtcClock {
+        self.clock
+    }
+
+    pub fn set_clock(&mut self, clock: RtcClock) {
+        self.clock = clock;
+        self.clock_chosen = true;
+    }
+
+    /// Keep the emulated clock in sync with the scheduler
+    pub(crate) fn set_cycles(&mut self, cycles: usize) {
+        self.cycles = cycles;
+    }
+
+    pub(crate) fn take_changed(&mut self) -> bool {
+        std::mem::replace(&mut self.changed, false)
+    }
+
+    /// The block kept at the end of the save file: the date time registers, the status register
+    /// and the unix time the date time was taken at
+    pub(crate) fn to_block(&self) -> [u8; RTC_BLOCK_SIZE] {
+        let latch = Local::now();
+        let offset = match self.clock {
+            RtcClock::Offset(offset) => offset,
+            _ => 0,
+        };
+        let time = latch.naive_local() + Duration::seconds(offset);
+        let pm = if time.hour() >= 12 { 0x40 } else { 0 };
+
+        let mut block = [0; RTC_BLOCK_SIZE];
+        block[0] = num2bcd(time.year().rem_euclid(100) as u8);
+        block[1] = num2bcd(time.month() as u8);
+        block[2] = num2bcd(time.day() as u8);
+        block[3] = num2bcd(time.weekday().number_from_monday() as u8);
+        block[4] = num2bcd(time.hour() as u8) | pm;
+        block[5] = num2bcd(time.minute() as u8);
+        block[6] = num2bcd(time.second() as u8);
+        block[7] = self.status.read();
+        block[8..].copy_from_slice(&(latch.timestamp() as u64).to_le_bytes());
+        block
+    }
+
+    /// Restores the status register, and the offset from the host time unless a clock was chosen
+    pub(crate) fn load_block(&mut self, block: &[u8; RTC_BLOCK_SIZE]) {
+        self.status.write(block[7]);
+        if self.clock_chosen {
+            return;
+        }
+
+        let mut hour = bcd2num(block[4] & 0x3f) as u32;
+        if block[4] & 0x40 != 0 && hour < 12 {
+            hour += 12;
+        }
+        let time = NaiveDate::from_ymd_opt(
+            2000 + bcd2num(block[0]) as i32,
+            bcd2num(block[1]) as u32,
+            bcd2num(block[2]) as u32,
+        )
+        .and_then(|date| {
+            date.and_hms_opt(hour, bcd2num(block[5]) as u32, bcd2num(block[6]) as u32)
+        });
+        let mut latch = [0; 8];
+        latch.copy_from_slice(&block[8..]);
+        let latch = Local
+            .timestamp_opt(u64::from_le_bytes(latch) as i64, 0)
+            .single();
+
+        let offset = match (time, latch) {
+            (Some(time), Some(latch)) => Some(to_seconds(time) - to_seconds(latch.naive_local())),
+            _ => None,
+        };
+        match offset {
+            Some(offset) if offset.abs() <= MAX_OFFSET => {
+                if offset != 0 {
+                    info!("RTC: the save file shifts the clock by {} seconds", offset);
+                    self.clock = RtcClock::Offset(offset);
+                }
+            }
+            _ => warn!("RTC: ignoring the invalid time in the save file"),
         }
     }
 
@@ -234,9 +462,9 @@ impl Rtc {
         match r {
             RegisterKind::Status => self.internal_buffer[0] = self.status.read(),
             RegisterKind::DateTime => {
-                let local: DateTime<Local> = Local::now();
-                let year = local.year();
-                assert!((2000..=2099).contains(&year)); // Wonder if I will live to see this one fail
+                let local = self.clock.now(self.cycles);
+                // Only the last two digits are kept, like the year 2100 would show as 2000
+                let year = local.year().rem_euclid(100);
 
                 let hour = if self.status.mode_24h() {
                     local.hour()
@@ -245,7 +473,7 @@ impl Rtc {
                     hour12 - 1
                 };
 
-                self.internal_buffer[0] = num2bcd((year % 100) as u8);
+                self.internal_buffer[0] = num2bcd(year as u8);
                 self.internal_buffer[1] = num2bcd(local.month() as u8);
                 self.internal_buffer[2] = num2bcd(local.day() as u8);
                 self.internal_buffer[3] = num2bcd(local.weekday().number_from_monday() as u8);
@@ -254,7 +482,7 @@ impl Rtc {
                 self.internal_buffer[6] = num2bcd(local.second() as u8);
             }
             RegisterKind::Time => {
-                let local: DateTime<Local> = Local::now();
+                let local = self.clock.now(self.cycles);
                 let hour = if self.status.mode_24h() {
                     local.hour()
                 } else {
@@ -272,8 +500,14 @@ impl Rtc {
     fn store_register(&mut self, r: RegisterKind) {
         use RegisterKind::*;
         match r {
-            Status => self.status.write(self.internal_buffer[0]),
-            ForceReset => self.force_reset(),
+            Status => {
+                self.status.write(self.internal_buffer[0]);
+                self.changed = true;
+            }
+            ForceReset => {
+                self.force_reset();
+                self.changed = true;
+            }
             _ => warn!("RTC: write {:?} not implemented", r),
         }
     }
@@ -644,4 +878,93 @@ mod tests {
         assert_eq!(bytes[1], num2bcd(local.month() as u8));
         assert_eq!(bytes[2], num2bcd(local.day() as u8));
     }
+
+    fn read_date_time(rtc: &mut Rtc) -> [u8; 7] {
+        let mut gpio_state = [GpioDirection::Out; 4];
+        start_serial_transfer(rtc, &gpio_state);
+        transmit_bits(rtc, &gpio_state, &[0, 1, 1, 0, 0, 1, 0, 1]);
+        gpio_state[Port::Sio.index()] = GpioDirection::In;
+        let mut bytes = [0; 7];
+        receive_bytes(rtc, &gpio_state, &mut bytes);
+        bytes
+    }
+
+    #[test]
+    fn test_clock() {
+        let mut rtc = Rtc::new();
+        rtc.status.set_mode_24h(true);
+        let clock: RtcClock = "frozen:2004-09-01T23:59:58".parse().unwrap();
+        assert_eq!(clock.to_string(), "frozen:2004-09-01T23:59:58");
+        rtc.set_clock(clock);
+        rtc.set_cycles(10 * CYCLES_PER_SECOND);
+        // 2004-09-01 was a wednesday
+        assert_eq!(
+            read_date_time(&mut rtc),
+            [0x04, 0x09, 0x01, 0x03, 0x23, 0x59, 0x58]
+        );
+
+        rtc.set_clock("emulated:2004-09-01T23:59:58".parse().unwrap());
+        rtc.set_cycles(CYCLES_PER_SECOND);
+        assert_eq!(
+            read_date_time(&mut rtc),
+            [0x04, 0x09, 0x01, 0x03, 0x23, 0x59, 0x59]
+        );
+        rtc.set_cycles(2 * CYCLES_PER_SECOND);
+        assert_eq!(
+            read_date_time(&mut rtc),
+            [0x04, 0x09, 0x02, 0x04, 0x00, 0x00, 0x00]
+        );
+
+        // the year wraps around past 2099
+        rtc.set_clock(RtcClock::Emulated(to_seconds(
+            "2099-12-31T23:59:58".parse().unwrap(),
+        )));
+        assert_eq!(
+            read_date_time(&mut rtc),
+            [0x00, 0x01, 0x01, 0x05, 0x00, 0x00, 0x00]
+        );
+
+        assert_eq!("offset:-3600".parse(), Ok(RtcClock::Offset(-3600)));
+        assert!("offset:9223372036854775807".parse::<RtcClock>().is_err());
+        assert_eq!(RtcClock::Frozen(0).to_deterministic(1), RtcClock::Frozen(0));
+        assert!(RtcClock::RealTime.to_deterministic(0).is_deterministic());
+        assert!("frozen:1999-12-31T23:59:59".parse::<RtcClock>().is_err());
+        assert!("yesterday".parse::<RtcClock>().is_err());
+    }
+
+    #[test]
+    fn test_block() {
+        let mut rtc = Rtc::new();
+        rtc.set_clock(RtcClock::Offset(-3600));
+        rtc.status.set_mode_24h(true);
+        let block = rtc.to_block();
+
+        let mut loaded = Rtc::new();
+        loaded.load_block(&block);
+        assert_eq!(loaded.status.read(), 0x42);
+        assert_eq!(loaded.clock(), RtcClock::Offset(-3600));
+
+        // a chosen clock is kept, even the host time
+        for clock in [RtcClock::Frozen(0), RtcClock::RealTime].iter() {
+            let mut chosen = Rtc::new();
+            chosen.set_clock(*clock);
+            chosen.load_block(&block);
+            assert_eq!(chosen.status.read(), 0x42);
+            assert_eq!(chosen.clock(), *clock);
+        }
+
+        // 2004-09-01 1 PM in 12 hour mode, latched at a unix time of 1000000000
+        let mut block = [
+            0x04, 0x09, 0x01, 0x03, 0x41, 0x00, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        block[8..].copy_from_slice(&1_000_000_000u64.to_le_bytes());
+        let latch = Local.timestamp_opt(1_000_000_000, 0).unwrap().naive_local();
+        let mut loaded = Rtc::new();
+        loaded.load_block(&block);
+        let expected = "2004-09-01T13:00:00".parse::<NaiveDateTime>().unwrap();
+        assert_eq!(
+            loaded.clock(),
+            RtcClock::Offset(to_seconds(expected) - to_seconds(latch))
+        );
+    }
 }
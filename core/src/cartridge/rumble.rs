@@ -0,0 +1,64 @@
+use bit::BitIndex;
+use serde::{Deserialize, Serialize};
+
+use super::gpio::{GpioDevice, GpioDirection, GpioState};
+
+/// The motor is driven by the last pin of the gpio port
+const RUMBLE_PIN: usize = 3;
+
+/// Force feedback on the host, frontends implement it with whatever the platform has (game
+/// controller rumble, phone vibrator...).
+pub trait RumbleInterface {
+    /// Called when the rumble motor of the cartridge turns on or off
+    fn set_rumble(&mut self, on: bool);
+}
+
+pub type DynRumbleInterface = Box<dyn RumbleInterface>;
+
+/// The rumble motor of Drill Dozer and WarioWare: Twisted!
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Rumble {
+    on: bool,
+}
+
+impl Rumble {
+    pub fn new() -> Self {
+        Rumble { on: false }
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.on
+    }
+}
+
+impl GpioDevice for Rumble {
+    fn write(&mut self, gpio_state: &GpioState, data: u16) {
+        if gpio_state[RUMBLE_PIN] == GpioDirection::Out {
+            self.on = data.bit(RUMBLE_PIN);
+        }
+    }
+
+    fn read(&self, _gpio_state: &GpioState) -> u16 {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_motor() {
+        let mut gpio_state = [GpioDirection::Out; 4];
+        let mut rumble = Rumble::new();
+        rumble.write(&gpio_state, 0b1000);
+        assert!(rumble.is_on());
+        rumble.write(&gpio_state, 0b0111);
+        assert!(!rumble.is_on());
+
+        // The pin is an input, the motor isn't driven
+        gpio_state[RUMBLE_PIN] = GpioDirection::In;
+        rumble.write(&gpio_state, 0b1000);
+        assert!(!rumble.is_on());
+    }
+}
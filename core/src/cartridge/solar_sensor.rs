@@ -0,0 +1,124 @@
+use bit::BitIndex;
+use serde::{Deserialize, Serialize};
+
+use super::gpio::{GpioDevice, GpioState};
+
+/// Light levels for frontends to step through, from darkness to full sunlight.
+/// Boktai shows them as 0 to 10 bars on its sun gauge.
+pub const SOLAR_LIGHT_LEVELS: [u8; 11] = [22, 27, 33, 40, 49, 64, 84, 106, 131, 161, 205];
+
+#[derive(Clone, Copy, Debug)]
+enum Pin {
+    /// Clocks the counter on rising edges
+    Clock = 0,
+    /// Clears the counter and samples the light
+    Reset = 1,
+    /// Chip select, active low
+    ChipSelect = 2,
+    /// Raised once the counter reaches the sampled light
+    Flag = 3,
+}
+
+impl Pin {
+    #[inline]
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// The photodiode of the Boktai cartridges.
+///
+/// The game resets the counter, then clocks it until the flag goes up. The brighter the light,
+/// the fewer clocks that takes.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SolarSensor {
+    light: u8,
+    /// The count at which the flag goes up, sampled from the light on reset
+    threshold: u16,
+    counter: u16,
+    clock: bool,
+}
+
+impl SolarSensor {
+    pub fn new() -> Self {
+        let light = SOLAR_LIGHT_LEVELS[0];
+        SolarSensor {
+            light,
+            threshold: 0xff - light as u16,
+            counter: 0,
+            clock: false,
+        }
+    }
+
+    /// How bright the light shining on the sensor is, 0 is darkness.
+    /// The game sees the change on its next measurement.
+    pub fn set_light(&mut self, light: u8) {
+        self.light = light;
+    }
+}
+
+impl GpioDevice for SolarSensor {
+    fn write(&mut self, _gpio_state: &GpioState, data: u16) {
+        if data.bit(Pin::ChipSelect.index()) {
+            return;
+        }
+        if data.bit(Pin::Reset.index()) {
+            self.counter = 0;
+            self.threshold = 0xff - self.light as u16;
+        }
+        let clock = data.bit(Pin::Clock.index());
+        if clock && !self.clock {
+            self.counter = self.counter.saturating_add(1);
+        }
+        self.clock = clock;
+    }
+
+    fn read(&self, _gpio_state: &GpioState) -> u16 {
+        let mut result = 0;
+        result.set_bit(Pin::Flag.index(), self.counter >= self.threshold);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::gpio::GpioDirection;
+    use super::*;
+
+    const GPIO_STATE: GpioState = [
+        GpioDirection::Out,
+        GpioDirection::Out,
+        GpioDirection::Out,
+        GpioDirection::In,
+    ];
+
+    /// Count the clocks until the flag goes up, like the game does
+    fn measure(sensor: &mut SolarSensor) -> usize {
+        sensor.write(&GPIO_STATE, 0b0010);
+        sensor.write(&GPIO_STATE, 0b0000);
+        let mut clocks = 0;
+        while sensor.read(&GPIO_STATE) == 0 {
+            sensor.write(&GPIO_STATE, 0b0001);
+            sensor.write(&GPIO_STATE, 0b0000);
+            clocks += 1;
+        }
+        clocks
+    }
+
+    #[test]
+    fn test_measure() {
+        let mut sensor = SolarSensor::new();
+        let dark = measure(&mut sensor);
+        assert_eq!(dark, 0xff - SOLAR_LIGHT_LEVELS[0] as usize);
+
+        sensor.set_light(SOLAR_LIGHT_LEVELS[10]);
+        let bright = measure(&mut sensor);
+        assert!(bright < dark);
+
+        // Not selected, the clocks are ignored
+        sensor.write(&GPIO_STATE, 0b0010);
+        sensor.write(&GPIO_STATE, 0b0101);
+        sensor.write(&GPIO_STATE, 0b0100);
+        assert_eq!(sensor.counter, 0);
+    }
+}
@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+
+/// The samples when the cartridge lies flat
+const TILT_CENTER_X: i32 = 0x392;
+const TILT_CENTER_Y: i32 = 0x3a0;
+
+/// The accelerometer is mapped to these offsets of the backup memory region
+const TILT_START: u32 = 0x8000;
+const TILT_SAMPLE: u32 = 0x8100;
+const TILT_X_LOW: u32 = 0x8200;
+const TILT_X_HIGH: u32 = 0x8300;
+const TILT_Y_LOW: u32 = 0x8400;
+const TILT_Y_HIGH: u32 = 0x8500;
+/// Set in the high x byte once a sample is ready
+const TILT_READY: u8 = 0x80;
+
+pub fn is_tilt_access(addr: u32) -> bool {
+    matches!(
+        addr & 0xffff,
+        TILT_START | TILT_SAMPLE | TILT_X_LOW | TILT_X_HIGH | TILT_Y_LOW | TILT_Y_HIGH
+    )
+}
+
+/// The 2 axis accelerometer of Yoshi Topsy-Turvy and Koro Koro Puzzle.
+///
+/// The game writes 0x55 and then 0xaa to sample both axes, and reads back the 12 bit samples.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TiltSensor {
+    tilt: (i16, i16),
+    sample: (u16, u16),
+    armed: bool,
+}
+
+impl TiltSensor {
+    pub fn new() -> Self {
+        TiltSensor {
+            tilt: (0, 0),
+            sample: (TILT_CENTER_X as u16, TILT_CENTER_Y as u16),
+            armed: false,
+        }
+    }
+
+    /// How far the cartridge is tilted to the right (x) and towards the player (y).
+    /// The full range of an analog stick axis is about 1g.
+    pub fn set_tilt(&mut self, x: i16, y: i16) {
+        self.tilt = (x, y);
+    }
+
+    pub fn write(&mut self, addr: u32, value: u8) {
+        match (addr & 0xffff, value) {
+            (TILT_START, 0x55) => self.armed = true,
+            (TILT_SAMPLE, 0xaa) if self.armed => {
+                self.armed = false;
+                self.sample = (
+                    (TILT_CENTER_X + (self.tilt.0 as i32 >> 7)) as u16,
+                    (TILT_CENTER_Y + (self.tilt.1 as i32 >> 7)) as u16,
+                );
+            }
+            _ => warn!("tilt: unexpected write {:#x} to {:#x}", value, addr),
+        }
+    }
+
+    pub fn read(&self, addr: u32) -> u8 {
+        let (x, y) = self.sample;
+        match addr & 0xffff {
+            TILT_X_LOW => x as u8,
+            TILT_X_HIGH => (x >> 8) as u8 & 0xf | TILT_READY,
+            TILT_Y_LOW => y as u8,
+            TILT_Y_HIGH => (y >> 8) as u8 & 0xf,
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRAM: u32 = 0x0e00_0000;
+
+    fn sample(tilt: &mut TiltSensor) -> (u16, u16) {
+        tilt.write(SRAM + TILT_START, 0x55);
+        tilt.write(SRAM + TILT_SAMPLE, 0xaa);
+        let high_x = tilt.read(SRAM + TILT_X_HIGH);
+        assert_ne!(high_x & TILT_READY, 0);
+        (
+            (high_x as u16 & 0xf) << 8 | tilt.read(SRAM + TILT_X_LOW) as u16,
+            (tilt.read(SRAM + TILT_Y_HIGH) as u16) << 8 | tilt.read(SRAM + TILT_Y_LOW) as u16,
+        )
+    }
+
+    #[test]
+    fn test_sample() {
+        let mut tilt = TiltSensor::new();
+        assert_eq!(
+            sample(&mut tilt),
+            (TILT_CENTER_X as u16, TILT_CENTER_Y as u16)
+        );
+
+        tilt.set_tilt(i16::MAX, i16::MIN);
+        let (x, y) = sample(&mut tilt);
+        assert!(x > TILT_CENTER_X as u16 && y < TILT_CENTER_Y as u16);
+
+        // Without arming first the sample is kept
+        tilt.set_tilt(0, 0);
+        tilt.write(SRAM + TILT_SAMPLE, 0xaa);
+        assert_eq!(tilt.read(SRAM + TILT_X_LOW), x as u8);
+    }
+}
@@ -0,0 +1,195 @@
+//! Action Replay MAX and GameShark v3 codes (Pro Action Replay v3)
+//!
+//! Encrypted like the GameShark v1 codes with different seeds. After decrypting, `aaaaaa` in the
+//! first word is the address `0a0aaaaa`, its top digit being the memory region.
+//!
+//! | Code                                  | Action                                          |
+//! |---------------------------------------|-------------------------------------------------|
+//! | `00aaaaaa nnnnnnyy`                   | `[a + i] = yy` for `i` in `0..=nnnnnn`          |
+//! | `02aaaaaa nnnnyyyy`                   | `[a + i * 2] = yyyy` for `i` in `0..=nnnn`      |
+//! | `04aaaaaa yyyyyyyy`                   | `[a] = yyyyyyyy`                                |
+//! | `40aaaaaa ooooooyy`                   | `[[a] + oooooo] = yy`                           |
+//! | `42aaaaaa ooooyyyy`                   | `[[a] + oooo * 2] = yyyy`                       |
+//! | `44aaaaaa yyyyyyyy`                   | `[[a]] = yyyyyyyy`                              |
+//! | `80aaaaaa 000000yy`, `82`, `84`       | `[a] += y`                                      |
+//! | `C4aaaaaa 0000000x`                   | Master code, hook at `0x8aaaaaa`                |
+//! | `C6aaaaaa 0000yyyy`, `C7aaaaaa yyyyyyyy` | IO register write, `[0x4aaaaaa] = y`         |
+//! | `00000000 18aaaaaa` `0000yyyy 00000000` | ROM patch, `[0x8000000 + aaaaaa * 2] = yyyy`  |
+//! | `00000000 80aaaaaa` `000000yy vvccssss` | `cc + 1` writes of `yy` (also `82`, `84`), stepping the value by `vv` and the address by `ssss` units |
+//! | `00000000 40000000`                   | End if                                          |
+//! | `00000000 60000000`                   | Else                                            |
+//!
+//! Conditions test `[a]` against `y`, bits 27-29 are the test, bits 25-26 the width and bits 30-31
+//! what to skip when it is false: the next code, the next two codes, the block up to the else or
+//! end if, or all the following codes.
+use super::gameshark::decrypt;
+use super::{CheatError, CheatResult, Cond, Line, Op, Program, Skip, Test, Width};
+
+pub(super) const SEEDS: [u32; 4] = [0x7aa9_648f, 0x7fae_6994, 0xc0ef_aad5, 0x4271_2c57];
+
+fn address(op: u32) -> u32 {
+    (op & 0x000f_ffff) | ((op << 4) & 0x0f00_0000)
+}
+
+/// `None` for the width that makes a condition always false
+fn width(op: u32) -> Option<Width> {
+    match (op >> 25) & 3 {
+        0 => Some(Width::Byte),
+        1 => Some(Width::Half),
+        2 => Some(Width::Word),
+        _ => None,
+    }
+}
+
+fn unsupported(line: &Line, reason: &'static str) -> CheatError {
+    CheatError::Unsupported {
+        code: line.to_string(),
+        reason,
+    }
+}
+
+pub(super) fn decode(lines: &[Line], encrypted: bool) -> CheatResult<Program> {
+    let mut program = Program::default();
+    let mut lines = lines.iter().map(|line| {
+        let (op1, op2) = if encrypted {
+            decrypt(line.op1, line.op2, &SEEDS)
+        } else {
+            (line.op1, line.op2)
+        };
+        (line, op1, op2)
+    });
+    while let Some((line, op1, op2)) = lines.next() {
+        if op1 == 0 {
+            let op = match op2 >> 24 {
+                // Terminates the list on the device, some lists are padded with it
+                0x00 => continue,
+                0x18 | 0x1a | 0x1c | 0x1e => {
+                    let (_, value, _) = lines
+                        .next()
+                        .ok_or_else(|| CheatError::Incomplete(line.to_string()))?;
+                    let addr = 0x0800_0000 + ((op2 & 0x00ff_ffff) << 1);
+                    program.patches.push((addr, value as u16));
+                    continue;
+                }
+                0x40 => Op::EndIf,
+                0x60 => Op::Else,
+                0x80 | 0x82 | 0x84 => {
+                    let (_, value, steps) = lines
+                        .next()
+                        .ok_or_else(|| CheatError::Incomplete(line.to_string()))?;
+                    let width = width(op2).unwrap();
+                    Op::Fill {
+                        addr: address(op2),
+                        width,
+                        value: value & width.mask(),
+                        count: ((steps >> 16) & 0xff) + 1,
+                        addr_step: (steps & 0xffff) * width.bytes(),
+                        value_step: steps >> 24,
+                    }
+                }
+                0x08 => return Err(unsupported(line, "slowdown")),
+                0x10 | 0x12 | 0x14 => return Err(unsupported(line, "the device button")),
+                _ => return Err(unsupported(line, "unknown Action Replay code type")),
+            };
+            program.ops.push(op);
+            continue;
+        }
+
+        let addr = address(op1);
+        if op1 & 0x3800_0000 != 0 {
+            let test = match op1 & 0x3800_0000 {
+                0x0800_0000 => Test::Eq,
+                0x1000_0000 => Test::Ne,
+                0x1800_0000 => Test::Lt,
+                0x2000_0000 => Test::Gt,
+                0x2800_0000 => Test::Ult,
+                0x3000_0000 => Test::Ugt,
+                _ => Test::And,
+            };
+            let cond = match width(op1) {
+                Some(width) => Cond::Compare {
+                    addr,
+                    width,
+                    test,
+                    value: op2 & width.mask(),
+                },
+                None => Cond::Never,
+            };
+            let skip = match op1 >> 30 {
+                0 => Skip::Next(1),
+                1 => Skip::Next(2),
+                2 => Skip::Block,
+                _ => Skip::Rest,
+            };
+            program.ops.push(Op::If { cond, skip });
+            continue;
+        }
+
+        let op = match op1 >> 24 {
+            0x00 => Op::Fill {
+                addr,
+                width: Width::Byte,
+                value: op2 & 0xff,
+                count: (op2 >> 8) + 1,
+                addr_step: 1,
+                value_step: 0,
+            },
+            0x02 => Op::Fill {
+                addr,
+                width: Width::Half,
+                value: op2 & 0xffff,
+                count: (op2 >> 16) + 1,
+                addr_step: 2,
+                value_step: 0,
+            },
+            0x04 => Op::Write {
+                addr,
+                width: Width::Word,
+                value: op2,
+            },
+            0x40 => Op::WriteIndirect {
+                pointer: addr,
+                offset: op2 >> 8,
+                width: Width::Byte,
+                value: op2 & 0xff,
+            },
+            0x42 => Op::WriteIndirect {
+                pointer: addr,
+                offset: (op2 >> 16) * 2,
+                width: Width::Half,
+                value: op2 & 0xffff,
+            },
+            0x44 => Op::WriteIndirect {
+                pointer: addr,
+                offset: 0,
+                width: Width::Word,
+                value: op2,
+            },
+            0x80 | 0x82 | 0x84 => {
+                let width = width(op1).unwrap();
+                Op::Add {
+                    addr,
+                    width,
+                    value: op2 & width.mask(),
+                }
+            }
+            0xc4 => {
+                program.hook = Some(0x0800_0000 | (op1 & 0x00ff_ffff));
+                continue;
+            }
+            0xc6 => Op::Write {
+                addr: 0x0400_0000 | (op1 & 0x00ff_ffff),
+                width: Width::Half,
+                value: op2 & 0xffff,
+            },
+            0xc7 => Op::Write {
+                addr: 0x0400_0000 | (op1 & 0x00ff_ffff),
+                width: Width::Word,
+                value: op2,
+            },
+            _ => return Err(unsupported(line, "unknown Action Replay code type")),
+        };
+        program.ops.push(op);
+    }
+    Ok(program)
+}
@@ -0,0 +1,60 @@
+//! RetroArch style `.cht` files
+//!
+//! ```text
+//! cheats = 1
+//!
+//! cheat0_desc = "Infinite health"
+//! cheat0_code = "XXXXXXXX+YYYYYYYY"
+//! cheat0_enable = true
+//! ```
+//!
+//! The files don't name the code format, it is detected from the codes.
+use std::collections::HashMap;
+
+use super::{Cheat, CheatError, CheatResult};
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// Parse the cheats of a `.cht` file, entries without a code are skipped
+pub fn parse_cht(text: &str) -> CheatResult<Vec<Cheat>> {
+    let mut entries = HashMap::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| CheatError::BadChtFile(format!("expected key = value: {}", line)))?;
+        entries.insert(key.trim(), unquote(value.trim()));
+    }
+
+    let count: usize = entries
+        .get("cheats")
+        .ok_or_else(|| CheatError::BadChtFile("missing the cheats count".to_string()))?
+        .parse()
+        .map_err(|_| CheatError::BadChtFile("bad cheats count".to_string()))?;
+    let mut cheats = Vec::with_capacity(count);
+    for i in 0..count {
+        let codes = match entries.get(format!("cheat{}_code", i).as_str()) {
+            Some(codes) if !codes.is_empty() => *codes,
+            _ => {
+                warn!("cht: cheat{} has no code, skipping", i);
+                continue;
+            }
+        };
+        let default_name = format!("Cheat {}", i);
+        let name = entries
+            .get(format!("cheat{}_desc", i).as_str())
+            .copied()
+            .unwrap_or(&default_name);
+        let mut cheat = Cheat::autodetect(name, codes)?;
+        cheat.enabled = entries.get(format!("cheat{}_enable", i).as_str()) == Some(&"true");
+        cheats.push(cheat);
+    }
+    Ok(cheats)
+}
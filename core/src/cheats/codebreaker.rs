@@ -0,0 +1,124 @@
+//! CodeBreaker codes, `taaaaaaa yyyy` where `t` is the code type
+//!
+//! | Code                          | Action                                                      |
+//! |-------------------------------|-------------------------------------------------------------|
+//! | `0000xxxx yyyy`               | Game id, part of the master code                            |
+//! | `1aaaaaaa 000x`               | Master code, hook at `a`                                    |
+//! | `2aaaaaaa yyyy`               | `[a] = [a] OR yyyy`                                         |
+//! | `3aaaaaaa 00yy`               | `[a] = yy`                                                  |
+//! | `4aaaaaaa yyyy` `ccccvvvv ssss` | `cccc` writes of `yyyy`, adding `vvvv` to the value and `ssss` halfwords to the address after each |
+//! | `5aaaaaaa nnnn`               | Write the `nnnn` bytes written on the next lines            |
+//! | `6aaaaaaa yyyy`               | `[a] &= yyyy`                                               |
+//! | `7aaaaaaa yyyy`               | If `[a] == yyyy` run the next code                          |
+//! | `8aaaaaaa yyyy`               | `[a] = yyyy`                                                |
+//! | `9xxxxxxx xxxx`               | Encrypts the codes that follow                              |
+//! | `Aaaaaaaa yyyy`               | If `[a] != yyyy` run the next code                          |
+//! | `Baaaaaaa yyyy`               | If `[a] > yyyy` run the next code                           |
+//! | `Caaaaaaa yyyy`               | If `[a] < yyyy` run the next code                           |
+//! | `D0000020 yyyy`               | If the keys `yyyy` are held run the next code               |
+//! | `Eaaaaaaa yyyy`               | `[a] += yyyy`                                               |
+//! | `Faaaaaaa yyyy`               | If `[a] & yyyy` run the next code                           |
+use super::{CheatError, CheatResult, Cond, Line, Op, Program, Skip, Test, Width};
+
+fn compare(addr: u32, test: Test, value: u32) -> Op {
+    Op::If {
+        cond: Cond::Compare {
+            addr,
+            width: Width::Half,
+            test,
+            value,
+        },
+        skip: Skip::Next(1),
+    }
+}
+
+pub(super) fn decode(lines: &[Line]) -> CheatResult<Program> {
+    let mut program = Program::default();
+    let mut lines = lines.iter();
+    while let Some(line) = lines.next() {
+        let addr = line.op1 & 0x0fff_ffff;
+        let value = line.op2 & 0xffff;
+        let op = match line.op1 >> 28 {
+            // The device checks the game id, a wrong one is as good as no cheats
+            0x0 => continue,
+            0x1 => {
+                program.hook = Some(addr);
+                continue;
+            }
+            0x2 => Op::Or {
+                addr,
+                width: Width::Half,
+                value,
+            },
+            0x3 => Op::Write {
+                addr,
+                width: Width::Byte,
+                value: value & 0xff,
+            },
+            0x4 => {
+                let steps = lines
+                    .next()
+                    .ok_or_else(|| CheatError::Incomplete(line.to_string()))?;
+                Op::Fill {
+                    addr,
+                    width: Width::Half,
+                    value,
+                    count: steps.op1 >> 16,
+                    addr_step: steps.op2 * 2,
+                    value_step: steps.op1 & 0xffff,
+                }
+            }
+            0x5 => {
+                let count = value as usize;
+                let mut bytes = Vec::with_capacity(count);
+                while bytes.len() < count {
+                    let data = lines
+                        .next()
+                        .ok_or_else(|| CheatError::Incomplete(line.to_string()))?;
+                    bytes.extend_from_slice(&data.op1.to_be_bytes());
+                    bytes.extend_from_slice(&(data.op2 as u16).to_be_bytes());
+                }
+                bytes.truncate(count);
+                Op::Bytes { addr, bytes }
+            }
+            0x6 => Op::And {
+                addr,
+                width: Width::Half,
+                value,
+            },
+            0x7 => compare(addr, Test::Eq, value),
+            0x8 => Op::Write {
+                addr,
+                width: Width::Half,
+                value,
+            },
+            0x9 => {
+                return Err(CheatError::Unsupported {
+                    code: line.to_string(),
+                    reason: "encrypted CodeBreaker codes",
+                })
+            }
+            0xa => compare(addr, Test::Ne, value),
+            0xb => compare(addr, Test::Ugt, value),
+            0xc => compare(addr, Test::Ult, value),
+            0xd if line.op1 == 0xd000_0020 => Op::If {
+                cond: Cond::KeysHeld(value as u16),
+                skip: Skip::Next(1),
+            },
+            0xe => Op::Add {
+                addr,
+                width: Width::Half,
+                value,
+            },
+            0xf => compare(addr, Test::And, value),
+            _ => {
+                return Err(CheatError::Unsupported {
+                    code: line.to_string(),
+                    reason: "unknown CodeBreaker code type",
+                })
+            }
+        };
+        program.ops.push(op);
+    }
+    Ok(program)
+}
@@ -0,0 +1,155 @@
+//! GameShark (Action Replay) v1 and v2 codes
+//!
+//! The codes are encrypted with TEA, after decrypting the top nibble of the first word is the
+//! code type and the rest the address:
+//!
+//! | Code                | Action                                                     |
+//! |---------------------|------------------------------------------------------------|
+//! | `0aaaaaaa 000000yy` | `[a] = yy`                                                 |
+//! | `1aaaaaaa 0000yyyy` | `[a] = yyyy`                                               |
+//! | `2aaaaaaa yyyyyyyy` | `[a] = yyyyyyyy`                                           |
+//! | `3000cccc yyyyyyyy` | `[a] = yyyyyyyy` for the `cccc` addresses on the next lines |
+//! | `6aaaaaaa 0000yyyy` | ROM patch, `[0x8000000 + aaaaaa * 2] = yyyy`               |
+//! | `Daaaaaaa 0000yyyy` | If `[a] == yyyy` run the next code                         |
+//! | `E0ccyyyy 0aaaaaaa` | If `[a] == yyyy` run the next `cc` codes                   |
+//! | `Faaaaaaa 0000000x` | Master code, hook at `a`                                   |
+use super::{CheatError, CheatResult, Cond, Line, Op, Program, Skip, Test, Width};
+
+pub(super) const SEEDS: [u32; 4] = [0x09f4_fbbd, 0x9681_884a, 0x3520_27e9, 0xf3de_e5a7];
+
+const DELTA: u32 = 0x9e37_79b9;
+const ROUNDS: u32 = 32;
+
+/// Changes the seeds, which needs the tables of the device
+const RESEED: u32 = 0xdead_face;
+
+/// TEA decryption, shared with the Action Replay MAX which only uses other seeds
+pub(super) fn decrypt(mut op1: u32, mut op2: u32, seeds: &[u32; 4]) -> (u32, u32) {
+    let mut sum = DELTA.wrapping_mul(ROUNDS);
+    for _ in 0..ROUNDS {
+        op2 = op2.wrapping_sub(
+            (op1 << 4).wrapping_add(seeds[2])
+                ^ op1.wrapping_add(sum)
+                ^ (op1 >> 5).wrapping_add(seeds[3]),
+        );
+        op1 = op1.wrapping_sub(
+            (op2 << 4).wrapping_add(seeds[0])
+                ^ op2.wrapping_add(sum)
+                ^ (op2 >> 5).wrapping_add(seeds[1]),
+        );
+        sum = sum.wrapping_sub(DELTA);
+    }
+    (op1, op2)
+}
+
+#[cfg(test)]
+pub(super) fn encrypt(mut op1: u32, mut op2: u32, seeds: &[u32; 4]) -> (u32, u32) {
+    let mut sum = 0u32;
+    for _ in 0..ROUNDS {
+        sum = sum.wrapping_add(DELTA);
+        op1 = op1.wrapping_add(
+            (op2 << 4).wrapping_add(seeds[0])
+                ^ op2.wrapping_add(sum)
+                ^ (op2 >> 5).wrapping_add(seeds[1]),
+        );
+        op2 = op2.wrapping_add(
+            (op1 << 4).wrapping_add(seeds[2])
+                ^ op1.wrapping_add(sum)
+                ^ (op1 >> 5).wrapping_add(seeds[3]),
+        );
+    }
+    (op1, op2)
+}
+
+pub(super) fn decode(lines: &[Line], encrypted: bool) -> CheatResult<Program> {
+    let mut program = Program::default();
+    let mut lines = lines.iter().map(|line| {
+        let (op1, op2) = if encrypted {
+            decrypt(line.op1, line.op2, &SEEDS)
+        } else {
+            (line.op1, line.op2)
+        };
+        (line, op1, op2)
+    });
+    while let Some((line, op1, op2)) = lines.next() {
+        let addr = op1 & 0x0fff_ffff;
+        let op = match op1 >> 28 {
+            _ if op1 == RESEED => {
+                return Err(CheatError::Unsupported {
+                    code: line.to_string(),
+                    reason: "changing the encryption seeds",
+                })
+            }
+            0x0 => Op::Write {
+                addr,
+                width: Width::Byte,
+                value: op2 & 0xff,
+            },
+            0x1 => Op::Write {
+                addr,
+                width: Width::Half,
+                value: op2 & 0xffff,
+            },
+            0x2 => Op::Write {
+                addr,
+                width: Width::Word,
+                value: op2,
+            },
+            0x3 => {
+                let count = (op1 & 0xffff) as usize;
+                let mut addresses = Vec::with_capacity(count);
+                while addresses.len() < count {
+                    let (_, addr1, addr2) = lines
+                        .next()
+                        .ok_or_else(|| CheatError::Incomplete(line.to_string()))?;
+                    addresses.push(addr1);
+                    addresses.push(addr2);
+                }
+                addresses.truncate(count);
+                for addr in addresses {
+                    program.ops.push(Op::Write {
+                        addr,
+                        width: Width::Word,
+                        value: op2,
+                    });
+                }
+                continue;
+            }
+            0x6 => {
+                let addr = 0x0800_0000 + ((op1 & 0x00ff_ffff) << 1);
+                program.patches.push((addr, op2 as u16));
+                continue;
+            }
+            0xd => Op::If {
+                cond: Cond::Compare {
+                    addr,
+                    width: Width::Half,
+                    test: Test::Eq,
+                    value: op2 & 0xffff,
+                },
+                skip: Skip::Next(1),
+            },
+            0xe => Op::If {
+                cond: Cond::Compare {
+                    addr: op2 & 0x0fff_ffff,
+                    width: Width::Half,
+                    test: Test::Eq,
+                    value: op1 & 0xffff,
+                },
+                skip: Skip::Next(((op1 >> 16) & 0xff) as usize),
+            },
+            0xf => {
+                program.hook = Some(addr);
+                continue;
+            }
+            _ => {
+                return Err(CheatError::Unsupported {
+                    code: line.to_string(),
+                    reason: "unknown GameShark code type",
+                })
+            }
+        };
+        program.ops.push(op);
+    }
+    Ok(program)
+}
@@ -0,0 +1,869 @@
+//! Cheat codes for the GameShark, Action Replay and CodeBreaker devices.
+//!
+//! Every format is decoded into the same small program of memory writes and conditionals, which
+//! runs once per frame. A cheat that comes with a master code naming a hook address runs whenever
+//! the cpu reaches that address instead, like it would on the real device.
+//! ROM patches are applied when the cheat is enabled and undone when it is disabled or removed.
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use arm7tdmi::memory::{Addr, BusIO, DebugRead, DebugWrite};
+
+use super::sysbus::SysBus;
+
+mod action_replay;
+mod cht;
+mod codebreaker;
+mod gameshark;
+
+pub use cht::parse_cht;
+
+#[derive(Debug)]
+pub enum CheatError {
+    IO(io::Error),
+    /// Not a `XXXXXXXX YYYYYYYY` or `XXXXXXXX YYYY` code
+    BadCode(String),
+    /// The code type is unknown, or needs something of the device that isn't emulated
+    Unsupported {
+        code: String,
+        reason: &'static str,
+    },
+    /// The code continues on lines that are missing
+    Incomplete(String),
+    /// None of the formats decode the codes into something sensible
+    UnknownFormat,
+    BadChtFile(String),
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheatError::IO(e) => write!(f, "cheat io error: {}", e),
+            CheatError::BadCode(code) => write!(f, "bad cheat code {:?}", code),
+            CheatError::Unsupported { code, reason } => {
+                write!(f, "unsupported cheat code {} ({})", code, reason)
+            }
+            CheatError::Incomplete(code) => write!(f, "cheat code {} is missing lines", code),
+            CheatError::UnknownFormat => write!(f, "could not detect the cheat code format"),
+            CheatError::BadChtFile(e) => write!(f, "bad cht file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CheatError {}
+
+impl From<io::Error> for CheatError {
+    fn from(err: io::Error) -> CheatError {
+        CheatError::IO(err)
+    }
+}
+
+pub type CheatResult<T> = Result<T, CheatError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheatFormat {
+    /// GameShark (Action Replay in Europe) v1 and v2, encrypted
+    GameSharkV1,
+    /// Already decrypted GameShark v1/v2 codes
+    GameSharkV1Raw,
+    /// Action Replay MAX, which is the same as GameShark v3, encrypted
+    ActionReplayMax,
+    /// Already decrypted Action Replay MAX / GameShark v3 codes
+    ActionReplayMaxRaw,
+    /// CodeBreaker, the `XXXXXXXX YYYY` codes
+    CodeBreaker,
+}
+
+impl CheatFormat {
+    /// The order autodetection prefers when several formats fit
+    const DETECT: [CheatFormat; 4] = [
+        CheatFormat::GameSharkV1,
+        CheatFormat::ActionReplayMax,
+        CheatFormat::GameSharkV1Raw,
+        CheatFormat::ActionReplayMaxRaw,
+    ];
+
+    fn decode(self, lines: &[Line]) -> CheatResult<Program> {
+        let codebreaker = self == CheatFormat::CodeBreaker;
+        if let Some(line) = lines.iter().find(|line| line.short != codebreaker) {
+            return Err(CheatError::BadCode(line.to_string()));
+        }
+        match self {
+            CheatFormat::GameSharkV1 => gameshark::decode(lines, true),
+            CheatFormat::GameSharkV1Raw => gameshark::decode(lines, false),
+            CheatFormat::ActionReplayMax => action_replay::decode(lines, true),
+            CheatFormat::ActionReplayMaxRaw => action_replay::decode(lines, false),
+            CheatFormat::CodeBreaker => codebreaker::decode(lines),
+        }
+    }
+}
+
+impl fmt::Display for CheatFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CheatFormat::GameSharkV1 => "gsv1",
+            CheatFormat::GameSharkV1Raw => "gsv1-raw",
+            CheatFormat::ActionReplayMax => "armax",
+            CheatFormat::ActionReplayMaxRaw => "armax-raw",
+            CheatFormat::CodeBreaker => "codebreaker",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for CheatFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "gsv1" | "gsv2" | "gameshark" => Ok(CheatFormat::GameSharkV1),
+            "gsv1-raw" | "gsv2-raw" => Ok(CheatFormat::GameSharkV1Raw),
+            "armax" | "gsv3" | "par3" => Ok(CheatFormat::ActionReplayMax),
+            "armax-raw" | "gsv3-raw" | "par3-raw" => Ok(CheatFormat::ActionReplayMaxRaw),
+            "codebreaker" | "cb" => Ok(CheatFormat::CodeBreaker),
+            _ => Err(format!("unknown cheat format {:?}", s)),
+        }
+    }
+}
+
+/// One line of a code, `short` if the second half is only 4 digits long
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Line {
+    op1: u32,
+    op2: u32,
+    short: bool,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.short {
+            write!(f, "{:08X} {:04X}", self.op1, self.op2)
+        } else {
+            write!(f, "{:08X} {:08X}", self.op1, self.op2)
+        }
+    }
+}
+
+fn parse_hex(digits: &str) -> Option<u32> {
+    if digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        u32::from_str_radix(digits, 16).ok()
+    } else {
+        None
+    }
+}
+
+/// Split codes into lines, they may be separated by whitespace, `+` or `,`
+/// and the two halves of a line may be written together.
+fn parse_lines(codes: &str) -> CheatResult<Vec<Line>> {
+    let tokens: Vec<&str> = codes
+        .split(|c: char| c.is_whitespace() || c == '+' || c == ',')
+        .filter(|token| !token.is_empty())
+        .collect();
+    let mut lines = Vec::new();
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        let (op1, op2) = match token.len() {
+            12 | 16 => token.split_at(8),
+            8 => (token, tokens.next().unwrap_or("")),
+            _ => return Err(CheatError::BadCode(token.to_string())),
+        };
+        let bad_code = || CheatError::BadCode(format!("{} {}", op1, op2));
+        if op2.len() != 4 && op2.len() != 8 {
+            return Err(bad_code());
+        }
+        lines.push(Line {
+            op1: parse_hex(op1).ok_or_else(bad_code)?,
+            op2: parse_hex(op2).ok_or_else(bad_code)?,
+            short: op2.len() == 4,
+        });
+    }
+    if lines.is_empty() {
+        return Err(CheatError::BadCode(codes.to_string()));
+    }
+    Ok(lines)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Width {
+    Byte,
+    Half,
+    Word,
+}
+
+impl Width {
+    fn bytes(self) -> u32 {
+        match self {
+            Width::Byte => 1,
+            Width::Half => 2,
+            Width::Word => 4,
+        }
+    }
+
+    fn mask(self) -> u32 {
+        match self {
+            Width::Byte => 0xff,
+            Width::Half => 0xffff,
+            Width::Word => 0xffff_ffff,
+        }
+    }
+
+    fn sign_extend(self, value: u32) -> i32 {
+        let shift = 32 - 8 * self.bytes();
+        ((value << shift) as i32) >> shift
+    }
+
+    fn read(self, bus: &mut SysBus, addr: Addr) -> u32 {
+        match self {
+            Width::Byte => bus.read_8(addr) as u32,
+            Width::Half => bus.read_16(addr) as u32,
+            Width::Word => bus.read_32(addr),
+        }
+    }
+
+    fn write(self, bus: &mut SysBus, addr: Addr, value: u32) {
+        match self {
+            Width::Byte => bus.write_8(addr, value as u8),
+            Width::Half => bus.write_16(addr, value as u16),
+            Width::Word => bus.write_32(addr, value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Test {
+    Eq,
+    Ne,
+    /// Signed
+    Lt,
+    /// Signed
+    Gt,
+    Ult,
+    Ugt,
+    /// Any of the bits are set
+    And,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cond {
+    Compare {
+        addr: Addr,
+        width: Width,
+        test: Test,
+        value: u32,
+    },
+    /// All of the keys in the mask are held down
+    KeysHeld(u16),
+    Never,
+}
+
+impl Cond {
+    fn eval(self, bus: &mut SysBus) -> bool {
+        match self {
+            Cond::Compare {
+                addr,
+                width,
+                test,
+                value,
+            } => {
+                let current = width.read(bus, addr);
+                match test {
+                    Test::Eq => current == value,
+                    Test::Ne => current != value,
+                    Test::Lt => width.sign_extend(current) < width.sign_extend(value),
+                    Test::Gt => width.sign_extend(current) > width.sign_extend(value),
+                    Test::Ult => current < value,
+                    Test::Ugt => current > value,
+                    Test::And => current & value != 0,
+                }
+            }
+            // KEYINPUT is active low
+            Cond::KeysHeld(mask) => !bus.io.keyinput & mask == mask,
+            Cond::Never => false,
+        }
+    }
+}
+
+/// What to skip when a condition is false
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Skip {
+    Next(usize),
+    /// Up to the matching `Else` or `EndIf`
+    Block,
+    /// Everything that follows
+    Rest,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Op {
+    Write {
+        addr: Addr,
+        width: Width,
+        value: u32,
+    },
+    /// `count` writes, the address and the value advance by a step after each
+    Fill {
+        addr: Addr,
+        width: Width,
+        value: u32,
+        count: u32,
+        addr_step: u32,
+        value_step: u32,
+    },
+    /// Write to the address held by `pointer`, plus `offset`
+    WriteIndirect {
+        pointer: Addr,
+        offset: u32,
+        width: Width,
+        value: u32,
+    },
+    Add {
+        addr: Addr,
+        width: Width,
+        value: u32,
+    },
+    Or {
+        addr: Addr,
+        width: Width,
+        value: u32,
+    },
+    And {
+        addr: Addr,
+        width: Width,
+        value: u32,
+    },
+    Bytes {
+        addr: Addr,
+        bytes: Vec<u8>,
+    },
+    If {
+        cond: Cond,
+        skip: Skip,
+    },
+    Else,
+    EndIf,
+}
+
+impl Op {
+    /// The address the op writes to or tests, if it is known before running
+    fn target(&self) -> Option<Addr> {
+        match *self {
+            Op::Write { addr, .. }
+            | Op::Fill { addr, .. }
+            | Op::Add { addr, .. }
+            | Op::Or { addr, .. }
+            | Op::And { addr, .. }
+            | Op::Bytes { addr, .. } => Some(addr),
+            Op::WriteIndirect { pointer, .. } => Some(pointer),
+            Op::If {
+                cond: Cond::Compare { addr, .. },
+                ..
+            } => Some(addr),
+            _ => None,
+        }
+    }
+}
+
+/// Something a decoded code could sensibly touch, used to tell formats apart
+fn is_plausible_address(addr: Addr) -> bool {
+    matches!(addr,
+        0x0200_0000..=0x0203_ffff
+        | 0x0300_0000..=0x0300_7fff
+        | 0x0400_0000..=0x0400_03ff
+        | 0x0500_0000..=0x0500_03ff
+        | 0x0600_0000..=0x0601_7fff
+        | 0x0700_0000..=0x0700_03ff
+        | 0x0e00_0000..=0x0e00_ffff)
+}
+
+fn is_rom_address(addr: Addr) -> bool {
+    (0x0800_0000..0x0e00_0000).contains(&addr)
+}
+
+/// A decoded cheat
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Program {
+    ops: Vec<Op>,
+    /// Halfwords to patch into the rom
+    patches: Vec<(Addr, u16)>,
+    hook: Option<Addr>,
+}
+
+impl Program {
+    fn is_plausible(&self) -> bool {
+        (!self.ops.is_empty() || !self.patches.is_empty())
+            && self.ops.iter().all(|op| match op {
+                Op::Else
+                | Op::EndIf
+                | Op::If {
+                    cond: Cond::KeysHeld(_),
+                    ..
+                } => true,
+                _ => op.target().map_or(false, is_plausible_address),
+            })
+            && self.patches.iter().all(|(addr, _)| is_rom_address(*addr))
+            && self.hook.map_or(true, is_rom_address)
+    }
+
+    fn run(&self, bus: &mut SysBus) {
+        let ops = &self.ops;
+        let mut pc = 0;
+        while let Some(op) = ops.get(pc) {
+            pc += 1;
+            match *op {
+                Op::Write { addr, width, value } => width.write(bus, addr, value),
+                Op::Fill {
+                    addr,
+                    width,
+                    value,
+                    count,
+                    addr_step,
+                    value_step,
+                } => {
+                    for i in 0..count {
+                        width.write(
+                            bus,
+                            addr.wrapping_add(i.wrapping_mul(addr_step)),
+                            value.wrapping_add(i.wrapping_mul(value_step)),
+                        );
+                    }
+                }
+                Op::WriteIndirect {
+                    pointer,
+                    offset,
+                    width,
+                    value,
+                } => {
+                    let addr = bus.read_32(pointer).wrapping_add(offset);
+                    width.write(bus, addr, value);
+                }
+                Op::Add { addr, width, value } => {
+                    let current = width.read(bus, addr);
+                    width.write(bus, addr, current.wrapping_add(value));
+                }
+                Op::Or { addr, width, value } => {
+                    let current = width.read(bus, addr);
+                    width.write(bus, addr, current | value);
+                }
+                Op::And { addr, width, value } => {
+                    let current = width.read(bus, addr);
+                    width.write(bus, addr, current & value);
+                }
+                Op::Bytes { addr, ref bytes } => {
+                    for (i, byte) in bytes.iter().enumerate() {
+                        bus.write_8(addr.wrapping_add(i as u32), *byte);
+                    }
+                }
+                Op::If { cond, skip } => {
+                    if !cond.eval(bus) {
+                        match skip {
+                            Skip::Next(count) => pc += count,
+                            Skip::Block => pc = skip_block(ops, pc, true),
+                            Skip::Rest => return,
+                        }
+                    }
+                }
+                // Reached the end of a taken if block
+                Op::Else => pc = skip_block(ops, pc, false),
+                Op::EndIf => {}
+            }
+        }
+    }
+}
+
+/// Find where execution continues after skipping the block that starts at `pc`,
+/// `Else` only ends the block when skipping a false condition.
+fn skip_block(ops: &[Op], mut pc: usize, stop_at_else: bool) -> usize {
+    let mut depth = 0;
+    while let Some(op) = ops.get(pc) {
+        pc += 1;
+        match op {
+            Op::If {
+                skip: Skip::Block, ..
+            } => depth += 1,
+            Op::Else if depth == 0 && stop_at_else => return pc,
+            Op::EndIf if depth == 0 => return pc,
+            Op::EndIf => depth -= 1,
+            _ => {}
+        }
+    }
+    pc
+}
+
+#[derive(Debug, Clone)]
+pub struct Cheat {
+    pub name: String,
+    format: CheatFormat,
+    enabled: bool,
+    lines: Vec<Line>,
+    program: Program,
+    /// The original rom contents under the applied patches
+    saved_rom: Vec<(Addr, u16)>,
+}
+
+impl Cheat {
+    /// Parse the codes of a single cheat, the cheat starts out enabled
+    pub fn new(name: &str, format: CheatFormat, codes: &str) -> CheatResult<Cheat> {
+        let lines = parse_lines(codes)?;
+        let program = format.decode(&lines)?;
+        Ok(Cheat::from_program(name, format, lines, program))
+    }
+
+    /// Like `new`, guessing the format from what the codes decode to
+    pub fn autodetect(name: &str, codes: &str) -> CheatResult<Cheat> {
+        let lines = parse_lines(codes)?;
+        if lines.iter().all(|line| line.short) {
+            return Cheat::new(name, CheatFormat::CodeBreaker, codes);
+        }
+        CheatFormat::DETECT
+            .iter()
+            .find_map(|format| match format.decode(&lines) {
+                Ok(program) if program.is_plausible() => Some((*format, program)),
+                _ => None,
+            })
+            .map(|(format, program)| Cheat::from_program(name, format, lines, program))
+            .ok_or(CheatError::UnknownFormat)
+    }
+
+    fn from_program(name: &str, format: CheatFormat, lines: Vec<Line>, program: Program) -> Cheat {
+        Cheat {
+            name: name.to_string(),
+            format,
+            enabled: true,
+            lines,
+            program,
+            saved_rom: Vec::new(),
+        }
+    }
+
+    pub fn format(&self) -> CheatFormat {
+        self.format
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The code lines, one per line
+    pub fn codes(&self) -> String {
+        self.lines
+            .iter()
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Address the cheat wants to run at, as set by its master code
+    pub fn hook(&self) -> Option<Addr> {
+        self.program.hook
+    }
+
+    fn apply_patches(&mut self, bus: &mut SysBus) {
+        if !self.saved_rom.is_empty() {
+            return;
+        }
+        for &(addr, value) in &self.program.patches {
+            self.saved_rom.push((addr, bus.debug_read_16(addr)));
+            bus.debug_write_16(addr, value);
+        }
+    }
+
+    fn undo_patches(&mut self, bus: &mut SysBus) {
+        for (addr, value) in self.saved_rom.drain(..).rev() {
+            bus.debug_write_16(addr, value);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CheatId(u32);
+
+/// The cheats added to a `GameBoyAdvance`
+#[derive(Debug, Default)]
+pub struct CheatList {
+    cheats: Vec<(CheatId, Cheat)>,
+    next_id: u32,
+    /// Hook of the first enabled cheat that has one, all cheats run there instead of every frame
+    hook: Option<Addr>,
+}
+
+impl CheatList {
+    pub fn iter(&self) -> impl Iterator<Item = (CheatId, &Cheat)> {
+        self.cheats.iter().map(|(id, cheat)| (*id, cheat))
+    }
+
+    pub fn get(&self, id: CheatId) -> Option<&Cheat> {
+        self.cheats
+            .iter()
+            .find(|(cheat_id, _)| *cheat_id == id)
+            .map(|(_, cheat)| cheat)
+    }
+
+    pub fn len(&self) -> usize {
+        self.cheats.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cheats.is_empty()
+    }
+
+    #[inline]
+    pub(crate) fn hook(&self) -> Option<Addr> {
+        self.hook
+    }
+
+    fn update_hook(&mut self) {
+        self.hook = self
+            .cheats
+            .iter()
+            .filter(|(_, cheat)| cheat.enabled)
+            .find_map(|(_, cheat)| cheat.hook())
+            .map(|addr| addr & !1);
+    }
+
+    pub(crate) fn add(&mut self, mut cheat: Cheat, bus: &mut SysBus) -> CheatId {
+        let id = CheatId(self.next_id);
+        self.next_id += 1;
+        if cheat.enabled {
+            cheat.apply_patches(bus);
+        }
+        self.cheats.push((id, cheat));
+        self.update_hook();
+        id
+    }
+
+    pub(crate) fn remove(&mut self, id: CheatId, bus: &mut SysBus) -> Option<Cheat> {
+        let index = self
+            .cheats
+            .iter()
+            .position(|(cheat_id, _)| *cheat_id == id)?;
+        let (_, mut cheat) = self.cheats.remove(index);
+        cheat.undo_patches(bus);
+        self.update_hook();
+        Some(cheat)
+    }
+
+    /// Returns false if there is no such cheat
+    pub(crate) fn set_enabled(&mut self, id: CheatId, enabled: bool, bus: &mut SysBus) -> bool {
+        let cheat = match self.cheats.iter_mut().find(|(cheat_id, _)| *cheat_id == id) {
+            Some((_, cheat)) => cheat,
+            None => return false,
+        };
+        cheat.enabled = enabled;
+        if enabled {
+            cheat.apply_patches(bus);
+        } else {
+            cheat.undo_patches(bus);
+        }
+        self.update_hook();
+        true
+    }
+
+    /// Run every enabled cheat
+    pub(crate) fn run(&self, bus: &mut SysBus) {
+        for (_, cheat) in &self.cheats {
+            if cheat.enabled {
+                cheat.program.run(bus);
+            }
+        }
+    }
+
+    /// Called once per frame, does nothing while the cheats wait for a hook instead
+    #[inline]
+    pub(crate) fn on_frame(&self, bus: &mut SysBus) {
+        if self.hook.is_none() && !self.cheats.is_empty() {
+            self.run(bus);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::{make_mock_gba, make_rom};
+
+    #[test]
+    fn test_parse_lines() {
+        let lines = parse_lines("0200A000 000000FF+12345678ABCDEF01\n83001234 0063").unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                Line {
+                    op1: 0x0200_a000,
+                    op2: 0xff,
+                    short: false
+                },
+                Line {
+                    op1: 0x1234_5678,
+                    op2: 0xabcd_ef01,
+                    short: false
+                },
+                Line {
+                    op1: 0x8300_1234,
+                    op2: 0x63,
+                    short: true
+                },
+            ]
+        );
+        assert!(parse_lines("0200A000 0FF").is_err());
+        assert!(parse_lines("0200A00G 000000FF").is_err());
+        assert!(parse_lines("").is_err());
+    }
+
+    #[test]
+    fn test_decrypt_roundtrip() {
+        for &(op1, op2) in &[(0x1200_0010, 0x1234), (0xdead_face, 0), (0, 0xffff_ffff)] {
+            let (e1, e2) = gameshark::encrypt(op1, op2, &gameshark::SEEDS);
+            assert_ne!((e1, e2), (op1, op2));
+            assert_eq!(gameshark::decrypt(e1, e2, &gameshark::SEEDS), (op1, op2));
+        }
+    }
+
+    #[test]
+    fn test_autodetect() {
+        let (e1, e2) = gameshark::encrypt(0x1200_0010, 0x1234, &gameshark::SEEDS);
+        let codes = format!("{:08X} {:08X}", e1, e2);
+        let cheat = Cheat::autodetect("gsv1", &codes).unwrap();
+        assert_eq!(cheat.format(), CheatFormat::GameSharkV1);
+
+        let (e1, e2) = gameshark::encrypt(0x0222_0010, 0x12, &action_replay::SEEDS);
+        let codes = format!("{:08X} {:08X}", e1, e2);
+        let cheat = Cheat::autodetect("armax", &codes).unwrap();
+        assert_eq!(cheat.format(), CheatFormat::ActionReplayMax);
+
+        let cheat = Cheat::autodetect("raw", "12000010 00001234").unwrap();
+        assert_eq!(cheat.format(), CheatFormat::GameSharkV1Raw);
+        let cheat = Cheat::autodetect("cb", "82000010 1234").unwrap();
+        assert_eq!(cheat.format(), CheatFormat::CodeBreaker);
+        assert!(Cheat::autodetect("junk", "FFFFFFFF FFFFFFFF").is_err());
+    }
+
+    #[test]
+    fn test_writes_every_frame() {
+        let mut gba = make_mock_gba(&make_rom(&[0xeafffffe])); // b .
+        let cheat = Cheat::new(
+            "writes",
+            CheatFormat::GameSharkV1Raw,
+            "02000000 000000AA 13000010 00001234 22000020 CAFEBABE",
+        )
+        .unwrap();
+        let id = gba.add_cheat(cheat);
+        gba.frame();
+        assert_eq!(gba.debug_read_8(0x0200_0000), 0xaa);
+        assert_eq!(gba.debug_read_16(0x0300_0010), 0x1234);
+        assert_eq!(gba.debug_read_32(0x0200_0020), 0xcafe_babe);
+
+        gba.sysbus.write_8(0x0200_0000, 0);
+        assert!(gba.set_cheat_enabled(id, false));
+        gba.frame();
+        assert_eq!(gba.debug_read_8(0x0200_0000), 0);
+        assert!(gba.remove_cheat(id).is_some());
+        assert!(!gba.set_cheat_enabled(id, true));
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let mut gba = make_mock_gba(&make_rom(&[0xeafffffe])); // b .
+        let cheat = Cheat::new(
+            "if",
+            CheatFormat::ActionReplayMaxRaw,
+            // if [0x02000000] == 5 { [0x02000010] = 1 } else { [0x02000010] = 2 }
+            "8A200000 00000005
+             00200010 00000001
+             00000000 60000000
+             00200010 00000002
+             00000000 40000000
+             04200020 11223344",
+        )
+        .unwrap();
+        gba.add_cheat(cheat);
+        gba.frame();
+        assert_eq!(gba.debug_read_8(0x0200_0010), 2);
+        assert_eq!(gba.debug_read_32(0x0200_0020), 0x1122_3344);
+
+        gba.sysbus.write_16(0x0200_0000, 5);
+        gba.frame();
+        assert_eq!(gba.debug_read_8(0x0200_0010), 1);
+
+        let mut gba = make_mock_gba(&make_rom(&[0xeafffffe])); // b .
+        let cheat = Cheat::new(
+            "cb",
+            CheatFormat::CodeBreaker,
+            // if [0x02000000] != 0 then [0x02000004] = 0x63, [0x02000006] += 2
+            "A2000000 0000 32000004 0063 E2000006 0002",
+        )
+        .unwrap();
+        gba.add_cheat(cheat);
+        gba.frame();
+        assert_eq!(gba.debug_read_8(0x0200_0004), 0);
+        assert_eq!(gba.debug_read_16(0x0200_0006), 2);
+    }
+
+    #[test]
+    fn test_rom_patch() {
+        let mut gba = make_mock_gba(&make_rom(&[0xeafffffe])); // b .
+        let original = gba.debug_read_16(0x0800_0100);
+        let cheat = Cheat::new("patch", CheatFormat::GameSharkV1Raw, "60000080 0000BEEF").unwrap();
+        let id = gba.add_cheat(cheat);
+        assert_eq!(gba.debug_read_16(0x0800_0100), 0xbeef);
+        gba.set_cheat_enabled(id, false);
+        assert_eq!(gba.debug_read_16(0x0800_0100), original);
+        gba.set_cheat_enabled(id, true);
+        assert_eq!(gba.debug_read_16(0x0800_0100), 0xbeef);
+        gba.remove_cheat(id);
+        assert_eq!(gba.debug_read_16(0x0800_0100), original);
+    }
+
+    #[test]
+    fn test_hook() {
+        let mut gba = make_mock_gba(&make_rom(&[0xeafffffe])); // b .
+        // The hook is never reached, the cpu spins at 0x08000000
+        let cheat = Cheat::new(
+            "hooked",
+            CheatFormat::GameSharkV1Raw,
+            "F8000100 00000001 02000000 00000011",
+        )
+        .unwrap();
+        let id = gba.add_cheat(cheat);
+        gba.frame();
+        assert_eq!(gba.debug_read_8(0x0200_0000), 0);
+
+        gba.remove_cheat(id);
+        let cheat = Cheat::new(
+            "hooked",
+            CheatFormat::GameSharkV1Raw,
+            "F8000000 00000001 02000000 00000011",
+        )
+        .unwrap();
+        gba.add_cheat(cheat);
+        gba.frame();
+        assert_eq!(gba.debug_read_8(0x0200_0000), 0x11);
+    }
+
+    #[test]
+    fn test_parse_cht() {
+        let text = r#"
+cheats = 3
+
+cheat0_desc = "Max money"
+cheat0_code = "12000010+00001234"
+cheat0_enable = true
+
+cheat1_desc = "Skipped"
+cheat1_code = ""
+
+cheat2_code = "82000010 0063"
+"#;
+        let cheats = parse_cht(text).unwrap();
+        assert_eq!(cheats.len(), 2);
+        assert_eq!(cheats[0].name, "Max money");
+        assert_eq!(cheats[0].format(), CheatFormat::GameSharkV1Raw);
+        assert!(cheats[0].is_enabled());
+        assert_eq!(cheats[1].name, "Cheat 2");
+        assert_eq!(cheats[1].format(), CheatFormat::CodeBreaker);
+        assert!(!cheats[1].is_enabled());
+
+        assert!(parse_cht("cheat0_code = \"12000010 00001234\"").is_err());
+    }
+}
@@ -0,0 +1,56 @@
+use std::fmt;
+
+use super::parser::Condition;
+use super::Addr;
+
+#[derive(Debug, Clone)]
+pub struct Breakpoint {
+    pub id: usize,
+    pub addr: Addr,
+    pub enabled: bool,
+    /// Only stop when this evaluates to true
+    pub condition: Option<Condition>,
+    /// Number of hits to skip before stopping
+    pub ignore_count: u32,
+    /// Number of hits where the condition held, including ignored ones
+    pub hit_count: u32,
+    /// Debugger commands to run on every stop, ending them with `continue` resumes execution
+    pub commands: Vec<String>,
+}
+
+impl Breakpoint {
+    pub fn new(id: usize, addr: Addr, condition: Option<Condition>) -> Breakpoint {
+        Breakpoint {
+            id,
+            addr,
+            enabled: true,
+            condition,
+            ignore_count: 0,
+            hit_count: 0,
+            commands: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] 0x{:08x} {} hits={}",
+            self.id,
+            self.addr,
+            if self.enabled { "enabled" } else { "disabled" },
+            self.hit_count
+        )?;
+        if self.ignore_count != 0 {
+            write!(f, " ignore={}", self.ignore_count)?;
+        }
+        if let Some(condition) = &self.condition {
+            write!(f, " if {}", condition)?;
+        }
+        for command in &self.commands {
+            write!(f, "\n\t> {}", command)?;
+        }
+        Ok(())
+    }
+}
@@ -3,16 +3,20 @@ use std::path::{Path, PathBuf};
 use std::time;
 
 use crate::arm7tdmi::arm::ArmInstruction;
+use crate::arm7tdmi::memory::{Addr, BusIO, DebugRead};
 use crate::arm7tdmi::thumb::ThumbInstruction;
 use crate::arm7tdmi::CpuState;
-use crate::bus::{Addr, Bus, DebugRead};
 use crate::disass::Disassembler;
+use crate::memsearch::{MemorySearch, SearchFilter, SearchWidth};
+use crate::watchpoint::{WatchKind, Watchpoint};
+use rustboyadvance_utils::elf::read_symbols;
 use rustboyadvance_utils::{read_bin_file, write_bin_file};
 
-// use super::palette_view::create_palette_view;
-// use super::tile_view::create_tile_view;
 use super::GameBoyAdvance;
-use super::{parser::Value, Debugger, DebuggerError, DebuggerResult};
+use super::{
+    parser::{Condition, Value},
+    Debugger, DebuggerError, DebuggerResult,
+};
 
 use ansi_term::Colour;
 
@@ -21,8 +25,6 @@ use fuzzy_matcher::FuzzyMatcher;
 
 use hexdump;
 
-use goblin;
-
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum DisassMode {
     ModeArm,
@@ -63,12 +65,18 @@ pub enum Command {
     HexDump(Addr, u32),
     MemWrite(MemWriteCommandSize, Addr, u32),
     Disass(DisassMode, Addr, u32),
-    AddBreakpoint(Addr),
+    AddBreakpoint(Addr, Option<Condition>),
     DelBreakpoint(Addr),
-    // PaletteView,
-    // TileView(u32),
+    EnableBreakpoint(usize, bool),
+    IgnoreBreakpoint(usize, u32),
+    SetBreakpointCondition(usize, Option<Condition>),
+    SetBreakpointCommands(usize, Vec<String>),
     ClearBreakpoints,
     ListBreakpoints,
+    AddWatchpoint(Watchpoint),
+    DelWatchpoint(Addr),
+    ClearWatchpoints,
+    ListWatchpoints,
     Reset,
     Quit,
     TraceToggle(TraceFlags),
@@ -76,6 +84,26 @@ pub enum Command {
     LoadState(String),
     AddSymbolsFile(PathBuf, Option<u32>),
     ListSymbols(Option<String>),
+    Search(SearchWidth, Option<u32>),
+    Narrow(SearchFilter),
+    ListSearchResults,
+    SearchResultToCheat(Addr, u32),
+}
+
+/// How many search results get printed
+const SEARCH_RESULTS_SHOWN: usize = 20;
+const NO_SEARCH: &str = "No search in progress, start one with `search <width> [value]`";
+
+fn print_search_results(search: &MemorySearch) {
+    for result in search.results().iter().take(SEARCH_RESULTS_SHOWN) {
+        println!(
+            "0x{:08x}: {} (was {})",
+            result.addr, result.value, result.previous
+        );
+    }
+    if search.len() > SEARCH_RESULTS_SHOWN {
+        println!("... and {} more", search.len() - SEARCH_RESULTS_SHOWN);
+    }
 }
 
 fn find_nearest_symbol(addr: u32, symbols: &HashMap<String, u32>) -> Option<(String, u32)> {
@@ -159,8 +187,10 @@ impl Debugger {
                 println!("{}\n", gba.cpu);
             }
             Continue => 'running: loop {
-                gba.key_poll();
                 if let Some(breakpoint) = gba.step_debugger() {
+                    if !self.breakpoint_hit(gba, breakpoint) {
+                        continue 'running;
+                    }
                     let mut bp_sym = None;
                     if let Some(symbols) = gba.sysbus.cartridge.get_symbols() {
                         for s in symbols.keys() {
@@ -174,6 +204,19 @@ impl Debugger {
                     } else {
                         println!("Breakpoint reached! @{:x}", breakpoint);
                     }
+                    if self.run_breakpoint_commands(gba, breakpoint) {
+                        continue 'running;
+                    }
+                    break 'running;
+                }
+                if let Some(hit) = gba.sysbus.watchpoints.take_hit() {
+                    println!(
+                        "Watchpoint reached! {} of 0x{:x} at 0x{:08x}, pc=0x{:08x}",
+                        hit.access,
+                        hit.value,
+                        hit.addr,
+                        gba.cpu.get_next_pc()
+                    );
                     break 'running;
                 }
             },
@@ -216,20 +259,78 @@ impl Debugger {
                 print!("Quitting!");
                 self.stop();
             }
-            AddBreakpoint(addr) => match gba.add_breakpoint(addr) {
-                Some(index) => println!("Added breakpoint [{}] 0x{:08x}", index, addr),
+            AddBreakpoint(addr, condition) => match self.add_breakpoint(gba, addr, condition) {
+                Some(id) => println!("Added breakpoint [{}] 0x{:08x}", id, addr),
                 None => println!("Breakpint already exists."),
             },
             DelBreakpoint(addr) => self.delete_breakpoint(gba, addr),
-            ClearBreakpoints => gba.cpu.dbg.breakpoints.clear(),
+            EnableBreakpoint(id, enabled) => {
+                if let Err(e) = self.enable_breakpoint(gba, id, enabled) {
+                    println!("{:?}", e);
+                }
+            }
+            IgnoreBreakpoint(id, count) => match self.find_breakpoint(id) {
+                Ok(bp) => {
+                    bp.ignore_count = count;
+                    println!("Will ignore next {} hits of breakpoint [{}]", count, id)
+                }
+                Err(e) => println!("{:?}", e),
+            },
+            SetBreakpointCondition(id, condition) => match self.find_breakpoint(id) {
+                Ok(bp) => bp.condition = condition,
+                Err(e) => println!("{:?}", e),
+            },
+            SetBreakpointCommands(id, commands) => match self.find_breakpoint(id) {
+                Ok(bp) => bp.commands = commands,
+                Err(e) => println!("{:?}", e),
+            },
+            ClearBreakpoints => {
+                gba.cpu.dbg.breakpoints.clear();
+                self.breakpoints.clear();
+            }
             ListBreakpoints => {
                 println!("breakpoint list:");
-                for (i, b) in gba.cpu.dbg.breakpoints.iter().enumerate() {
-                    println!("[{}] 0x{:08x}", i, b)
+                for bp in &self.breakpoints {
+                    println!("{}", bp)
+                }
+            }
+            AddWatchpoint(watchpoint) => {
+                if gba.sysbus.watchpoints.add(watchpoint) {
+                    println!(
+                        "Added {} watchpoint 0x{:08x}..0x{:08x}",
+                        watchpoint.kind,
+                        watchpoint.addr,
+                        watchpoint.addr + watchpoint.len
+                    )
+                } else {
+                    println!("Watchpoint already exists.")
+                }
+            }
+            DelWatchpoint(addr) => {
+                let matching: Vec<Watchpoint> = gba
+                    .sysbus
+                    .watchpoints
+                    .iter()
+                    .filter(|w| w.addr == addr)
+                    .cloned()
+                    .collect();
+                for watchpoint in matching {
+                    gba.sysbus.watchpoints.remove(watchpoint);
+                }
+            }
+            ClearWatchpoints => gba.sysbus.watchpoints.clear(),
+            ListWatchpoints => {
+                println!("watchpoint list:");
+                for (i, w) in gba.sysbus.watchpoints.iter().enumerate() {
+                    println!(
+                        "[{}] {} 0x{:08x}..0x{:08x}",
+                        i,
+                        w.kind,
+                        w.addr,
+                        w.addr + w.len
+                    )
                 }
             }
-            // PaletteView => create_palette_view(&gba.sysbus.palette_ram.mem),
-            // TileView(bg) => create_tile_view(bg, &gba),
             Reset => {
                 println!("resetting cpu...");
                 gba.cpu.reset();
@@ -301,15 +402,9 @@ impl Debugger {
             AddSymbolsFile(elf_file, offset) => {
                 let offset = offset.unwrap_or(0);
                 if let Ok(elf_buffer) = read_bin_file(&elf_file) {
-                    if let Ok(elf) = goblin::elf::Elf::parse(&elf_buffer) {
-                        let strtab = elf.strtab;
-                        for sym in elf.syms.iter() {
-                            if let Some(Ok(name)) = strtab.get(sym.st_name) {
-                                self.symbols
-                                    .insert(name.to_owned(), offset + (sym.st_value as u32));
-                            } else {
-                                warn!("failed to parse symbol name sym {:?}", sym);
-                            }
+                    if let Ok(symbols) = read_symbols(&elf_buffer) {
+                        for (name, addr) in symbols {
+                            self.symbols.insert(name, offset + addr);
                         }
                     } else {
                         println!("[error] Failed to parse elf file!");
@@ -320,6 +415,36 @@ impl Debugger {
                     return;
                 };
             }
+            Search(width, value) => {
+                let mut search = MemorySearch::new(gba, width);
+                if let Some(value) = value {
+                    search.narrow(gba, SearchFilter::Value(width.interpret(value)));
+                }
+                println!("{} search: {} candidates", width, search.len());
+                print_search_results(&search);
+                self.memory_search = Some(search);
+            }
+            Narrow(filter) => match &mut self.memory_search {
+                Some(search) => {
+                    println!("{} candidates left", search.narrow(gba, filter));
+                    print_search_results(search);
+                }
+                None => println!("{}", NO_SEARCH),
+            },
+            ListSearchResults => match &self.memory_search {
+                Some(search) => print_search_results(search),
+                None => println!("{}", NO_SEARCH),
+            },
+            SearchResultToCheat(addr, value) => match &self.memory_search {
+                Some(search) => match search.make_cheat(addr, search.width().interpret(value)) {
+                    Ok(cheat) => {
+                        println!("Added cheat {}", cheat.name);
+                        gba.add_cheat(cheat);
+                    }
+                    Err(e) => println!("[error] {}", e),
+                },
+                None => println!("{}", NO_SEARCH),
+            },
             _ => println!("Not Implemented",),
         }
     }
@@ -531,7 +656,28 @@ impl Debugger {
                     ))
                 } else {
                     let addr = self.val_address(gba, &args[0])?;
-                    Ok(Command::AddBreakpoint(addr))
+                    Ok(Command::AddBreakpoint(addr, None))
+                }
+            }
+            "enable" | "disable" => {
+                if args.len() != 1 {
+                    Err(DebuggerError::InvalidCommandFormat(format!(
+                        "{} <breakpoint-id>",
+                        command
+                    )))
+                } else {
+                    let id = self.val_number(&args[0])? as usize;
+                    Ok(Command::EnableBreakpoint(id, command == "enable"))
+                }
+            }
+            "ignore" => {
+                if args.len() != 2 {
+                    Err(DebuggerError::InvalidCommandFormat(
+                        "ignore <breakpoint-id> <count>".to_string(),
+                    ))
+                } else {
+                    let id = self.val_number(&args[0])? as usize;
+                    Ok(Command::IgnoreBreakpoint(id, self.val_number(&args[1])?))
                 }
             }
             "bd" | "breakdel" => match args.len() {
@@ -544,14 +690,35 @@ impl Debugger {
                     "breakdel [addr]",
                 ))),
             },
-            // "palette-view" => Ok(Command::PaletteView),
-            // "tiles" => {
-            //     if args.len() != 1 {
-            //         return Err(DebuggerError::InvalidCommandFormat("tile <bg>".to_string()));
-            //     }
-            //     let bg = self.val_number(&args[0])?;
-            //     Ok(Command::TileView(bg))
-            // }
+            "watch" | "rwatch" | "awatch" => {
+                let (addr, len) = match args.len() {
+                    1 => (self.val_address(gba, &args[0])?, 4),
+                    2 => (self.val_address(gba, &args[0])?, self.val_number(&args[1])?),
+                    _ => {
+                        return Err(DebuggerError::InvalidCommandFormat(format!(
+                            "{} <addr> [len]",
+                            command
+                        )))
+                    }
+                };
+                let kind = match command.as_ref() {
+                    "watch" => WatchKind::Write,
+                    "rwatch" => WatchKind::Read,
+                    _ => WatchKind::ReadWrite,
+                };
+                Ok(Command::AddWatchpoint(Watchpoint { addr, len, kind }))
+            }
+            "wd" | "watchdel" => match args.len() {
+                0 => Ok(Command::ClearWatchpoints),
+                1 => {
+                    let addr = self.val_address(gba, &args[0])?;
+                    Ok(Command::DelWatchpoint(addr))
+                }
+                _ => Err(DebuggerError::InvalidCommandFormat(String::from(
+                    "watchdel [addr]",
+                ))),
+            },
+            "wl" => Ok(Command::ListWatchpoints),
             "bl" => Ok(Command::ListBreakpoints),
             "q" | "quit" => Ok(Command::Quit),
             "r" | "reset" => Ok(Command::Reset),
@@ -643,6 +810,84 @@ impl Debugger {
                     command
                 ))),
             },
+            "search" => {
+                let usage = DebuggerError::InvalidCommandFormat(String::from(
+                    "search [u8|i8|u16|i16|u32|i32] [value]",
+                ));
+                match args.len() {
+                    0 => Ok(Command::ListSearchResults),
+                    1 | 2 => {
+                        let width = match &args[0] {
+                            Value::Identifier(width) => width
+                                .parse::<SearchWidth>()
+                                .map_err(DebuggerError::InvalidArgument)?,
+                            _ => return Err(usage),
+                        };
+                        let value = match args.get(1) {
+                            Some(value) => Some(self.val_number(value)?),
+                            None => None,
+                        };
+                        Ok(Command::Search(width, value))
+                    }
+                    _ => Err(usage),
+                }
+            }
+            "narrow" => {
+                let usage = DebuggerError::InvalidCommandFormat(String::from(
+                    "narrow equal|changed|increased|decreased|<value>",
+                ));
+                if args.len() != 1 {
+                    return Err(usage);
+                }
+                let filter = match &args[0] {
+                    Value::Identifier(filter) => match filter.as_ref() {
+                        "eq" | "equal" | "unchanged" => SearchFilter::Equal,
+                        "ne" | "changed" => SearchFilter::Changed,
+                        "inc" | "increased" => SearchFilter::Increased,
+                        "dec" | "decreased" => SearchFilter::Decreased,
+                        _ => return Err(usage),
+                    },
+                    value => {
+                        let width = match &self.memory_search {
+                            Some(search) => search.width(),
+                            None => {
+                                return Err(DebuggerError::InvalidCommand(String::from(
+                                    "no search in progress",
+                                )))
+                            }
+                        };
+                        SearchFilter::Value(width.interpret(self.val_number(value)?))
+                    }
+                };
+                Ok(Command::Narrow(filter))
+            }
+            "search-cheat" => {
+                if args.len() != 2 {
+                    Err(DebuggerError::InvalidCommandFormat(
+                        "search-cheat <addr> <value>".to_string(),
+                    ))
+                } else {
+                    let addr = self.val_address(gba, &args[0])?;
+                    Ok(Command::SearchResultToCheat(
+                        addr,
+                        self.val_number(&args[1])?,
+                    ))
+                }
+            }
+            "search-watch" => match (&self.memory_search, args.len()) {
+                (Some(search), 1) => {
+                    let addr = self.val_address(gba, &args[0])?;
+                    Ok(Command::AddWatchpoint(
+                        search.make_watchpoint(addr, WatchKind::Write),
+                    ))
+                }
+                (None, _) => Err(DebuggerError::InvalidCommand(String::from(
+                    "no search in progress",
+                ))),
+                _ => Err(DebuggerError::InvalidCommandFormat(
+                    "search-watch <addr>".to_string(),
+                )),
+            },
             _ => Err(DebuggerError::InvalidCommand(command)),
         }
     }
@@ -7,18 +7,20 @@ use rustyline::Editor;
 
 use colored::*;
 
+use arm7tdmi::memory::{Addr, BusIO, DebugRead};
+
 use super::GameBoyAdvance;
-use super::{Addr, Bus};
+use crate::memsearch::MemorySearch;
 
 mod parser;
-use parser::{parse_expr, DerefType, Expr, Value};
+use parser::{parse_expr, CompareOp, Condition, DerefType, Expr, Value};
+
+mod breakpoint;
+use breakpoint::Breakpoint;
 
 mod command;
 use command::Command;
 
-mod palette_view;
-mod tile_view;
-
 #[derive(Debug)]
 pub enum DebuggerError {
     ParsingError(String),
@@ -28,6 +30,21 @@ pub enum DebuggerError {
     IoError(::std::io::Error),
 }
 
+impl PartialEq for DebuggerError {
+    fn eq(&self, other: &DebuggerError) -> bool {
+        use DebuggerError::*;
+        match (self, other) {
+            (ParsingError(a), ParsingError(b))
+            | (InvalidCommand(a), InvalidCommand(b))
+            | (InvalidArgument(a), InvalidArgument(b))
+            | (InvalidCommandFormat(a), InvalidCommandFormat(b)) => a == b,
+            // io::Error has no PartialEq, its kind is the closest we get
+            (IoError(a), IoError(b)) => a.kind() == b.kind(),
+            _ => false,
+        }
+    }
+}
+
 impl From<::std::io::Error> for DebuggerError {
     fn from(e: ::std::io::Error) -> DebuggerError {
         DebuggerError::IoError(e)
@@ -40,6 +57,9 @@ pub struct Debugger {
     running: bool,
     pub previous_command: Option<Command>,
     pub symbols: HashMap<String, u32>,
+    pub breakpoints: Vec<Breakpoint>,
+    next_breakpoint_id: usize,
+    pub memory_search: Option<MemorySearch>,
 }
 
 impl Debugger {
@@ -48,6 +68,9 @@ impl Debugger {
             running: false,
             previous_command: None,
             symbols: HashMap::new(),
+            breakpoints: Vec::new(),
+            next_breakpoint_id: 0,
+            memory_search: None,
         }
     }
 
@@ -64,6 +87,103 @@ impl Debugger {
 
     pub fn delete_breakpoint(&mut self, gba: &mut GameBoyAdvance, addr: u32) {
         gba.cpu.dbg.breakpoints.retain(|&a| a != addr);
+        self.breakpoints.retain(|bp| bp.addr != addr);
+    }
+
+    fn add_breakpoint(
+        &mut self,
+        gba: &mut GameBoyAdvance,
+        addr: Addr,
+        condition: Option<Condition>,
+    ) -> Option<usize> {
+        gba.add_breakpoint(addr)?;
+        let id = self.next_breakpoint_id;
+        self.next_breakpoint_id += 1;
+        self.breakpoints.push(Breakpoint::new(id, addr, condition));
+        Some(id)
+    }
+
+    fn find_breakpoint(&mut self, id: usize) -> DebuggerResult<&mut Breakpoint> {
+        self.breakpoints
+            .iter_mut()
+            .find(|bp| bp.id == id)
+            .ok_or_else(|| DebuggerError::InvalidArgument(format!("no breakpoint {}", id)))
+    }
+
+    fn enable_breakpoint(
+        &mut self,
+        gba: &mut GameBoyAdvance,
+        id: usize,
+        enabled: bool,
+    ) -> DebuggerResult<()> {
+        let bp = self.find_breakpoint(id)?;
+        bp.enabled = enabled;
+        let addr = bp.addr;
+        if enabled {
+            gba.add_breakpoint(addr);
+        } else if !self
+            .breakpoints
+            .iter()
+            .any(|bp| bp.enabled && bp.addr == addr)
+        {
+            gba.cpu.dbg.breakpoints.retain(|&a| a != addr);
+        }
+        Ok(())
+    }
+
+    /// Called when the cpu stops on `addr`, decides whether the debugger should stop there
+    fn breakpoint_hit(&mut self, gba: &mut GameBoyAdvance, addr: Addr) -> bool {
+        let index = match self
+            .breakpoints
+            .iter()
+            .position(|bp| bp.enabled && bp.addr == addr)
+        {
+            Some(index) => index,
+            None => return true,
+        };
+        if let Some(condition) = self.breakpoints[index].condition.clone() {
+            match self.eval_condition(gba, &condition) {
+                Ok(true) => {}
+                Ok(false) => return false,
+                Err(e) => println!("{}: {:?}", "breakpoint condition error".red(), e),
+            }
+        }
+        let bp = &mut self.breakpoints[index];
+        bp.hit_count += 1;
+        if bp.ignore_count > 0 {
+            bp.ignore_count -= 1;
+            return false;
+        }
+        true
+    }
+
+    /// Run the commands attached to the breakpoint at `addr`.
+    /// Returns true if they end with `continue`, in which case execution should resume.
+    fn run_breakpoint_commands(&mut self, gba: &mut GameBoyAdvance, addr: Addr) -> bool {
+        let commands = match self
+            .breakpoints
+            .iter()
+            .find(|bp| bp.enabled && bp.addr == addr)
+        {
+            Some(bp) => bp.commands.clone(),
+            None => return false,
+        };
+        let resume = matches!(
+            commands.last().map(|c| c.as_str()),
+            Some("c") | Some("continue")
+        );
+        let count = if resume {
+            commands.len() - 1
+        } else {
+            commands.len()
+        };
+        for command in &commands[..count] {
+            match parse_expr(command) {
+                Ok(expr) => self.eval_expr(gba, expr),
+                Err(e) => println!("{}: {:?}", "breakpoint command error".red(), e),
+            }
+        }
+        resume
     }
 
     fn decode_reg(&self, s: &str) -> DebuggerResult<usize> {
@@ -127,6 +247,49 @@ impl Debugger {
         }
     }
 
+    /// Like `val_address`, but also dereferences memory
+    fn val_eval(&self, gba: &mut GameBoyAdvance, arg: &Value) -> DebuggerResult<u32> {
+        match arg {
+            Value::Deref(addr_value, deref_type) => {
+                let addr = self.val_address(gba, addr_value)?;
+                Ok(match deref_type {
+                    DerefType::Word => gba.sysbus.debug_read_32(addr),
+                    DerefType::HalfWord => gba.sysbus.debug_read_16(addr) as u32,
+                    DerefType::Byte => gba.sysbus.debug_read_8(addr) as u32,
+                })
+            }
+            Value::Boolean(b) => Ok(*b as u32),
+            _ => self.val_address(gba, arg),
+        }
+    }
+
+    fn eval_condition(
+        &self,
+        gba: &mut GameBoyAdvance,
+        condition: &Condition,
+    ) -> DebuggerResult<bool> {
+        match condition {
+            Condition::Compare(lhs, op, rhs) => {
+                let lhs = self.val_eval(gba, lhs)?;
+                let rhs = self.val_eval(gba, rhs)?;
+                Ok(match op {
+                    CompareOp::Eq => lhs == rhs,
+                    CompareOp::Ne => lhs != rhs,
+                    CompareOp::Lt => lhs < rhs,
+                    CompareOp::Le => lhs <= rhs,
+                    CompareOp::Gt => lhs > rhs,
+                    CompareOp::Ge => lhs >= rhs,
+                })
+            }
+            Condition::And(lhs, rhs) => {
+                Ok(self.eval_condition(gba, lhs)? && self.eval_condition(gba, rhs)?)
+            }
+            Condition::Or(lhs, rhs) => {
+                Ok(self.eval_condition(gba, lhs)? || self.eval_condition(gba, rhs)?)
+            }
+        }
+    }
+
     fn eval_assignment(
         &mut self,
         gba: &mut GameBoyAdvance,
@@ -173,6 +336,16 @@ impl Debugger {
                 }
                 _ => (),
             },
+            Expr::BreakIf(addr, condition) => match self.val_address(gba, &addr) {
+                Ok(addr) => self.run_command(gba, Command::AddBreakpoint(addr, Some(condition))),
+                Err(e) => println!("{}: {:?}", "invalid argument".red(), e),
+            },
+            Expr::SetCondition(id, condition) => {
+                self.run_command(gba, Command::SetBreakpointCondition(id as usize, condition))
+            }
+            Expr::SetCommands(id, commands) => {
+                self.run_command(gba, Command::SetBreakpointCommands(id as usize, commands))
+            }
             Expr::Empty => println!("Got empty expr"),
         }
     }
@@ -1,10 +1,12 @@
+use std::fmt;
+
 use nom;
 use nom::branch::alt;
 use nom::bytes::complete::{tag, take_while1, take_while_m_n};
 use nom::character::complete::{char, digit1, multispace0, multispace1};
-use nom::combinator::{cut, map, map_res, opt};
+use nom::combinator::{cut, map, map_res, opt, rest};
 use nom::error::{context, convert_error, ParseError, VerboseError};
-use nom::multi::separated_list;
+use nom::multi::{fold_many0, separated_list};
 use nom::sequence::{delimited, preceded, separated_pair, terminated, tuple};
 use nom::IResult;
 
@@ -25,12 +27,69 @@ pub enum Value {
     Deref(Box<Value>, DerefType),
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Condition {
+    /// value op value, a bare value is parsed as `value != 0`
+    Compare(Value, CompareOp, Value),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Num(n) => write!(f, "0x{:x}", n),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Identifier(s) => write!(f, "{}", s),
+            Value::Deref(v, DerefType::Word) => write!(f, "*(u32*){}", v),
+            Value::Deref(v, DerefType::HalfWord) => write!(f, "*(u16*){}", v),
+            Value::Deref(v, DerefType::Byte) => write!(f, "*(u8*){}", v),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Compare(lhs, op, rhs) => {
+                let op = match op {
+                    CompareOp::Eq => "==",
+                    CompareOp::Ne => "!=",
+                    CompareOp::Lt => "<",
+                    CompareOp::Le => "<=",
+                    CompareOp::Gt => ">",
+                    CompareOp::Ge => ">=",
+                };
+                write!(f, "{} {} {}", lhs, op, rhs)
+            }
+            Condition::And(lhs, rhs) => write!(f, "{} && {}", lhs, rhs),
+            Condition::Or(lhs, rhs) => write!(f, "{} || {}", lhs, rhs),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum Expr {
     /// (command-name arg0 arg1 ...)
     Command(Value, Vec<Value>),
     /// constant = value
     Assignment(Value, Value),
+    /// break addr if condition
+    BreakIf(Value, Condition),
+    /// cond breakpoint-id [condition]
+    SetCondition(u32, Option<Condition>),
+    /// commands breakpoint-id command0; command1; ...
+    SetCommands(u32, Vec<String>),
     Empty,
 }
 
@@ -104,6 +163,114 @@ fn parse_value<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, Value
     )(i)
 }
 
+fn parse_compare_op<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, CompareOp, E> {
+    alt((
+        map(tag("=="), |_| CompareOp::Eq),
+        map(tag("!="), |_| CompareOp::Ne),
+        map(tag("<="), |_| CompareOp::Le),
+        map(tag(">="), |_| CompareOp::Ge),
+        map(tag("<"), |_| CompareOp::Lt),
+        map(tag(">"), |_| CompareOp::Gt),
+    ))(i)
+}
+
+fn parse_comparison<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, Condition, E> {
+    context(
+        "comparison",
+        map(
+            tuple((
+                parse_value,
+                opt(tuple((
+                    preceded(multispace0, parse_compare_op),
+                    cut(preceded(multispace0, parse_value)),
+                ))),
+            )),
+            |(lhs, rhs)| match rhs {
+                Some((op, rhs)) => Condition::Compare(lhs, op, rhs),
+                None => Condition::Compare(lhs, CompareOp::Ne, Value::Num(0)),
+            },
+        ),
+    )(i)
+}
+
+fn parse_and<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, Condition, E> {
+    let (i, first) = parse_comparison(i)?;
+    fold_many0(
+        preceded(
+            delimited(multispace0, tag("&&"), multispace0),
+            cut(parse_comparison),
+        ),
+        first,
+        |lhs, rhs| Condition::And(Box::new(lhs), Box::new(rhs)),
+    )(i)
+}
+
+fn parse_condition<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, Condition, E> {
+    let (i, first) = parse_and(i)?;
+    context(
+        "condition",
+        fold_many0(
+            preceded(
+                delimited(multispace0, tag("||"), multispace0),
+                cut(parse_and),
+            ),
+            first,
+            |lhs, rhs| Condition::Or(Box::new(lhs), Box::new(rhs)),
+        ),
+    )(i)
+}
+
+fn parse_break_if<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, Expr, E> {
+    context(
+        "conditional breakpoint",
+        map(
+            tuple((
+                terminated(alt((tag("break"), tag("b"))), multispace1),
+                terminated(parse_value, multispace1),
+                preceded(
+                    terminated(tag("if"), multispace1),
+                    cut(terminated(parse_condition, multispace0)),
+                ),
+            )),
+            |(_, addr, condition)| Expr::BreakIf(addr, condition),
+        ),
+    )(i)
+}
+
+fn parse_set_condition<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, Expr, E> {
+    context(
+        "cond",
+        map(
+            tuple((
+                preceded(terminated(tag("cond"), multispace1), cut(parse_u32)),
+                opt(preceded(multispace1, parse_condition)),
+            )),
+            |(id, condition)| Expr::SetCondition(id, condition),
+        ),
+    )(i)
+}
+
+fn parse_set_commands<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, Expr, E> {
+    context(
+        "commands",
+        map(
+            tuple((
+                preceded(terminated(tag("commands"), multispace1), cut(parse_u32)),
+                rest,
+            )),
+            |(id, commands): (u32, &str)| {
+                let commands = commands
+                    .split(';')
+                    .map(str::trim)
+                    .filter(|command| !command.is_empty())
+                    .map(String::from)
+                    .collect();
+                Expr::SetCommands(id, commands)
+            },
+        ),
+    )(i)
+}
+
 fn parse_command<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&str, Expr, E> {
     context(
         "command",
@@ -138,6 +305,9 @@ fn _parse_expr<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&str, Expr, E>
             multispace0,
             alt((
                 parse_assignment,
+                parse_break_if,
+                parse_set_condition,
+                parse_set_commands,
                 parse_command,
                 map(multispace0, |_| Expr::Empty),
             )),
@@ -211,6 +381,59 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_break_if() {
+        let r0 = || Value::Identifier("r0".to_string());
+        assert_eq!(
+            parse_expr("b 0x08000100 if r0 == 3 && *(u8*)0x03000010 || r0"),
+            Ok(Expr::BreakIf(
+                Value::Num(0x0800_0100),
+                Condition::Or(
+                    Box::new(Condition::And(
+                        Box::new(Condition::Compare(r0(), CompareOp::Eq, Value::Num(3))),
+                        Box::new(Condition::Compare(
+                            Value::Deref(Box::new(Value::Num(0x0300_0010)), DerefType::Byte),
+                            CompareOp::Ne,
+                            Value::Num(0)
+                        ))
+                    )),
+                    Box::new(Condition::Compare(r0(), CompareOp::Ne, Value::Num(0)))
+                )
+            ))
+        );
+        assert!(parse_expr("break 0x100 if r0 ==").is_err());
+        assert_eq!(
+            parse_expr("break 0x100"),
+            Ok(Expr::Command(
+                Value::Identifier("break".to_string()),
+                vec![Value::Num(0x100)]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_breakpoint_settings() {
+        assert_eq!(
+            parse_expr("cond 2 r1>=0x10"),
+            Ok(Expr::SetCondition(
+                2,
+                Some(Condition::Compare(
+                    Value::Identifier("r1".to_string()),
+                    CompareOp::Ge,
+                    Value::Num(0x10)
+                ))
+            ))
+        );
+        assert_eq!(parse_expr("cond 2"), Ok(Expr::SetCondition(2, None)));
+        assert_eq!(
+            parse_expr("commands 1 info cpu;  c "),
+            Ok(Expr::SetCommands(
+                1,
+                vec!["info cpu".to_string(), "c".to_string()]
+            ))
+        );
+    }
+
     #[test]
     fn test_parse_deref() {
         assert_eq!(
@@ -157,6 +157,7 @@ impl DmaChannel {
         if fifo_mode {
             for _ in 0..4 {
                 let v = sb.load_32(self.internal.src_addr & !3, access);
+                sb.dma_bus = v;
                 sb.store_32(self.internal.dst_addr & !3, v, access);
                 access = MemoryAccess::Seq;
                 self.internal.src_addr += 4;
@@ -164,6 +165,7 @@ impl DmaChannel {
         } else if word_size == 4 {
             for _ in 0..count {
                 let w = sb.load_32(self.internal.src_addr & !3, access);
+                sb.dma_bus = w;
                 sb.store_32(self.internal.dst_addr & !3, w, access);
                 access = MemoryAccess::Seq;
                 self.internal.src_addr += src_adj;
@@ -172,6 +174,8 @@ impl DmaChannel {
         } else {
             for _ in 0..count {
                 let hw = sb.load_16(self.internal.src_addr & !1, access);
+                // halfwords show up on both halves of the bus
+                sb.dma_bus = (hw as u32) * 0x0001_0001;
                 sb.store_16(self.internal.dst_addr & !1, hw, access);
                 access = MemoryAccess::Seq;
                 self.internal.src_addr += src_adj;
@@ -229,11 +233,13 @@ impl DmaController {
     }
 
     pub fn perform_work(&mut self, sb: &mut SysBus) {
+        sb.dma_active = true;
         for id in 0..4 {
             if self.pending_set & (1 << id) != 0 {
                 self.channels[id].transfer(sb);
             }
         }
+        sb.dma_active = false;
         self.pending_set = 0;
     }
 
@@ -1,5 +1,7 @@
 /// Struct containing everything
 use std::cell::Cell;
+use std::fs;
+use std::path::Path;
 use std::rc::Rc;
 
 use bincode;
@@ -7,18 +9,25 @@ use serde::{Deserialize, Serialize};
 
 use crate::gdb_support::{gdb_thread::start_gdb_server_thread, DebuggerRequestHandler};
 
-use super::cartridge::Cartridge;
+use super::cartridge::{
+    self, Cartridge, DynRumbleInterface, RtcClock, SaveConvertResult, SaveFormat,
+};
+use super::cheats::{self, Cheat, CheatId, CheatList, CheatResult};
 use super::dma::DmaController;
 use super::gpu::*;
+use super::hle;
 use super::interrupt::*;
 use super::iodev::*;
-use super::sched::{EventType, Scheduler, SchedulerConnect, SharedScheduler};
+use super::savestate::{self, SaveStateHeader, SaveStateResult, Thumbnail};
+use super::sched::{EventType, Scheduler, SchedulerConnect, SharedScheduler, SioEvent};
+use super::sio::{DynSioTransport, Sio};
 use super::sound::SoundController;
-use super::sysbus::SysBus;
+use super::sysbus::{Prefetch, SysBus};
 use super::timer::Timers;
 
 use super::sound::interface::DynAudioInterface;
 
+use arm7tdmi::memory::{Addr, DebugRead};
 use arm7tdmi::{self, Arm7tdmiCore};
 use rustboyadvance_utils::Shared;
 
@@ -29,7 +38,14 @@ pub struct GameBoyAdvance {
     pub(crate) scheduler: SharedScheduler,
     interrupt_flags: SharedInterruptFlags,
     audio_interface: DynAudioInterface,
+    sio_transport: Option<DynSioTransport>,
+    rumble_interface: Option<DynRumbleInterface>,
+    /// Whether the rumble interface was last told to rumble
+    rumbling: bool,
+    /// Cycles the last frame ran past its end, taken off the next one
+    pub(crate) overshoot: usize,
     pub(crate) debugger: Option<DebuggerRequestHandler>,
+    cheats: CheatList,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -41,6 +57,8 @@ struct SaveState {
     iwram: Box<[u8]>,
     interrupt_flags: u16,
     cpu_state: arm7tdmi::SavedCpuState,
+    prefetch: Prefetch,
+    dma_bus: u32,
 }
 
 #[derive(Debug, PartialEq)]
@@ -51,15 +69,9 @@ enum BusMaster {
 
 /// Checks if the bios provided is the real one
 fn check_real_bios(bios: &[u8]) -> bool {
-    use sha2::{Digest, Sha256};
-
-    let mut hasher = Sha256::new();
-    hasher.input(bios);
-    let digest = hasher.result();
-
     let expected_hash = hex!("fd2547724b505f487e6dcb29ec2ecff3af35a841a77ab2e85fd87350abd36570");
 
-    digest.as_slice() == &expected_hash[..]
+    savestate::sha256(bios) == expected_hash
 }
 
 impl GameBoyAdvance {
@@ -68,10 +80,14 @@ impl GameBoyAdvance {
         gamepak: Cartridge,
         audio_interface: DynAudioInterface,
     ) -> GameBoyAdvance {
+        let hle_swi = hle::is_builtin_bios(&bios_rom);
         // Warn the user if the bios is not the real one
-        match check_real_bios(&bios_rom) {
-            true => info!("Verified bios rom"),
-            false => warn!("This is not the real bios rom, some games may not be compatible"),
+        match (check_real_bios(&bios_rom), hle_swi) {
+            (true, _) => info!("Verified bios rom"),
+            (false, true) => info!("Using the built-in bios with high level emulation"),
+            (false, false) => {
+                warn!("This is not the real bios rom, some games may not be compatible")
+            }
         };
 
         let interrupt_flags = Rc::new(Cell::new(IrqBitmask(0)));
@@ -85,12 +101,14 @@ impl GameBoyAdvance {
             &mut scheduler,
             audio_interface.get_sample_rate() as f32,
         ));
+        let sio = Sio::new(interrupt_flags.clone());
         let io_devs = Shared::new(IoDevices::new(
             intc,
             gpu,
             dmac,
             timers,
             sound_controller,
+            sio,
             scheduler.clone(),
         ));
         let sysbus = Shared::new(SysBus::new(
@@ -100,16 +118,22 @@ impl GameBoyAdvance {
             gamepak,
         ));
 
-        let cpu = Box::new(Arm7tdmiCore::new(sysbus.clone()));
+        let mut cpu = Box::new(Arm7tdmiCore::new(sysbus.clone()));
+        cpu.hle_swi = hle_swi;
 
         let mut gba = GameBoyAdvance {
             cpu,
             sysbus,
             io_devs,
             audio_interface,
+            sio_transport: None,
+            rumble_interface: None,
+            rumbling: false,
+            overshoot: 0,
             scheduler,
             interrupt_flags,
             debugger: None,
+            cheats: CheatList::default(),
         };
 
         gba.sysbus.init(gba.cpu.weak_ptr());
@@ -122,8 +146,14 @@ impl GameBoyAdvance {
         bios: Box<[u8]>,
         rom: Box<[u8]>,
         audio_interface: DynAudioInterface,
-    ) -> bincode::Result<GameBoyAdvance> {
-        let decoded: Box<SaveState> = bincode::deserialize_from(savestate)?;
+    ) -> SaveStateResult<GameBoyAdvance> {
+        let (header, state) = savestate::decode(savestate)?;
+        let game_code = cartridge::header::parse(&rom)
+            .map(|header| header.game_code)
+            .unwrap_or_default();
+        header.check(&game_code, &rom, &bios)?;
+        let hle_swi = hle::is_builtin_bios(&bios);
+        let decoded: Box<SaveState> = bincode::deserialize_from(&state[..])?;
 
         let interrupts = Rc::new(Cell::new(IrqBitmask(decoded.interrupt_flags)));
         let scheduler = decoded.scheduler.make_shared();
@@ -140,10 +170,13 @@ impl GameBoyAdvance {
             decoded.ewram,
             decoded.iwram,
         ));
+        sysbus.prefetch = decoded.prefetch;
+        sysbus.dma_bus = decoded.dma_bus;
         let mut arm7tdmi = Box::new(Arm7tdmiCore::from_saved_state(
             sysbus.clone(),
             decoded.cpu_state,
         ));
+        arm7tdmi.hle_swi = hle_swi;
 
         sysbus.init(arm7tdmi.weak_ptr());
 
@@ -153,12 +186,70 @@ impl GameBoyAdvance {
             io_devs,
             interrupt_flags: interrupts,
             audio_interface,
+            sio_transport: None,
+            rumble_interface: None,
+            rumbling: false,
+            overshoot: 0,
             scheduler,
             debugger: None,
+            cheats: CheatList::default(),
         })
     }
 
-    pub fn save_state(&self) -> bincode::Result<Vec<u8>> {
+    /// Serialize the emulator state into the savestate container
+    pub fn save_state(&self) -> SaveStateResult<Vec<u8>> {
+        savestate::encode(&self.savestate_header(None), &self.save_snapshot()?)
+    }
+
+    /// Like `save_state`, with a half sized picture of the current frame
+    pub fn save_state_with_thumbnail(&self) -> SaveStateResult<Vec<u8>> {
+        let thumbnail = self.make_thumbnail();
+        savestate::encode(
+            &self.savestate_header(Some(thumbnail)),
+            &self.save_snapshot()?,
+        )
+    }
+
+    pub fn restore_state(&mut self, bytes: &[u8]) -> SaveStateResult<()> {
+        let (header, state) = savestate::decode(bytes)?;
+        header.check(
+            &self.get_game_code(),
+            self.sysbus.cartridge.get_rom_bytes(),
+            self.sysbus.bios.rom(),
+        )?;
+        Ok(self.restore_snapshot(&state)?)
+    }
+
+    fn savestate_header(&self, thumbnail: Option<Thumbnail>) -> SaveStateHeader {
+        SaveStateHeader {
+            emulator_version: env!("CARGO_PKG_VERSION").to_string(),
+            game_title: self.get_game_title(),
+            game_code: self.get_game_code(),
+            rom_hash: savestate::sha256(self.sysbus.cartridge.get_rom_bytes()),
+            bios_hash: savestate::sha256(self.sysbus.bios.rom()),
+            timestamp: chrono::Utc::now().timestamp(),
+            thumbnail,
+        }
+    }
+
+    fn make_thumbnail(&self) -> Thumbnail {
+        let frame_buffer = self.get_frame_buffer();
+        let (width, height) = (DISPLAY_WIDTH / 2, DISPLAY_HEIGHT / 2);
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(frame_buffer[(y * 2) * DISPLAY_WIDTH + x * 2]);
+            }
+        }
+        Thumbnail {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// The bare emulator state without the savestate container, cheap enough for rewinding
+    pub(crate) fn save_snapshot(&self) -> bincode::Result<Vec<u8>> {
         let s = SaveState {
             cpu_state: self.cpu.save_state(),
             io_devs: self.io_devs.clone_inner(),
@@ -167,32 +258,67 @@ impl GameBoyAdvance {
             ewram: Box::from(self.sysbus.get_ewram()),
             interrupt_flags: self.interrupt_flags.get().value(),
             scheduler: self.scheduler.clone_inner(),
+            prefetch: self.sysbus.prefetch.clone(),
+            dma_bus: self.sysbus.dma_bus,
         };
 
         bincode::serialize(&s)
     }
 
-    pub fn restore_state(&mut self, bytes: &[u8]) -> bincode::Result<()> {
+    pub(crate) fn restore_snapshot(&mut self, bytes: &[u8]) -> bincode::Result<()> {
         let decoded: Box<SaveState> = bincode::deserialize_from(bytes)?;
 
         self.cpu.restore_state(decoded.cpu_state);
         self.scheduler = Scheduler::make_shared(decoded.scheduler);
         self.interrupt_flags = Rc::new(Cell::new(IrqBitmask(decoded.interrupt_flags)));
+        // Not part of the emulated state
+        let layer_mask = self.io_devs.gpu.layer_mask;
         self.io_devs = Shared::new(decoded.io_devs);
+        self.io_devs.gpu.layer_mask = layer_mask;
         // Restore memory state
         self.cpu.set_memory_interface(self.sysbus.clone());
         self.sysbus.set_iwram(decoded.iwram);
         self.sysbus.set_ewram(decoded.ewram);
         // Redistribute shared pointers
         self.io_devs.connect_irq(self.interrupt_flags.clone());
-        self.sysbus.connect_scheduler(self.scheduler.clone());
+        // The io devices need the new scheduler too, so they go in first
         self.sysbus.set_io_devices(self.io_devs.clone());
+        self.sysbus.connect_scheduler(self.scheduler.clone());
         self.sysbus.cartridge.update_from(decoded.cartridge);
+        self.sysbus.on_waitcnt_written(self.io_devs.waitcnt);
+        self.sysbus.prefetch = decoded.prefetch;
+        self.sysbus.dma_bus = decoded.dma_bus;
         self.sysbus.init(self.cpu.weak_ptr());
+        self.reconnect_link_cable();
 
         Ok(())
     }
 
+    /// Plug in a link cable, the transport decides whether we are the parent or a child
+    pub fn connect_link_cable(&mut self, transport: DynSioTransport) {
+        self.sio_transport = Some(transport);
+        self.reconnect_link_cable();
+    }
+
+    pub fn disconnect_link_cable(&mut self) -> Option<DynSioTransport> {
+        self.io_devs.sio.disconnect();
+        self.scheduler
+            .cancel_pending(EventType::Sio(SioEvent::Poll));
+        self.sio_transport.take()
+    }
+
+    fn reconnect_link_cable(&mut self) {
+        let poll_event = EventType::Sio(SioEvent::Poll);
+        self.scheduler.cancel_pending(poll_event);
+        match &self.sio_transport {
+            Some(transport) => {
+                self.io_devs.sio.connect(transport.player_id());
+                self.scheduler.schedule((poll_event, 0));
+            }
+            None => self.io_devs.sio.disconnect(),
+        }
+    }
+
     pub fn get_game_title(&self) -> String {
         self.sysbus.cartridge.header.game_title.clone()
     }
@@ -213,18 +339,33 @@ impl GameBoyAdvance {
 
     /// Advance the emulation for one frame worth of time
     pub fn frame(&mut self) {
-        static mut OVERSHOOT: usize = 0;
-        unsafe {
-            OVERSHOOT = CYCLES_FULL_REFRESH - self.run::<false>(CYCLES_FULL_REFRESH - OVERSHOOT);
+        self.cheats.on_frame(&mut self.sysbus);
+        let cycles_to_run = CYCLES_FULL_REFRESH - self.overshoot;
+        self.overshoot = self
+            .run::<false>(cycles_to_run)
+            .saturating_sub(cycles_to_run);
+        self.update_rumble();
+    }
+
+    /// Tell the rumble interface when the game turns the motor on or off
+    fn update_rumble(&mut self) {
+        let rumbling = self.sysbus.cartridge.is_rumbling();
+        if rumbling != self.rumbling {
+            self.rumbling = rumbling;
+            if let Some(rumble_interface) = &mut self.rumble_interface {
+                rumble_interface.set_rumble(rumbling);
+            }
         }
     }
 
     /// like frame() but stop if a breakpoint is reached
     fn frame_interruptible(&mut self) {
-        static mut OVERSHOOT: usize = 0;
-        unsafe {
-            OVERSHOOT = CYCLES_FULL_REFRESH - self.run::<true>(CYCLES_FULL_REFRESH - OVERSHOOT);
-        }
+        self.cheats.on_frame(&mut self.sysbus);
+        let cycles_to_run = CYCLES_FULL_REFRESH - self.overshoot;
+        self.overshoot = self
+            .run::<true>(cycles_to_run)
+            .saturating_sub(cycles_to_run);
+        self.update_rumble();
     }
 
     pub fn start_gdbserver(&mut self, port: u16) {
@@ -252,6 +393,10 @@ impl GameBoyAdvance {
     pub fn debugger_run(&mut self) {
         let debugger = self.debugger.take().expect("debugger should be None here");
         self.debugger = debugger.handle_incoming_requests(self);
+        let keyinput = self.sysbus.io.keyinput;
+        if let Some(debugger) = &mut self.debugger {
+            debugger.history.record_input(keyinput);
+        }
         self.frame_interruptible();
     }
 
@@ -271,7 +416,15 @@ impl GameBoyAdvance {
         if self.io_devs.intc.irq_pending() {
             self.cpu_interrupt();
         }
+        if let Some(hook) = self.cheats.hook() {
+            if self.cpu.get_next_pc() == hook {
+                self.cheats.run(&mut self.sysbus);
+            }
+        }
         self.cpu.step();
+        if let Some(number) = self.cpu.take_pending_swi() {
+            hle::software_interrupt(self, number);
+        }
     }
 
     #[inline]
@@ -305,6 +458,24 @@ impl GameBoyAdvance {
         }
     }
 
+    /// Execute a single step, then handle the scheduler events that became due.
+    /// Every path that moves the emulation forward goes through here, so that replaying the
+    /// execution history takes the exact same steps as the original run.
+    /// @return true if the run limit was reached
+    #[inline]
+    pub(crate) fn step(&mut self) -> bool {
+        self.single_step();
+        // Only go through Scheduler::handle_events when an event is due, most steps have none.
+        // Safety: the gpu always has an event pending
+        if self.scheduler.timestamp()
+            >= unsafe { self.scheduler.timestamp_of_next_event_unchecked() }
+        {
+            self.handle_events()
+        } else {
+            false
+        }
+    }
+
     /// Runs the emulation for a given amount of cycles
     /// @return number of cycle actually ran
     #[inline]
@@ -316,47 +487,70 @@ impl GameBoyAdvance {
         self.scheduler
             .schedule_at(EventType::RunLimitReached, end_time);
 
-        'running: loop {
-            // The tricky part is to avoid unnecessary calls for Scheduler::handle_events,
-            // performance-wise it would be best to run as many cycles as fast as possible while we know there are no pending events.
-            // Safety: Since we pushed a RunLimitReached event, we know this check has a hard limit
-            while self.scheduler.timestamp()
-                <= unsafe { self.scheduler.timestamp_of_next_event_unchecked() }
-            {
-                self.single_step();
-                if CHECK_BREAKPOINTS {
-                    if let Some(bp) = self.cpu.check_breakpoint() {
-                        debug!("Arm7tdmi breakpoint hit 0x{:08x}", bp);
-                        self.scheduler.cancel_pending(EventType::RunLimitReached);
-                        let _ = self.handle_events();
-                        if let Some(debugger) = &mut self.debugger {
-                            debugger.notify_breakpoint(bp);
-                        }
-                        break 'running;
+        loop {
+            let run_limit_reached = self.step();
+            if CHECK_BREAKPOINTS {
+                self.advance_history();
+                if let Some(bp) = self.cpu.check_breakpoint() {
+                    debug!("Arm7tdmi breakpoint hit 0x{:08x}", bp);
+                    self.scheduler.cancel_pending(EventType::RunLimitReached);
+                    if let Some(debugger) = &mut self.debugger {
+                        debugger.notify_breakpoint(bp);
+                    }
+                    break;
+                }
+                if let Some(hit) = self.sysbus.watchpoints.take_hit() {
+                    debug!(
+                        "Watchpoint hit, {} of 0x{:08x} at 0x{:08x}",
+                        hit.access, hit.value, hit.addr
+                    );
+                    self.scheduler.cancel_pending(EventType::RunLimitReached);
+                    if let Some(debugger) = &mut self.debugger {
+                        debugger.notify_watchpoint(hit);
                     }
+                    break;
                 }
             }
-
-            if self.handle_events() {
-                break 'running;
+            if run_limit_reached {
+                break;
             }
         }
 
+        if !CHECK_BREAKPOINTS {
+            // Nobody is going to stop on this one
+            self.sysbus.watchpoints.take_hit();
+        }
+
         self.scheduler.timestamp() - start_time
     }
 
+    /// Count the step for reverse execution, taking a snapshot when it is due
+    #[inline]
+    fn advance_history(&mut self) {
+        let snapshot_due = self
+            .debugger
+            .as_mut()
+            .map_or(false, |debugger| debugger.history.advance());
+        if snapshot_due {
+            let mut debugger = self.debugger.take().unwrap();
+            debugger.history.take_snapshot(self);
+            self.debugger = Some(debugger);
+        }
+    }
+
     /// Handle all pending scheduler events and return if run limit was reached.
     #[inline]
     pub(super) fn handle_events(&mut self) -> bool {
         let io = &mut (*self.io_devs);
+        let mut run_limit_reached = false;
         while let Some((event, event_time)) = self.scheduler.pop_pending_event() {
             // Since we only examine the scheduler queue every so often, most events will be handled late by a few cycles.
             // We sacrifice accuricy in favor of performance, otherwise we would have to check the event queue
             // every cpu cycle, where in 99% of cases it will always be empty.
             let new_event = match event {
                 EventType::RunLimitReached => {
-                    // If we have pending events, we handle by the next frame.
-                    return true;
+                    run_limit_reached = true;
+                    None
                 }
                 EventType::DmaActivateChannel(channel_id) => {
                     io.dmac.activate_channel(channel_id);
@@ -369,14 +563,15 @@ impl GameBoyAdvance {
                     Some(timers.handle_overflow_event(channel_id, event_time, apu, dmac))
                 }
                 EventType::Gpu(gpu_event) => Some(io.gpu.on_event(gpu_event, &mut *self.sysbus)),
-                EventType::Apu(event) => Some(io.sound.on_event(event, &mut self.audio_interface)),
+                EventType::Apu(event) => io.sound.on_event(event, &mut self.audio_interface),
+                EventType::Sio(event) => io.sio.on_event(event, &mut self.sio_transport),
             };
             if let Some((new_event, when)) = new_event {
                 // We schedule events added by event handlers relative to the handled event time
                 self.scheduler.schedule_at(new_event, event_time + when)
             }
         }
-        false
+        run_limit_reached
     }
 
     pub fn skip_bios(&mut self) {
@@ -416,10 +611,147 @@ impl GameBoyAdvance {
         None
     }
 
+    /// Execute one step for the debugger repl, returns the breakpoint it stopped on
+    #[cfg(feature = "debugger")]
+    pub fn step_debugger(&mut self) -> Option<u32> {
+        let _ = self.step();
+        self.check_breakpoint()
+    }
+
     pub fn get_frame_buffer(&self) -> &[u32] {
         self.sysbus.io.gpu.get_frame_buffer()
     }
 
+    /// For the debug viewers in `gpu::viewer`
+    pub fn get_gpu_mut(&mut self) -> &mut Gpu {
+        &mut self.io_devs.gpu
+    }
+
+    /// Read from the memory map without the cpu timing side effects
+    pub fn debug_read_8(&mut self, addr: Addr) -> u8 {
+        self.sysbus.debug_read_8(addr)
+    }
+
+    pub fn debug_read_16(&mut self, addr: Addr) -> u16 {
+        self.sysbus.debug_read_16(addr)
+    }
+
+    pub fn debug_read_32(&mut self, addr: Addr) -> u32 {
+        self.sysbus.debug_read_32(addr)
+    }
+
+    pub fn debug_get_bytes(&mut self, range: std::ops::Range<Addr>) -> Vec<u8> {
+        self.sysbus.debug_get_bytes(range)
+    }
+
+    /// Add a cheat, patching the rom right away if it is enabled
+    pub fn add_cheat(&mut self, cheat: Cheat) -> CheatId {
+        self.cheats.add(cheat, &mut self.sysbus)
+    }
+
+    pub fn remove_cheat(&mut self, id: CheatId) -> Option<Cheat> {
+        self.cheats.remove(id, &mut self.sysbus)
+    }
+
+    /// Returns false if there is no such cheat
+    pub fn set_cheat_enabled(&mut self, id: CheatId, enabled: bool) -> bool {
+        self.cheats.set_enabled(id, enabled, &mut self.sysbus)
+    }
+
+    pub fn cheats(&self) -> &CheatList {
+        &self.cheats
+    }
+
+    /// Add all the cheats of a `.cht` file
+    pub fn load_cheats_file(&mut self, path: &Path) -> CheatResult<Vec<CheatId>> {
+        let cheats = cheats::parse_cht(&fs::read_to_string(path)?)?;
+        Ok(cheats
+            .into_iter()
+            .map(|cheat| self.add_cheat(cheat))
+            .collect())
+    }
+
+    pub fn has_rtc(&self) -> bool {
+        self.sysbus.cartridge.has_rtc()
+    }
+
+    pub fn rtc_clock(&self) -> Option<RtcClock> {
+        self.sysbus.cartridge.rtc_clock()
+    }
+
+    /// Set where the RTC of the cartridge takes the time from
+    pub fn set_rtc_clock(&mut self, clock: RtcClock) {
+        self.sysbus.cartridge.set_rtc_clock(clock);
+    }
+
+    /// Switch an RTC that follows the host clock to the emulated clock, starting from the current
+    /// time. Returns the clock the RTC runs on now.
+    pub(crate) fn make_rtc_deterministic(&mut self) -> Option<RtcClock> {
+        let cycles = self.scheduler.timestamp();
+        let clock = self.rtc_clock()?.to_deterministic(cycles);
+        self.set_rtc_clock(clock);
+        Some(clock)
+    }
+
+    pub fn has_solar_sensor(&self) -> bool {
+        self.sysbus.cartridge.has_solar_sensor()
+    }
+
+    /// Set how bright the light on the solar sensor of the cartridge is, 0 is darkness.
+    /// `SOLAR_LIGHT_LEVELS` has the levels the Boktai sun gauge shows.
+    pub fn set_solar_light(&mut self, light: u8) {
+        self.sysbus.cartridge.set_solar_light(light);
+    }
+
+    pub fn has_gyro(&self) -> bool {
+        self.sysbus.cartridge.has_gyro()
+    }
+
+    /// Set how fast the cartridge turns, positive is clockwise.
+    /// Fits an analog stick axis, the extremes are the fastest the sensor measures.
+    pub fn set_gyro_rotation(&mut self, rotation: i16) {
+        self.sysbus.cartridge.set_gyro_rotation(rotation);
+    }
+
+    pub fn has_rumble(&self) -> bool {
+        self.sysbus.cartridge.has_rumble()
+    }
+
+    /// Connect the host force feedback, it's updated at the end of every frame
+    pub fn connect_rumble(&mut self, rumble_interface: DynRumbleInterface) {
+        self.rumble_interface = Some(rumble_interface);
+        self.rumbling = false;
+    }
+
+    pub fn disconnect_rumble(&mut self) -> Option<DynRumbleInterface> {
+        if self.rumbling {
+            self.rumbling = false;
+            if let Some(rumble_interface) = &mut self.rumble_interface {
+                rumble_interface.set_rumble(false);
+            }
+        }
+        self.rumble_interface.take()
+    }
+
+    pub fn has_tilt(&self) -> bool {
+        self.sysbus.cartridge.has_tilt()
+    }
+
+    /// Set how far the cartridge is tilted to the right (x) and towards the player (y).
+    /// Fits the axes of an analog stick.
+    pub fn set_tilt(&mut self, x: i16, y: i16) {
+        self.sysbus.cartridge.set_tilt(x, y);
+    }
+
+    /// Replace the save of the game, see `SaveFormat` for the supported layouts
+    pub fn import_save(&mut self, bytes: &[u8]) -> SaveConvertResult<()> {
+        self.sysbus.cartridge.import_save(bytes)
+    }
+
+    pub fn export_save(&self, format: SaveFormat) -> SaveConvertResult<Vec<u8>> {
+        self.sysbus.cartridge.export_save(format)
+    }
+
     /// Reset the emulator
     pub fn soft_reset(&mut self) {
         self.cpu.reset();
@@ -430,21 +762,11 @@ impl GameBoyAdvance {
 mod tests {
     use super::*;
 
-    use crate::prelude::*;
-
-    fn make_mock_gba(rom: &[u8]) -> GameBoyAdvance {
-        let bios = vec![0; 0x4000].into_boxed_slice();
-        let cartridge = GamepakBuilder::new()
-            .buffer(rom)
-            .with_sram()
-            .without_backup_to_file()
-            .build()
-            .unwrap();
-        let mut gba = GameBoyAdvance::new(bios, cartridge, NullAudio::new());
-        gba.skip_bios();
+    use std::cell::RefCell;
 
-        gba
-    }
+    use crate::cartridge::RumbleInterface;
+    use crate::prelude::*;
+    use crate::test_utils::{make_mock_gba, make_mock_gba_with, make_rom};
 
     #[test]
     fn test_arm7tdmi_arm_eggvance() {
@@ -471,4 +793,109 @@ mod tests {
         assert_eq!(insn, 0xe7fe); // loop
         assert_eq!(0, gba.cpu.gpr[7]);
     }
+
+    #[test]
+    fn test_debug_write() {
+        use arm7tdmi::memory::DebugWrite;
+
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        let sysbus = &mut gba.sysbus;
+
+        sysbus.debug_write_32(0x0800_0100, 0xeafffffe);
+        assert_eq!(sysbus.debug_read_32(0x0800_0100), 0xeafffffe);
+        sysbus.debug_write_16(0x0300_0000, 0x1234);
+        assert_eq!(sysbus.debug_read_16(0x0300_0000), 0x1234);
+        sysbus.debug_write_8(0x0700_0001, 0x56);
+        assert_eq!(sysbus.debug_read_8(0x0700_0001), 0x56);
+        sysbus.debug_write_8(0x0e00_0010, 0x78);
+        assert_eq!(sysbus.debug_read_8(0x0e00_0010), 0x78);
+
+        sysbus.debug_write_16(0x0400_0000, 0x0403);
+        assert_eq!(sysbus.debug_read_16(0x0400_0000), 0x0403);
+        // IF is set instead of acknowledged
+        sysbus.debug_write_16(0x0400_0202, 0x0001);
+        assert_eq!(sysbus.debug_read_16(0x0400_0202), 0x0001);
+        // enabling a DMA channel has side effects
+        sysbus.debug_write_16(0x0400_00de, 0x8000);
+        assert_eq!(sysbus.debug_read_16(0x0400_00de), 0);
+    }
+
+    #[test]
+    fn test_watchpoint_stops_run() {
+        use crate::watchpoint::{WatchKind, Watchpoint};
+
+        let code: [u32; 4] = [
+            0xe3a00403, // mov r0, #0x03000000
+            0xe3a01001, // mov r1, #1
+            0xe5801010, // str r1, [r0, #0x10]
+            0xeafffffe, // b .
+        ];
+        let mut gba = make_mock_gba(&make_rom(&code));
+        gba.sysbus.watchpoints.add(Watchpoint {
+            addr: 0x0300_0010,
+            len: 4,
+            kind: WatchKind::Write,
+        });
+
+        gba.frame_interruptible();
+        assert!(gba.scheduler.timestamp() < CYCLES_FULL_REFRESH);
+        assert_eq!(gba.cpu.gpr[1], 1);
+        assert_eq!(gba.debug_read_32(0x0300_0010), 1);
+    }
+
+    #[test]
+    fn test_read_watchpoint_ignores_fetches() {
+        use crate::watchpoint::{WatchKind, Watchpoint};
+
+        let mut gba = make_mock_gba(&make_rom(&[0xeafffffe])); // b .
+        gba.sysbus.watchpoints.add(Watchpoint {
+            addr: 0x0800_0000,
+            len: 4,
+            kind: WatchKind::Read,
+        });
+
+        gba.frame_interruptible();
+        assert!(gba.scheduler.timestamp() >= CYCLES_FULL_REFRESH);
+    }
+
+    struct RumbleRecorder(Rc<RefCell<Vec<bool>>>);
+
+    impl RumbleInterface for RumbleRecorder {
+        fn set_rumble(&mut self, on: bool) {
+            self.0.borrow_mut().push(on);
+        }
+    }
+
+    #[test]
+    fn test_rumble() {
+        let rom = make_rom(&[0xeafffffe]); // b .
+        let mut gba = make_mock_gba_with(GamepakBuilder::new().buffer(&rom).with_rumble());
+        let events = Rc::new(RefCell::new(Vec::new()));
+        gba.connect_rumble(Box::new(RumbleRecorder(events.clone())));
+
+        let gpio = 0x0800_0000;
+        gba.sysbus
+            .write_16(gpio + cartridge::GPIO_PORT_DIRECTION, 0b1000);
+        gba.sysbus
+            .write_16(gpio + cartridge::GPIO_PORT_DATA, 0b1000);
+        gba.frame();
+        gba.frame();
+        assert_eq!(*events.borrow(), vec![true]);
+
+        gba.sysbus.write_16(gpio + cartridge::GPIO_PORT_DATA, 0);
+        gba.frame();
+        assert_eq!(*events.borrow(), vec![true, false]);
+
+        // Turned off when the host disconnects while the motor runs
+        gba.sysbus
+            .write_16(gpio + cartridge::GPIO_PORT_DATA, 0b1000);
+        gba.frame();
+        assert!(gba.disconnect_rumble().is_some());
+        assert_eq!(*events.borrow(), vec![true, false, true, false]);
+
+        // Frames run under the debugger too
+        gba.connect_rumble(Box::new(RumbleRecorder(events.clone())));
+        gba.frame_interruptible();
+        assert_eq!(*events.borrow(), vec![true, false, true, false, true]);
+    }
 }
@@ -5,19 +5,24 @@ type SendSync<T> = Arc<Mutex<T>>;
 
 use arm7tdmi::gdbstub::common::Signal;
 use arm7tdmi::gdbstub::stub::{DisconnectReason, SingleThreadStopReason};
+use arm7tdmi::gdbstub::target::ext::base::reverse_exec::ReplayLogPosition;
+use arm7tdmi::gdbstub::target::ext::breakpoints::WatchKind as GdbWatchKind;
 use arm7tdmi::gdbstub::target::TargetError;
 use arm7tdmi::gdbstub::target::{ext::base::singlethread::SingleThreadBase, Target};
 use arm7tdmi::gdbstub_arch::arm::reg::ArmCoreRegs;
-use arm7tdmi::memory::Addr;
+use arm7tdmi::memory::{Addr, BusIO};
 use crossbeam::channel::Receiver;
 
 // mod target;
 mod event_loop;
 pub(crate) mod gdb_thread;
 mod memory_map;
+pub(crate) mod reverse;
 mod target;
+use reverse::{ExecutionHistory, ReverseStop};
 use target::DebuggerTarget;
 
+use crate::watchpoint::{WatchKind, Watchpoint, WatchpointHit};
 use crate::GameBoyAdvance;
 
 #[derive(Debug)]
@@ -25,13 +30,18 @@ pub(crate) enum DebuggerRequest {
     ReadRegs(SendSync<ArmCoreRegs>),
     WriteRegs(ArmCoreRegs),
     ReadAddrs(Addr, SendSync<Box<[u8]>>),
-    #[allow(unused)]
     WriteAddrs(Addr, Box<[u8]>),
+    /// A regular cpu store, with all the side effects a debugger write avoids
+    BusWrite16(Addr, u16),
     AddSwBreakpoint(Addr),
     DelSwBreakpoint(Addr),
+    AddWatchpoint(Watchpoint),
+    DelWatchpoint(Watchpoint),
     Interrupt,
     Resume,
     SingleStep,
+    ReverseStep,
+    ReverseContinue,
     Reset,
     Disconnected(DisconnectReason),
 }
@@ -42,6 +52,22 @@ pub(crate) struct DebuggerRequestHandler {
     stop_signal: Arc<(Mutex<Option<SingleThreadStopReason<u32>>>, Condvar)>,
     thread: JoinHandle<()>,
     pub(crate) stopped: bool,
+    pub(crate) history: ExecutionHistory,
+}
+
+fn watchpoint_stop_reason(hit: WatchpointHit) -> SingleThreadStopReason<u32> {
+    let kind = match hit.watchpoint.kind {
+        WatchKind::Write => GdbWatchKind::Write,
+        WatchKind::Read => GdbWatchKind::Read,
+        WatchKind::ReadWrite => GdbWatchKind::ReadWrite,
+    };
+    // gdb expects an address inside the range it asked to watch
+    let addr = hit.addr.max(hit.watchpoint.addr);
+    SingleThreadStopReason::Watch {
+        tid: (),
+        kind,
+        addr,
+    }
 }
 
 impl DebuggerRequestHandler {
@@ -93,6 +119,8 @@ impl DebuggerRequestHandler {
             WriteRegs(regs) => {
                 trace!("Debugger requested to write regs: {:?}", regs);
                 gba.cpu.write_registers(regs)?;
+                // Writes from the debugger can't be replayed, start the history over
+                self.history = ExecutionHistory::new(gba);
                 self.complete_request(None)
             }
             ReadAddrs(addr, data) => {
@@ -112,6 +140,17 @@ impl DebuggerRequestHandler {
                     addr
                 );
                 gba.cpu.write_addrs(*addr, &data)?;
+                self.history = ExecutionHistory::new(gba);
+                self.complete_request(None)
+            }
+            BusWrite16(addr, value) => {
+                trace!(
+                    "Debugger requested a bus write of 0x{:04x} to 0x{:08x}",
+                    value,
+                    addr
+                );
+                gba.sysbus.write_16(*addr & !1, *value);
+                self.history = ExecutionHistory::new(gba);
                 self.complete_request(None)
             }
             Interrupt => {
@@ -128,15 +167,47 @@ impl DebuggerRequestHandler {
                 debug!("Sending reset interrupt to gba");
                 self.stopped = true;
                 gba.cpu.reset();
+                self.history = ExecutionHistory::new(gba);
                 self.complete_request(Some(SingleThreadStopReason::Signal(Signal::SIGTRAP)))
             }
             SingleStep => {
                 debug!("Debugger requested single step");
                 self.stopped = true;
-                gba.single_step();
-                let _ = gba.handle_events();
+                let _ = gba.step();
+                if self.history.advance() {
+                    self.history.take_snapshot(gba);
+                }
                 self.complete_request(Some(SingleThreadStopReason::DoneStep))
             }
+            ReverseStep => {
+                debug!("Debugger requested reverse step");
+                self.stopped = true;
+                let stop_reason = if self.history.reverse_step(gba) {
+                    SingleThreadStopReason::DoneStep
+                } else {
+                    SingleThreadStopReason::ReplayLog {
+                        tid: None,
+                        pos: ReplayLogPosition::Begin,
+                    }
+                };
+                self.complete_request(Some(stop_reason))
+            }
+            ReverseContinue => {
+                debug!("Debugger requested reverse continue");
+                self.stopped = true;
+                let stop_reason = match self.history.reverse_continue(gba) {
+                    ReverseStop::Breakpoint(bp) => {
+                        debug!("Went back to breakpoint 0x{:08x}", bp);
+                        SingleThreadStopReason::SwBreak(())
+                    }
+                    ReverseStop::Watchpoint(hit) => watchpoint_stop_reason(hit),
+                    ReverseStop::Begin => SingleThreadStopReason::ReplayLog {
+                        tid: None,
+                        pos: ReplayLogPosition::Begin,
+                    },
+                };
+                self.complete_request(Some(stop_reason))
+            }
             AddSwBreakpoint(addr) => {
                 gba.cpu.add_breakpoint(*addr);
                 self.complete_request(None)
@@ -145,6 +216,16 @@ impl DebuggerRequestHandler {
                 gba.cpu.del_breakpoint(*addr);
                 self.complete_request(None)
             }
+            AddWatchpoint(watchpoint) => {
+                debug!("Adding watchpoint {:x?}", watchpoint);
+                gba.sysbus.watchpoints.add(*watchpoint);
+                self.complete_request(None)
+            }
+            DelWatchpoint(watchpoint) => {
+                debug!("Deleting watchpoint {:x?}", watchpoint);
+                gba.sysbus.watchpoints.remove(*watchpoint);
+                self.complete_request(None)
+            }
             Disconnected(reason) => Ok(Some(*reason)),
         }
     }
@@ -189,4 +270,9 @@ impl DebuggerRequestHandler {
         self.stopped = true;
         self.notify_stop_reason(SingleThreadStopReason::SwBreak(()));
     }
+
+    pub fn notify_watchpoint(&mut self, hit: WatchpointHit) {
+        self.stopped = true;
+        self.notify_stop_reason(watchpoint_stop_reason(hit));
+    }
 }
@@ -10,6 +10,7 @@ use arm7tdmi::{
 
 use crate::{GBAError, GameBoyAdvance};
 
+use super::reverse::ExecutionHistory;
 use super::target::DebuggerTarget;
 use super::{event_loop::DebuggerEventLoop, DebuggerRequestHandler};
 
@@ -46,6 +47,7 @@ pub(crate) fn start_gdb_server_thread(
         stop_signal,
         thread,
         stopped: true,
+        history: ExecutionHistory::new(gba),
     };
     Ok(debugger)
 }
@@ -0,0 +1,305 @@
+//! Reverse execution for the gdb stub.
+//!
+//! While gdb is attached, every step is counted, a snapshot is taken every `SNAPSHOT_INTERVAL`
+//! steps and every change of the keys is logged. Going back to an earlier step restores the
+//! closest snapshot before it and re-executes from there, which is deterministic since the keys
+//! are the only input coming from outside.
+use std::collections::VecDeque;
+
+use arm7tdmi::memory::Addr;
+
+use crate::sched::EventType;
+use crate::watchpoint::WatchpointHit;
+use crate::GameBoyAdvance;
+
+/// Steps between snapshots, this bounds the re-execution needed for a single reverse step
+const SNAPSHOT_INTERVAL: u64 = 1 << 18;
+/// Together with the interval, this is how far back we can go (about 16M steps)
+const MAX_SNAPSHOTS: usize = 64;
+
+#[derive(Debug)]
+pub(crate) enum ReverseStop {
+    Breakpoint(Addr),
+    Watchpoint(WatchpointHit),
+    /// Reached the oldest point in the history
+    Begin,
+}
+
+pub(crate) struct ExecutionHistory {
+    /// Number of `GameBoyAdvance::step` calls since the history started
+    step: u64,
+    snapshots: VecDeque<(u64, Vec<u8>)>,
+    /// KEYINPUT changes, sorted by step. Each one applies to the steps after the logged one.
+    inputs: Vec<(u64, u16)>,
+}
+
+impl ExecutionHistory {
+    pub fn new(gba: &GameBoyAdvance) -> ExecutionHistory {
+        let mut history = ExecutionHistory {
+            step: 0,
+            snapshots: VecDeque::with_capacity(MAX_SNAPSHOTS),
+            inputs: vec![(0, gba.sysbus.io.keyinput)],
+        };
+        history.take_snapshot(gba);
+        history
+    }
+
+    /// Call after every step, returns true when `take_snapshot` should be called
+    #[inline]
+    pub fn advance(&mut self) -> bool {
+        self.step += 1;
+        self.step % SNAPSHOT_INTERVAL == 0
+    }
+
+    pub fn take_snapshot(&mut self, gba: &GameBoyAdvance) {
+        let snapshot = match gba.save_snapshot() {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                warn!("failed to take a snapshot for reverse execution: {}", e);
+                return;
+            }
+        };
+        if self.snapshots.len() == MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+            // Only the input in effect at the oldest snapshot is still needed
+            let oldest = self.snapshots[0].0;
+            let first = self
+                .inputs
+                .iter()
+                .rposition(|&(step, _)| step <= oldest)
+                .unwrap_or(0);
+            self.inputs.drain(..first);
+        }
+        self.snapshots.push_back((self.step, snapshot));
+    }
+
+    /// Call whenever the frontend may have changed the keys
+    pub fn record_input(&mut self, keyinput: u16) {
+        match self.inputs.last_mut() {
+            Some((_, last)) if *last == keyinput => {}
+            Some((step, last)) if *step == self.step => *last = keyinput,
+            _ => self.inputs.push((self.step, keyinput)),
+        }
+    }
+
+    fn input_at(&self, step: u64) -> Option<u16> {
+        self.inputs
+            .iter()
+            .rev()
+            .find(|&&(s, _)| s <= step)
+            .map(|&(_, keyinput)| keyinput)
+    }
+
+    fn oldest_step(&self) -> u64 {
+        self.snapshots.front().map_or(self.step, |&(step, _)| step)
+    }
+
+    fn restore(&mut self, gba: &mut GameBoyAdvance, index: usize) {
+        let (step, snapshot) = &self.snapshots[index];
+        gba.restore_snapshot(snapshot)
+            .expect("snapshots in the history are always valid");
+        // Left over from the run that was going on when the snapshot was taken
+        gba.scheduler.cancel_pending(EventType::RunLimitReached);
+        gba.sysbus.watchpoints.take_hit();
+        self.step = *step;
+        if let Some(keyinput) = self.input_at(self.step) {
+            gba.sysbus.io.keyinput = keyinput;
+        }
+    }
+
+    /// Re-execute up to `target`, calling `on_stop` for every step the debugger would have
+    /// stopped on
+    fn replay_until<F>(&mut self, gba: &mut GameBoyAdvance, target: u64, mut on_stop: F)
+    where
+        F: FnMut(u64, ReverseStop),
+    {
+        while self.step < target {
+            let _ = gba.step();
+            self.step += 1;
+            if let Ok(i) = self
+                .inputs
+                .binary_search_by_key(&self.step, |&(step, _)| step)
+            {
+                gba.sysbus.io.keyinput = self.inputs[i].1;
+            }
+            if let Some(bp) = gba.cpu.check_breakpoint() {
+                on_stop(self.step, ReverseStop::Breakpoint(bp));
+            }
+            if let Some(hit) = gba.sysbus.watchpoints.take_hit() {
+                on_stop(self.step, ReverseStop::Watchpoint(hit));
+            }
+        }
+    }
+
+    /// Drop everything after the current step, execution may take another path from here
+    fn truncate(&mut self) {
+        let step = self.step;
+        while self.snapshots.len() > 1 && self.snapshots.back().unwrap().0 > step {
+            self.snapshots.pop_back();
+        }
+        self.inputs.retain(|&(s, _)| s <= step);
+    }
+
+    /// Go back one step, returns false if the history doesn't go back any further
+    pub fn reverse_step(&mut self, gba: &mut GameBoyAdvance) -> bool {
+        if self.step <= self.oldest_step() {
+            return false;
+        }
+        let target = self.step - 1;
+        let index = self
+            .snapshots
+            .iter()
+            .rposition(|&(step, _)| step <= target)
+            .unwrap();
+        self.restore(gba, index);
+        self.replay_until(gba, target, |_, _| {});
+        self.truncate();
+        true
+    }
+
+    /// Go back to the last step that hit a breakpoint or a watchpoint, or to the oldest point in
+    /// the history if there is none
+    pub fn reverse_continue(&mut self, gba: &mut GameBoyAdvance) -> ReverseStop {
+        // Scan the history one snapshot at a time, newest first.
+        // Each pass checks the steps in (start, end).
+        let mut end = self.step;
+        for index in (0..self.snapshots.len()).rev() {
+            let start = self.snapshots[index].0;
+            if start + 1 >= end {
+                end = end.min(start + 1);
+                continue;
+            }
+            self.restore(gba, index);
+            let mut last_stop = None;
+            self.replay_until(gba, end - 1, |step, _| last_stop = Some(step));
+            if let Some(target) = last_stop {
+                let mut stop = ReverseStop::Begin;
+                self.restore(gba, index);
+                self.replay_until(gba, target, |step, reason| {
+                    if step == target {
+                        stop = reason;
+                    }
+                });
+                self.truncate();
+                return stop;
+            }
+            end = start + 1;
+        }
+        if !self.snapshots.is_empty() {
+            self.restore(gba, 0);
+            self.truncate();
+        }
+        ReverseStop::Begin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::{make_hle_gba, make_mock_gba, make_rom};
+
+    /// Counts up in r0 forever
+    fn make_counter_rom() -> Vec<u8> {
+        let code: [u32; 2] = [
+            0xe2800001, // loop: add r0, r0, #1
+            0xeafffffd, // b loop
+        ];
+        let mut rom = vec![0; 0x200];
+        rom[0..4].copy_from_slice(&0xea00_002e_u32.to_le_bytes()); // b 0xc0
+        for (i, instr) in code.iter().enumerate() {
+            rom[0xc0 + i * 4..0xc4 + i * 4].copy_from_slice(&instr.to_le_bytes());
+        }
+        rom
+    }
+
+    fn step(gba: &mut GameBoyAdvance, history: &mut ExecutionHistory, count: u64) {
+        for _ in 0..count {
+            let _ = gba.step();
+            if history.advance() {
+                history.take_snapshot(gba);
+            }
+        }
+    }
+
+    #[test]
+    fn test_reverse_step() {
+        let mut gba = make_mock_gba(&make_counter_rom());
+        let mut history = ExecutionHistory::new(&gba);
+        step(&mut gba, &mut history, SNAPSHOT_INTERVAL + 10);
+        let r0 = gba.cpu.get_reg(0);
+
+        assert!(history.reverse_step(&mut gba));
+        assert!(history.reverse_step(&mut gba));
+        assert_eq!(history.step, SNAPSHOT_INTERVAL + 8);
+        // every other instruction increments r0
+        assert_eq!(gba.cpu.get_reg(0), r0 - 1);
+
+        let mut gba = make_mock_gba(&make_counter_rom());
+        let mut history = ExecutionHistory::new(&gba);
+        assert!(!history.reverse_step(&mut gba));
+    }
+
+    #[test]
+    fn test_reverse_continue() {
+        let mut gba = make_mock_gba(&make_counter_rom());
+        let mut history = ExecutionHistory::new(&gba);
+        step(&mut gba, &mut history, 1000);
+        assert!(matches!(
+            history.reverse_continue(&mut gba),
+            ReverseStop::Begin
+        ));
+        assert_eq!(history.step, 0);
+
+        step(&mut gba, &mut history, 1000);
+        gba.cpu.add_breakpoint(0x0800_00c4);
+        let stop = history.reverse_continue(&mut gba);
+        assert!(matches!(stop, ReverseStop::Breakpoint(0x0800_00c4)));
+        assert_eq!(history.step, 998);
+        assert_eq!(gba.cpu.get_next_pc(), 0x0800_00c4);
+
+        // and once more to the hit before that
+        history.reverse_continue(&mut gba);
+        assert_eq!(history.step, 996);
+    }
+
+    #[test]
+    fn test_reverse_step_over_irq() {
+        // Counts timer 0 overflow interrupts in r6
+        #[rustfmt::skip]
+        let code = [
+            0xe3a00301, // mov r0, #0x04000000
+            0xe3a01302, // mov r1, #0x08000000
+            0xe2811040, // add r1, r1, #0x40     ; handler
+            0xe5001004, // str r1, [r0, #-4]     ; irq handler pointer
+            0xe2804c01, // add r4, r0, #0x100
+            0xe3a02cff, // mov r2, #0xff00
+            0xe1c420b0, // strh r2, [r4]         ; TM0CNT_L, reload 0xff00
+            0xe3a020c0, // mov r2, #0xc0
+            0xe1c420b2, // strh r2, [r4, #2]     ; TM0CNT_H, start with irq
+            0xe2804c02, // add r4, r0, #0x200
+            0xe3a03008, // mov r3, #8
+            0xe1c430b0, // strh r3, [r4]         ; IE timer 0
+            0xe3a03001, // mov r3, #1
+            0xe5843008, // str r3, [r4, #8]      ; IME
+            0xe2855001, // loop: add r5, r5, #1
+            0xeafffffd, // b loop
+            0xe3a01008, // handler: mov r1, #8
+            0xe2802c02, // add r2, r0, #0x200
+            0xe1c210b2, // strh r1, [r2, #2]     ; acknowledge IF
+            0xe2866001, // add r6, r6, #1
+            0xe12fff1e, // bx lr
+        ];
+        // The irq vector is in the bios
+        let mut gba = make_hle_gba(&make_rom(&code));
+        let mut history = ExecutionHistory::new(&gba);
+        for _ in 0..300 {
+            let expected = gba.save_snapshot().unwrap();
+            step(&mut gba, &mut history, 1);
+            assert!(history.reverse_step(&mut gba));
+            assert_eq!(gba.save_snapshot().unwrap(), expected);
+            step(&mut gba, &mut history, 1);
+        }
+        assert!(gba.cpu.get_reg(6) > 0);
+    }
+}
@@ -6,17 +6,21 @@ use arm7tdmi::gdb::{copy_range_to_buf, gdbstub, gdbstub_arch};
 use crossbeam::channel::Sender;
 use gdbstub::common::Signal;
 use gdbstub::stub::{DisconnectReason, SingleThreadStopReason};
+use gdbstub::target::ext::base::reverse_exec::{
+    ReverseCont, ReverseContOps, ReverseStep, ReverseStepOps,
+};
 use gdbstub::target::ext::base::singlethread::{
     SingleThreadBase, SingleThreadResume, SingleThreadSingleStep,
 };
 use gdbstub::target::ext::base::singlethread::{SingleThreadResumeOps, SingleThreadSingleStepOps};
 use gdbstub::target::ext::base::BaseOps;
-use gdbstub::target::ext::breakpoints::BreakpointsOps;
+use gdbstub::target::ext::breakpoints::{BreakpointsOps, WatchKind as GdbWatchKind};
 use gdbstub::target::ext::monitor_cmd::{outputln, ConsoleOutput};
-use gdbstub::target::{self, Target, TargetError, TargetResult};
+use gdbstub::target::{self, Target, TargetResult};
 use gdbstub_arch::arm::reg::ArmCoreRegs;
 
 use super::DebuggerRequest;
+use crate::watchpoint::{WatchKind, Watchpoint};
 
 pub(crate) struct DebuggerTarget {
     tx: Sender<DebuggerRequest>,
@@ -130,9 +134,9 @@ impl SingleThreadBase for DebuggerTarget {
         Ok(())
     }
 
-    fn write_addrs(&mut self, _start_addr: u32, _data: &[u8]) -> TargetResult<(), Self> {
-        // todo!("implement DebugWrite bus extention")
-        Err(TargetError::NonFatal)
+    fn write_addrs(&mut self, start_addr: u32, data: &[u8]) -> TargetResult<(), Self> {
+        self.debugger_request(DebuggerRequest::WriteAddrs(start_addr, data.into()));
+        Ok(())
     }
 
     // most targets will want to support at resumption as well...
@@ -156,6 +160,16 @@ impl SingleThreadResume for DebuggerTarget {
     fn support_single_step(&mut self) -> Option<SingleThreadSingleStepOps<'_, Self>> {
         Some(self)
     }
+
+    #[inline(always)]
+    fn support_reverse_step(&mut self) -> Option<ReverseStepOps<'_, (), Self>> {
+        Some(self)
+    }
+
+    #[inline(always)]
+    fn support_reverse_cont(&mut self) -> Option<ReverseContOps<'_, (), Self>> {
+        Some(self)
+    }
 }
 
 impl SingleThreadSingleStep for DebuggerTarget {
@@ -165,6 +179,20 @@ impl SingleThreadSingleStep for DebuggerTarget {
     }
 }
 
+impl ReverseStep<()> for DebuggerTarget {
+    fn reverse_step(&mut self, _tid: ()) -> Result<(), Self::Error> {
+        self.debugger_request(DebuggerRequest::ReverseStep);
+        Ok(())
+    }
+}
+
+impl ReverseCont<()> for DebuggerTarget {
+    fn reverse_cont(&mut self) -> Result<(), Self::Error> {
+        self.debugger_request(DebuggerRequest::ReverseContinue);
+        Ok(())
+    }
+}
+
 impl target::ext::memory_map::MemoryMap for DebuggerTarget {
     fn memory_map_xml(
         &self,
@@ -189,6 +217,13 @@ impl target::ext::breakpoints::Breakpoints for DebuggerTarget {
     ) -> Option<target::ext::breakpoints::SwBreakpointOps<'_, Self>> {
         Some(self)
     }
+
+    #[inline(always)]
+    fn support_hw_watchpoint(
+        &mut self,
+    ) -> Option<target::ext::breakpoints::HwWatchpointOps<'_, Self>> {
+        Some(self)
+    }
 }
 
 impl target::ext::breakpoints::SwBreakpoint for DebuggerTarget {
@@ -211,6 +246,46 @@ impl target::ext::breakpoints::SwBreakpoint for DebuggerTarget {
     }
 }
 
+fn make_watchpoint(addr: u32, len: u32, kind: GdbWatchKind) -> Watchpoint {
+    let kind = match kind {
+        GdbWatchKind::Write => WatchKind::Write,
+        GdbWatchKind::Read => WatchKind::Read,
+        GdbWatchKind::ReadWrite => WatchKind::ReadWrite,
+    };
+    Watchpoint { addr, len, kind }
+}
+
+impl target::ext::breakpoints::HwWatchpoint for DebuggerTarget {
+    fn add_hw_watchpoint(
+        &mut self,
+        addr: u32,
+        len: u32,
+        kind: GdbWatchKind,
+    ) -> TargetResult<bool, Self> {
+        let watchpoint = make_watchpoint(addr, len, kind);
+        self.debugger_request(DebuggerRequest::AddWatchpoint(watchpoint));
+        Ok(true)
+    }
+
+    fn remove_hw_watchpoint(
+        &mut self,
+        addr: u32,
+        len: u32,
+        kind: GdbWatchKind,
+    ) -> TargetResult<bool, Self> {
+        let watchpoint = make_watchpoint(addr, len, kind);
+        self.debugger_request(DebuggerRequest::DelWatchpoint(watchpoint));
+        Ok(true)
+    }
+}
+
+fn parse_u32(s: &str) -> Option<u32> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
 impl target::ext::monitor_cmd::MonitorCmd for DebuggerTarget {
     fn handle_monitor_cmd(
         &mut self,
@@ -225,11 +300,23 @@ impl target::ext::monitor_cmd::MonitorCmd for DebuggerTarget {
             }
         };
 
-        match cmd {
+        let mut args = cmd.split_whitespace();
+        match args.next().unwrap_or_default() {
             "reset" => {
                 self.debugger_request(DebuggerRequest::Reset);
                 outputln!(out, "sent reset signal");
             }
+            // gdb memory writes skip the side effects of io registers, this one doesn't
+            "write16" => match (
+                args.next().and_then(parse_u32),
+                args.next().and_then(parse_u32),
+            ) {
+                (Some(addr), Some(value)) if value <= 0xffff => {
+                    self.debugger_request(DebuggerRequest::BusWrite16(addr, value as u16));
+                    outputln!(out, "wrote 0x{:04x} to 0x{:08x}", value, addr);
+                }
+                _ => outputln!(out, "usage: write16 <addr> <value>"),
+            },
             unk => {
                 outputln!(out, "unknown command: {}", unk);
             }
@@ -2,6 +2,37 @@ use num::FromPrimitive;
 
 use super::*;
 
+bitflags! {
+    /// What the renderer is allowed to draw, for debugging. This is not part of the emulated
+    /// state, hidden layers are skipped on top of what DISPCNT and the windows select.
+    pub struct LayerMask: u8 {
+        const BG0 = 0b0000_0001;
+        const BG1 = 0b0000_0010;
+        const BG2 = 0b0000_0100;
+        const BG3 = 0b0000_1000;
+        const OBJ = 0b0001_0000;
+        /// When hidden, the backdrop is drawn black
+        const BACKDROP = 0b0010_0000;
+        /// When hidden, every pixel is drawn as if there were no windows
+        const WINDOWS = 0b0100_0000;
+        /// When hidden, alpha blending and brightness effects are skipped
+        const BLENDING = 0b1000_0000;
+    }
+}
+
+impl LayerMask {
+    #[inline]
+    pub fn from_bg(bg: usize) -> LayerMask {
+        LayerMask::from_bits_truncate(1 << bg)
+    }
+}
+
+impl Default for LayerMask {
+    fn default() -> LayerMask {
+        LayerMask::all()
+    }
+}
+
 #[derive(Primitive, Debug, Ord, Eq, PartialOrd, PartialEq, Clone, Copy)]
 pub enum RenderLayerKind {
     Backdrop = 0b00100000,
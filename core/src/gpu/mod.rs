@@ -1,7 +1,7 @@
 use num::FromPrimitive;
 use serde::{Deserialize, Serialize};
 
-use arm7tdmi::memory::{Addr, BusIO, DebugRead};
+use arm7tdmi::memory::{Addr, BusIO, DebugRead, DebugWrite};
 use rustboyadvance_utils::index2d;
 
 use super::dma::{DmaNotifer, TIMING_HBLANK, TIMING_VBLANK};
@@ -11,6 +11,7 @@ pub use super::sysbus::consts::*;
 
 mod render;
 
+pub use render::obj::{ObjMode, ObjType};
 use render::Point;
 
 mod layer;
@@ -19,12 +20,15 @@ mod rgb15;
 mod sfx;
 mod window;
 
+pub use layer::LayerMask;
 pub use rgb15::Rgb15;
 pub use window::*;
 
 pub mod regs;
 pub use regs::*;
 
+pub mod viewer;
+
 #[cfg(feature = "debugger")]
 use std::fmt;
 
@@ -131,6 +135,9 @@ pub struct Gpu {
     pub(super) obj_buffer: Box<[ObjBufferEntry]>,
     pub(super) frame_buffer: Box<[u32]>,
     pub(super) bg_line: [Box<[Rgb15]>; 4],
+    /// Debug option, see `LayerMask`
+    #[serde(skip)]
+    pub layer_mask: LayerMask,
 }
 
 impl InterruptConnect for Gpu {
@@ -180,6 +187,7 @@ impl Gpu {
                 alloc_scanline_buffer(),
             ],
             vram_obj_tiles_start: VRAM_OBJ_TILES_START_TEXT,
+            layer_mask: LayerMask::all(),
         }
     }
 
@@ -336,6 +344,49 @@ impl Gpu {
         &self.frame_buffer
     }
 
+    /// Render a whole frame from the current vram and registers, with only the layers in `mask`
+    /// visible. The emulated state and the frame buffer are left untouched, and raster effects
+    /// done during the frame are not reproduced.
+    pub fn render_frame_with_layers(&mut self, mask: LayerMask) -> Box<[u32]> {
+        let saved_vcount = self.vcount;
+        let saved_bg_aff = self.bg_aff;
+        let saved_layer_mask = self.layer_mask;
+        let saved_obj_buffer = self.obj_buffer.clone();
+        let saved_frame_buffer = self.frame_buffer.clone();
+
+        self.layer_mask = mask;
+        self.latch_bg_ref_points();
+        self.obj_buffer_reset();
+        for line in 0..DISPLAY_HEIGHT {
+            self.vcount = line;
+            self.render_scanline();
+            self.advance_bg_ref_points();
+        }
+        let frame = std::mem::replace(&mut self.frame_buffer, saved_frame_buffer);
+
+        self.vcount = saved_vcount;
+        self.bg_aff = saved_bg_aff;
+        self.layer_mask = saved_layer_mask;
+        self.obj_buffer = saved_obj_buffer;
+        frame
+    }
+
+    /// Update BG2/3 reference points on the end of a scanline
+    fn advance_bg_ref_points(&mut self) {
+        for i in 0..2 {
+            self.bg_aff[i].internal_x += self.bg_aff[i].pb as i16 as i32;
+            self.bg_aff[i].internal_y += self.bg_aff[i].pd as i16 as i32;
+        }
+    }
+
+    /// Latch BG2/3 reference points on vblank
+    fn latch_bg_ref_points(&mut self) {
+        for i in 0..2 {
+            self.bg_aff[i].internal_x = self.bg_aff[i].x;
+            self.bg_aff[i].internal_y = self.bg_aff[i].y;
+        }
+    }
+
     #[inline]
     fn update_vcount(&mut self, value: usize) {
         self.vcount = value;
@@ -365,19 +416,11 @@ impl Gpu {
         if self.vcount < DISPLAY_HEIGHT {
             self.dispstat.hblank_flag = false;
             self.render_scanline();
-            // update BG2/3 reference points on the end of a scanline
-            for i in 0..2 {
-                self.bg_aff[i].internal_x += self.bg_aff[i].pb as i16 as i32;
-                self.bg_aff[i].internal_y += self.bg_aff[i].pd as i16 as i32;
-            }
+            self.advance_bg_ref_points();
 
             (GpuEvent::HDraw, CYCLES_HDRAW)
         } else {
-            // latch BG2/3 reference points on vblank
-            for i in 0..2 {
-                self.bg_aff[i].internal_x = self.bg_aff[i].x;
-                self.bg_aff[i].internal_y = self.bg_aff[i].y;
-            }
+            self.latch_bg_ref_points();
 
             self.dispstat.vblank_flag = true;
             self.dispstat.hblank_flag = false;
@@ -498,6 +541,20 @@ impl DebugRead for Gpu {
     }
 }
 
+impl DebugWrite for Gpu {
+    fn debug_write_8(&mut self, addr: Addr, value: u8) {
+        let page = (addr >> 24) as usize;
+        match page {
+            PAGE_PALRAM => self.palette_ram.write_8(addr & 0x3ff, value),
+            PAGE_VRAM => self
+                .vram
+                .write_8(addr & ((VIDEO_RAM_SIZE as u32) - 1), value),
+            PAGE_OAM => self.oam.write_8(addr & 0x3ff, value),
+            _ => unreachable!(),
+        }
+    }
+}
+
 #[cfg(feature = "debugger")]
 impl fmt::Display for Gpu {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -523,6 +580,35 @@ mod tests {
         fn notify(&mut self, _timing: u16) {}
     }
 
+    #[test]
+    fn test_render_frame_with_layers() {
+        let mut sched = Scheduler::new();
+        let mut gpu = Gpu::new(&mut sched, Rc::new(Cell::new(Default::default())));
+        gpu.skip_bios();
+        gpu.write_dispcnt(0x0404); // mode 4, bg2
+        gpu.palette_ram.write_16(0, 0x001f);
+        gpu.palette_ram.write_16(2, 0x7fff);
+        gpu.vram.write_8(0, 1);
+        gpu.vcount = 100;
+
+        let frame = gpu.render_frame_with_layers(LayerMask::all());
+        assert_eq!(frame[0..2], [0xf8f8f8, 0xf80000]);
+        let frame = gpu.render_frame_with_layers(LayerMask::BACKDROP);
+        assert_eq!(frame[0..2], [0xf80000, 0xf80000]);
+        let frame = gpu.render_frame_with_layers(LayerMask::BG2);
+        assert_eq!(frame[0..2], [0xf8f8f8, 0]);
+
+        // the emulated state is untouched
+        assert_eq!(gpu.vcount, 100);
+        assert_eq!(gpu.layer_mask, LayerMask::all());
+        assert!(gpu.get_frame_buffer().iter().all(|&pixel| pixel == 0));
+
+        gpu.layer_mask.remove(LayerMask::BG2);
+        gpu.vcount = 0;
+        gpu.render_scanline();
+        assert_eq!(gpu.get_frame_buffer()[0], 0xf80000);
+    }
+
     #[test]
     fn test_gpu_state_machine() {
         let mut sched = Scheduler::new();
@@ -7,12 +7,16 @@ const OVRAM: u32 = 0x0601_0000;
 const PALRAM_OFS_FG: u32 = 0x200;
 const ATTRS_SIZE: u32 = 2 * 3 + 2;
 
-struct ObjAttrs(Attribute0, Attribute1, Attribute2);
+pub(in super::super) struct ObjAttrs(
+    pub(in super::super) Attribute0,
+    pub(in super::super) Attribute1,
+    pub(in super::super) Attribute2,
+);
 
 const AFFINE_FILL: u32 = 2 * 3;
 
 impl ObjAttrs {
-    fn size(&self) -> (i32, i32) {
+    pub(in super::super) fn size(&self) -> (i32, i32) {
         match (self.1.size(), self.0.shape()) {
             (0, 0) /* Square */  => (8, 8),
             (1, 0) /* Square */  => (16, 16),
@@ -29,7 +33,7 @@ impl ObjAttrs {
             _ => (8, 8), // according to commit f01016a30b2e8482d06798895ebc674370e81816 in melonDS
         }
     }
-    fn coords(&self) -> (i32, i32) {
+    pub(in super::super) fn coords(&self) -> (i32, i32) {
         let mut y = self.0.y_coord() as i16 as i32;
         let mut x = self.1.x_coord() as i16 as i32;
         if y >= (DISPLAY_HEIGHT as i32) {
@@ -40,14 +44,14 @@ impl ObjAttrs {
         }
         (x, y)
     }
-    fn tile_format(&self) -> (usize, PixelFormat) {
+    pub(in super::super) fn tile_format(&self) -> (usize, PixelFormat) {
         if self.0.is_8bpp() {
             (0x40, PixelFormat::BPP8)
         } else {
             (0x20, PixelFormat::BPP4)
         }
     }
-    fn affine_index(&self) -> u32 {
+    pub(in super::super) fn affine_index(&self) -> u32 {
         let attr1 = (self.1).0;
         ((attr1 >> 9) & 0x1f) as u32
     }
@@ -57,7 +61,7 @@ impl ObjAttrs {
 }
 
 impl Gpu {
-    fn get_affine_matrix(&mut self, affine_index: u32) -> AffineMatrix {
+    pub(in super::super) fn get_affine_matrix(&mut self, affine_index: u32) -> AffineMatrix {
         let mut offset = AFFINE_FILL + affine_index * 16 * 2;
         let pa = self.oam.read_16(offset) as i16 as i32;
         offset += 2 + AFFINE_FILL;
@@ -70,7 +74,7 @@ impl Gpu {
         AffineMatrix { pa, pb, pc, pd }
     }
 
-    fn read_obj_attrs(&mut self, obj: usize) -> ObjAttrs {
+    pub(in super::super) fn read_obj_attrs(&mut self, obj: usize) -> ObjAttrs {
         let addr = ATTRS_SIZE * (obj as u32);
         let attr0 = Attribute0(self.oam.read_16(addr));
         let attr1 = Attribute1(self.oam.read_16(addr + 2));
@@ -320,7 +324,7 @@ impl From<u16> for ObjMode {
 }
 
 #[derive(Debug, Primitive, Copy, Clone, PartialEq)]
-enum ObjType {
+pub enum ObjType {
     Normal = 0b00,
     Affine = 0b01,
     Hidden = 0b10,
@@ -337,10 +341,10 @@ bitfield! {
     pub struct Attribute0(u16);
     u16;
     y_coord, _ : 7, 0;
-    into ObjType, objtype, _: 9, 8;
-    into ObjMode, objmode, _: 11, 10;
+    pub into ObjType, objtype, _: 9, 8;
+    pub into ObjMode, objmode, _: 11, 10;
     pub mosaic, _: 12;
-    is_8bpp, _: 13;
+    pub is_8bpp, _: 13;
     shape, _: 15, 14;
 }
 
@@ -348,15 +352,15 @@ bitfield! {
     pub struct Attribute1(u16);
     u16;
     x_coord, _ : 8, 0;
-    h_flip, _: 12;
-    v_flip, _: 13;
+    pub h_flip, _: 12;
+    pub v_flip, _: 13;
     size, _: 15, 14;
 }
 
 bitfield! {
     pub struct Attribute2(u16);
     u16;
-    tile, _: 9, 0;
-    priority, _: 11, 10;
-    into u32, palette, _: 15, 12;
+    pub tile, _: 9, 0;
+    pub priority, _: 11, 10;
+    pub into u32, palette, _: 15, 12;
 }
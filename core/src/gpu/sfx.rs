@@ -43,18 +43,24 @@ impl Gpu {
 
     /// Composes the render layers into a final scanline while applying needed special effects, and render it to the frame buffer
     pub fn finalize_scanline(&mut self, bg_start: usize, bg_end: usize) {
-        let backdrop_color = Rgb15(self.palette_ram.read_16(0));
+        let backdrop_color = if self.layer_mask.contains(LayerMask::BACKDROP) {
+            Rgb15(self.palette_ram.read_16(0))
+        } else {
+            Rgb15::BLACK
+        };
 
         // filter out disabled backgrounds and sort by priority
         // the backgrounds are sorted once for the entire scanline
         let mut sorted_backgrounds: ArrayVec<[usize; 4]> = (bg_start..=bg_end)
-            .filter(|bg| self.dispcnt.enable_bg[*bg])
+            .filter(|bg| {
+                self.dispcnt.enable_bg[*bg] && self.layer_mask.contains(LayerMask::from_bg(*bg))
+            })
             .collect();
         sorted_backgrounds.sort_by_key(|bg| (self.bgcnt[*bg].priority, *bg));
 
         let y = self.vcount;
 
-        if !self.dispcnt.is_using_windows() {
+        if !self.dispcnt.is_using_windows() || !self.layer_mask.contains(LayerMask::WINDOWS) {
             for x in 0..DISPLAY_WIDTH {
                 let win = WindowInfo::new(WindowType::WinNone, WindowFlags::all());
                 self.finalize_pixel(x, y, &win, &sorted_backgrounds, backdrop_color);
@@ -164,7 +170,11 @@ impl Gpu {
 
         // Now that backgrounds are taken care of, we need to check if there is an object pixel that takes priority of one of the layers
         let obj_entry = self.obj_buffer_get(x, y);
-        if win.flags.obj_enabled() && self.dispcnt.enable_obj && !obj_entry.color.is_transparent() {
+        if win.flags.obj_enabled()
+            && self.dispcnt.enable_obj
+            && self.layer_mask.contains(LayerMask::OBJ)
+            && !obj_entry.color.is_transparent()
+        {
             let obj_layer = RenderLayer::objects(obj_entry.color, obj_entry.priority);
             if obj_layer.priority <= top_layer.priority {
                 bot_layer = top_layer;
@@ -181,7 +191,8 @@ impl Gpu {
         let bot_flags = self.bldcnt.target2;
 
         let sfx_enabled = (self.bldcnt.mode != BlendMode::BldNone || obj_alpha_blend)
-            && top_flags.contains_render_layer(&top_layer); // sfx must at least have a first target configured
+            && top_flags.contains_render_layer(&top_layer) // sfx must at least have a first target configured
+            && self.layer_mask.contains(LayerMask::BLENDING);
 
         if win.flags.sfx_enabled() && sfx_enabled {
             if top_layer.is_object()
@@ -0,0 +1,419 @@
+//! Images of the gpu memories for debug viewers, independent of any renderer.
+//!
+//! Pixels are 0xAARRGGBB, color index 0 (or an empty tile) is left fully transparent.
+use std::fmt;
+
+use arm7tdmi::memory::BusIO;
+use rustboyadvance_utils::index2d;
+
+use super::*;
+
+const OPAQUE: u32 = 0xff00_0000;
+const OUTLINE_COLOR: u32 = 0xffff_0000;
+
+/// Size of a character base block
+const CHAR_BLOCK_SIZE: u32 = 0x4000;
+
+#[derive(Debug, Clone)]
+pub struct ViewerImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u32>,
+}
+
+impl ViewerImage {
+    fn new(width: usize, height: usize) -> ViewerImage {
+        ViewerImage {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    #[inline]
+    fn put(&mut self, x: usize, y: usize, color: Rgb15) {
+        if !color.is_transparent() {
+            self.pixels[index2d!(x, y, self.width)] = OPAQUE | color.to_rgb24();
+        }
+    }
+}
+
+/// Which palette to draw tiles with. The bank is ignored for 8bpp tiles.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TilePalette {
+    Bg(u32),
+    Obj(u32),
+}
+
+/// Decoded attributes of an OAM entry
+#[derive(Debug, Clone)]
+pub struct ObjInfo {
+    pub index: usize,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub objtype: ObjType,
+    pub mode: ObjMode,
+    pub mosaic: bool,
+    pub is_8bpp: bool,
+    pub h_flip: bool,
+    pub v_flip: bool,
+    /// Index and parameters of the rotation/scaling group, for affine objs
+    pub affine: Option<(u32, AffineMatrix)>,
+    pub tile: u16,
+    pub priority: u16,
+    pub palette: u32,
+}
+
+impl fmt::Display for ObjInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "OBJ #{} {:?} {}x{} at ({}, {}) mode={:?} tile={:#x} priority={}",
+            self.index,
+            self.objtype,
+            self.width,
+            self.height,
+            self.x,
+            self.y,
+            self.mode,
+            self.tile,
+            self.priority
+        )?;
+        if self.is_8bpp {
+            write!(f, " 8bpp")?;
+        } else {
+            write!(f, " palette={}", self.palette)?;
+        }
+        if self.mosaic {
+            write!(f, " mosaic")?;
+        }
+        match self.affine {
+            Some((index, m)) => write!(
+                f,
+                " affine={} [{:#x} {:#x} {:#x} {:#x}]",
+                index, m.pa as i16, m.pb as i16, m.pc as i16, m.pd as i16
+            ),
+            None if self.h_flip || self.v_flip => write!(
+                f,
+                " flip={}{}",
+                if self.h_flip { "h" } else { "" },
+                if self.v_flip { "v" } else { "" }
+            ),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Gpu {
+    /// Draw one character base block (0-3 for backgrounds, 4-5 for objs) as a grid of tiles,
+    /// 32 tiles wide in 4bpp and 16 tiles wide in 8bpp. Returns `None` past the last block.
+    pub fn tile_view(
+        &mut self,
+        char_block: u32,
+        format: PixelFormat,
+        palette: TilePalette,
+    ) -> Option<ViewerImage> {
+        if char_block >= 6 {
+            return None;
+        }
+        let (tile_size, tiles_per_row) = match format {
+            PixelFormat::BPP4 => (TILE_SIZE, 32),
+            PixelFormat::BPP8 => (2 * TILE_SIZE, 16),
+        };
+        let num_tiles = CHAR_BLOCK_SIZE / tile_size;
+        let rows = num_tiles / tiles_per_row;
+        let (palette_bank, offset) = match palette {
+            TilePalette::Bg(bank) => (bank & 0xf, 0),
+            TilePalette::Obj(bank) => (bank & 0xf, 0x200),
+        };
+        let palette_bank = match format {
+            PixelFormat::BPP4 => palette_bank,
+            PixelFormat::BPP8 => 0,
+        };
+
+        let mut image = ViewerImage::new(8 * tiles_per_row as usize, 8 * rows as usize);
+        for tile in 0..num_tiles {
+            let tile_addr = char_block * CHAR_BLOCK_SIZE + tile * tile_size;
+            let tile_x = 8 * (tile % tiles_per_row);
+            let tile_y = 8 * (tile / tiles_per_row);
+            for y in 0..8 {
+                for x in 0..8 {
+                    let index = self.read_pixel_index(tile_addr, x, y, format) as u32;
+                    let color = self.get_palette_color(index, palette_bank, offset);
+                    image.put((tile_x + x) as usize, (tile_y + y) as usize, color);
+                }
+            }
+        }
+        Some(image)
+    }
+
+    /// Draw the whole tilemap of `bg` as configured by its BGCNT, with the area currently on
+    /// screen outlined. Returns `None` if `bg` isn't a tiled background in the current mode.
+    pub fn tilemap_view(&mut self, bg: usize) -> Option<ViewerImage> {
+        let mut image = match (self.dispcnt.mode, bg) {
+            (0, 0..=3) | (1, 0..=1) => self.draw_reg_tilemap(bg),
+            (1, 2) | (2, 2..=3) => self.draw_aff_tilemap(bg),
+            _ => return None,
+        };
+        self.outline_viewport(bg, &mut image);
+        Some(image)
+    }
+
+    /// All 512 palette entries, one pixel each in 16 columns. The first 16 rows are the
+    /// background palette and the next 16 the obj palette.
+    pub fn palette_view(&mut self) -> ViewerImage {
+        let mut image = ViewerImage::new(16, 32);
+        for (i, pixel) in image.pixels.iter_mut().enumerate() {
+            let color = Rgb15(self.palette_ram.read_16(2 * i as u32) & 0x7fff);
+            *pixel = OPAQUE | color.to_rgb24();
+        }
+        image
+    }
+
+    /// Decode OAM entry `index` (0-127)
+    pub fn obj_info(&mut self, index: usize) -> ObjInfo {
+        let attrs = self.read_obj_attrs(index);
+        let (x, y) = attrs.coords();
+        let (width, height) = attrs.size();
+        let objtype = attrs.0.objtype();
+        let affine = match objtype {
+            ObjType::Affine | ObjType::AffineDoubleSize => {
+                let affine_index = attrs.affine_index();
+                Some((affine_index, self.get_affine_matrix(affine_index)))
+            }
+            _ => None,
+        };
+        ObjInfo {
+            index,
+            x,
+            y,
+            width,
+            height,
+            objtype,
+            mode: attrs.0.objmode(),
+            mosaic: attrs.0.mosaic(),
+            is_8bpp: attrs.0.is_8bpp(),
+            // these bits are part of the affine index for affine objs
+            h_flip: affine.is_none() && attrs.1.h_flip(),
+            v_flip: affine.is_none() && attrs.1.v_flip(),
+            affine,
+            tile: attrs.2.tile(),
+            priority: attrs.2.priority(),
+            palette: attrs.2.palette(),
+        }
+    }
+
+    /// Draw the tiles of OAM entry `index` at the obj's size, flipped but not transformed
+    pub fn obj_view(&mut self, index: usize) -> ViewerImage {
+        let info = self.obj_info(index);
+        let (tile_size, format) = self.read_obj_attrs(index).tile_format();
+        let palette_bank = if info.is_8bpp { 0 } else { info.palette };
+        let tile_base = VRAM_OBJ_TILES_START_TEXT + TILE_SIZE * info.tile as u32;
+        let tile_array_width = match self.dispcnt.obj_mapping() {
+            ObjMapping::OneDimension => info.width / 8,
+            ObjMapping::TwoDimension if info.is_8bpp => 16,
+            ObjMapping::TwoDimension => 32,
+        };
+
+        let mut image = ViewerImage::new(info.width as usize, info.height as usize);
+        for y in 0..info.height {
+            for x in 0..info.width {
+                let sprite_x = if info.h_flip { info.width - x - 1 } else { x };
+                let sprite_y = if info.v_flip { info.height - y - 1 } else { y };
+                let tile_addr = tile_base
+                    + index2d!(u32, sprite_x / 8, sprite_y / 8, tile_array_width)
+                        * (tile_size as u32);
+                // the obj tiles wrap around within their 32k
+                let tile_addr = VRAM_OBJ_TILES_START_TEXT + (tile_addr & 0x7fff);
+                let index = self.read_pixel_index(
+                    tile_addr,
+                    (sprite_x % 8) as u32,
+                    (sprite_y % 8) as u32,
+                    format,
+                ) as u32;
+                let color = self.get_palette_color(index, palette_bank, 0x200);
+                image.put(x as usize, y as usize, color);
+            }
+        }
+        image
+    }
+
+    fn draw_reg_tilemap(&mut self, bg: usize) -> ViewerImage {
+        let tileset_base = self.bgcnt[bg].char_block();
+        let tilemap_base = self.bgcnt[bg].screen_block();
+        let (tile_size, format) = self.bgcnt[bg].tile_format();
+        let (bg_width, bg_height) = self.bgcnt[bg].size_regular();
+
+        let mut image = ViewerImage::new(bg_width as usize, bg_height as usize);
+        for tile_y in 0..bg_height / 8 {
+            for tile_x in 0..bg_width / 8 {
+                // screen blocks are 32x32 tiles, laid out left to right then top to bottom
+                let sbb = tile_x / 32 + (bg_width / 256) * (tile_y / 32);
+                let map_addr = tilemap_base
+                    + SCREEN_BLOCK_SIZE * sbb
+                    + 2 * index2d!(u32, tile_x % 32, tile_y % 32, 32);
+                let entry = self.vram.read_16(map_addr);
+                let tile_addr = tileset_base + (entry as u32 & 0x3ff) * tile_size;
+                let x_flip = entry & (1 << 10) != 0;
+                let y_flip = entry & (1 << 11) != 0;
+                let palette_bank = match format {
+                    PixelFormat::BPP4 => (entry >> 12) as u32,
+                    PixelFormat::BPP8 => 0,
+                };
+                for y in 0..8 {
+                    for x in 0..8 {
+                        let index = self.read_pixel_index(
+                            tile_addr,
+                            if x_flip { 7 - x } else { x },
+                            if y_flip { 7 - y } else { y },
+                            format,
+                        ) as u32;
+                        let color = self.get_palette_color(index, palette_bank, 0);
+                        image.put((8 * tile_x + x) as usize, (8 * tile_y + y) as usize, color);
+                    }
+                }
+            }
+        }
+        image
+    }
+
+    fn draw_aff_tilemap(&mut self, bg: usize) -> ViewerImage {
+        let texture_size = 128 << self.bgcnt[bg].size;
+        let screen_block = self.bgcnt[bg].screen_block();
+        let char_block = self.bgcnt[bg].char_block();
+
+        let mut image = ViewerImage::new(texture_size, texture_size);
+        let tiles_per_row = texture_size as u32 / 8;
+        for tile in 0..tiles_per_row * tiles_per_row {
+            let tile_index = self.vram.read_8(screen_block + tile) as u32;
+            let tile_addr = char_block + tile_index * 0x40;
+            for y in 0..8 {
+                for x in 0..8 {
+                    let index = self.read_pixel_index_bpp8(tile_addr, x, y) as u32;
+                    let color = self.get_palette_color(index, 0, 0);
+                    let image_x = 8 * (tile % tiles_per_row) + x;
+                    let image_y = 8 * (tile / tiles_per_row) + y;
+                    image.put(image_x as usize, image_y as usize, color);
+                }
+            }
+        }
+        image
+    }
+
+    /// Mark where the edges of the screen land on the tilemap
+    fn outline_viewport(&self, bg: usize, image: &mut ViewerImage) {
+        let (width, height) = (image.width as i32, image.height as i32);
+        let map_point = |screen_x: i32, screen_y: i32| -> Option<(i32, i32)> {
+            if bg < 2 || self.dispcnt.mode == 0 {
+                let x = screen_x + self.bg_hofs[bg] as i32;
+                let y = screen_y + self.bg_vofs[bg] as i32;
+                return Some((x.rem_euclid(width), y.rem_euclid(height)));
+            }
+            // The reference point at the start of the frame
+            let aff = &self.bg_aff[bg - 2];
+            let x = (aff.x + screen_x * aff.pa as i32 + screen_y * aff.pb as i32) >> 8;
+            let y = (aff.y + screen_x * aff.pc as i32 + screen_y * aff.pd as i32) >> 8;
+            if self.bgcnt[bg].affine_wraparound {
+                Some((x.rem_euclid(width), y.rem_euclid(height)))
+            } else if x >= 0 && x < width && y >= 0 && y < height {
+                Some((x, y))
+            } else {
+                None
+            }
+        };
+
+        let (screen_w, screen_h) = (DISPLAY_WIDTH as i32, DISPLAY_HEIGHT as i32);
+        let edges = (0..screen_w)
+            .flat_map(|x| vec![(x, 0), (x, screen_h - 1)])
+            .chain((0..screen_h).flat_map(|y| vec![(0, y), (screen_w - 1, y)]));
+        for (screen_x, screen_y) in edges {
+            if let Some((x, y)) = map_point(screen_x, screen_y) {
+                image.pixels[index2d!(x as usize, y as usize, image.width)] = OUTLINE_COLOR;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    fn make_gpu() -> Gpu {
+        let mut sched = Scheduler::new();
+        Gpu::new(&mut sched, Rc::new(Cell::new(Default::default())))
+    }
+
+    #[test]
+    fn test_tile_view() {
+        let mut gpu = make_gpu();
+        gpu.palette_ram.write_16(2 * 0x11, 0x001f); // bank 1, color 1 is red
+                                                    // top left pixel of tile 1 in char block 1
+        gpu.vram.write_8(CHAR_BLOCK_SIZE + TILE_SIZE, 0x01);
+
+        let image = gpu
+            .tile_view(1, PixelFormat::BPP4, TilePalette::Bg(1))
+            .unwrap();
+        assert_eq!((image.width, image.height), (256, 128));
+        assert_eq!(image.pixels[8], 0xfff8_0000);
+        assert_eq!(image.pixels[9], 0);
+
+        let image = gpu
+            .tile_view(1, PixelFormat::BPP4, TilePalette::Obj(1))
+            .unwrap();
+        assert_eq!(image.pixels[8], OPAQUE);
+
+        assert!(gpu
+            .tile_view(6, PixelFormat::BPP4, TilePalette::Bg(0))
+            .is_none());
+    }
+
+    #[test]
+    fn test_tilemap_view() {
+        let mut gpu = make_gpu();
+        gpu.dispcnt.mode = 0;
+        gpu.bgcnt[0].size = 1; // 512x256
+        gpu.bgcnt[0].screen_base_block = 4;
+        gpu.palette_ram.write_16(2, 0x7c00); // blue
+                                             // tile 1 is filled with color 1, put it at the top left of the second screen block
+        for i in 0..TILE_SIZE {
+            gpu.vram.write_8(TILE_SIZE + i, 0x11);
+        }
+        gpu.vram.write_16(5 * SCREEN_BLOCK_SIZE, 1);
+        gpu.bg_hofs[0] = 500;
+
+        let image = gpu.tilemap_view(0).unwrap();
+        assert_eq!((image.width, image.height), (512, 256));
+        assert_eq!(image.pixels[index2d!(257, 1, 512)], 0xff00_00f8);
+        assert_eq!(image.pixels[index2d!(256, 8, 512)], 0);
+        // the viewport starts at x=500 and wraps around
+        assert_eq!(image.pixels[index2d!(500, 0, 512)], OUTLINE_COLOR);
+        assert_eq!(image.pixels[index2d!(227, 159, 512)], OUTLINE_COLOR);
+        assert_eq!(image.pixels[index2d!(227, 160, 512)], 0);
+
+        gpu.dispcnt.mode = 3;
+        assert!(gpu.tilemap_view(0).is_none());
+    }
+
+    #[test]
+    fn test_obj_info() {
+        let mut gpu = make_gpu();
+        gpu.oam.write_16(8 * 3, 0x4000 | 0x0100 | 0x10); // wide, affine, y=16
+        gpu.oam.write_16(8 * 3 + 2, 0x4000 | (2 << 9) | 0x1f0); // size 1, affine group 2, x=-16
+        gpu.oam.write_16(8 * 3 + 4, 0x5000 | 0x0400 | 0x20); // palette 5, priority 1, tile 0x20
+        gpu.oam.write_16(32 * 2 + 6, 0x0100); // pa of group 2
+
+        let info = gpu.obj_info(3);
+        assert_eq!((info.x, info.y), (-16, 16));
+        assert_eq!((info.width, info.height), (32, 8));
+        assert_eq!(info.objtype, ObjType::Affine);
+        assert_eq!((info.tile, info.priority, info.palette), (0x20, 1, 5));
+        let (group, matrix) = info.affine.unwrap();
+        assert_eq!((group, matrix.pa), (2, 0x100));
+        assert!(!info.h_flip);
+        assert_eq!(gpu.obj_view(3).pixels.len(), 32 * 8);
+    }
+}
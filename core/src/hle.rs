@@ -0,0 +1,572 @@
+//! High level emulation of the bios, for running without a bios file.
+//!
+//! `builtin_bios` builds a tiny bios image with the reset code, the irq vector and `IntrWait`,
+//! which has to halt and take interrupts while it waits. When the gba runs on this image, the cpu
+//! hands every software interrupt to `software_interrupt`, which does the work of the bios
+//! function in one go. The functions take no emulated time.
+use std::f64::consts::PI;
+
+use arm7tdmi::exception::Exception;
+use arm7tdmi::memory::{Addr, BusIO};
+
+use super::iodev::consts::REG_SOUNDBIAS;
+use super::iodev::HaltState;
+use super::GameBoyAdvance;
+
+const BIOS_SIZE: usize = 0x4000;
+
+const RESET_HANDLER: Addr = 0x20;
+/// Same place as in the real bios
+const IRQ_HANDLER: Addr = 0x128;
+const INTR_WAIT: Addr = 0x200;
+
+const COND_EQ: u32 = 0x0;
+const COND_NE: u32 = 0x1;
+const COND_AL: u32 = 0xe;
+
+/// Encode a branch at `addr` to `target`
+fn branch(cond: u32, addr: Addr, target: Addr) -> u32 {
+    (cond << 28) | 0x0a00_0000 | ((target.wrapping_sub(addr + 8) >> 2) & 0x00ff_ffff)
+}
+
+fn put_code(rom: &mut [u8], addr: Addr, code: &[u32]) {
+    for (i, insn) in code.iter().enumerate() {
+        let offset = addr as usize + i * 4;
+        rom[offset..offset + 4].copy_from_slice(&insn.to_le_bytes());
+    }
+}
+
+/// Build the bios image to use when there is no bios file
+pub fn builtin_bios() -> Box<[u8]> {
+    let mut rom = vec![0; BIOS_SIZE];
+
+    // Exception vectors, the unused ones loop forever
+    for vector in (0..0x20).step_by(4) {
+        let target = match vector {
+            0x00 => RESET_HANDLER,
+            0x08 => INTR_WAIT,
+            0x18 => IRQ_HANDLER,
+            _ => vector,
+        };
+        put_code(&mut rom, vector, &[branch(COND_AL, vector, target)]);
+    }
+
+    #[rustfmt::skip]
+    put_code(&mut rom, RESET_HANDLER, &[
+        0xe3a000d2, // mov r0, #0xd2             ; irq mode
+        0xe129f000, // msr cpsr_fc, r0
+        0xe3a0d403, // mov sp, #0x03000000
+        0xe28ddc7f, // add sp, sp, #0x7f00
+        0xe28dd0a0, // add sp, sp, #0xa0
+        0xe3a000d3, // mov r0, #0xd3             ; supervisor mode
+        0xe129f000, // msr cpsr_fc, r0
+        0xe3a0d403, // mov sp, #0x03000000
+        0xe28ddc7f, // add sp, sp, #0x7f00
+        0xe28dd0e0, // add sp, sp, #0xe0
+        0xe3a0001f, // mov r0, #0x1f             ; system mode, interrupts enabled
+        0xe129f000, // msr cpsr_fc, r0
+        0xe3a0d403, // mov sp, #0x03000000
+        0xe28ddc7f, // add sp, sp, #0x7f00
+        0xe3a00301, // mov r0, #0x04000000
+        0xe3a01c01, // mov r1, #0x100
+        0xe1c012b0, // strh r1, [r0, #0x20]      ; BG2PA
+        0xe1c012b6, // strh r1, [r0, #0x26]      ; BG2PD
+        0xe1c013b0, // strh r1, [r0, #0x30]      ; BG3PA
+        0xe1c013b6, // strh r1, [r0, #0x36]      ; BG3PD
+        0xe3a01c02, // mov r1, #0x200
+        0xe1c018b8, // strh r1, [r0, #0x88]      ; SOUNDBIAS
+        0xe3a0e302, // mov lr, #0x08000000
+        0xe12fff1e, // bx lr
+    ]);
+
+    #[rustfmt::skip]
+    put_code(&mut rom, IRQ_HANDLER, &[
+        0xe92d500f, // stmfd sp!, {r0-r3, r12, lr}
+        0xe3a00301, // mov r0, #0x04000000
+        0xe28fe000, // add lr, pc, #0
+        0xe510f004, // ldr pc, [r0, #-4]         ; user handler at 0x03fffffc
+        0xe8bd500f, // ldmfd sp!, {r0-r3, r12, lr}
+        0xe25ef004, // subs pc, lr, #4
+    ]);
+
+    // IntrWait(r0 = discard old flags, r1 = flags to wait for), the flags are the ones the irq
+    // handler sets at 0x03fffff8
+    #[rustfmt::skip]
+    put_code(&mut rom, INTR_WAIT, &[
+        0xe92d500c, // stmfd sp!, {r2, r3, r12, lr}
+        0xe3a0c301, // mov r12, #0x04000000
+        0xe3a02001, // mov r2, #1
+        0xe5cc2208, // strb r2, [r12, #0x208]    ; IME
+        0xe3500000, // cmp r0, #0
+        branch(COND_EQ, INTR_WAIT + 0x14, INTR_WAIT + 0x24),
+        0xe15c30b8, // ldrh r3, [r12, #-8]
+        0xe1c33001, // bic r3, r3, r1
+        0xe14c30b8, // strh r3, [r12, #-8]
+        0xe15c30b8, // wait: ldrh r3, [r12, #-8]
+        0xe0133001, // ands r3, r3, r1
+        branch(COND_NE, INTR_WAIT + 0x2c, INTR_WAIT + 0x44),
+        0xe3a02000, // mov r2, #0
+        0xe5cc2301, // strb r2, [r12, #0x301]    ; HALTCNT
+        0xe321f013, // msr cpsr_c, #0x13         ; let the pending interrupt in
+        0xe321f093, // msr cpsr_c, #0x93
+        branch(COND_AL, INTR_WAIT + 0x40, INTR_WAIT + 0x24),
+        0xe15c20b8, // done: ldrh r2, [r12, #-8]
+        0xe1c22003, // bic r2, r2, r3
+        0xe14c20b8, // strh r2, [r12, #-8]
+        0xe8bd500c, // ldmfd sp!, {r2, r3, r12, lr}
+        0xe1b0f00e, // movs pc, lr
+    ]);
+
+    rom.into_boxed_slice()
+}
+
+pub(crate) fn is_builtin_bios(bios: &[u8]) -> bool {
+    *bios == *builtin_bios()
+}
+
+/// Emulate the bios function `number`, called right after the swi instruction
+pub(crate) fn software_interrupt(gba: &mut GameBoyAdvance, number: u32) {
+    trace!("bios call 0x{:02x}", number);
+    match number {
+        0x02 => gba.io_devs.haltcnt = HaltState::Halt,
+        0x04 => intr_wait(gba),
+        0x05 => {
+            gba.cpu.set_reg(0, 1);
+            gba.cpu.set_reg(1, 1);
+            intr_wait(gba);
+        }
+        0x06 => div(gba, gba.cpu.get_reg(0) as i32, gba.cpu.get_reg(1) as i32),
+        0x07 => div(gba, gba.cpu.get_reg(1) as i32, gba.cpu.get_reg(0) as i32),
+        0x08 => {
+            let root = sqrt(gba.cpu.get_reg(0));
+            gba.cpu.set_reg(0, root);
+        }
+        0x09 => {
+            let angle = arctan(gba.cpu.get_reg(0) as i32);
+            gba.cpu.set_reg(0, angle as u32);
+        }
+        0x0a => {
+            let x = gba.cpu.get_reg(0) as i16 as i32;
+            let y = gba.cpu.get_reg(1) as i16 as i32;
+            gba.cpu.set_reg(0, arctan2(x, y) as u32);
+        }
+        0x0b => cpu_set(gba),
+        0x0c => cpu_fast_set(gba),
+        0x0e => bg_affine_set(gba),
+        0x0f => obj_affine_set(gba),
+        0x11 => decompress(gba, lz77, false),
+        0x12 => decompress(gba, lz77, true),
+        0x13 => decompress(gba, huffman, false),
+        0x14 => decompress(gba, run_length, false),
+        0x15 => decompress(gba, run_length, true),
+        0x19 => {
+            let level = if gba.cpu.get_reg(0) != 0 { 0x200 } else { 0 };
+            let bias = gba.sysbus.read_16(REG_SOUNDBIAS);
+            gba.sysbus.write_16(REG_SOUNDBIAS, (bias & !0x3fe) | level);
+        }
+        _ => warn!(
+            "unimplemented bios call 0x{:02x} at 0x{:08x}",
+            number,
+            gba.cpu.get_next_pc()
+        ),
+    }
+}
+
+/// Waiting needs interrupts to be taken in the middle, so this one runs in the built-in bios
+fn intr_wait(gba: &mut GameBoyAdvance) {
+    let return_addr = gba.cpu.get_next_pc();
+    gba.cpu.exception(Exception::SoftwareInterrupt, return_addr);
+}
+
+fn div(gba: &mut GameBoyAdvance, num: i32, denom: i32) {
+    if denom == 0 {
+        // The real bios never returns
+        warn!("bios Div by zero");
+        return;
+    }
+    let quotient = num.wrapping_div(denom);
+    gba.cpu.set_reg(0, quotient as u32);
+    gba.cpu.set_reg(1, num.wrapping_rem(denom) as u32);
+    gba.cpu.set_reg(3, quotient.wrapping_abs() as u32);
+}
+
+fn sqrt(value: u32) -> u32 {
+    let mut rem = value;
+    let mut root = 0;
+    let mut bit = 1 << 30;
+    while bit > rem {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if rem >= root + bit {
+            rem -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    root
+}
+
+/// Same polynomial as the bios, `tan` is 1.14 fixed point
+fn arctan(tan: i32) -> i32 {
+    let a = -(tan.wrapping_mul(tan) >> 14);
+    let mut b = ((0xa9 * a) >> 14) + 0x390;
+    for c in &[0x91c, 0xfb6, 0x16aa, 0x2081, 0x3651, 0xa2f9] {
+        b = (b.wrapping_mul(a) >> 14) + c;
+    }
+    tan.wrapping_mul(b) >> 16
+}
+
+/// Returns the angle of (x, y) in 0..0x10000
+fn arctan2(x: i32, y: i32) -> u16 {
+    let angle = if y == 0 {
+        if x >= 0 {
+            0
+        } else {
+            0x8000
+        }
+    } else if x == 0 {
+        if y >= 0 {
+            0x4000
+        } else {
+            0xc000
+        }
+    } else if y >= 0 {
+        if x >= 0 && x >= y {
+            arctan((y << 14) / x)
+        } else if x < 0 && -x >= y {
+            arctan((y << 14) / x) + 0x8000
+        } else {
+            0x4000 - arctan((x << 14) / y)
+        }
+    } else if x <= 0 && -x > -y {
+        arctan((y << 14) / x) + 0x8000
+    } else if x > 0 && x >= -y {
+        arctan((y << 14) / x) + 0x10000
+    } else {
+        0xc000 - arctan((x << 14) / y)
+    };
+    angle as u16
+}
+
+fn cpu_set(gba: &mut GameBoyAdvance) {
+    let mut src = gba.cpu.get_reg(0);
+    let mut dst = gba.cpu.get_reg(1);
+    let control = gba.cpu.get_reg(2);
+    let count = control & 0x1f_ffff;
+    let fill = control & (1 << 24) != 0;
+    if control & (1 << 26) != 0 {
+        src &= !3;
+        dst &= !3;
+        let fill_value = gba.sysbus.read_32(src);
+        for _ in 0..count {
+            let value = if fill {
+                fill_value
+            } else {
+                src += 4;
+                gba.sysbus.read_32(src - 4)
+            };
+            gba.sysbus.write_32(dst, value);
+            dst += 4;
+        }
+    } else {
+        src &= !1;
+        dst &= !1;
+        let fill_value = gba.sysbus.read_16(src);
+        for _ in 0..count {
+            let value = if fill {
+                fill_value
+            } else {
+                src += 2;
+                gba.sysbus.read_16(src - 2)
+            };
+            gba.sysbus.write_16(dst, value);
+            dst += 2;
+        }
+    }
+}
+
+fn cpu_fast_set(gba: &mut GameBoyAdvance) {
+    let mut src = gba.cpu.get_reg(0) & !3;
+    let mut dst = gba.cpu.get_reg(1) & !3;
+    let control = gba.cpu.get_reg(2);
+    // Copies blocks of 8 words
+    let count = ((control & 0x1f_ffff) + 7) & !7;
+    let fill = control & (1 << 24) != 0;
+    let fill_value = gba.sysbus.read_32(src);
+    for _ in 0..count {
+        let value = if fill {
+            fill_value
+        } else {
+            src += 4;
+            gba.sysbus.read_32(src - 4)
+        };
+        gba.sysbus.write_32(dst, value);
+        dst += 4;
+    }
+}
+
+/// Sine of `angle` / 256 of a full turn, in 1.14 fixed point like the table in the bios
+fn sin_1_14(angle: u32) -> i32 {
+    ((angle & 0xff) as f64 * PI / 128.0)
+        .sin()
+        .mul_add(16384.0, 0.0)
+        .round() as i32
+}
+
+/// Rotation and scaling matrix for scales in 8.8 fixed point
+fn affine_matrix(scale_x: i32, scale_y: i32, angle: u32) -> [i32; 4] {
+    let sin = sin_1_14(angle);
+    let cos = sin_1_14(angle + 64);
+    [
+        (scale_x * cos) >> 14,
+        -((scale_x * sin) >> 14),
+        (scale_y * sin) >> 14,
+        (scale_y * cos) >> 14,
+    ]
+}
+
+fn bg_affine_set(gba: &mut GameBoyAdvance) {
+    let mut src = gba.cpu.get_reg(0);
+    let mut dst = gba.cpu.get_reg(1);
+    for _ in 0..gba.cpu.get_reg(2) {
+        let origin_x = gba.sysbus.read_32(src) as i32;
+        let origin_y = gba.sysbus.read_32(src + 4) as i32;
+        let display_x = gba.sysbus.read_16(src + 8) as i16 as i32;
+        let display_y = gba.sysbus.read_16(src + 10) as i16 as i32;
+        let scale_x = gba.sysbus.read_16(src + 12) as i16 as i32;
+        let scale_y = gba.sysbus.read_16(src + 14) as i16 as i32;
+        let angle = (gba.sysbus.read_16(src + 16) >> 8) as u32;
+        src += 20;
+
+        let [pa, pb, pc, pd] = affine_matrix(scale_x, scale_y, angle);
+        for (i, p) in [pa, pb, pc, pd].iter().enumerate() {
+            gba.sysbus.write_16(dst + 2 * i as u32, *p as u16);
+        }
+        let x = origin_x - (pa * display_x + pb * display_y);
+        let y = origin_y - (pc * display_x + pd * display_y);
+        gba.sysbus.write_32(dst + 8, x as u32);
+        gba.sysbus.write_32(dst + 12, y as u32);
+        dst += 16;
+    }
+}
+
+fn obj_affine_set(gba: &mut GameBoyAdvance) {
+    let mut src = gba.cpu.get_reg(0);
+    let mut dst = gba.cpu.get_reg(1);
+    let stride = gba.cpu.get_reg(3);
+    for _ in 0..gba.cpu.get_reg(2) {
+        let scale_x = gba.sysbus.read_16(src) as i16 as i32;
+        let scale_y = gba.sysbus.read_16(src + 2) as i16 as i32;
+        let angle = (gba.sysbus.read_16(src + 4) >> 8) as u32;
+        src += 8;
+
+        for p in &affine_matrix(scale_x, scale_y, angle) {
+            gba.sysbus.write_16(dst, *p as u16);
+            dst += stride;
+        }
+    }
+}
+
+/// Decompress the data at r0 to r1. The vram variants write in halfwords, vram ignores byte writes.
+fn decompress(
+    gba: &mut GameBoyAdvance,
+    decoder: fn(&mut GameBoyAdvance, Addr, usize) -> Vec<u8>,
+    vram: bool,
+) {
+    let src = gba.cpu.get_reg(0);
+    let dst = gba.cpu.get_reg(1);
+    let size = (gba.sysbus.read_32(src) >> 8) as usize;
+    let data = decoder(gba, src, size);
+    if vram {
+        for (i, pair) in data.chunks(2).enumerate() {
+            let value = pair[0] as u16 | (*pair.get(1).unwrap_or(&0) as u16) << 8;
+            gba.sysbus.write_16(dst + 2 * i as u32, value);
+        }
+    } else {
+        for (i, byte) in data.iter().enumerate() {
+            gba.sysbus.write_8(dst + i as u32, *byte);
+        }
+    }
+}
+
+fn lz77(gba: &mut GameBoyAdvance, mut src: Addr, size: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(size);
+    src += 4;
+    while out.len() < size {
+        let flags = gba.sysbus.read_8(src);
+        src += 1;
+        for i in (0..8).rev() {
+            if out.len() >= size {
+                break;
+            }
+            if flags & (1 << i) == 0 {
+                out.push(gba.sysbus.read_8(src));
+                src += 1;
+                continue;
+            }
+            let b0 = gba.sysbus.read_8(src) as usize;
+            let b1 = gba.sysbus.read_8(src + 1) as usize;
+            src += 2;
+            let len = (b0 >> 4) + 3;
+            let disp = ((b0 & 0xf) << 8 | b1) + 1;
+            for _ in 0..len.min(size - out.len()) {
+                let byte = match out.len().checked_sub(disp) {
+                    Some(i) => out[i],
+                    None => 0,
+                };
+                out.push(byte);
+            }
+        }
+    }
+    out
+}
+
+fn run_length(gba: &mut GameBoyAdvance, mut src: Addr, size: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(size);
+    src += 4;
+    while out.len() < size {
+        let flag = gba.sysbus.read_8(src);
+        src += 1;
+        if flag & 0x80 != 0 {
+            let len = (flag & 0x7f) as usize + 3;
+            let byte = gba.sysbus.read_8(src);
+            src += 1;
+            out.extend(std::iter::repeat(byte).take(len));
+        } else {
+            for _ in 0..(flag & 0x7f) + 1 {
+                out.push(gba.sysbus.read_8(src));
+                src += 1;
+            }
+        }
+    }
+    out.truncate(size);
+    out
+}
+
+fn huffman(gba: &mut GameBoyAdvance, src: Addr, size: usize) -> Vec<u8> {
+    let bits = gba.sysbus.read_32(src) & 0xf;
+    if bits != 4 && bits != 8 {
+        warn!("bios HuffUnComp with {} bit data is not supported", bits);
+        return Vec::new();
+    }
+    let tree_size = gba.sysbus.read_8(src + 4) as u32;
+    let root = src + 5;
+    let mut data = src + 4 + (tree_size + 1) * 2;
+
+    let mut out = Vec::with_capacity(size);
+    let mut node_addr = root;
+    let mut word_out = 0u32;
+    let mut word_out_bits = 0;
+    while out.len() < size {
+        let word = gba.sysbus.read_32(data);
+        data += 4;
+        for i in (0..32).rev() {
+            let node = gba.sysbus.read_8(node_addr);
+            let left = (node_addr & !1) + (node & 0x3f) as u32 * 2 + 2;
+            let (child, is_data) = if (word >> i) & 1 == 0 {
+                (left, node & 0x80 != 0)
+            } else {
+                (left + 1, node & 0x40 != 0)
+            };
+            if !is_data {
+                node_addr = child;
+                continue;
+            }
+            let value = gba.sysbus.read_8(child) as u32 & ((1 << bits) - 1);
+            word_out |= value << word_out_bits;
+            word_out_bits += bits;
+            node_addr = root;
+            if word_out_bits == 32 {
+                out.extend_from_slice(&word_out.to_le_bytes());
+                word_out = 0;
+                word_out_bits = 0;
+                if out.len() >= size {
+                    break;
+                }
+            }
+        }
+    }
+    out.truncate(size);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::{make_hle_gba, make_rom};
+
+    fn run_until(gba: &mut GameBoyAdvance, pc: Addr) {
+        for _ in 0..1_000_000 {
+            let _ = gba.step();
+            if gba.cpu.get_next_pc() == pc {
+                return;
+            }
+        }
+        panic!("pc never reached 0x{:08x}", pc);
+    }
+
+    #[test]
+    fn test_builtin_reset() {
+        let mut gba = make_hle_gba(&make_rom(&[0xeafffffe])); // b .
+        run_until(&mut gba, 0x0800_0000);
+        assert_eq!(gba.cpu.get_reg(13), 0x0300_7f00);
+        assert_eq!(gba.cpu.cpsr.get() & 0xff, 0x1f);
+    }
+
+    #[test]
+    fn test_swi() {
+        #[rustfmt::skip]
+        let mut gba = make_hle_gba(&make_rom(&[
+            0xe3a00064, // mov r0, #100
+            0xe3e01006, // mov r1, #-7
+            0xef060000, // swi 0x06          ; Div
+            0xe1a04000, // mov r4, r0
+            0xe1a05001, // mov r5, r1
+            0xe3a00c01, // mov r0, #0x100
+            0xe2800001, // add r0, r0, #1
+            0xef080000, // swi 0x08          ; Sqrt
+            0xeafffffe, // b .
+        ]));
+        run_until(&mut gba, 0x0800_0020);
+        assert_eq!(gba.cpu.get_reg(4) as i32, -14);
+        assert_eq!(gba.cpu.get_reg(5) as i32, 2);
+        assert_eq!(gba.cpu.get_reg(3), 14);
+        assert_eq!(gba.cpu.get_reg(0), 16);
+    }
+
+    #[test]
+    fn test_vblank_intr_wait() {
+        #[rustfmt::skip]
+        let mut gba = make_hle_gba(&make_rom(&[
+            0xe3a00301, // mov r0, #0x04000000
+            0xe3a01008, // mov r1, #8
+            0xe1c010b4, // strh r1, [r0, #4]     ; DISPSTAT vblank irq
+            0xe3a01001, // mov r1, #1
+            0xe1a00000, // nop
+            0xe2800c02, // add r0, r0, #0x200
+            0xe1c010b0, // strh r1, [r0]         ; IE = vblank
+            0xe59f1010, // ldr r1, =handler
+            0xe3a00403, // mov r0, #0x03000000
+            0xe2800c7f, // add r0, r0, #0x7f00
+            0xe58010fc, // str r1, [r0, #0xfc]   ; irq handler pointer
+            0xef050000, // swi 0x05              ; VBlankIntrWait
+            0xeafffffe, // b .
+            0x0800_0038, // .word handler
+            // handler: acknowledge vblank in IF and in the bios flags
+            0xe3a00301, // mov r0, #0x04000000
+            0xe3a01001, // mov r1, #1
+            0xe2800c02, // add r0, r0, #0x200
+            0xe1c010b2, // strh r1, [r0, #2]     ; IF
+            0xe3a00301, // mov r0, #0x04000000
+            0xe14010b8, // strh r1, [r0, #-8]    ; bios IF
+            0xe12fff1e, // bx lr
+        ]));
+        run_until(&mut gba, 0x0800_0030);
+        assert_eq!(gba.io_devs.gpu.vcount, 160);
+        assert_eq!(gba.debug_read_16(0x0300_7ff8), 0);
+    }
+}
@@ -1,15 +1,16 @@
 use std::cmp;
 
-use arm7tdmi::memory::{Addr, BusIO, DebugRead};
+use arm7tdmi::memory::{Addr, BusIO, DebugRead, DebugWrite};
 
 use super::dma::DmaController;
 use super::gpu::regs::GpuMemoryMappedIO;
 use super::gpu::regs::WindowFlags;
 use super::gpu::*;
-use super::interrupt::{InterruptConnect, InterruptController, SharedInterruptFlags};
+use super::interrupt::{InterruptConnect, InterruptController, IrqBitmask, SharedInterruptFlags};
 use super::keypad;
 use super::mgba_debug::DebugPort;
 use super::sched::{Scheduler, SchedulerConnect, SharedScheduler};
+use super::sio::Sio;
 use super::sound::SoundController;
 use super::sysbus::SysBusPtr;
 use super::timer::Timers;
@@ -32,6 +33,7 @@ pub struct IoDevices {
     pub sound: Box<SoundController>,
     pub timers: Timers,
     pub dmac: DmaController,
+    pub sio: Sio,
     pub keyinput: u16,
     pub post_boot_flag: bool,
     pub waitcnt: WaitControl, // TODO also implement 4000800
@@ -55,6 +57,7 @@ impl IoDevices {
         dmac: DmaController,
         timers: Timers,
         sound_controller: Box<SoundController>,
+        sio: Sio,
         scheduler: SharedScheduler,
     ) -> IoDevices {
         IoDevices {
@@ -62,6 +65,7 @@ impl IoDevices {
             gpu,
             timers,
             dmac,
+            sio,
             sound: sound_controller,
             post_boot_flag: false,
             haltcnt: HaltState::Running,
@@ -76,6 +80,15 @@ impl IoDevices {
     pub fn set_sysbus_ptr(&mut self, ptr: SysBusPtr) {
         self.sysbus_ptr = ptr;
     }
+
+    fn write_haltcnt(&mut self, value: u8) {
+        if value & 0x80 != 0 {
+            // self.haltcnt = HaltState::Stop;
+            panic!("Can't handle HaltCtrl == Stop yet");
+        } else {
+            self.haltcnt = HaltState::Halt;
+        }
+    }
 }
 
 impl InterruptConnect for IoDevices {
@@ -83,7 +96,8 @@ impl InterruptConnect for IoDevices {
         self.intc.connect_irq(interrupt_flags.clone());
         self.gpu.connect_irq(interrupt_flags.clone());
         self.dmac.connect_irq(interrupt_flags.clone());
-        self.timers.connect_irq(interrupt_flags);
+        self.timers.connect_irq(interrupt_flags.clone());
+        self.sio.connect_irq(interrupt_flags);
     }
 }
 
@@ -93,15 +107,12 @@ impl SchedulerConnect for IoDevices {
     }
 }
 
-impl BusIO for IoDevices {
-    fn read_16(&mut self, addr: Addr) -> u16 {
+impl IoDevices {
+    /// The value of the register at `io_addr`, None for addresses nothing answers.
+    /// Write only registers still give their value, for merging byte writes and the debugger.
+    fn read_register(&mut self, io_addr: Addr) -> Option<u16> {
         let io = self;
-        let io_addr = addr + IO_BASE;
-        // if addr > 0x0800 {
-        //     return 0;
-        // }
-
-        match io_addr {
+        let value = match io_addr {
             REG_DISPCNT => io.gpu.dispcnt.read(),
             REG_DISPSTAT => io.gpu.dispstat.read(),
             REG_VCOUNT => io.gpu.vcount as u16,
@@ -133,9 +144,8 @@ impl BusIO for IoDevices {
             REG_DMA1CNT_H => io.dmac.channels[1].ctrl.0,
             REG_DMA2CNT_H => io.dmac.channels[2].ctrl.0,
             REG_DMA3CNT_H => io.dmac.channels[3].ctrl.0,
-            // Even though these registers are write only,
-            // some games may still try to read them.
-            // TODO: should this be treated as an open-bus read?
+            // Write only, but unlike the other write only registers they read as zero, which
+            // matters for the many games that read the whole control word
             REG_DMA0CNT_L => 0,
             REG_DMA1CNT_L => 0,
             REG_DMA2CNT_L => 0,
@@ -147,17 +157,42 @@ impl BusIO for IoDevices {
             REG_HALTCNT => 0,
             REG_KEYINPUT => io.keyinput,
 
+            REG_SIOMULTI0..=REG_SIODATA8 | REG_RCNT | REG_JOYCNT..=REG_JOYSTAT => {
+                io.sio.read(io_addr)
+            }
+
             x if DebugPort::is_debug_access(x) => io.debug.read(io_addr),
 
-            _ => {
-                trace!(
-                    "Unimplemented read from {:x} {}",
-                    io_addr,
-                    io_reg_string(io_addr)
-                );
-                0
+            // The unused upper halves of WAITCNT, IME and POSTFLG/HALTCNT read as zero
+            0x0400_0206 | 0x0400_020A | 0x0400_0302 => 0,
+            // Internal memory control, not emulated
+            0x0400_0800 | 0x0400_0802 => 0,
+
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// The halfword of the open bus value a read of `addr` sees
+    fn read_open_bus(&mut self, addr: Addr) -> u16 {
+        (self.sysbus_ptr.open_bus() >> ((addr & 2) << 3)) as u16
+    }
+}
+
+impl BusIO for IoDevices {
+    fn read_16(&mut self, addr: Addr) -> u16 {
+        let io_addr = addr + IO_BASE;
+        if !is_write_only(io_addr) {
+            if let Some(value) = self.read_register(io_addr) {
+                return value;
             }
+            trace!(
+                "Unimplemented read from {:x} {}",
+                io_addr,
+                io_reg_string(io_addr)
+            );
         }
+        self.read_open_bus(addr)
     }
 
     fn read_8(&mut self, addr: Addr) -> u8 {
@@ -270,7 +305,7 @@ impl BusIO for IoDevices {
             }
 
             SOUND_BASE..=SOUND_END => {
-                io.sound.handle_write(io_addr, value);
+                io.sound.handle_write(io_addr, value, &mut io.scheduler);
             }
 
             DMA_BASE..=REG_DMA3CNT_H => {
@@ -285,16 +320,13 @@ impl BusIO for IoDevices {
                 (*io.sysbus_ptr).on_waitcnt_written(io.waitcnt);
             }
 
-            REG_POSTFLG => io.post_boot_flag = value != 0,
-            REG_HALTCNT => {
-                if value & 0x80 != 0 {
-                    // io.haltcnt = HaltState::Stop;
-                    panic!("Can't handle HaltCtrl == Stop yet");
-                } else {
-                    io.haltcnt = HaltState::Halt;
-                }
+            REG_SIOMULTI0..=REG_SIODATA8 | REG_RCNT | REG_JOYCNT..=REG_JOYSTAT => {
+                io.sio.write(io_addr, value, &mut io.scheduler)
             }
 
+            REG_POSTFLG => io.post_boot_flag = value != 0,
+            REG_HALTCNT => io.write_haltcnt(value as u8),
+
             x if DebugPort::is_debug_access(x) => io.debug.write(io_addr, value),
 
             _ => {
@@ -317,8 +349,14 @@ impl BusIO for IoDevices {
             0x0400_00A4 | 0x0400_00A5 | 0x0400_00A6 | 0x0400_00A7 => {
                 self.sound.write_fifo(1, value as i8)
             }
+            io_addr @ SOUND_BASE..=SOUND_END => {
+                self.sound
+                    .handle_write_8(io_addr, value, &mut self.scheduler)
+            }
+            // Merged into a halfword write, this would only reach REG_POSTFLG
+            REG_HALTCNT => self.write_haltcnt(value),
             _ => {
-                let t = self.read_16(addr & !1);
+                let t = self.read_register((addr & !1) + IO_BASE).unwrap_or(0);
                 let t = if addr & 1 != 0 {
                     (t & 0xff) | (value as u16) << 8
                 } else {
@@ -331,8 +369,78 @@ impl BusIO for IoDevices {
 }
 
 impl DebugRead for IoDevices {
+    /// Shows the value of write only registers instead of the open bus
     fn debug_read_8(&mut self, addr: Addr) -> u8 {
-        self.read_8(addr)
+        let t = self.read_register((addr & !1) + IO_BASE).unwrap_or(0);
+        (t >> ((addr & 1) << 3)) as u8
+    }
+}
+
+/// Registers that can't be read back, a read sees the open bus instead
+fn is_write_only(io_addr: Addr) -> bool {
+    matches!(
+        io_addr,
+        REG_BG0HOFS..=REG_BG3VOFS
+            | REG_BG2PA..=REG_BG3Y_H
+            | REG_WIN0H..=REG_WIN1V
+            | REG_MOSAIC
+            | REG_BLDY
+    ) || (REG_FIFO_A..SOUND_END).contains(&io_addr)
+        || [REG_DMA0SAD, REG_DMA1SAD, REG_DMA2SAD, REG_DMA3SAD]
+            .iter()
+            .any(|&sad| (sad..sad + 8).contains(&io_addr))
+}
+
+/// Registers where a write starts a transfer, a timer or a sound channel, acknowledges
+/// interrupts or halts the cpu, rather than just storing the value.
+fn has_write_side_effects(io_addr: Addr) -> bool {
+    matches!(
+        io_addr,
+        REG_IF
+            | REG_HALTCNT
+            | REG_DMA0CNT_H
+            | REG_DMA1CNT_H
+            | REG_DMA2CNT_H
+            | REG_DMA3CNT_H
+            | REG_TM0CNT_H
+            | REG_TM1CNT_H
+            | REG_TM2CNT_H
+            | REG_TM3CNT_H
+            | REG_SIOCNT
+            | REG_SIODATA8
+            | REG_SOUND1CNT_X
+            | REG_SOUND2CNT_H
+            | REG_SOUND3CNT_X
+            | REG_SOUND4CNT_H
+            | REG_SOUNDCNT_H
+            | REG_SOUNDCNT_X
+    ) || (REG_FIFO_A..SOUND_END).contains(&io_addr)
+}
+
+impl DebugWrite for IoDevices {
+    /// Writes go through the regular register write, except for registers with side effects.
+    /// IF is set as is, so the debugger can raise interrupts, the rest are ignored.
+    /// Use `write_8` for those when the side effects are wanted.
+    fn debug_write_8(&mut self, addr: Addr, value: u8) {
+        let io_addr = (addr & !1) + IO_BASE;
+        if !has_write_side_effects(io_addr) {
+            return self.write_8(addr, value);
+        }
+        match io_addr {
+            REG_IF => {
+                let flags = self.intc.interrupt_flags.get().value();
+                let flags = if addr & 1 != 0 {
+                    (flags & 0xff) | (value as u16) << 8
+                } else {
+                    (flags & 0xff00) | (value as u16)
+                };
+                self.intc.interrupt_flags.set(IrqBitmask(flags & 0x3fff));
+            }
+            _ => warn!(
+                "ignoring debugger write to {}, it has side effects",
+                io_reg_string(io_addr)
+            ),
+        }
     }
 }
 
@@ -350,7 +458,7 @@ bitfield! {
     pub ws2_second_access, _:      10, 10;
     #[allow(non_snake_case)]
     PHI_terminal_output, _:    12, 11;
-    prefetch, _:               14;
+    pub prefetch, _:           14;
 }
 
 #[rustfmt::skip]
@@ -445,14 +553,14 @@ pub mod consts {
     pub const REG_TM2CNT_H: Addr = 0x0400_010A;     //  2    R/W    Timer 2 Control
     pub const REG_TM3CNT_L: Addr = 0x0400_010C;     //  2    R/W    Timer 3 Counter/Reload
     pub const REG_TM3CNT_H: Addr = 0x0400_010E;     //  2    R/W    Timer 3 Control
-    // pub const REG_SIODATA32: Addr = 0x0400_0120;    //  4    R/W    SIO Data (Normal-32bit Mode; shared with below)
-    // pub const REG_SIOMULTI0: Addr = 0x0400_0120;    //  2    R/W    SIO Data 0 (Parent)    (Multi-Player Mode)
-    // pub const REG_SIOMULTI1: Addr = 0x0400_0122;    //  2    R/W    SIO Data 1 (1st Child) (Multi-Player Mode)
-    // pub const REG_SIOMULTI2: Addr = 0x0400_0124;    //  2    R/W    SIO Data 2 (2nd Child) (Multi-Player Mode)
-    // pub const REG_SIOMULTI3: Addr = 0x0400_0126;    //  2    R/W    SIO Data 3 (3rd Child) (Multi-Player Mode)
-    // pub const REG_SIOCNT: Addr = 0x0400_0128;       //  2    R/W    SIO Control Register
-    // pub const REG_SIOMLT_SEND: Addr = 0x0400_012A;  //  2    R/W    SIO Data (Local of MultiPlayer; shared below)
-    // pub const REG_SIODATA8: Addr = 0x0400_012A;     //  2    R/W    SIO Data (Normal-8bit and UART Mode)
+    pub const REG_SIODATA32: Addr = 0x0400_0120;    //  4    R/W    SIO Data (Normal-32bit Mode; shared with below)
+    pub const REG_SIOMULTI0: Addr = 0x0400_0120;    //  2    R/W    SIO Data 0 (Parent)    (Multi-Player Mode)
+    pub const REG_SIOMULTI1: Addr = 0x0400_0122;    //  2    R/W    SIO Data 1 (1st Child) (Multi-Player Mode)
+    pub const REG_SIOMULTI2: Addr = 0x0400_0124;    //  2    R/W    SIO Data 2 (2nd Child) (Multi-Player Mode)
+    pub const REG_SIOMULTI3: Addr = 0x0400_0126;    //  2    R/W    SIO Data 3 (3rd Child) (Multi-Player Mode)
+    pub const REG_SIOCNT: Addr = 0x0400_0128;       //  2    R/W    SIO Control Register
+    pub const REG_SIOMLT_SEND: Addr = 0x0400_012A;  //  2    R/W    SIO Data (Local of MultiPlayer; shared below)
+    pub const REG_SIODATA8: Addr = 0x0400_012A;     //  2    R/W    SIO Data (Normal-8bit and UART Mode)
     pub const REG_KEYINPUT: Addr = 0x0400_0130;     //  2    R      Key Status
     pub const REG_KEYCNT: Addr = 0x0400_0132;       //  2    R/W    Key Interrupt Control
     pub const REG_RCNT: Addr = 0x0400_0134;         //  2    R/W    SIO Mode Select/General Purpose Data
@@ -557,14 +665,14 @@ pub fn io_reg_string(addr: u32) -> &'static str {
         REG_TM2CNT_H => "REG_TM2CNT_H",
         REG_TM3CNT_L => "REG_TM3CNT_L",
         REG_TM3CNT_H => "REG_TM3CNT_H",
-        // REG_SIODATA32 => "REG_SIODATA32",
+        REG_SIODATA32 => "REG_SIODATA32",
         // REG_SIOMULTI0 => "REG_SIOMULTI0",
-        // REG_SIOMULTI1 => "REG_SIOMULTI1",
-        // REG_SIOMULTI2 => "REG_SIOMULTI2",
-        // REG_SIOMULTI3 => "REG_SIOMULTI3",
-        // REG_SIOCNT => "REG_SIOCNT",
+        REG_SIOMULTI1 => "REG_SIOMULTI1",
+        REG_SIOMULTI2 => "REG_SIOMULTI2",
+        REG_SIOMULTI3 => "REG_SIOMULTI3",
+        REG_SIOCNT => "REG_SIOCNT",
         // REG_SIOMLT_SEND => "REG_SIOMLT_SEND",
-        // REG_SIODATA8 => "REG_SIODATA8",
+        REG_SIODATA8 => "REG_SIODATA8",
         REG_KEYINPUT => "REG_KEYINPUT",
         REG_KEYCNT => "REG_KEYCNT",
         REG_RCNT => "REG_RCNT",
@@ -33,8 +33,10 @@ pub use arm7tdmi;
 pub use arm7tdmi::disass;
 mod bios;
 pub mod cartridge;
+pub mod cheats;
 pub mod gpu;
 mod sched;
+pub mod sio;
 pub mod sound;
 pub mod sysbus;
 pub use sysbus::SysBus;
@@ -46,10 +48,19 @@ pub mod gba;
 pub use gba::GameBoyAdvance;
 pub mod dma;
 pub mod gdb_support;
+pub mod hle;
 pub mod keypad;
+pub mod memsearch;
 mod mgba_debug;
+pub mod movie;
 pub(crate) mod overrides;
+pub mod rewind;
+pub mod savestate;
 pub mod timer;
+pub mod watchpoint;
+
+#[cfg(test)]
+pub(crate) mod test_utils;
 
 use arm7tdmi::gdb::gdbstub::stub::GdbStubError;
 
@@ -105,7 +116,7 @@ impl From<GdbStubError<(), std::io::Error>> for GBAError {
 }
 
 pub mod prelude {
-    pub use super::cartridge::{Cartridge, GamepakBuilder};
+    pub use super::cartridge::{Cartridge, DynRumbleInterface, GamepakBuilder, RumbleInterface};
     #[cfg(feature = "debugger")]
     pub use super::debugger::Debugger;
     pub use super::gpu::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
@@ -0,0 +1,281 @@
+//! Memory search over EWRAM and IWRAM, for finding where a game keeps its variables.
+//!
+//! A search starts with a snapshot of every aligned value and is then narrowed down by how the
+//! values changed since the previous step, or by a known value, like Cheat Engine does.
+//! The remaining addresses can be turned into cheats or watchpoints.
+use std::fmt;
+use std::str::FromStr;
+
+use arm7tdmi::memory::{Addr, DebugRead};
+
+use super::cheats::{Cheat, CheatError, CheatFormat, CheatResult};
+use super::sysbus::consts::{EWRAM_ADDR, IWRAM_ADDR};
+use super::watchpoint::{WatchKind, Watchpoint};
+use super::GameBoyAdvance;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchWidth {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+}
+
+impl SearchWidth {
+    pub fn bytes(self) -> u32 {
+        match self {
+            SearchWidth::U8 | SearchWidth::I8 => 1,
+            SearchWidth::U16 | SearchWidth::I16 => 2,
+            SearchWidth::U32 | SearchWidth::I32 => 4,
+        }
+    }
+
+    fn is_signed(self) -> bool {
+        matches!(self, SearchWidth::I8 | SearchWidth::I16 | SearchWidth::I32)
+    }
+
+    /// The value of the low `bytes()` bytes of `raw`
+    pub fn interpret(self, raw: u32) -> i64 {
+        let shift = 32 - 8 * self.bytes();
+        if self.is_signed() {
+            ((raw << shift) as i32 >> shift) as i64
+        } else {
+            ((raw << shift) >> shift) as i64
+        }
+    }
+
+    fn read(self, bus: &mut impl DebugRead, addr: Addr) -> i64 {
+        let raw = match self.bytes() {
+            1 => bus.debug_read_8(addr) as u32,
+            2 => bus.debug_read_16(addr) as u32,
+            _ => bus.debug_read_32(addr),
+        };
+        self.interpret(raw)
+    }
+
+    fn read_snapshot(self, memory: &[u8], offset: usize) -> i64 {
+        let mut bytes = [0; 4];
+        let len = self.bytes() as usize;
+        bytes[..len].copy_from_slice(&memory[offset..offset + len]);
+        self.interpret(u32::from_le_bytes(bytes))
+    }
+}
+
+impl fmt::Display for SearchWidth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SearchWidth::U8 => "u8",
+            SearchWidth::I8 => "i8",
+            SearchWidth::U16 => "u16",
+            SearchWidth::I16 => "i16",
+            SearchWidth::U32 => "u32",
+            SearchWidth::I32 => "i32",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for SearchWidth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "u8" => Ok(SearchWidth::U8),
+            "i8" | "s8" => Ok(SearchWidth::I8),
+            "u16" => Ok(SearchWidth::U16),
+            "i16" | "s16" => Ok(SearchWidth::I16),
+            "u32" => Ok(SearchWidth::U32),
+            "i32" | "s32" => Ok(SearchWidth::I32),
+            _ => Err(format!("unknown search width {:?}", s)),
+        }
+    }
+}
+
+/// How to narrow down a search, everything but `Value` compares against the previous step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchFilter {
+    Equal,
+    Changed,
+    Increased,
+    Decreased,
+    Value(i64),
+}
+
+impl SearchFilter {
+    fn matches(self, previous: i64, current: i64) -> bool {
+        match self {
+            SearchFilter::Equal => current == previous,
+            SearchFilter::Changed => current != previous,
+            SearchFilter::Increased => current > previous,
+            SearchFilter::Decreased => current < previous,
+            SearchFilter::Value(value) => current == value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchResult {
+    pub addr: Addr,
+    pub value: i64,
+    /// The value before the last narrowing
+    pub previous: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct MemorySearch {
+    width: SearchWidth,
+    results: Vec<SearchResult>,
+}
+
+impl MemorySearch {
+    /// Snapshot every value of EWRAM and IWRAM that is aligned to the width
+    pub fn new(gba: &GameBoyAdvance, width: SearchWidth) -> MemorySearch {
+        let bus = &gba.sysbus;
+        let step = width.bytes() as usize;
+        let mut results = Vec::new();
+        for (base, memory) in &[(EWRAM_ADDR, bus.get_ewram()), (IWRAM_ADDR, bus.get_iwram())] {
+            results.extend((0..memory.len()).step_by(step).map(|offset| {
+                let value = width.read_snapshot(memory, offset);
+                SearchResult {
+                    addr: base + offset as Addr,
+                    value,
+                    previous: value,
+                }
+            }));
+        }
+        MemorySearch { width, results }
+    }
+
+    /// Keep the addresses that pass the filter, returns how many are left
+    pub fn narrow(&mut self, gba: &mut GameBoyAdvance, filter: SearchFilter) -> usize {
+        let width = self.width;
+        let bus = &mut *gba.sysbus;
+        self.results = self
+            .results
+            .iter()
+            .filter_map(|result| {
+                let current = width.read(bus, result.addr);
+                if filter.matches(result.value, current) {
+                    Some(SearchResult {
+                        addr: result.addr,
+                        value: current,
+                        previous: result.value,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect();
+        self.results.len()
+    }
+
+    pub fn width(&self) -> SearchWidth {
+        self.width
+    }
+
+    pub fn results(&self) -> &[SearchResult] {
+        &self.results
+    }
+
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+
+    /// A cheat that keeps `value` at `addr`, as a decrypted GameShark code
+    pub fn make_cheat(&self, addr: Addr, value: i64) -> CheatResult<Cheat> {
+        let (code_type, mask) = match self.width.bytes() {
+            1 => (0, 0xff),
+            2 => (1, 0xffff),
+            _ => (2, 0xffff_ffff),
+        };
+        if addr > 0x0fff_ffff {
+            return Err(CheatError::Unsupported {
+                code: format!("0x{:08x}", addr),
+                reason: "write codes only have 28 bits for the address",
+            });
+        }
+        let codes = format!("{:X}{:07X} {:08X}", code_type, addr, value as u32 & mask);
+        Cheat::new(
+            &format!("{} at 0x{:08x} = {}", self.width, addr, value),
+            CheatFormat::GameSharkV1Raw,
+            &codes,
+        )
+    }
+
+    /// A watchpoint that covers the value at `addr`
+    pub fn make_watchpoint(&self, addr: Addr, kind: WatchKind) -> Watchpoint {
+        Watchpoint {
+            addr,
+            len: self.width.bytes(),
+            kind,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::prelude::*;
+    use crate::test_utils::{make_mock_gba, make_rom};
+
+    #[test]
+    fn test_interpret() {
+        assert_eq!(SearchWidth::U8.interpret(0x1ff), 0xff);
+        assert_eq!(SearchWidth::I8.interpret(0x1ff), -1);
+        assert_eq!(SearchWidth::I16.interpret(0x8000), -0x8000);
+        assert_eq!(SearchWidth::U32.interpret(0xffff_ffff), 0xffff_ffff);
+        assert_eq!(SearchWidth::I32.interpret(0xffff_ffff), -1);
+    }
+
+    #[test]
+    fn test_narrow() {
+        let mut gba = make_mock_gba(&make_rom(&[0xeafffffe])); // b .
+        gba.sysbus.write_16(0x0200_1000, 100);
+        gba.sysbus.write_16(0x0300_0100, 100);
+
+        let mut search = MemorySearch::new(&gba, SearchWidth::I16);
+        assert_eq!(search.len(), (0x40000 + 0x8000) / 2);
+
+        gba.sysbus.write_16(0x0200_1000, 99);
+        gba.sysbus.write_16(0x0300_0100, 101);
+        assert_eq!(search.narrow(&mut gba, SearchFilter::Changed), 2);
+
+        gba.sysbus.write_16(0x0200_1000, 0xffff);
+        search.narrow(&mut gba, SearchFilter::Decreased);
+        assert_eq!(
+            search.results(),
+            &[SearchResult {
+                addr: 0x0200_1000,
+                value: -1,
+                previous: 99,
+            }]
+        );
+        assert_eq!(search.narrow(&mut gba, SearchFilter::Equal), 1);
+        assert_eq!(search.narrow(&mut gba, SearchFilter::Value(5)), 0);
+    }
+
+    #[test]
+    fn test_promote() {
+        let mut gba = make_mock_gba(&make_rom(&[0xeafffffe])); // b .
+        let search = MemorySearch::new(&gba, SearchWidth::U16);
+        let cheat = search.make_cheat(0x0300_0010, 0x1234).unwrap();
+        gba.add_cheat(cheat);
+        gba.frame();
+        assert_eq!(gba.debug_read_16(0x0300_0010), 0x1234);
+
+        let watchpoint = search.make_watchpoint(0x0300_0010, WatchKind::Write);
+        assert_eq!(watchpoint.len, 2);
+
+        assert!(matches!(
+            search.make_cheat(0x1000_0000, 1),
+            Err(CheatError::Unsupported { .. })
+        ));
+    }
+}
@@ -0,0 +1,334 @@
+//! Input movies, a recording of the keys held on every frame that can be played back
+//! deterministically from the same starting point.
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::cartridge::RtcClock;
+use super::savestate::{self, SaveStateError};
+use super::GameBoyAdvance;
+
+const MOVIE_MAGIC: [u8; 4] = *b"RBAM";
+const MOVIE_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum MovieError {
+    IO(io::Error),
+    Serialization(bincode::Error),
+    SaveState(SaveStateError),
+    BadMagic,
+    UnsupportedVersion(u32),
+    /// The movie was recorded with a different rom
+    RomMismatch,
+    /// Power-on movies must start before the first frame is emulated
+    NotAtPowerOn,
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::IO(e) => write!(f, "movie io error: {}", e),
+            MovieError::Serialization(e) => write!(f, "movie serialization error: {}", e),
+            MovieError::SaveState(e) => write!(f, "movie savestate error: {}", e),
+            MovieError::BadMagic => write!(f, "not a movie file"),
+            MovieError::UnsupportedVersion(v) => write!(f, "unsupported movie version {}", v),
+            MovieError::RomMismatch => write!(f, "movie was recorded with a different rom"),
+            MovieError::NotAtPowerOn => write!(f, "power-on movies must start at power-on"),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl From<io::Error> for MovieError {
+    fn from(err: io::Error) -> MovieError {
+        MovieError::IO(err)
+    }
+}
+
+impl From<bincode::Error> for MovieError {
+    fn from(err: bincode::Error) -> MovieError {
+        MovieError::Serialization(err)
+    }
+}
+
+impl From<SaveStateError> for MovieError {
+    fn from(err: SaveStateError) -> MovieError {
+        MovieError::SaveState(err)
+    }
+}
+
+pub type MovieResult<T> = Result<T, MovieError>;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum MovieStart {
+    PowerOn { skip_bios: bool },
+    Savestate(Vec<u8>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Movie {
+    /// SHA-256 of the rom the movie was recorded with
+    pub rom_hash: [u8; 32],
+    pub game_code: String,
+    pub start: MovieStart,
+    /// KEYINPUT for every frame
+    pub frames: Vec<u16>,
+    /// The clock of the RTC, switched to a deterministic one for the recording
+    pub rtc_clock: Option<RtcClock>,
+}
+
+fn rom_hash(gba: &GameBoyAdvance) -> [u8; 32] {
+    savestate::sha256(gba.sysbus.cartridge.get_rom_bytes())
+}
+
+impl Movie {
+    pub fn to_bytes(&self) -> MovieResult<Vec<u8>> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&MOVIE_MAGIC);
+        bytes.extend_from_slice(&MOVIE_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut bytes, self)?;
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> MovieResult<Movie> {
+        if bytes.len() < 8 || bytes[0..4] != MOVIE_MAGIC {
+            return Err(MovieError::BadMagic);
+        }
+        let mut version = [0; 4];
+        version.copy_from_slice(&bytes[4..8]);
+        match u32::from_le_bytes(version) {
+            MOVIE_VERSION => Ok(bincode::deserialize(&bytes[8..])?),
+            version => Err(MovieError::UnsupportedVersion(version)),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> MovieResult<()> {
+        Ok(fs::write(path, self.to_bytes()?)?)
+    }
+
+    pub fn load(path: &Path) -> MovieResult<Movie> {
+        Movie::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+/// Both the recording and the playback start with the frame timing reset,
+/// so the input lands at the exact same cycle every time.
+fn align_frame(gba: &mut GameBoyAdvance) {
+    gba.overshoot = 0;
+}
+
+fn check_power_on(gba: &GameBoyAdvance) -> MovieResult<()> {
+    match gba.scheduler.timestamp() {
+        0 => Ok(()),
+        _ => Err(MovieError::NotAtPowerOn),
+    }
+}
+
+/// Records the key state of every frame.
+/// Call `record_frame` right before `GameBoyAdvance::frame` once the frontend updated the input.
+pub struct MovieRecorder {
+    movie: Movie,
+}
+
+impl MovieRecorder {
+    /// Record from power-on, the gba must not have run a single frame yet
+    pub fn power_on(gba: &mut GameBoyAdvance, skip_bios: bool) -> MovieResult<MovieRecorder> {
+        check_power_on(gba)?;
+        if skip_bios {
+            gba.skip_bios();
+        }
+        let rtc_clock = gba.make_rtc_deterministic();
+        Ok(MovieRecorder::new(
+            gba,
+            MovieStart::PowerOn { skip_bios },
+            rtc_clock,
+        ))
+    }
+
+    /// Record from the current state, which gets embedded into the movie
+    pub fn from_savestate(gba: &mut GameBoyAdvance) -> MovieResult<MovieRecorder> {
+        let rtc_clock = gba.make_rtc_deterministic();
+        let savestate = gba.save_state()?;
+        Ok(MovieRecorder::new(
+            gba,
+            MovieStart::Savestate(savestate),
+            rtc_clock,
+        ))
+    }
+
+    fn new(
+        gba: &mut GameBoyAdvance,
+        start: MovieStart,
+        rtc_clock: Option<RtcClock>,
+    ) -> MovieRecorder {
+        align_frame(gba);
+        MovieRecorder {
+            movie: Movie {
+                rom_hash: rom_hash(gba),
+                game_code: gba.get_game_code(),
+                start,
+                frames: Vec::new(),
+                rtc_clock,
+            },
+        }
+    }
+
+    pub fn record_frame(&mut self, gba: &mut GameBoyAdvance) {
+        self.movie.frames.push(*gba.get_key_state());
+    }
+
+    pub fn frames_recorded(&self) -> usize {
+        self.movie.frames.len()
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+/// Feeds the recorded input back.
+/// Call `play_frame` right before `GameBoyAdvance::frame`, instead of updating the input.
+pub struct MoviePlayer {
+    movie: Movie,
+    frame: usize,
+}
+
+impl MoviePlayer {
+    /// Bring the gba to the starting point of the movie.
+    /// Power-on movies need a freshly created gba, the bios skip is handled here.
+    pub fn start(movie: Movie, gba: &mut GameBoyAdvance) -> MovieResult<MoviePlayer> {
+        if rom_hash(gba) != movie.rom_hash {
+            return Err(MovieError::RomMismatch);
+        }
+        match &movie.start {
+            MovieStart::PowerOn { skip_bios } => {
+                check_power_on(gba)?;
+                if *skip_bios {
+                    gba.skip_bios();
+                }
+            }
+            MovieStart::Savestate(savestate) => gba.restore_state(savestate)?,
+        }
+        if let Some(rtc_clock) = movie.rtc_clock {
+            gba.set_rtc_clock(rtc_clock);
+        }
+        align_frame(gba);
+        Ok(MoviePlayer { movie, frame: 0 })
+    }
+
+    /// Apply the input for the next frame, returns false once the movie is over
+    pub fn play_frame(&mut self, gba: &mut GameBoyAdvance) -> bool {
+        match self.movie.frames.get(self.frame) {
+            Some(keyinput) => {
+                *gba.get_key_state_mut() = *keyinput;
+                self.frame += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn current_frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.movie.frames.len()
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::{make_mock_gba, make_rom};
+
+    /// Keeps adding KEYINPUT into the first word of iwram
+    const CODE: [u32; 8] = [
+        0xe3a00301, // mov r0, #0x04000000
+        0xe2800e13, // add r0, r0, #0x130
+        0xe3a02403, // mov r2, #0x03000000
+        0xe1d010b0, // loop: ldrh r1, [r0]
+        0xe5923000, // ldr r3, [r2]
+        0xe0833001, // add r3, r3, r1
+        0xe5823000, // str r3, [r2]
+        0xeafffffa, // b loop
+    ];
+
+    fn record(gba: &mut GameBoyAdvance, mut recorder: MovieRecorder) -> Movie {
+        for frame in 0..30 {
+            *gba.get_key_state_mut() = 0x3ff & !((frame * 7) & 0xff);
+            recorder.record_frame(gba);
+            gba.frame();
+        }
+        recorder.finish()
+    }
+
+    fn play(gba: &mut GameBoyAdvance, movie: Movie) {
+        let mut player = MoviePlayer::start(movie, gba).unwrap();
+        while player.play_frame(gba) {
+            gba.frame();
+        }
+        assert!(player.is_finished());
+    }
+
+    #[test]
+    fn test_power_on_playback() {
+        let mut gba = make_mock_gba(&make_rom(&CODE));
+        let recorder = MovieRecorder::power_on(&mut gba, true).unwrap();
+        let movie = record(&mut gba, recorder);
+        let expected = gba.debug_read_32(0x0300_0000);
+        assert_ne!(expected, 0);
+
+        let movie = Movie::from_bytes(&movie.to_bytes().unwrap()).unwrap();
+        assert_eq!(movie.len(), 30);
+
+        let mut gba = make_mock_gba(&make_rom(&CODE));
+        play(&mut gba, movie);
+        assert_eq!(gba.debug_read_32(0x0300_0000), expected);
+    }
+
+    #[test]
+    fn test_savestate_playback() {
+        let mut gba = make_mock_gba(&make_rom(&CODE));
+        for _ in 0..3 {
+            gba.frame();
+        }
+        assert!(MovieRecorder::power_on(&mut gba, false).is_err());
+
+        let recorder = MovieRecorder::from_savestate(&mut gba).unwrap();
+        let movie = record(&mut gba, recorder);
+        let expected = gba.debug_read_32(0x0300_0000);
+
+        // keep running so playback has to rewind to the embedded state
+        for _ in 0..3 {
+            gba.frame();
+        }
+        play(&mut gba, movie);
+        assert_eq!(gba.debug_read_32(0x0300_0000), expected);
+    }
+
+    #[test]
+    fn test_bad_magic() {
+        assert!(matches!(
+            Movie::from_bytes(b"not a movie"),
+            Err(MovieError::BadMagic)
+        ));
+    }
+}
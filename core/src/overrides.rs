@@ -8,6 +8,10 @@ use super::cartridge::BackupType;
 #[derive(Debug)]
 pub struct GameOverride {
     force_rtc: bool,
+    solar_sensor: bool,
+    gyro: bool,
+    tilt: bool,
+    rumble: bool,
     save_type: Option<BackupType>,
 }
 
@@ -15,6 +19,18 @@ impl GameOverride {
     pub fn force_rtc(&self) -> bool {
         self.force_rtc
     }
+    pub fn solar_sensor(&self) -> bool {
+        self.solar_sensor
+    }
+    pub fn gyro(&self) -> bool {
+        self.gyro
+    }
+    pub fn tilt(&self) -> bool {
+        self.tilt
+    }
+    pub fn rumble(&self) -> bool {
+        self.rumble
+    }
     pub fn save_type(&self) -> Option<BackupType> {
         self.save_type
     }
@@ -33,6 +49,10 @@ lazy_static! {
         for game in games {
             let game_code = String::from(game["code"].as_str().unwrap());
             let force_rtc = game["rtc"].as_bool().unwrap_or(false);
+            let solar_sensor = game["solar_sensor"].as_bool().unwrap_or(false);
+            let gyro = game["gyro"].as_bool().unwrap_or(false);
+            let tilt = game["tilt"].as_bool().unwrap_or(false);
+            let rumble = game["rumble"].as_bool().unwrap_or(false);
             let save_type = if let Some(save_type) = game["save_type"].as_str() {
                 match BackupType::from_str(save_type) {
                     Ok(x) => Some(x),
@@ -44,6 +64,10 @@ lazy_static! {
 
             let game_overrride = GameOverride {
                 force_rtc,
+                solar_sensor,
+                gyro,
+                tilt,
+                rumble,
                 save_type,
             };
             m.insert(game_code, game_overrride);
@@ -0,0 +1,221 @@
+//! Rewind support.
+//!
+//! Keeps the latest savestate in full and a ring buffer of deltas that take a state back to the
+//! one before it. Consecutive states barely differ, so each delta is the XOR of the two states
+//! with the runs of zeros compressed away.
+use std::collections::VecDeque;
+
+use super::GameBoyAdvance;
+
+const FRAMES_PER_SECOND: usize = 60;
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+/// Encode the delta that turns `from` into `to`.
+///
+/// The format is the length of `to` followed by (zero run, literal length, literal bytes) triplets
+/// of `from ^ to`, where the shorter of the two is padded with zeros.
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let len = from.len().max(to.len());
+    let xor_at = |i: usize| from.get(i).unwrap_or(&0) ^ to.get(i).unwrap_or(&0);
+
+    let mut out = Vec::new();
+    write_varint(&mut out, to.len());
+    let mut i = 0;
+    while i < len {
+        let zeros_start = i;
+        while i < len && xor_at(i) == 0 {
+            i += 1;
+        }
+        let literal_start = i;
+        // Short zero runs inside a literal are cheaper to keep than to start a new triplet
+        while i < len && (xor_at(i) != 0 || (i + 1 < len && xor_at(i + 1) != 0)) {
+            i += 1;
+        }
+        write_varint(&mut out, literal_start - zeros_start);
+        write_varint(&mut out, i - literal_start);
+        out.extend((literal_start..i).map(xor_at));
+    }
+    out
+}
+
+/// Apply a delta produced by `encode_delta(from, to)` on `from`, giving back `to`
+fn apply_delta(from: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let to_len = read_varint(delta, &mut pos);
+    let mut out = from.to_vec();
+    out.resize(from.len().max(to_len), 0);
+
+    let mut i = 0;
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let literal_len = read_varint(delta, &mut pos);
+        for (dst, x) in out[i..i + literal_len]
+            .iter_mut()
+            .zip(&delta[pos..pos + literal_len])
+        {
+            *dst ^= x;
+        }
+        i += literal_len;
+        pos += literal_len;
+    }
+    out.truncate(to_len);
+    out
+}
+
+pub struct RewindBuffer {
+    /// Full copy of the most recent snapshot
+    current: Option<Vec<u8>>,
+    /// Deltas going one snapshot back, the newest is at the back
+    deltas: VecDeque<Vec<u8>>,
+    capacity: usize,
+    frames_per_snapshot: usize,
+    frame_counter: usize,
+}
+
+impl RewindBuffer {
+    /// Snapshot every frame, keeping `seconds` worth of history
+    pub fn new(seconds: usize) -> RewindBuffer {
+        RewindBuffer::with_interval(seconds, 1)
+    }
+
+    /// Snapshot every `frames_per_snapshot` frames, which is lighter but rewinds in bigger steps
+    pub fn with_interval(seconds: usize, frames_per_snapshot: usize) -> RewindBuffer {
+        let frames_per_snapshot = frames_per_snapshot.max(1);
+        let capacity = seconds * FRAMES_PER_SECOND / frames_per_snapshot;
+        RewindBuffer {
+            current: None,
+            deltas: VecDeque::with_capacity(capacity),
+            capacity,
+            frames_per_snapshot,
+            frame_counter: 0,
+        }
+    }
+
+    /// Call once after every emulated frame
+    pub fn push(&mut self, gba: &GameBoyAdvance) -> bincode::Result<()> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+        self.frame_counter += 1;
+        if self.frame_counter < self.frames_per_snapshot {
+            return Ok(());
+        }
+        self.frame_counter = 0;
+
+        let state = gba.save_snapshot()?;
+        if let Some(prev) = self.current.take() {
+            if self.deltas.len() == self.capacity {
+                self.deltas.pop_front();
+            }
+            self.deltas.push_back(encode_delta(&state, &prev));
+        }
+        self.current = Some(state);
+        Ok(())
+    }
+
+    /// Step one snapshot back, returns false when there is no more history
+    pub fn rewind(&mut self, gba: &mut GameBoyAdvance) -> bincode::Result<bool> {
+        let (current, delta) = match (&self.current, self.deltas.pop_back()) {
+            (Some(current), Some(delta)) => (current, delta),
+            _ => return Ok(false),
+        };
+        let prev = apply_delta(current, &delta);
+        gba.restore_snapshot(&prev)?;
+        self.current = Some(prev);
+        self.frame_counter = 0;
+        Ok(true)
+    }
+
+    /// Number of snapshots that can be rewound
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.current = None;
+        self.deltas.clear();
+        self.frame_counter = 0;
+    }
+
+    /// Memory used by the snapshots in bytes
+    pub fn size_in_bytes(&self) -> usize {
+        let current = self.current.as_ref().map_or(0, |state| state.len());
+        current + self.deltas.iter().map(|delta| delta.len()).sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::{make_mock_gba, make_rom};
+
+    #[test]
+    fn test_delta_roundtrip() {
+        let from: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        let mut to = from.clone();
+        to[3] ^= 0xff;
+        to[500] = 0;
+        to[501] = 1;
+        to.extend_from_slice(&[1, 2, 3]);
+
+        let delta = encode_delta(&from, &to);
+        assert!(delta.len() < 32);
+        assert_eq!(apply_delta(&from, &delta), to);
+        assert_eq!(apply_delta(&to, &encode_delta(&to, &from)), from);
+        assert_eq!(apply_delta(&from, &encode_delta(&from, &from)), from);
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut gba = make_mock_gba(&make_rom(&[0xeafffffe])); // b .
+
+        let mut rewind = RewindBuffer::with_interval(1, 10);
+        let mut states = Vec::new();
+        for _ in 0..8 {
+            for _ in 0..10 {
+                gba.frame();
+                rewind.push(&gba).unwrap();
+            }
+            states.push(gba.save_snapshot().unwrap());
+        }
+        // 1 second of history at 10 frames per snapshot
+        assert_eq!(rewind.len(), 6);
+
+        states.pop();
+        for expected in states.iter().rev().take(6) {
+            assert!(rewind.rewind(&mut gba).unwrap());
+            assert_eq!(&gba.save_snapshot().unwrap(), expected);
+        }
+        assert!(!rewind.rewind(&mut gba).unwrap());
+    }
+}
@@ -0,0 +1,213 @@
+//! The savestate container.
+//!
+//! ```text
+//! magic (8 bytes) | format version (u32 le) | header (bincode) | emulator state (bincode)
+//! ```
+//! The header identifies the game and the build that created the state, so a state from another
+//! rom or bios is rejected with a proper error instead of restoring garbage.
+use std::fmt;
+use std::io::Cursor;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub const SAVESTATE_MAGIC: [u8; 8] = *b"RBA-SAVE";
+pub const SAVESTATE_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum SaveStateError {
+    BadMagic,
+    /// The state was created by a newer build, or by an old one we can't migrate
+    UnsupportedVersion(u32),
+    /// The state was created with another rom, holds the game codes
+    RomMismatch {
+        savestate: String,
+        loaded: String,
+    },
+    BiosMismatch,
+    Serialization(bincode::Error),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::BadMagic => write!(f, "not a savestate file"),
+            SaveStateError::UnsupportedVersion(v) => {
+                write!(f, "unsupported savestate version {}", v)
+            }
+            SaveStateError::RomMismatch { savestate, loaded } => write!(
+                f,
+                "savestate is for another rom ({}), the loaded rom is {}",
+                savestate, loaded
+            ),
+            SaveStateError::BiosMismatch => write!(f, "savestate was created with another bios"),
+            SaveStateError::Serialization(e) => write!(f, "savestate serialization error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+impl From<bincode::Error> for SaveStateError {
+    fn from(err: bincode::Error) -> SaveStateError {
+        SaveStateError::Serialization(err)
+    }
+}
+
+pub type SaveStateResult<T> = Result<T, SaveStateError>;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Thumbnail {
+    pub width: usize,
+    pub height: usize,
+    /// Same pixel format as `GameBoyAdvance::get_frame_buffer`
+    pub pixels: Vec<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SaveStateHeader {
+    pub emulator_version: String,
+    pub game_title: String,
+    pub game_code: String,
+    pub rom_hash: [u8; 32],
+    pub bios_hash: [u8; 32],
+    /// Creation time in seconds since the unix epoch
+    pub timestamp: i64,
+    pub thumbnail: Option<Thumbnail>,
+}
+
+impl SaveStateHeader {
+    /// Make sure the state belongs to this rom and bios
+    pub(crate) fn check(&self, game_code: &str, rom: &[u8], bios: &[u8]) -> SaveStateResult<()> {
+        if self.rom_hash != sha256(rom) {
+            return Err(SaveStateError::RomMismatch {
+                savestate: self.game_code.clone(),
+                loaded: game_code.to_string(),
+            });
+        }
+        if self.bios_hash != sha256(bios) {
+            return Err(SaveStateError::BiosMismatch);
+        }
+        if self.emulator_version != env!("CARGO_PKG_VERSION") {
+            warn!(
+                "savestate was created by version {} of the emulator",
+                self.emulator_version
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Upgrades the payload (header and state) of a savestate to the next format version
+pub type Migration = fn(Vec<u8>) -> SaveStateResult<Vec<u8>>;
+
+/// Whenever the layout of the header or the emulator state changes, bump `SAVESTATE_VERSION`
+/// and add the migration from the previous version here. `MIGRATIONS[i]` takes version `i + 1`
+/// to version `i + 2`.
+const MIGRATIONS: &[Migration] = &[];
+
+pub(crate) fn sha256(bytes: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.input(bytes);
+    let mut digest = [0; 32];
+    digest.copy_from_slice(hasher.result().as_slice());
+    digest
+}
+
+pub(crate) fn encode(header: &SaveStateHeader, state: &[u8]) -> SaveStateResult<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(state.len() + 1024);
+    bytes.extend_from_slice(&SAVESTATE_MAGIC);
+    bytes.extend_from_slice(&SAVESTATE_VERSION.to_le_bytes());
+    bincode::serialize_into(&mut bytes, header)?;
+    bytes.extend_from_slice(state);
+    Ok(bytes)
+}
+
+/// Returns the header and the emulator state, migrated to the current version
+pub(crate) fn decode(bytes: &[u8]) -> SaveStateResult<(SaveStateHeader, Vec<u8>)> {
+    const PAYLOAD_OFFSET: usize = SAVESTATE_MAGIC.len() + 4;
+    if bytes.len() < PAYLOAD_OFFSET || bytes[..SAVESTATE_MAGIC.len()] != SAVESTATE_MAGIC {
+        return Err(SaveStateError::BadMagic);
+    }
+    let mut version = [0; 4];
+    version.copy_from_slice(&bytes[SAVESTATE_MAGIC.len()..PAYLOAD_OFFSET]);
+    let version = u32::from_le_bytes(version);
+    if version == 0 || version > SAVESTATE_VERSION {
+        return Err(SaveStateError::UnsupportedVersion(version));
+    }
+
+    let mut payload = bytes[PAYLOAD_OFFSET..].to_vec();
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        payload = migration(payload)?;
+    }
+
+    let mut cursor = Cursor::new(&payload[..]);
+    let header: SaveStateHeader = bincode::deserialize_from(&mut cursor)?;
+    let state_offset = cursor.position() as usize;
+    payload.drain(..state_offset);
+    Ok((header, payload))
+}
+
+/// Read only the header, for showing the game and thumbnail of a state without loading it
+pub fn read_header(bytes: &[u8]) -> SaveStateResult<SaveStateHeader> {
+    decode(bytes).map(|(header, _)| header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::{make_mock_gba, make_rom};
+
+    /// b .
+    const CODE: [u32; 1] = [0xeafffffe];
+
+    #[test]
+    fn test_migrations_cover_all_versions() {
+        assert_eq!(MIGRATIONS.len(), SAVESTATE_VERSION as usize - 1);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut gba = make_mock_gba(&make_rom(&CODE));
+        gba.frame();
+        let state = gba.save_state_with_thumbnail().unwrap();
+
+        let header = read_header(&state).unwrap();
+        assert_eq!(header.emulator_version, env!("CARGO_PKG_VERSION"));
+        let thumbnail = header.thumbnail.unwrap();
+        assert_eq!((thumbnail.width, thumbnail.height), (120, 80));
+        assert_eq!(thumbnail.pixels.len(), 120 * 80);
+
+        let snapshot = gba.save_snapshot().unwrap();
+        gba.frame();
+        gba.restore_state(&state).unwrap();
+        assert_eq!(gba.save_snapshot().unwrap(), snapshot);
+    }
+
+    #[test]
+    fn test_bad_container() {
+        let mut gba = make_mock_gba(&make_rom(&CODE));
+        assert!(matches!(
+            gba.restore_state(b"not a savestate"),
+            Err(SaveStateError::BadMagic)
+        ));
+
+        let mut state = gba.save_state().unwrap();
+        state[8..12].copy_from_slice(&(SAVESTATE_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            gba.restore_state(&state),
+            Err(SaveStateError::UnsupportedVersion(v)) if v == SAVESTATE_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn test_rom_mismatch() {
+        let state = make_mock_gba(&make_rom(&CODE)).save_state().unwrap();
+        let mut other = make_mock_gba(&make_rom(&[CODE[0], 1]));
+        assert!(matches!(
+            other.restore_state(&state),
+            Err(SaveStateError::RomMismatch { .. })
+        ));
+    }
+}
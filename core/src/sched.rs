@@ -23,9 +23,17 @@ pub enum ApuEvent {
     Psg2Generate,
     Psg3Generate,
     Psg4Generate,
+    Sequencer,
     Sample,
 }
 
+#[repr(u32)]
+#[derive(Serialize, Deserialize, Debug, PartialOrd, PartialEq, Eq, Copy, Clone)]
+pub enum SioEvent {
+    TransferComplete,
+    Poll,
+}
+
 #[repr(u32)]
 #[derive(Serialize, Deserialize, Debug, PartialOrd, PartialEq, Eq, Copy, Clone)]
 pub enum EventType {
@@ -34,6 +42,7 @@ pub enum EventType {
     Apu(ApuEvent),
     DmaActivateChannel(usize),
     TimerOverflow(usize),
+    Sio(SioEvent),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Eq)]
@@ -0,0 +1,460 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use super::interrupt::{self, Interrupt, InterruptConnect, SharedInterruptFlags};
+use super::iodev::consts::*;
+use super::sched::{EventType, FutureEvent, Scheduler, SioEvent};
+
+use arm7tdmi::memory::Addr;
+
+pub mod transport;
+pub use transport::{DynSioTransport, LocalTransport, SioMessage, SioTransport, TcpTransport};
+
+const CPU_FREQ: usize = 16 * 1024 * 1024;
+const MULTIPLAYER_BAUD_RATES: [usize; 4] = [9600, 38400, 57600, 115200];
+/// Every player sends a start bit, 16 data bits and a stop bit
+const MULTIPLAYER_BITS_PER_PLAYER: usize = 18;
+const MAX_PLAYERS: usize = 4;
+const UART_FIFO_LEN: usize = 4;
+/// How often to check the link cable for incoming messages
+const CYCLES_PER_POLL: usize = 4096;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SioMode {
+    Normal8,
+    Normal32,
+    Multiplayer,
+    Uart,
+    GeneralPurpose,
+    JoyBus,
+}
+
+bitfield! {
+    #[derive(Serialize, Deserialize, Clone, Copy, Default)]
+    pub struct SioControl(u16);
+    impl Debug;
+    u16;
+    pub internal_clock, _: 0;
+    pub fast_clock, _: 1;
+    pub baud_rate, _: 1, 0;
+    pub uart_receive_enable, _: 11;
+    pub start, set_start: 7;
+    pub mode_bits, _: 13, 12;
+    pub irq_enabled, _: 14;
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Sio {
+    pub siocnt: SioControl,
+    pub rcnt: u16,
+    /// SIOMULTI0-3, the first two also make up SIODATA32
+    multi: [u16; 4],
+    /// SIOMLT_SEND / SIODATA8
+    send_data: u16,
+    joycnt: u16,
+    /// JOY_RECV and JOY_TRANS as halfwords
+    joy_data: [u16; 4],
+    joystat: u16,
+    uart_rx: VecDeque<u8>,
+    uart_tx: VecDeque<u8>,
+
+    /// The last outgoing data published by the other side of the cable
+    remote_data: u32,
+    publish_pending: bool,
+    // The link cable itself is not part of the emulated state
+    #[serde(skip)]
+    connected: bool,
+    #[serde(skip)]
+    player_id: usize,
+
+    interrupt_flags: SharedInterruptFlags,
+}
+
+impl InterruptConnect for Sio {
+    fn connect_irq(&mut self, interrupt_flags: SharedInterruptFlags) {
+        self.interrupt_flags = interrupt_flags;
+    }
+}
+
+impl Sio {
+    pub fn new(interrupt_flags: SharedInterruptFlags) -> Sio {
+        Sio {
+            siocnt: SioControl(0),
+            rcnt: 0,
+            multi: [0; 4],
+            send_data: 0,
+            joycnt: 0,
+            joy_data: [0; 4],
+            joystat: 0,
+            uart_rx: VecDeque::with_capacity(UART_FIFO_LEN),
+            uart_tx: VecDeque::with_capacity(UART_FIFO_LEN),
+            remote_data: !0,
+            publish_pending: false,
+            connected: false,
+            player_id: 0,
+            interrupt_flags,
+        }
+    }
+
+    pub fn mode(&self) -> SioMode {
+        match self.rcnt >> 14 {
+            0 | 1 => match self.siocnt.mode_bits() {
+                0 => SioMode::Normal8,
+                1 => SioMode::Normal32,
+                2 => SioMode::Multiplayer,
+                _ => SioMode::Uart,
+            },
+            2 => SioMode::GeneralPurpose,
+            _ => SioMode::JoyBus,
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    pub(crate) fn connect(&mut self, player_id: usize) {
+        self.connected = true;
+        self.player_id = player_id;
+        self.publish_pending = true;
+    }
+
+    pub(crate) fn disconnect(&mut self) {
+        self.connected = false;
+        self.player_id = 0;
+        self.remote_data = !0;
+    }
+
+    pub fn read(&mut self, io_addr: Addr) -> u16 {
+        match io_addr {
+            REG_SIOMULTI0..=REG_SIOMULTI3 => self.multi[((io_addr - REG_SIOMULTI0) / 2) as usize],
+            REG_SIOCNT => self.read_siocnt(),
+            REG_SIODATA8 => match self.mode() {
+                SioMode::Uart => self.uart_rx.pop_front().unwrap_or(0) as u16,
+                _ => self.send_data,
+            },
+            REG_RCNT => self.rcnt,
+            REG_JOYCNT => self.joycnt,
+            REG_JOY_RECV..=REG_JOYSTAT if io_addr < REG_JOYSTAT => {
+                self.joy_data[((io_addr - REG_JOY_RECV) / 2) as usize]
+            }
+            REG_JOYSTAT => self.joystat,
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, io_addr: Addr, value: u16, sched: &mut Scheduler) {
+        match io_addr {
+            REG_SIOMULTI0..=REG_SIOMULTI3 => {
+                self.multi[((io_addr - REG_SIOMULTI0) / 2) as usize] = value;
+                self.publish_pending = true;
+            }
+            REG_SIOCNT => self.write_siocnt(value, sched),
+            REG_SIODATA8 => match self.mode() {
+                SioMode::Uart => self.uart_send(value as u8),
+                _ => {
+                    self.send_data = value;
+                    self.publish_pending = true;
+                }
+            },
+            REG_RCNT => self.rcnt = value,
+            // The status bits are cleared by writing 1 to them
+            REG_JOYCNT => self.joycnt = (self.joycnt & !(value & 0b111) & !0x40) | (value & 0x40),
+            REG_JOY_RECV..=REG_JOYSTAT if io_addr < REG_JOYSTAT => {
+                self.joy_data[((io_addr - REG_JOY_RECV) / 2) as usize] = value
+            }
+            REG_JOYSTAT => self.joystat = (self.joystat & !0x30) | (value & 0x30),
+            _ => {}
+        }
+    }
+
+    fn read_siocnt(&self) -> u16 {
+        let mut value = self.siocnt.0;
+        match self.mode() {
+            SioMode::Multiplayer => {
+                value &= !0b111_1100;
+                value |= ((self.player_id != 0) as u16) << 2;
+                value |= (self.connected as u16) << 3;
+                value |= (self.player_id as u16) << 4;
+            }
+            SioMode::Uart => {
+                value &= !0b111_0000;
+                value |= ((self.uart_tx.len() >= UART_FIFO_LEN) as u16) << 4;
+                value |= (self.uart_rx.is_empty() as u16) << 5;
+            }
+            _ => {}
+        }
+        value
+    }
+
+    fn write_siocnt(&mut self, value: u16, sched: &mut Scheduler) {
+        let was_active = self.siocnt.start();
+        self.siocnt = SioControl(value);
+        match self.mode() {
+            // Only the parent may start a multiplayer transfer
+            SioMode::Multiplayer if self.player_id != 0 => self.siocnt.set_start(was_active),
+            SioMode::Normal8 | SioMode::Normal32 | SioMode::Multiplayer => {
+                if self.siocnt.start() && !was_active {
+                    self.start_transfer(sched);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn start_transfer(&mut self, sched: &mut Scheduler) {
+        let cycles = match self.mode() {
+            // With an external clock we wait for the other side to drive the transfer
+            SioMode::Normal8 | SioMode::Normal32 if !self.siocnt.internal_clock() => return,
+            SioMode::Normal8 | SioMode::Normal32 => {
+                let cycles_per_bit = if self.siocnt.fast_clock() { 8 } else { 64 };
+                self.transfer_bits() * cycles_per_bit
+            }
+            SioMode::Multiplayer => {
+                let baud = MULTIPLAYER_BAUD_RATES[self.siocnt.baud_rate() as usize];
+                MULTIPLAYER_BITS_PER_PLAYER * MAX_PLAYERS * CPU_FREQ / baud
+            }
+            _ => return,
+        };
+        let event = EventType::Sio(SioEvent::TransferComplete);
+        sched.cancel_pending(event);
+        sched.schedule((event, cycles));
+    }
+
+    fn transfer_bits(&self) -> usize {
+        match self.mode() {
+            SioMode::Normal32 => 32,
+            SioMode::Multiplayer => 16,
+            _ => 8,
+        }
+    }
+
+    fn outgoing_data(&self) -> u32 {
+        match self.mode() {
+            SioMode::Normal32 => (self.multi[0] as u32) | (self.multi[1] as u32) << 16,
+            SioMode::Normal8 => (self.send_data & 0xff) as u32,
+            _ => self.send_data as u32,
+        }
+    }
+
+    fn store_normal(&mut self, data: u32) {
+        match self.mode() {
+            SioMode::Normal32 => {
+                self.multi[0] = data as u16;
+                self.multi[1] = (data >> 16) as u16;
+            }
+            _ => self.send_data = data as u16 & 0xff,
+        }
+    }
+
+    fn finish_transfer(&mut self) {
+        self.siocnt.set_start(false);
+        if self.siocnt.irq_enabled() {
+            interrupt::signal_irq(&self.interrupt_flags, Interrupt::SerialCommunication);
+        }
+    }
+
+    fn uart_send(&mut self, byte: u8) {
+        // Without a cable the bytes just go nowhere
+        if self.connected && self.uart_tx.len() < UART_FIFO_LEN {
+            self.uart_tx.push_back(byte);
+        }
+    }
+
+    pub fn on_event(
+        &mut self,
+        event: SioEvent,
+        transport: &mut Option<DynSioTransport>,
+    ) -> Option<FutureEvent> {
+        match event {
+            SioEvent::TransferComplete => {
+                self.complete_transfer(transport);
+                None
+            }
+            SioEvent::Poll => {
+                // Polling stops once the cable is pulled out
+                let transport = transport.as_mut()?;
+                self.poll(transport);
+                Some((EventType::Sio(SioEvent::Poll), CYCLES_PER_POLL))
+            }
+        }
+    }
+
+    /// Called on the side driving the clock once the transfer time has passed
+    fn complete_transfer(&mut self, transport: &mut Option<DynSioTransport>) {
+        let data = self.outgoing_data();
+        let bits = self.transfer_bits() as u8;
+        if let Some(transport) = transport {
+            transport.send(SioMessage::Transfer { data, bits });
+        }
+        match self.mode() {
+            SioMode::Normal8 | SioMode::Normal32 => {
+                // Nothing connected means the input line stays high
+                let received = if transport.is_some() {
+                    self.remote_data
+                } else {
+                    !0
+                };
+                self.store_normal(received);
+            }
+            SioMode::Multiplayer => {
+                self.multi = [data as u16, 0xffff, 0xffff, 0xffff];
+                if transport.is_some() {
+                    self.multi[1] = self.remote_data as u16;
+                }
+            }
+            _ => return,
+        }
+        self.finish_transfer();
+    }
+
+    fn poll(&mut self, transport: &mut DynSioTransport) {
+        if self.publish_pending {
+            self.publish_pending = false;
+            transport.send(SioMessage::Data(self.outgoing_data()));
+        }
+        while let Some(byte) = self.uart_tx.pop_front() {
+            transport.send(SioMessage::Uart(byte));
+        }
+        while let Some(message) = transport.recv() {
+            self.on_message(message);
+        }
+    }
+
+    fn on_message(&mut self, message: SioMessage) {
+        match message {
+            SioMessage::Data(data) => self.remote_data = data,
+            SioMessage::Transfer { data, .. } => match self.mode() {
+                SioMode::Normal8 | SioMode::Normal32
+                    if self.siocnt.start() && !self.siocnt.internal_clock() =>
+                {
+                    self.store_normal(data);
+                    self.finish_transfer();
+                }
+                SioMode::Multiplayer if self.player_id != 0 => {
+                    self.multi = [data as u16, 0xffff, 0xffff, 0xffff];
+                    self.multi[self.player_id] = self.send_data;
+                    self.finish_transfer();
+                }
+                mode => trace!("SIO: dropping {:?} in mode {:?}", message, mode),
+            },
+            SioMessage::Uart(byte) => {
+                if self.mode() == SioMode::Uart && self.siocnt.uart_receive_enable() {
+                    if self.uart_rx.len() < UART_FIFO_LEN {
+                        self.uart_rx.push_back(byte);
+                    }
+                    if self.siocnt.irq_enabled() {
+                        interrupt::signal_irq(
+                            &self.interrupt_flags,
+                            Interrupt::SerialCommunication,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use crate::interrupt::IrqBitmask;
+
+    fn make_sio() -> Sio {
+        Sio::new(Rc::new(Cell::new(IrqBitmask(0))))
+    }
+
+    fn irq_pending(sio: &Sio) -> bool {
+        sio.interrupt_flags.get().value() & (1 << Interrupt::SerialCommunication as u16) != 0
+    }
+
+    #[test]
+    fn test_normal_transfer_without_cable() {
+        let mut sched = Scheduler::new_shared();
+        let mut sio = make_sio();
+        sio.write(REG_SIODATA8, 0x42, &mut sched);
+        // internal clock, start, irq enable
+        sio.write(REG_SIOCNT, 0x4081, &mut sched);
+        assert!(sio.siocnt.start());
+
+        sio.on_event(SioEvent::TransferComplete, &mut None);
+        assert!(!sio.siocnt.start());
+        assert_eq!(sio.read(REG_SIODATA8), 0xff);
+        assert!(irq_pending(&sio));
+    }
+
+    #[test]
+    fn test_normal32_transfer() {
+        let mut sched = Scheduler::new_shared();
+        let (parent_transport, child_transport) = LocalTransport::pair();
+        let mut parent_transport: Option<DynSioTransport> = Some(parent_transport);
+        let mut child_transport: Option<DynSioTransport> = Some(child_transport);
+        let mut parent = make_sio();
+        let mut child = make_sio();
+        parent.connect(0);
+        child.connect(1);
+
+        parent.write(REG_SIODATA32, 0x5678, &mut sched);
+        parent.write(REG_SIODATA32 + 2, 0x1234, &mut sched);
+        child.write(REG_SIODATA32, 0xbeef, &mut sched);
+        child.write(REG_SIODATA32 + 2, 0xdead, &mut sched);
+        // child waits on the external clock
+        child.write(REG_SIOCNT, 0x1080, &mut sched);
+        child.on_event(SioEvent::Poll, &mut child_transport);
+        parent.on_event(SioEvent::Poll, &mut parent_transport);
+
+        parent.write(REG_SIOCNT, 0x1081, &mut sched);
+        parent.on_event(SioEvent::TransferComplete, &mut parent_transport);
+        child.on_event(SioEvent::Poll, &mut child_transport);
+
+        assert!(!parent.siocnt.start());
+        assert!(!child.siocnt.start());
+        assert_eq!(parent.read(REG_SIODATA32), 0xbeef);
+        assert_eq!(parent.read(REG_SIODATA32 + 2), 0xdead);
+        assert_eq!(child.read(REG_SIODATA32), 0x5678);
+        assert_eq!(child.read(REG_SIODATA32 + 2), 0x1234);
+    }
+
+    #[test]
+    fn test_multiplayer_transfer() {
+        let mut sched = Scheduler::new_shared();
+        let (parent_transport, child_transport) = LocalTransport::pair();
+        let mut parent_transport: Option<DynSioTransport> = Some(parent_transport);
+        let mut child_transport: Option<DynSioTransport> = Some(child_transport);
+        let mut parent = make_sio();
+        let mut child = make_sio();
+        parent.connect(0);
+        child.connect(1);
+
+        parent.write(REG_SIOCNT, 0x2003, &mut sched);
+        child.write(REG_SIOCNT, 0x6003, &mut sched);
+        assert_eq!(parent.read(REG_SIOCNT) & 0x3c, 0b1000);
+        assert_eq!(child.read(REG_SIOCNT) & 0x3c, 0b01_1100);
+
+        parent.write(REG_SIOMLT_SEND, 0x1111, &mut sched);
+        child.write(REG_SIOMLT_SEND, 0x2222, &mut sched);
+        child.on_event(SioEvent::Poll, &mut child_transport);
+        parent.on_event(SioEvent::Poll, &mut parent_transport);
+
+        // children can't start a transfer
+        child.write(REG_SIOCNT, 0x6083, &mut sched);
+        assert!(!child.siocnt.start());
+
+        parent.write(REG_SIOCNT, 0x2083, &mut sched);
+        parent.on_event(SioEvent::TransferComplete, &mut parent_transport);
+        child.on_event(SioEvent::Poll, &mut child_transport);
+
+        for sio in [&mut parent, &mut child].iter_mut() {
+            assert_eq!(sio.read(REG_SIOMULTI0), 0x1111);
+            assert_eq!(sio.read(REG_SIOMULTI1), 0x2222);
+            assert_eq!(sio.read(REG_SIOMULTI2), 0xffff);
+        }
+        assert!(irq_pending(&child));
+        assert!(!irq_pending(&parent));
+    }
+}
@@ -0,0 +1,155 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::thread;
+
+use crossbeam::channel::{self, Receiver, Sender};
+use serde::{Deserialize, Serialize};
+
+/// Messages sent between the two ends of a link cable
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SioMessage {
+    /// The outgoing data register of the sender was changed
+    Data(u32),
+    /// The sender drove the clock for a transfer of `bits` bits
+    Transfer { data: u32, bits: u8 },
+    /// A byte sent in UART mode
+    Uart(u8),
+}
+
+/// Link cable connection to another emulator instance.
+pub trait SioTransport {
+    /// Position on the cable, 0 for the parent that drives multiplayer transfers
+    fn player_id(&self) -> usize;
+
+    /// Send a message to the other side
+    fn send(&mut self, message: SioMessage);
+
+    /// Receive a pending message from the other side
+    /// Note: This must not block, the emulation thread polls it periodically
+    fn recv(&mut self) -> Option<SioMessage>;
+}
+
+pub type DynSioTransport = Box<dyn SioTransport>;
+
+/// Connects two `GameBoyAdvance` instances living in the same process
+pub struct LocalTransport {
+    player_id: usize,
+    tx: Sender<SioMessage>,
+    rx: Receiver<SioMessage>,
+}
+
+impl LocalTransport {
+    /// Create both ends of the cable, the first one is the parent
+    pub fn pair() -> (Box<LocalTransport>, Box<LocalTransport>) {
+        let (parent_tx, child_rx) = channel::unbounded();
+        let (child_tx, parent_rx) = channel::unbounded();
+        (
+            Box::new(LocalTransport {
+                player_id: 0,
+                tx: parent_tx,
+                rx: parent_rx,
+            }),
+            Box::new(LocalTransport {
+                player_id: 1,
+                tx: child_tx,
+                rx: child_rx,
+            }),
+        )
+    }
+}
+
+impl SioTransport for LocalTransport {
+    fn player_id(&self) -> usize {
+        self.player_id
+    }
+
+    fn send(&mut self, message: SioMessage) {
+        // The other side might have been dropped, which is like pulling out the cable
+        let _ = self.tx.send(message);
+    }
+
+    fn recv(&mut self) -> Option<SioMessage> {
+        self.rx.try_recv().ok()
+    }
+}
+
+/// Link cable over a TCP socket, used to connect two emulator processes.
+/// The listening side is the parent.
+pub struct TcpTransport {
+    player_id: usize,
+    stream: TcpStream,
+    rx: Receiver<SioMessage>,
+}
+
+impl TcpTransport {
+    /// Wait for the other side to connect
+    pub fn listen<A: ToSocketAddrs>(addr: A) -> io::Result<Box<TcpTransport>> {
+        let listener = TcpListener::bind(addr)?;
+        info!(
+            "link cable: waiting for a connection on {}",
+            listener.local_addr()?
+        );
+        let (stream, peer) = listener.accept()?;
+        info!("link cable: {} connected", peer);
+        TcpTransport::new(stream, 0)
+    }
+
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Box<TcpTransport>> {
+        let stream = TcpStream::connect(addr)?;
+        info!("link cable: connected to {}", stream.peer_addr()?);
+        TcpTransport::new(stream, 1)
+    }
+
+    fn new(stream: TcpStream, player_id: usize) -> io::Result<Box<TcpTransport>> {
+        stream.set_nodelay(true)?;
+        let (tx, rx) = channel::unbounded();
+        let mut reader = stream.try_clone()?;
+        thread::spawn(move || loop {
+            match TcpTransport::read_message(&mut reader) {
+                Ok(message) => {
+                    if tx.send(message).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    info!("link cable: disconnected ({})", e);
+                    break;
+                }
+            }
+        });
+        Ok(Box::new(TcpTransport {
+            player_id,
+            stream,
+            rx,
+        }))
+    }
+
+    /// Messages are framed with a single length byte
+    fn read_message(reader: &mut TcpStream) -> io::Result<SioMessage> {
+        let mut len = [0u8; 1];
+        reader.read_exact(&mut len)?;
+        let mut buf = vec![0u8; len[0] as usize];
+        reader.read_exact(&mut buf)?;
+        bincode::deserialize(&buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+impl SioTransport for TcpTransport {
+    fn player_id(&self) -> usize {
+        self.player_id
+    }
+
+    fn send(&mut self, message: SioMessage) {
+        let encoded = bincode::serialize(&message).unwrap();
+        let mut buf = Vec::with_capacity(encoded.len() + 1);
+        buf.push(encoded.len() as u8);
+        buf.extend_from_slice(&encoded);
+        if let Err(e) = self.stream.write_all(&buf) {
+            warn!("link cable: failed to send {:?} ({})", message, e);
+        }
+    }
+
+    fn recv(&mut self) -> Option<SioMessage> {
+        self.rx.try_recv().ok()
+    }
+}
@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 
 use super::dma::DmaController;
 use super::iodev::consts::*;
+use super::iodev::io_reg_string;
 use super::sched::*;
 
 mod fifo;
@@ -13,9 +14,14 @@ pub use interface::{AudioInterface, DynAudioInterface, StereoSample};
 mod dsp;
 use dsp::{CosineResampler, Resampler};
 
+mod psg;
+use psg::{NoiseChannel, SquareChannel, WaveChannel};
+
 const DMG_RATIOS: [f32; 4] = [0.25, 0.5, 1.0, 0.0];
 const DMA_TIMERS: [usize; 2] = [0, 1];
-const DUTY_RATIOS: [f32; 4] = [0.125, 0.25, 0.5, 0.75];
+
+/// The frame sequencer runs at 512hz and clocks the length, sweep and envelope units
+const CYCLES_PER_SEQUENCER_STEP: usize = 32768;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 struct DmaSoundChannel {
@@ -56,6 +62,8 @@ const REG_FIFO_A_H: u32 = REG_FIFO_A + 2;
 const REG_FIFO_B_L: u32 = REG_FIFO_B;
 const REG_FIFO_B_H: u32 = REG_FIFO_B + 2;
 
+const REG_WAVE_RAM_END: u32 = REG_WAVE_RAM + 0xf;
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct SoundController {
     cycles: usize, // cycles count when we last provided a new sample.
@@ -76,14 +84,11 @@ pub struct SoundController {
 
     dmg_volume_ratio: f32,
 
-    sqr1_rate: usize,
-    sqr1_timed: bool,
-    sqr1_length: f32,
-    sqr1_duty: f32,
-    sqr1_step_time: usize,
-    sqr1_step_increase: bool,
-    sqr1_initial_vol: usize,
-    sqr1_cur_vol: usize,
+    sqr1: SquareChannel,
+    sqr2: SquareChannel,
+    wave: WaveChannel,
+    noise: NoiseChannel,
+    sequencer_step: usize,
 
     sound_bias: u16,
 
@@ -101,6 +106,10 @@ impl SoundController {
         let resampler = CosineResampler::new(32768_f32, audio_device_sample_rate);
         let cycles_per_sample = 512;
         sched.schedule((EventType::Apu(ApuEvent::Sample), cycles_per_sample));
+        sched.schedule((
+            EventType::Apu(ApuEvent::Sequencer),
+            CYCLES_PER_SEQUENCER_STEP,
+        ));
         SoundController {
             cycles_per_sample,
             cycles: 0,
@@ -115,15 +124,12 @@ impl SoundController {
             right_sqr2: false,
             right_wave: false,
             right_noise: false,
-            dmg_volume_ratio: 0.0,
-            sqr1_rate: 0,
-            sqr1_timed: false,
-            sqr1_length: 0.0,
-            sqr1_duty: DUTY_RATIOS[0],
-            sqr1_step_time: 0,
-            sqr1_step_increase: false,
-            sqr1_initial_vol: 0,
-            sqr1_cur_vol: 0,
+            dmg_volume_ratio: DMG_RATIOS[0],
+            sqr1: Default::default(),
+            sqr2: Default::default(),
+            wave: Default::default(),
+            noise: Default::default(),
+            sequencer_step: 0,
             sound_bias: 0x200,
             sample_rate: 32_768f32,
             dma_sound: [Default::default(), Default::default()],
@@ -135,18 +141,24 @@ impl SoundController {
 
     pub fn handle_read(&self, io_addr: u32) -> u16 {
         let value = match io_addr {
-            REG_SOUNDCNT_X => cbit(7, self.mse),
+            REG_SOUNDCNT_X => {
+                cbit(0, self.sqr1.enabled)
+                    | cbit(1, self.sqr2.enabled)
+                    | cbit(2, self.wave.enabled)
+                    | cbit(3, self.noise.enabled)
+                    | cbit(7, self.mse)
+            }
             REG_SOUNDCNT_L => {
-                self.left_volume as u16
-                    | (self.right_volume as u16) << 4
-                    | cbit(8, self.left_sqr1)
-                    | cbit(9, self.left_sqr2)
-                    | cbit(10, self.left_wave)
-                    | cbit(11, self.left_noise)
-                    | cbit(12, self.right_sqr1)
-                    | cbit(13, self.right_sqr2)
-                    | cbit(14, self.right_wave)
-                    | cbit(15, self.right_noise)
+                self.right_volume as u16
+                    | (self.left_volume as u16) << 4
+                    | cbit(8, self.right_sqr1)
+                    | cbit(9, self.right_sqr2)
+                    | cbit(10, self.right_wave)
+                    | cbit(11, self.right_noise)
+                    | cbit(12, self.left_sqr1)
+                    | cbit(13, self.left_sqr2)
+                    | cbit(14, self.left_wave)
+                    | cbit(15, self.left_noise)
             }
 
             REG_SOUNDCNT_H => {
@@ -166,6 +178,23 @@ impl SoundController {
 
             REG_SOUNDBIAS => self.sound_bias,
 
+            REG_SOUND1CNT_L => self.sqr1.read_sweep(),
+            REG_SOUND1CNT_H => self.sqr1.read_duty_envelope(),
+            REG_SOUND1CNT_X => self.sqr1.read_freq_control(),
+            REG_SOUND2CNT_L => self.sqr2.read_duty_envelope(),
+            REG_SOUND2CNT_H => self.sqr2.read_freq_control(),
+            REG_SOUND3CNT_L => self.wave.read_stop_bank(),
+            REG_SOUND3CNT_H => self.wave.read_length_volume(),
+            REG_SOUND3CNT_X => self.wave.read_freq_control(),
+            REG_SOUND4CNT_L => self.noise.read_length_envelope(),
+            REG_SOUND4CNT_H => self.noise.read_freq_control(),
+
+            REG_WAVE_RAM..=REG_WAVE_RAM_END => {
+                let offset = (io_addr - REG_WAVE_RAM) as usize;
+                self.wave.read_wave_ram(offset) as u16
+                    | (self.wave.read_wave_ram(offset + 1) as u16) << 8
+            }
+
             _ => {
                 // println!(
                 //     "Unimplemented read from {:x} {}",
@@ -184,7 +213,39 @@ impl SoundController {
         value
     }
 
-    pub fn handle_write(&mut self, io_addr: u32, value: u16) {
+    /// Reconstructs the full value of a register including its write-only fields,
+    /// so that 8-bit writes don't clobber the other half of the register.
+    fn read_raw(&self, io_addr: u32) -> u16 {
+        match io_addr {
+            REG_SOUND1CNT_H => self.sqr1.raw_duty_envelope(),
+            REG_SOUND1CNT_X => self.sqr1.raw_freq_control(),
+            REG_SOUND2CNT_L => self.sqr2.raw_duty_envelope(),
+            REG_SOUND2CNT_H => self.sqr2.raw_freq_control(),
+            REG_SOUND3CNT_H => self.wave.raw_length_volume(),
+            REG_SOUND3CNT_X => self.wave.raw_freq_control(),
+            REG_SOUND4CNT_L => self.noise.raw_length_envelope(),
+            _ => self.handle_read(io_addr),
+        }
+    }
+
+    pub fn handle_write_8(&mut self, io_addr: u32, value: u8, sched: &mut Scheduler) {
+        let aligned = io_addr & !1;
+        let t = self.read_raw(aligned);
+        let t = if io_addr & 1 != 0 {
+            (t & 0xff) | (value as u16) << 8
+        } else {
+            (t & 0xff00) | (value as u16)
+        };
+        self.handle_write(aligned, t, sched);
+    }
+
+    /// (Re)start the generator of a PSG channel
+    fn restart_psg(&mut self, event: ApuEvent, cycles: usize, sched: &mut Scheduler) {
+        sched.cancel_pending(EventType::Apu(event));
+        sched.schedule((EventType::Apu(event), cycles));
+    }
+
+    pub fn handle_write(&mut self, io_addr: u32, value: u16, sched: &mut Scheduler) {
         if io_addr == REG_SOUNDCNT_X {
             if value & bit(7) != 0 {
                 if !self.mse {
@@ -194,34 +255,68 @@ impl SoundController {
             } else if self.mse {
                 info!("MSE disabled!");
                 self.mse = false;
+                self.reset_psg();
             }
 
             // other fields of this register are read-only anyway, ignore them.
             return;
         }
 
-        // TODO - figure out which writes should be disabled when MSE is off
-        // if !self.mse {
-        //     warn!("MSE disabled, refusing to write");
-        //     return;
-        // }
+        // The PSG registers can't be written while the sound circuit is turned off
+        if !self.mse && (REG_SOUND1CNT_L..=REG_SOUNDCNT_L).contains(&io_addr) {
+            trace!("MSE disabled, refusing to write {}", io_reg_string(io_addr));
+            return;
+        }
 
         match io_addr {
+            REG_SOUND1CNT_L => self.sqr1.write_sweep(value),
+            REG_SOUND1CNT_H => self.sqr1.write_duty_envelope(value),
+            REG_SOUND1CNT_X => {
+                if self.sqr1.write_freq_control(value) {
+                    self.restart_psg(ApuEvent::Psg1Generate, self.sqr1.period(), sched);
+                }
+            }
+            REG_SOUND2CNT_L => self.sqr2.write_duty_envelope(value),
+            REG_SOUND2CNT_H => {
+                if self.sqr2.write_freq_control(value) {
+                    self.restart_psg(ApuEvent::Psg2Generate, self.sqr2.period(), sched);
+                }
+            }
+            REG_SOUND3CNT_L => self.wave.write_stop_bank(value),
+            REG_SOUND3CNT_H => self.wave.write_length_volume(value),
+            REG_SOUND3CNT_X => {
+                if self.wave.write_freq_control(value) {
+                    self.restart_psg(ApuEvent::Psg3Generate, self.wave.period(), sched);
+                }
+            }
+            REG_SOUND4CNT_L => self.noise.write_length_envelope(value),
+            REG_SOUND4CNT_H => {
+                if self.noise.write_freq_control(value) {
+                    self.restart_psg(ApuEvent::Psg4Generate, self.noise.period(), sched);
+                }
+            }
+
+            REG_WAVE_RAM..=REG_WAVE_RAM_END => {
+                let offset = (io_addr - REG_WAVE_RAM) as usize;
+                self.wave.write_wave_ram(offset, (value & 0xff) as u8);
+                self.wave.write_wave_ram(offset + 1, (value >> 8) as u8);
+            }
+
             REG_SOUNDCNT_L => {
-                self.left_volume = value.bit_range(0..2) as usize;
-                self.right_volume = value.bit_range(4..6) as usize;
-                self.left_sqr1 = value.bit(8);
-                self.left_sqr2 = value.bit(9);
-                self.left_wave = value.bit(10);
-                self.left_noise = value.bit(11);
-                self.right_sqr1 = value.bit(12);
-                self.right_sqr2 = value.bit(13);
-                self.right_wave = value.bit(14);
-                self.right_noise = value.bit(15);
+                self.right_volume = value.bit_range(0..3) as usize;
+                self.left_volume = value.bit_range(4..7) as usize;
+                self.right_sqr1 = value.bit(8);
+                self.right_sqr2 = value.bit(9);
+                self.right_wave = value.bit(10);
+                self.right_noise = value.bit(11);
+                self.left_sqr1 = value.bit(12);
+                self.left_sqr2 = value.bit(13);
+                self.left_wave = value.bit(14);
+                self.left_noise = value.bit(15);
             }
 
             REG_SOUNDCNT_H => {
-                self.dmg_volume_ratio = DMG_RATIOS[value.bit_range(0..1) as usize];
+                self.dmg_volume_ratio = DMG_RATIOS[value.bit_range(0..2) as usize];
                 self.dma_sound[0].volume_shift = value.bit(2) as i16;
                 self.dma_sound[1].volume_shift = value.bit(3) as i16;
                 self.dma_sound[0].enable_right = value.bit(8);
@@ -239,22 +334,6 @@ impl SoundController {
                 }
             }
 
-            REG_SOUND1CNT_H => {
-                self.sqr1_length = (64 - value.bit_range(0..5) as usize) as f32 / 256.0;
-                self.sqr1_duty = DUTY_RATIOS[value.bit_range(6..7) as usize];
-                self.sqr1_step_time = value.bit_range(8..10) as usize;
-                self.sqr1_step_increase = value.bit(11);
-                self.sqr1_initial_vol = value.bit_range(12..15) as usize;
-            }
-
-            REG_SOUND1CNT_X => {
-                self.sqr1_rate = value.bit_range(0..10) as usize;
-                self.sqr1_timed = value.bit(14);
-                if value.bit(15) {
-                    self.sqr1_cur_vol = self.sqr1_initial_vol;
-                }
-            }
-
             REG_FIFO_A_L | REG_FIFO_A_H => {
                 self.dma_sound[0].fifo.write((value & 0xff) as i8);
                 self.dma_sound[0].fifo.write(((value >> 8) & 0xff) as i8);
@@ -315,21 +394,102 @@ impl SoundController {
         }
     }
 
+    /// Turning off the master enable resets all the PSG registers, the wave RAM is kept.
+    fn reset_psg(&mut self) {
+        self.sqr1 = Default::default();
+        self.sqr2 = Default::default();
+        self.wave.reset();
+        self.noise = Default::default();
+        self.left_volume = 0;
+        self.right_volume = 0;
+        self.left_sqr1 = false;
+        self.left_sqr2 = false;
+        self.left_wave = false;
+        self.left_noise = false;
+        self.right_sqr1 = false;
+        self.right_sqr2 = false;
+        self.right_wave = false;
+        self.right_noise = false;
+    }
+
+    /// Mix the PSG channels for the given stereo channel (0 - left, 1 - right)
+    #[inline]
+    fn mix_psg(&self, channel: usize) -> i16 {
+        let (volume, sqr1, sqr2, wave, noise) = match channel {
+            0 => (
+                self.left_volume,
+                self.left_sqr1,
+                self.left_sqr2,
+                self.left_wave,
+                self.left_noise,
+            ),
+            1 => (
+                self.right_volume,
+                self.right_sqr1,
+                self.right_sqr2,
+                self.right_wave,
+                self.right_noise,
+            ),
+            _ => unreachable!(),
+        };
+
+        let mut sample = 0;
+        if sqr1 {
+            sample += self.sqr1.output();
+        }
+        if sqr2 {
+            sample += self.sqr2.output();
+        }
+        if wave {
+            sample += self.wave.output();
+        }
+        if noise {
+            sample += self.noise.output();
+        }
+
+        // Each channel is in the range of [-15, 15], at full volume this is roughly the range of a DMA channel.
+        sample *= volume as i16 + 1;
+        (sample as f32 * self.dmg_volume_ratio) as i16
+    }
+
+    /// Step the 512hz frame sequencer
+    fn on_sequencer(&mut self) -> FutureEvent {
+        if self.sequencer_step % 2 == 0 {
+            self.sqr1.clock_length();
+            self.sqr2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if self.sequencer_step == 2 || self.sequencer_step == 6 {
+            self.sqr1.clock_sweep();
+        }
+        if self.sequencer_step == 7 {
+            self.sqr1.clock_envelope();
+            self.sqr2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+        self.sequencer_step = (self.sequencer_step + 1) % 8;
+        (
+            EventType::Apu(ApuEvent::Sequencer),
+            CYCLES_PER_SEQUENCER_STEP,
+        )
+    }
+
     #[inline]
     fn on_sample(&mut self, audio_device: &mut DynAudioInterface) -> FutureEvent {
         let mut sample = [0f32, 0f32];
 
         for (channel, out_sample) in sample.iter_mut().enumerate() {
-            let mut dma_sample = 0;
+            let mut mixed_sample = self.mix_psg(channel);
             for dma in &mut self.dma_sound {
                 if dma.is_stereo_channel_enabled(channel) {
                     let value = dma.value as i16;
-                    dma_sample += value * (2 << dma.volume_shift);
+                    mixed_sample += value * (2 << dma.volume_shift);
                 }
             }
 
-            apply_bias(&mut dma_sample, self.sound_bias.bit_range(0..10) as i16);
-            *out_sample = dma_sample as i32 as f32;
+            apply_bias(&mut mixed_sample, self.sound_bias.bit_range(0..10) as i16);
+            *out_sample = mixed_sample as i32 as f32;
         }
 
         self.resampler.feed(&sample, &mut self.output_buffer);
@@ -343,15 +503,21 @@ impl SoundController {
         (EventType::Apu(ApuEvent::Sample), self.cycles_per_sample)
     }
 
+    /// Returns the next event to schedule, PSG generators are not rescheduled once their channel stops
     pub fn on_event(
         &mut self,
         event: ApuEvent,
         audio_device: &mut DynAudioInterface,
-    ) -> FutureEvent {
-        match event {
-            ApuEvent::Sample => self.on_sample(audio_device),
-            _ => unimplemented!("got {:?} event", event),
-        }
+    ) -> Option<FutureEvent> {
+        let cycles = match event {
+            ApuEvent::Sample => return Some(self.on_sample(audio_device)),
+            ApuEvent::Sequencer => return Some(self.on_sequencer()),
+            ApuEvent::Psg1Generate => self.sqr1.step(),
+            ApuEvent::Psg2Generate => self.sqr2.step(),
+            ApuEvent::Psg3Generate => self.wave.step(),
+            ApuEvent::Psg4Generate => self.noise.step(),
+        };
+        cycles.map(|cycles| (EventType::Apu(event), cycles))
     }
 }
 
@@ -0,0 +1,554 @@
+/// The four legacy (DMG) sound channels
+/// http://problemkaputt.de/gbatek.htm#gbasoundchannel1tonesweep
+use bit::BitIndex;
+use serde::{Deserialize, Serialize};
+
+use super::cbit;
+
+/// Waveforms for the 12.5%, 25%, 50% and 75% duty cycles, msb is played first.
+const SQUARE_DUTY_PATTERNS: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+
+/// Channel 3 volume multipliers (in quarters) for 0%, 100%, 50% and 25%
+const WAVE_VOLUME_MULTIPLIERS: [i16; 4] = [0, 4, 2, 1];
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+struct LengthCounter {
+    enabled: bool,
+    counter: u16,
+}
+
+impl LengthCounter {
+    fn load(&mut self, max: u16, value: u16) {
+        self.counter = max - value;
+    }
+
+    /// Clocked at 256hz by the frame sequencer
+    /// returns true if the channel should be disabled
+    fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            self.counter == 0
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+struct Envelope {
+    initial_volume: u8,
+    increase: bool,
+    step_time: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    /// Expects the NRx2 part of the register in bits 8-15
+    fn write(&mut self, value: u16) {
+        self.step_time = value.bit_range(8..11) as u8;
+        self.increase = value.bit(11);
+        self.initial_volume = value.bit_range(12..16) as u8;
+    }
+
+    fn read(&self) -> u16 {
+        (self.step_time as u16) << 8 | cbit(11, self.increase) | (self.initial_volume as u16) << 12
+    }
+
+    /// When both the initial volume and the direction are zero the channel is muted
+    fn dac_enabled(&self) -> bool {
+        self.initial_volume != 0 || self.increase
+    }
+
+    fn trigger(&mut self) {
+        self.volume = self.initial_volume;
+        self.timer = self.step_time;
+    }
+
+    /// Clocked at 64hz by the frame sequencer
+    fn clock(&mut self) {
+        if self.step_time == 0 {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = self.step_time;
+            if self.increase && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+/// Channels 1 & 2, only channel 1 has a frequency sweep unit.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SquareChannel {
+    pub enabled: bool,
+
+    sweep_shift: u8,
+    sweep_decrease: bool,
+    sweep_time: u8,
+    sweep_timer: u8,
+    sweep_enabled: bool,
+    shadow_frequency: u16,
+
+    duty: usize,
+    duty_step: usize,
+    length_load: u16,
+    length: LengthCounter,
+    envelope: Envelope,
+    frequency: u16,
+}
+
+impl SquareChannel {
+    /// Cycles between two duty steps
+    #[inline]
+    pub fn period(&self) -> usize {
+        (2048 - self.frequency as usize) * 16
+    }
+
+    pub fn read_sweep(&self) -> u16 {
+        self.sweep_shift as u16 | cbit(3, self.sweep_decrease) | (self.sweep_time as u16) << 4
+    }
+
+    pub fn write_sweep(&mut self, value: u16) {
+        self.sweep_shift = value.bit_range(0..3) as u8;
+        self.sweep_decrease = value.bit(3);
+        self.sweep_time = value.bit_range(4..7) as u8;
+    }
+
+    /// The sound length is write-only
+    pub fn read_duty_envelope(&self) -> u16 {
+        (self.duty as u16) << 6 | self.envelope.read()
+    }
+
+    pub fn write_duty_envelope(&mut self, value: u16) {
+        self.length_load = value.bit_range(0..6);
+        self.length.load(64, self.length_load);
+        self.duty = value.bit_range(6..8) as usize;
+        self.envelope.write(value);
+        if !self.envelope.dac_enabled() {
+            self.enabled = false;
+        }
+    }
+
+    /// The frequency is write-only
+    pub fn read_freq_control(&self) -> u16 {
+        cbit(14, self.length.enabled)
+    }
+
+    /// Returns true if the channel was restarted
+    pub fn write_freq_control(&mut self, value: u16) -> bool {
+        self.frequency = value.bit_range(0..11);
+        self.length.enabled = value.bit(14);
+        if value.bit(15) {
+            self.trigger();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Reconstructs the full register values, used for 8-bit writes
+    pub(super) fn raw_duty_envelope(&self) -> u16 {
+        self.read_duty_envelope() | self.length_load
+    }
+
+    pub(super) fn raw_freq_control(&self) -> u16 {
+        self.read_freq_control() | self.frequency
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        if self.length.counter == 0 {
+            self.length.counter = 64;
+        }
+        self.envelope.trigger();
+        self.duty_step = 0;
+
+        self.shadow_frequency = self.frequency;
+        self.sweep_timer = self.sweep_time;
+        self.sweep_enabled = self.sweep_time != 0 || self.sweep_shift != 0;
+        if self.sweep_shift != 0 && self.calculate_sweep() > 2047 {
+            self.enabled = false;
+        }
+    }
+
+    fn calculate_sweep(&self) -> u16 {
+        let delta = self.shadow_frequency >> self.sweep_shift;
+        if self.sweep_decrease {
+            self.shadow_frequency.saturating_sub(delta)
+        } else {
+            self.shadow_frequency + delta
+        }
+    }
+
+    /// Advance the duty cycle, returns the number of cycles until the next step
+    pub fn step(&mut self) -> Option<usize> {
+        if !self.enabled {
+            return None;
+        }
+        self.duty_step = (self.duty_step + 1) % 8;
+        Some(self.period())
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Clocked at 128hz by the frame sequencer
+    pub fn clock_sweep(&mut self) {
+        if self.sweep_timer > 0 {
+            self.sweep_timer -= 1;
+        }
+        if self.sweep_timer != 0 {
+            return;
+        }
+        self.sweep_timer = self.sweep_time;
+        if !self.sweep_enabled || self.sweep_time == 0 || self.sweep_shift == 0 {
+            return;
+        }
+        let new_frequency = self.calculate_sweep();
+        if new_frequency > 2047 {
+            self.enabled = false;
+        } else {
+            self.shadow_frequency = new_frequency;
+            self.frequency = new_frequency;
+        }
+    }
+
+    pub fn output(&self) -> i16 {
+        if !self.enabled {
+            return 0;
+        }
+        let volume = self.envelope.volume as i16;
+        if SQUARE_DUTY_PATTERNS[self.duty].bit(7 - self.duty_step) {
+            volume
+        } else {
+            -volume
+        }
+    }
+}
+
+/// Channel 3, plays 4-bit samples from the wave RAM.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct WaveChannel {
+    pub enabled: bool,
+
+    playback: bool,
+    two_banks: bool,
+    bank: usize,
+    wave_ram: [[u8; 16]; 2],
+    position: usize,
+    sample: u8,
+
+    length_load: u16,
+    length: LengthCounter,
+    volume: usize,
+    force_volume: bool,
+    frequency: u16,
+}
+
+impl WaveChannel {
+    /// Cycles between two samples
+    #[inline]
+    pub fn period(&self) -> usize {
+        (2048 - self.frequency as usize) * 8
+    }
+
+    pub fn read_stop_bank(&self) -> u16 {
+        cbit(5, self.two_banks) | (self.bank as u16) << 6 | cbit(7, self.playback)
+    }
+
+    pub fn write_stop_bank(&mut self, value: u16) {
+        self.two_banks = value.bit(5);
+        self.bank = value.bit(6) as usize;
+        self.playback = value.bit(7);
+        if !self.playback {
+            self.enabled = false;
+        }
+    }
+
+    /// The sound length is write-only
+    pub fn read_length_volume(&self) -> u16 {
+        (self.volume as u16) << 13 | cbit(15, self.force_volume)
+    }
+
+    pub fn write_length_volume(&mut self, value: u16) {
+        self.length_load = value.bit_range(0..8);
+        self.length.load(256, self.length_load);
+        self.volume = value.bit_range(13..15) as usize;
+        self.force_volume = value.bit(15);
+    }
+
+    pub fn read_freq_control(&self) -> u16 {
+        cbit(14, self.length.enabled)
+    }
+
+    /// Returns true if the channel was restarted
+    pub fn write_freq_control(&mut self, value: u16) -> bool {
+        self.frequency = value.bit_range(0..11);
+        self.length.enabled = value.bit(14);
+        if value.bit(15) {
+            self.trigger();
+            true
+        } else {
+            false
+        }
+    }
+
+    pub(super) fn raw_length_volume(&self) -> u16 {
+        self.read_length_volume() | self.length_load
+    }
+
+    pub(super) fn raw_freq_control(&self) -> u16 {
+        self.read_freq_control() | self.frequency
+    }
+
+    /// Reset all the registers, the contents of the wave RAM are kept
+    pub fn reset(&mut self) {
+        *self = WaveChannel {
+            wave_ram: self.wave_ram,
+            ..Default::default()
+        };
+    }
+
+    /// The CPU can only access the bank that is not currently played
+    pub fn read_wave_ram(&self, offset: usize) -> u8 {
+        self.wave_ram[self.bank ^ 1][offset]
+    }
+
+    pub fn write_wave_ram(&mut self, offset: usize, value: u8) {
+        self.wave_ram[self.bank ^ 1][offset] = value;
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.playback;
+        if self.length.counter == 0 {
+            self.length.counter = 256;
+        }
+        self.position = 0;
+    }
+
+    /// Fetch the next sample, returns the number of cycles until the next step
+    pub fn step(&mut self) -> Option<usize> {
+        if !self.enabled {
+            return None;
+        }
+        let num_samples = if self.two_banks { 64 } else { 32 };
+        let bank = (self.bank + self.position / 32) % 2;
+        let index = self.position % 32;
+        let byte = self.wave_ram[bank][index / 2];
+        // The upper nibble is played first
+        self.sample = if index % 2 == 0 {
+            byte >> 4
+        } else {
+            byte & 0xf
+        };
+        self.position = (self.position + 1) % num_samples;
+        Some(self.period())
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn output(&self) -> i16 {
+        if !self.enabled {
+            return 0;
+        }
+        let multiplier = if self.force_volume {
+            3
+        } else {
+            WAVE_VOLUME_MULTIPLIERS[self.volume]
+        };
+        ((self.sample as i16) * 2 - 15) * multiplier / 4
+    }
+}
+
+/// Channel 4, pseudo-random noise generated by a 15-bit or 7-bit LFSR.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct NoiseChannel {
+    pub enabled: bool,
+
+    length_load: u16,
+    length: LengthCounter,
+    envelope: Envelope,
+    ratio: usize,
+    narrow: bool,
+    shift: usize,
+    lfsr: u16,
+    high: bool,
+}
+
+impl NoiseChannel {
+    /// Cycles between two shifts of the LFSR, based on 524288hz / r / 2^(s+1)
+    #[inline]
+    pub fn period(&self) -> usize {
+        let base = if self.ratio == 0 { 16 } else { 32 * self.ratio };
+        base << (self.shift + 1)
+    }
+
+    /// The sound length is write-only
+    pub fn read_length_envelope(&self) -> u16 {
+        self.envelope.read()
+    }
+
+    pub fn write_length_envelope(&mut self, value: u16) {
+        self.length_load = value.bit_range(0..6);
+        self.length.load(64, self.length_load);
+        self.envelope.write(value);
+        if !self.envelope.dac_enabled() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn read_freq_control(&self) -> u16 {
+        self.ratio as u16
+            | cbit(3, self.narrow)
+            | (self.shift as u16) << 4
+            | cbit(14, self.length.enabled)
+    }
+
+    /// Returns true if the channel was restarted
+    pub fn write_freq_control(&mut self, value: u16) -> bool {
+        self.ratio = value.bit_range(0..3) as usize;
+        self.narrow = value.bit(3);
+        self.shift = value.bit_range(4..8) as usize;
+        self.length.enabled = value.bit(14);
+        if value.bit(15) {
+            self.trigger();
+            true
+        } else {
+            false
+        }
+    }
+
+    pub(super) fn raw_length_envelope(&self) -> u16 {
+        self.read_length_envelope() | self.length_load
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        if self.length.counter == 0 {
+            self.length.counter = 64;
+        }
+        self.envelope.trigger();
+        self.lfsr = if self.narrow { 0x40 } else { 0x4000 };
+    }
+
+    /// Shift the LFSR, returns the number of cycles until the next step
+    pub fn step(&mut self) -> Option<usize> {
+        if !self.enabled {
+            return None;
+        }
+        let carry = self.lfsr & 1 != 0;
+        self.lfsr >>= 1;
+        if carry {
+            self.lfsr ^= if self.narrow { 0x60 } else { 0x6000 };
+        }
+        self.high = carry;
+        Some(self.period())
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn output(&self) -> i16 {
+        if !self.enabled {
+            return 0;
+        }
+        let volume = self.envelope.volume as i16;
+        if self.high {
+            volume
+        } else {
+            -volume
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_square_length_expires() {
+        let mut sqr = SquareChannel::default();
+        // 50% duty, max volume, length of 62 => 2 ticks
+        sqr.write_duty_envelope(0xf000 | 2 << 6 | 62);
+        assert!(sqr.write_freq_control(0x8000 | 0x4000 | 1024));
+        assert!(sqr.enabled);
+        assert_eq!(sqr.period(), 1024 * 16);
+
+        let mut outputs = vec![];
+        for _ in 0..8 {
+            outputs.push(sqr.output());
+            sqr.step();
+        }
+        assert_eq!(outputs.iter().filter(|&&s| s > 0).count(), 4);
+
+        sqr.clock_length();
+        assert!(sqr.enabled);
+        sqr.clock_length();
+        assert!(!sqr.enabled);
+        assert_eq!(sqr.step(), None);
+        assert_eq!(sqr.output(), 0);
+    }
+
+    #[test]
+    fn test_wave_ram_banking() {
+        let mut wave = WaveChannel::default();
+        // Select bank 0 for playback, so the CPU accesses bank 1
+        wave.write_stop_bank(0x80);
+        wave.write_wave_ram(0, 0xab);
+        assert_eq!(wave.wave_ram[1][0], 0xab);
+        assert_eq!(wave.wave_ram[0][0], 0);
+
+        // Play bank 1, full volume
+        wave.write_stop_bank(0x80 | 0x40);
+        wave.write_length_volume(1 << 13);
+        assert!(wave.write_freq_control(0x8000));
+        assert_eq!(wave.read_wave_ram(0), 0);
+        wave.step();
+        assert_eq!(wave.sample, 0xa);
+        wave.step();
+        assert_eq!(wave.sample, 0xb);
+
+        wave.reset();
+        assert!(!wave.enabled);
+        assert_eq!(wave.wave_ram[1][0], 0xab);
+    }
+
+    #[test]
+    fn test_noise_lfsr() {
+        let mut noise = NoiseChannel::default();
+        noise.write_length_envelope(0xf000);
+        assert!(noise.write_freq_control(0x8000 | 1 << 3));
+        assert_eq!(noise.lfsr, 0x40);
+        // The 7-bit LFSR has a period of 127
+        let initial = noise.lfsr;
+        for _ in 0..127 {
+            noise.step();
+        }
+        assert_eq!(noise.lfsr, initial);
+    }
+}
@@ -1,13 +1,14 @@
 use serde::{Deserialize, Serialize};
 
 use super::arm7tdmi::memory::{
-    Addr, BusIO, DebugRead, MemoryAccess, MemoryAccessWidth, MemoryInterface,
+    Addr, BusIO, DebugRead, DebugWrite, MemoryAccess, MemoryAccessWidth, MemoryInterface,
 };
 use super::bios::Bios;
 use super::cartridge::Cartridge;
 use super::dma::DmaNotifer;
 use super::iodev::{IoDevices, WaitControl};
 use super::sched::*;
+use super::watchpoint::{WatchKind, Watchpoints};
 use arm7tdmi::{self, Arm7tdmiCore};
 use rustboyadvance_utils::{Shared, WeakPointer};
 
@@ -143,6 +144,84 @@ impl CycleLookupTables {
     }
 }
 
+/// How many halfwords the prefetch buffer holds
+const PREFETCH_CAPACITY: u32 = 8;
+
+/// The GamePak prefetch buffer
+///
+/// While the cpu doesn't use the gamepak bus (internal cycles or accesses to other memory), the
+/// buffer reads the halfwords that follow the last opcode fetched from rom, so sequential opcode
+/// fetches are served without waitstates. A non-sequential fetch or a data access to the gamepak
+/// empties it.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub(crate) struct Prefetch {
+    active: bool,
+    /// Address of the oldest halfword in the buffer
+    head: Addr,
+    /// Number of halfwords in the buffer
+    count: u32,
+    /// Cycles until the halfword after the buffered ones arrives
+    countdown: usize,
+    /// Sequential access time of the rom region being prefetched
+    duty: usize,
+}
+
+impl Prefetch {
+    fn invalidate(&mut self) {
+        self.active = false;
+        self.count = 0;
+    }
+
+    fn restart(&mut self, addr: Addr, duty: usize) {
+        self.active = true;
+        self.head = addr;
+        self.count = 0;
+        self.countdown = duty;
+        self.duty = duty;
+    }
+
+    /// Let the buffer use the gamepak bus for `cycles` cycles
+    #[inline]
+    fn run(&mut self, mut cycles: usize) {
+        while self.active && self.count < PREFETCH_CAPACITY && cycles > 0 {
+            if cycles >= self.countdown {
+                cycles -= self.countdown;
+                self.count += 1;
+                self.countdown = self.duty;
+            } else {
+                self.countdown -= cycles;
+                cycles = 0;
+            }
+        }
+    }
+
+    /// Take `halfwords` opcode halfwords starting at `addr` out of the buffer, returns the cycles
+    /// the fetch takes or None if the buffer doesn't have them
+    fn read(&mut self, addr: Addr, halfwords: u32) -> Option<usize> {
+        if !self.active || addr != self.head {
+            return None;
+        }
+        let mut wait = 0;
+        for _ in 0..halfwords {
+            if self.count > 0 {
+                self.count -= 1;
+            } else {
+                // wait for the halfword that is on its way
+                wait += self.countdown;
+                self.countdown = self.duty;
+            }
+        }
+        self.head = addr.wrapping_add(2 * halfwords);
+        if wait == 0 {
+            // served from the buffer without waitstates, meanwhile the buffer keeps filling
+            self.run(1);
+            Some(1)
+        } else {
+            Some(wait)
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct SysBus {
     pub io: Shared<IoDevices>,
@@ -155,8 +234,16 @@ pub struct SysBus {
     pub cartridge: Cartridge,
 
     cycle_luts: CycleLookupTables,
+    prefetch_enabled: bool,
+    pub(crate) prefetch: Prefetch,
+    /// Set while a DMA channel owns the bus
+    pub(crate) dma_active: bool,
+    /// The last value moved by DMA, what DMA reads from unmapped memory or the bios
+    pub(crate) dma_bus: u32,
 
     pub trace_access: bool,
+
+    pub watchpoints: Watchpoints,
 }
 
 pub type SysBusPtr = WeakPointer<SysBus>;
@@ -180,6 +267,7 @@ impl SysBus {
         let mut luts = CycleLookupTables::default();
         luts.init();
         luts.update_gamepak_waitstates(io.waitcnt);
+        let prefetch_enabled = io.waitcnt.prefetch();
 
         SysBus {
             io,
@@ -191,7 +279,12 @@ impl SysBus {
             ewram,
             iwram,
             cycle_luts: luts,
+            prefetch_enabled,
+            prefetch: Prefetch::default(),
+            dma_active: false,
+            dma_bus: 0,
             trace_access: false,
+            watchpoints: Watchpoints::default(),
         }
     }
 
@@ -206,6 +299,13 @@ impl SysBus {
         SysBus::new_with_memories(scheduler, io, cartridge, bios_rom, ewram, iwram)
     }
 
+    #[inline]
+    fn watch(&mut self, addr: Addr, len: u32, access: WatchKind, value: u32) {
+        if !self.watchpoints.is_empty() {
+            self.watchpoints.check(addr, len, access, value);
+        }
+    }
+
     pub fn set_ewram(&mut self, buffer: Box<[u8]>) {
         self.ewram = buffer;
     }
@@ -237,9 +337,14 @@ impl SysBus {
 
     pub fn on_waitcnt_written(&mut self, waitcnt: WaitControl) {
         self.cycle_luts.update_gamepak_waitstates(waitcnt);
+        self.prefetch_enabled = waitcnt.prefetch();
+        if !self.prefetch_enabled {
+            self.prefetch.invalidate();
+        }
     }
     pub fn idle_cycle(&mut self) {
         self.scheduler.update(1);
+        self.prefetch.run(1);
     }
 
     #[inline(always)]
@@ -262,6 +367,50 @@ impl SysBus {
         };
 
         self.scheduler.update(*cycles);
+        match page {
+            // data accesses to the gamepak take the bus away from the prefetch buffer
+            PAGE_GAMEPAK_WS0..=PAGE_SRAM_HI => self.prefetch.invalidate(),
+            _ => self.prefetch.run(*cycles),
+        }
+    }
+
+    /// Like `add_cycles`, for opcode fetches that may be served by the prefetch buffer
+    #[inline(always)]
+    fn add_fetch_cycles(&mut self, addr: Addr, access: MemoryAccess, width: MemoryAccessWidth) {
+        let page = ((addr >> 24) & 0xF) as usize;
+        if !self.prefetch_enabled || !(PAGE_GAMEPAK_WS0..PAGE_SRAM_LO).contains(&page) {
+            return self.add_cycles(addr, access, width);
+        }
+
+        let halfwords = match width {
+            MemoryAccessWidth::MemoryAccess32 => 2,
+            _ => 1,
+        };
+        let buffered = match access {
+            MemoryAccess::Seq => self.prefetch.read(addr, halfwords),
+            MemoryAccess::NonSeq => None,
+        };
+        let cycles = match buffered {
+            Some(cycles) => cycles,
+            None => {
+                let cycles = match (access, width) {
+                    (MemoryAccess::NonSeq, MemoryAccessWidth::MemoryAccess32) => {
+                        self.cycle_luts.n_cycles32[page]
+                    }
+                    (MemoryAccess::Seq, MemoryAccessWidth::MemoryAccess32) => {
+                        self.cycle_luts.s_cycles32[page]
+                    }
+                    (MemoryAccess::NonSeq, _) => self.cycle_luts.n_cycles16[page],
+                    (MemoryAccess::Seq, _) => self.cycle_luts.s_cycles16[page],
+                };
+                self.prefetch.restart(
+                    addr.wrapping_add(2 * halfwords),
+                    self.cycle_luts.s_cycles16[page],
+                );
+                cycles
+            }
+        };
+        self.scheduler.update(cycles);
     }
 
     /// Helper for "open-bus" accesses
@@ -270,7 +419,16 @@ impl SysBus {
     /// `addr` is considered to be an address of
     fn read_invalid(&mut self, addr: Addr) -> u32 {
         warn!("invalid read @{:08x}", addr);
-        let value = match self.arm_core.cpsr.state() {
+        self.open_bus() >> ((addr & 3) << 3)
+    }
+
+    /// The word a read that nothing answers sees on the bus: the last value moved by DMA while a
+    /// channel owns the bus, otherwise the opcode prefetched by the cpu
+    pub(crate) fn open_bus(&self) -> u32 {
+        if self.dma_active {
+            return self.dma_bus;
+        }
+        match self.arm_core.cpsr.state() {
             arm7tdmi::CpuState::ARM => self.arm_core.get_prefetched_opcode(),
             arm7tdmi::CpuState::THUMB => {
                 // For THUMB code the result consists of two 16bit fragments and depends on the address area
@@ -301,8 +459,7 @@ impl SysBus {
                 }
                 value
             }
-        };
-        value >> ((addr & 3) << 3)
+        }
     }
 }
 
@@ -312,7 +469,7 @@ impl BusIO for SysBus {
     fn read_32(&mut self, addr: Addr) -> u32 {
         match addr & 0xff000000 {
             BIOS_ADDR => {
-                if addr <= 0x3ffc {
+                if addr <= 0x3ffc && !self.dma_active {
                     self.bios.read_32(addr)
                 } else {
                     self.read_invalid(addr)
@@ -342,7 +499,7 @@ impl BusIO for SysBus {
     fn read_16(&mut self, addr: Addr) -> u16 {
         match addr & 0xff000000 {
             BIOS_ADDR => {
-                if addr <= 0x3ffe {
+                if addr <= 0x3ffe && !self.dma_active {
                     self.bios.read_16(addr)
                 } else {
                     self.read_invalid(addr) as u16
@@ -372,7 +529,7 @@ impl BusIO for SysBus {
     fn read_8(&mut self, addr: Addr) -> u8 {
         match addr & 0xff000000 {
             BIOS_ADDR => {
-                if addr <= 0x3fff {
+                if addr <= 0x3fff && !self.dma_active {
                     self.bios.read_8(addr)
                 } else {
                     self.read_invalid(addr) as u8
@@ -413,7 +570,11 @@ impl BusIO for SysBus {
                 self.io.write_32(addr, value)
             }
             PALRAM_ADDR | VRAM_ADDR | OAM_ADDR => self.io.gpu.write_32(addr, value),
-            GAMEPAK_WS0_LO => self.cartridge.write_32(addr, value),
+            GAMEPAK_WS0_LO => {
+                // The RTC takes the time when the game writes to the gpio port
+                self.cartridge.update_rtc_cycles(self.scheduler.timestamp());
+                self.cartridge.write_32(addr, value)
+            }
             GAMEPAK_WS2_HI => self.cartridge.write_32(addr, value),
             SRAM_LO | SRAM_HI => self.cartridge.write_32(addr, value),
             _ => {
@@ -438,7 +599,10 @@ impl BusIO for SysBus {
                 self.io.write_16(addr, value)
             }
             PALRAM_ADDR | VRAM_ADDR | OAM_ADDR => self.io.gpu.write_16(addr, value),
-            GAMEPAK_WS0_LO => self.cartridge.write_16(addr, value),
+            GAMEPAK_WS0_LO => {
+                self.cartridge.update_rtc_cycles(self.scheduler.timestamp());
+                self.cartridge.write_16(addr, value)
+            }
             GAMEPAK_WS2_HI => self.cartridge.write_16(addr, value),
             SRAM_LO | SRAM_HI => self.cartridge.write_16(addr, value),
             _ => {
@@ -502,46 +666,98 @@ impl DebugRead for SysBus {
     }
 }
 
+impl DebugWrite for SysBus {
+    fn debug_write_8(&mut self, addr: Addr, value: u8) {
+        match addr & 0xff000000 {
+            BIOS_ADDR if addr <= 0x3fff => self.bios.debug_write_8(addr, value),
+            EWRAM_ADDR => self.ewram.debug_write_8(addr & 0x3_ffff, value),
+            IWRAM_ADDR => self.iwram.debug_write_8(addr & 0x7fff, value),
+            IOMEM_ADDR => {
+                let addr = if addr & 0xffff == 0x8000 {
+                    0x800
+                } else {
+                    addr & 0x00ffffff
+                };
+                self.io.debug_write_8(addr, value)
+            }
+            PALRAM_ADDR | VRAM_ADDR | OAM_ADDR => self.io.gpu.debug_write_8(addr, value),
+            GAMEPAK_WS0_LO | GAMEPAK_WS0_HI | GAMEPAK_WS1_LO | GAMEPAK_WS1_HI | GAMEPAK_WS2_LO
+            | GAMEPAK_WS2_HI | SRAM_LO | SRAM_HI => self.cartridge.debug_write_8(addr, value),
+            _ => {}
+        }
+    }
+}
+
 impl MemoryInterface for SysBus {
     #[inline]
     fn load_8(&mut self, addr: u32, access: MemoryAccess) -> u8 {
         self.add_cycles(addr, access, MemoryAccessWidth::MemoryAccess8);
-        self.read_8(addr)
+        let value = self.read_8(addr);
+        self.watch(addr, 1, WatchKind::Read, value as u32);
+        value
     }
 
     #[inline]
     fn load_16(&mut self, addr: u32, access: MemoryAccess) -> u16 {
         self.add_cycles(addr, access, MemoryAccessWidth::MemoryAccess16);
-        self.read_16(addr)
+        let value = self.read_16(addr);
+        self.watch(addr, 2, WatchKind::Read, value as u32);
+        value
     }
 
     #[inline]
     fn load_32(&mut self, addr: u32, access: MemoryAccess) -> u32 {
         self.add_cycles(addr, access, MemoryAccessWidth::MemoryAccess32);
-        self.read_32(addr)
+        let value = self.read_32(addr);
+        self.watch(addr, 4, WatchKind::Read, value);
+        value
     }
 
     #[inline]
     fn store_8(&mut self, addr: u32, value: u8, access: MemoryAccess) {
         self.add_cycles(addr, access, MemoryAccessWidth::MemoryAccess8);
         self.write_8(addr, value);
+        self.watch(addr, 1, WatchKind::Write, value as u32);
     }
 
     #[inline]
     fn store_16(&mut self, addr: u32, value: u16, access: MemoryAccess) {
         self.add_cycles(addr, access, MemoryAccessWidth::MemoryAccess8);
         self.write_16(addr, value);
+        self.watch(addr, 2, WatchKind::Write, value as u32);
     }
 
     #[inline]
     fn store_32(&mut self, addr: u32, value: u32, access: MemoryAccess) {
         self.add_cycles(addr, access, MemoryAccessWidth::MemoryAccess8);
         self.write_32(addr, value);
+        self.watch(addr, 4, WatchKind::Write, value);
+    }
+
+    #[inline]
+    fn fetch_16(&mut self, addr: u32, access: MemoryAccess) -> u16 {
+        self.add_fetch_cycles(addr, access, MemoryAccessWidth::MemoryAccess16);
+        let value = self.read_16(addr);
+        if addr <= 0x3fff {
+            self.bios.latch_fetch(addr);
+        }
+        value
+    }
+
+    #[inline]
+    fn fetch_32(&mut self, addr: u32, access: MemoryAccess) -> u32 {
+        self.add_fetch_cycles(addr, access, MemoryAccessWidth::MemoryAccess32);
+        let value = self.read_32(addr);
+        if addr <= 0x3fff {
+            self.bios.latch_fetch(addr);
+        }
+        value
     }
 
     #[inline]
     fn idle_cycle(&mut self) {
-        self.scheduler.update(1)
+        self.scheduler.update(1);
+        self.prefetch.run(1);
     }
 }
 
@@ -550,3 +766,139 @@ impl DmaNotifer for SysBus {
         self.io.dmac.notify_from_gpu(timing);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::prelude::*;
+    use crate::test_utils::{make_mock_gba, make_rom};
+
+    /// b . followed by a marker for the open bus
+    const CODE: [u32; 2] = [0xeafffffe, 0x1234_5678];
+
+    fn cycles(gba: &mut GameBoyAdvance, f: impl FnOnce(&mut SysBus)) -> usize {
+        let start = gba.scheduler.timestamp();
+        f(&mut gba.sysbus);
+        gba.scheduler.timestamp() - start
+    }
+
+    #[test]
+    fn test_prefetch() {
+        use MemoryAccess::*;
+
+        let mut gba = make_mock_gba(&make_rom(&CODE));
+        // ws0 3/1 waitstates with the prefetch buffer enabled
+        gba.sysbus.write_16(0x0400_0204, 0x4014);
+        let fetch = |addr, access| {
+            move |bus: &mut SysBus| {
+                bus.fetch_16(addr, access);
+            }
+        };
+
+        assert_eq!(cycles(&mut gba, fetch(0x0800_0000, NonSeq)), 4);
+        // two halfwords arrive while the cpu is busy elsewhere
+        assert_eq!(
+            cycles(&mut gba, |bus| {
+                for _ in 0..4 {
+                    bus.idle_cycle();
+                }
+            }),
+            4
+        );
+        assert_eq!(cycles(&mut gba, fetch(0x0800_0002, Seq)), 1);
+        // the buffer keeps filling while it serves the cpu
+        assert_eq!(cycles(&mut gba, fetch(0x0800_0004, Seq)), 1);
+        assert_eq!(cycles(&mut gba, fetch(0x0800_0006, Seq)), 1);
+        // the next halfword is half way there
+        assert_eq!(cycles(&mut gba, fetch(0x0800_0008, Seq)), 1);
+
+        // a branch empties the buffer
+        gba.sysbus.idle_cycle();
+        assert_eq!(cycles(&mut gba, fetch(0x0800_000a, NonSeq)), 4);
+
+        // and so does reading data from the gamepak
+        gba.sysbus.idle_cycle();
+        gba.sysbus.idle_cycle();
+        assert_eq!(
+            cycles(&mut gba, |bus| {
+                bus.load_16(0x0800_0100, NonSeq);
+            }),
+            4
+        );
+        assert_eq!(cycles(&mut gba, fetch(0x0800_000c, Seq)), 2);
+
+        // ARM opcodes take two halfwords out of the buffer
+        let fetch_arm = |addr, access| {
+            move |bus: &mut SysBus| {
+                bus.fetch_32(addr, access);
+            }
+        };
+        assert_eq!(cycles(&mut gba, fetch_arm(0x0800_0010, NonSeq)), 6);
+        for _ in 0..4 {
+            gba.sysbus.idle_cycle();
+        }
+        assert_eq!(cycles(&mut gba, fetch_arm(0x0800_0014, Seq)), 1);
+        assert_eq!(cycles(&mut gba, fetch_arm(0x0800_0018, Seq)), 3);
+
+        // without the buffer every fetch pays the waitstates
+        gba.sysbus.write_16(0x0400_0204, 0x0014);
+        for _ in 0..4 {
+            gba.sysbus.idle_cycle();
+        }
+        assert_eq!(cycles(&mut gba, fetch(0x0800_001c, Seq)), 2);
+    }
+
+    #[test]
+    fn test_open_bus_cpu() {
+        let mut gba = make_mock_gba(&make_rom(&CODE));
+        gba.cpu.reload_pipeline32();
+        // in ARM state the open bus is the opcode at $+8
+        assert_eq!(gba.cpu.get_prefetched_opcode(), 0x1234_5678);
+
+        let sysbus = &mut gba.sysbus;
+        assert_eq!(sysbus.read_32(0x0100_0000), 0x1234_5678);
+        assert_eq!(sysbus.read_8(0x1000_0003), 0x12);
+        // io holes and write only registers, each halfword sees its half of the bus
+        assert_eq!(sysbus.read_32(0x0400_00e0), 0x1234_5678);
+        assert_eq!(sysbus.read_16(0x0400_0010), 0x5678);
+        assert_eq!(sysbus.read_16(0x0400_0042), 0x1234);
+        assert_eq!(sysbus.read_32(0x0400_00b0), 0x1234_5678);
+        assert_eq!(sysbus.read_8(0x0400_004f), 0x12);
+        // unused halves of registers and DMAxCNT_L read as zero
+        assert_eq!(sysbus.read_16(0x0400_0206), 0);
+        sysbus.write_16(0x0400_00ba, 0x0040);
+        assert_eq!(sysbus.read_32(0x0400_00b8), 0x0040_0000);
+        // the debugger still sees the value of write only registers
+        sysbus.write_16(0x0400_0040, 0x10f0);
+        assert_eq!(sysbus.debug_read_16(0x0400_0040), 0x10f0);
+
+        // protected bios reads see the last opcode fetched from the bios
+        assert_eq!(sysbus.read_32(0x0000_0010), 0xe129_f000);
+        assert_eq!(sysbus.read_16(0x0000_0002), 0xe129);
+    }
+
+    #[test]
+    fn test_open_bus_dma() {
+        let mut gba = make_mock_gba(&make_rom(&CODE));
+        gba.cpu.reload_pipeline32();
+
+        let dma3 = |gba: &mut GameBoyAdvance, src: u32, dst: u32| {
+            gba.sysbus.write_32(0x0400_00d4, src);
+            gba.sysbus.write_32(0x0400_00d8, dst);
+            // one word, start immediately
+            gba.sysbus.write_32(0x0400_00dc, 0x8400_0001);
+            gba.io_devs.dmac.activate_channel(3);
+            gba.io_devs.dmac.perform_work(&mut gba.sysbus);
+        };
+        gba.sysbus.write_32(0x0300_0000, 0xcafe_babe);
+        dma3(&mut gba, 0x0300_0000, 0x0300_0100);
+        // DMA reads of unmapped memory and the bios see the last value DMA moved
+        dma3(&mut gba, 0x0100_0000, 0x0300_0104);
+        dma3(&mut gba, 0x0000_0000, 0x0300_0108);
+        assert_eq!(gba.sysbus.read_32(0x0300_0104), 0xcafe_babe);
+        assert_eq!(gba.sysbus.read_32(0x0300_0108), 0xcafe_babe);
+        // and the cpu sees its own prefetch again
+        assert_eq!(gba.sysbus.read_32(0x0100_0000), 0x1234_5678);
+    }
+}
@@ -0,0 +1,41 @@
+//! Fixtures shared by the unit tests
+use crate::cartridge::GamepakBuilder;
+use crate::hle::builtin_bios;
+use crate::prelude::*;
+
+/// A rom starting with `code`, big enough to hold the cartridge header
+pub(crate) fn make_rom(code: &[u32]) -> Vec<u8> {
+    let mut rom = vec![0; std::cmp::max(0x200, code.len() * 4)];
+    for (i, insn) in code.iter().enumerate() {
+        rom[i * 4..i * 4 + 4].copy_from_slice(&insn.to_le_bytes());
+    }
+    rom
+}
+
+/// A gba with a zeroed bios, as if the bios just started `rom`
+pub(crate) fn make_mock_gba(rom: &[u8]) -> GameBoyAdvance {
+    make_mock_gba_with(GamepakBuilder::new().buffer(rom).with_sram())
+}
+
+/// Same as `make_mock_gba`, for cartridges that need more from the builder
+pub(crate) fn make_mock_gba_with(builder: GamepakBuilder) -> GameBoyAdvance {
+    let bios = vec![0; 0x4000].into_boxed_slice();
+    let cartridge = builder.without_backup_to_file().build().unwrap();
+    let mut gba = GameBoyAdvance::new(bios, cartridge, NullAudio::new());
+    gba.skip_bios();
+
+    gba
+}
+
+/// A gba with the builtin bios, reset so that it boots into `rom` by itself
+pub(crate) fn make_hle_gba(rom: &[u8]) -> GameBoyAdvance {
+    let cartridge = GamepakBuilder::new()
+        .buffer(rom)
+        .without_backup_to_file()
+        .build()
+        .unwrap();
+    let mut gba = GameBoyAdvance::new(builtin_bios(), cartridge, NullAudio::new());
+    gba.cpu.reset();
+
+    gba
+}
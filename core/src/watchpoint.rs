@@ -0,0 +1,152 @@
+//! Watchpoints on memory ranges.
+//!
+//! The `SysBus` checks every cpu and dma access against the list, the first access that hits
+//! is kept until the debugger picks it up once the current instruction is done.
+use std::fmt;
+
+use arm7tdmi::memory::Addr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Write,
+    Read,
+    ReadWrite,
+}
+
+impl WatchKind {
+    #[inline]
+    fn matches(self, access: WatchKind) -> bool {
+        self == WatchKind::ReadWrite || self == access
+    }
+}
+
+impl fmt::Display for WatchKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchKind::Write => write!(f, "write"),
+            WatchKind::Read => write!(f, "read"),
+            WatchKind::ReadWrite => write!(f, "access"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addr: Addr,
+    pub len: u32,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    #[inline]
+    fn overlaps(&self, addr: Addr, len: u32) -> bool {
+        let (start, end) = (self.addr as u64, self.addr as u64 + self.len as u64);
+        (addr as u64) < end && start < addr as u64 + len as u64
+    }
+}
+
+/// An access that hit a watchpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchpointHit {
+    pub watchpoint: Watchpoint,
+    /// Address of the access, may be anywhere in the watched range
+    pub addr: Addr,
+    /// Either `Read` or `Write`
+    pub access: WatchKind,
+    /// The value that was read or written
+    pub value: u32,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Watchpoints {
+    list: Vec<Watchpoint>,
+    hit: Option<WatchpointHit>,
+}
+
+impl Watchpoints {
+    /// Returns false if the same watchpoint already exists
+    pub fn add(&mut self, watchpoint: Watchpoint) -> bool {
+        if self.list.contains(&watchpoint) {
+            false
+        } else {
+            self.list.push(watchpoint);
+            true
+        }
+    }
+
+    /// Returns false if there was no such watchpoint
+    pub fn remove(&mut self, watchpoint: Watchpoint) -> bool {
+        let len = self.list.len();
+        self.list.retain(|w| *w != watchpoint);
+        self.list.len() != len
+    }
+
+    pub fn clear(&mut self) {
+        self.list.clear();
+        self.hit = None;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Watchpoint> {
+        self.list.iter()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Check an access of `len` bytes
+    #[inline]
+    pub(crate) fn check(&mut self, addr: Addr, len: u32, access: WatchKind, value: u32) {
+        if self.hit.is_some() {
+            return;
+        }
+        if let Some(watchpoint) = self
+            .list
+            .iter()
+            .find(|w| w.kind.matches(access) && w.overlaps(addr, len))
+        {
+            self.hit = Some(WatchpointHit {
+                watchpoint: *watchpoint,
+                addr,
+                access,
+                value,
+            });
+        }
+    }
+
+    pub fn take_hit(&mut self) -> Option<WatchpointHit> {
+        self.hit.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watchpoints() {
+        let mut watchpoints = Watchpoints::default();
+        let watchpoint = Watchpoint {
+            addr: 0x0300_0010,
+            len: 4,
+            kind: WatchKind::Write,
+        };
+        assert!(watchpoints.add(watchpoint));
+        assert!(!watchpoints.add(watchpoint));
+
+        watchpoints.check(0x0300_0010, 4, WatchKind::Read, 0);
+        watchpoints.check(0x0300_000c, 4, WatchKind::Write, 0);
+        watchpoints.check(0x0300_0014, 2, WatchKind::Write, 0);
+        assert_eq!(watchpoints.take_hit(), None);
+
+        watchpoints.check(0x0300_0012, 2, WatchKind::Write, 0x1234);
+        watchpoints.check(0x0300_0010, 1, WatchKind::Write, 0x56);
+        let hit = watchpoints.take_hit().unwrap();
+        assert_eq!((hit.addr, hit.value), (0x0300_0012, 0x1234));
+        assert_eq!(watchpoints.take_hit(), None);
+
+        assert!(watchpoints.remove(watchpoint));
+        assert!(watchpoints.is_empty());
+    }
+}
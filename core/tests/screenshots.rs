@@ -0,0 +1,220 @@
+//! Screenshot regression suite
+//!
+//! Runs every rom listed in `tests/screenshots/manifest.yaml` for a number of frames and compares
+//! the frame buffer against the committed reference png. On a mismatch the reference, the actual
+//! frame and the differing pixels are written side by side to `target/screenshots/<name>.diff.png`.
+//!
+//! Set `UPDATE_SCREENSHOTS=1` to (re)write the reference images instead of comparing.
+use std::env;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use yaml_rust::YamlLoader;
+
+use rustboyadvance_core::hle;
+use rustboyadvance_core::prelude::*;
+
+type DynError = Box<dyn std::error::Error>;
+
+/// Every character of the printed diff map stands for a block of 8x8 pixels
+const MAP_BLOCK: usize = 8;
+const DIFF_COLOR: u32 = 0xff00ff;
+
+struct Case {
+    name: String,
+    rom: PathBuf,
+    frames: usize,
+}
+
+fn repo_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .to_path_buf()
+}
+
+fn screenshots_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/screenshots")
+}
+
+fn output_dir() -> PathBuf {
+    repo_root().join("target/screenshots")
+}
+
+fn load_manifest() -> Vec<Case> {
+    let path = screenshots_dir().join("manifest.yaml");
+    let text = fs::read_to_string(&path).expect("failed to read the manifest");
+    let docs = YamlLoader::load_from_str(&text).expect("failed to parse the manifest");
+    docs[0]
+        .as_vec()
+        .expect("the manifest should be a list")
+        .iter()
+        .map(|case| Case {
+            name: case["name"].as_str().expect("missing name").to_string(),
+            rom: repo_root().join(case["rom"].as_str().expect("missing rom")),
+            frames: case["frames"].as_i64().expect("missing frames") as usize,
+        })
+        .collect()
+}
+
+fn run(case: &Case) -> Result<Vec<u32>, DynError> {
+    let cartridge = GamepakBuilder::new()
+        .file(&case.rom)
+        .without_backup_to_file()
+        .build()?;
+    let mut gba = GameBoyAdvance::new(hle::builtin_bios(), cartridge, NullAudio::new());
+    gba.skip_bios();
+    for _ in 0..case.frames {
+        gba.frame();
+    }
+    Ok(gba
+        .get_frame_buffer()
+        .iter()
+        .map(|color| color & 0xffffff)
+        .collect())
+}
+
+fn read_png(path: &Path) -> Result<Vec<u32>, DynError> {
+    let decoder = png::Decoder::new(File::open(path)?);
+    let (info, mut reader) = decoder.read_info()?;
+    if (info.width as usize, info.height as usize) != (DISPLAY_WIDTH, DISPLAY_HEIGHT) {
+        return Err(format!("{} is {}x{}", path.display(), info.width, info.height).into());
+    }
+    let channels = match info.color_type {
+        png::ColorType::RGB => 3,
+        png::ColorType::RGBA => 4,
+        other => return Err(format!("{} is {:?}", path.display(), other).into()),
+    };
+    let mut data = vec![0; info.buffer_size()];
+    reader.next_frame(&mut data)?;
+    Ok(data
+        .chunks(channels)
+        .map(|c| (c[0] as u32) << 16 | (c[1] as u32) << 8 | c[2] as u32)
+        .collect())
+}
+
+fn write_png(path: &Path, pixels: &[u32], width: usize) -> Result<(), DynError> {
+    let file = BufWriter::new(File::create(path)?);
+    let height = pixels.len() / width;
+    let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut data = Vec::with_capacity(pixels.len() * 3);
+    for color in pixels {
+        data.push((color >> 16) as u8);
+        data.push((color >> 8) as u8);
+        data.push(*color as u8);
+    }
+    encoder.write_header()?.write_image_data(&data)?;
+    Ok(())
+}
+
+/// The reference, the actual frame and a dimmed reference with the differing pixels highlighted
+fn diff_image(reference: &[u32], actual: &[u32]) -> Vec<u32> {
+    let mut image = Vec::with_capacity(reference.len() * 3);
+    for y in 0..DISPLAY_HEIGHT {
+        let row = y * DISPLAY_WIDTH..(y + 1) * DISPLAY_WIDTH;
+        image.extend_from_slice(&reference[row.clone()]);
+        image.extend_from_slice(&actual[row.clone()]);
+        image.extend(
+            reference[row.clone()]
+                .iter()
+                .zip(&actual[row])
+                .map(|(r, a)| {
+                    if r == a {
+                        (r >> 2) & 0x3f3f3f
+                    } else {
+                        DIFF_COLOR
+                    }
+                }),
+        );
+    }
+    image
+}
+
+/// A map of the screen with a `#` for every 8x8 block that has differing pixels
+fn diff_map(reference: &[u32], actual: &[u32]) -> String {
+    let mut map = String::new();
+    for block_y in 0..DISPLAY_HEIGHT / MAP_BLOCK {
+        for block_x in 0..DISPLAY_WIDTH / MAP_BLOCK {
+            let differs = (0..MAP_BLOCK).any(|y| {
+                let start = (block_y * MAP_BLOCK + y) * DISPLAY_WIDTH + block_x * MAP_BLOCK;
+                reference[start..start + MAP_BLOCK] != actual[start..start + MAP_BLOCK]
+            });
+            map.push(if differs { '#' } else { '.' });
+        }
+        map.push('\n');
+    }
+    map
+}
+
+fn check(case: &Case, update: bool) -> Result<(), DynError> {
+    let actual = run(case)?;
+    let reference_path = screenshots_dir().join(format!("{}.png", case.name));
+    if update {
+        write_png(&reference_path, &actual, DISPLAY_WIDTH)?;
+        println!("{}: wrote {}", case.name, reference_path.display());
+        return Ok(());
+    }
+
+    fs::create_dir_all(output_dir())?;
+    let actual_path = output_dir().join(format!("{}.png", case.name));
+    if !reference_path.exists() {
+        write_png(&actual_path, &actual, DISPLAY_WIDTH)?;
+        return Err(format!(
+            "no reference image, the frame was written to {} (run with UPDATE_SCREENSHOTS=1 to accept it)",
+            actual_path.display()
+        )
+        .into());
+    }
+
+    let reference = read_png(&reference_path)?;
+    let mismatches = reference
+        .iter()
+        .zip(&actual)
+        .filter(|(r, a)| r != a)
+        .count();
+    if mismatches == 0 {
+        println!("{}: ok", case.name);
+        return Ok(());
+    }
+
+    let diff_path = output_dir().join(format!("{}.diff.png", case.name));
+    write_png(&actual_path, &actual, DISPLAY_WIDTH)?;
+    write_png(
+        &diff_path,
+        &diff_image(&reference, &actual),
+        DISPLAY_WIDTH * 3,
+    )?;
+    Err(format!(
+        "{} pixels differ after {} frames, see {}\n{}",
+        mismatches,
+        case.frames,
+        diff_path.display(),
+        diff_map(&reference, &actual)
+    )
+    .into())
+}
+
+#[test]
+fn test_screenshots() {
+    let update = env::var_os("UPDATE_SCREENSHOTS").is_some();
+    let mut failures = Vec::new();
+    for case in load_manifest() {
+        if !case.rom.exists() {
+            println!("{}: skipped, {} not found", case.name, case.rom.display());
+            continue;
+        }
+        if let Err(e) = check(&case, update) {
+            println!("{}: {}", case.name, e);
+            failures.push(case.name);
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "screenshots differ: {}",
+        failures.join(", ")
+    );
+}
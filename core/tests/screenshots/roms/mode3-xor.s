@@ -0,0 +1,40 @@
+@ Draws an xor pattern in mode 3 and spins, the reference image for it
+@ is mode3-xor.png
+@
+@ llvm-mc -triple=armv4t-none-eabi -filetype=obj mode3-xor.s -o mode3-xor.o
+@ llvm-objcopy -O binary mode3-xor.o mode3-xor.gba
+    .arm
+    .text
+    b       start
+    .space  0x9c                    @ logo
+    .ascii  "MODE3XOR"              @ title
+    .space  0x0a
+    .byte   0x96                    @ fixed value
+    .space  0x0a
+    .byte   0x00                    @ header checksum
+    .space  0x02
+start:
+    mov     r0, #0x04000000
+    mov     r1, #0x400
+    orr     r1, r1, #3              @ DISPCNT = mode 3 | bg2
+    strh    r1, [r0]
+    mov     r0, #0x06000000
+    mov     r2, #0                  @ y
+loop_y:
+    mov     r3, #0                  @ x
+loop_x:
+    eor     r4, r2, r3
+    and     r4, r4, #0x1f           @ red = x ^ y
+    mov     r5, r3, lsr #3
+    orr     r4, r4, r5, lsl #5      @ green = x / 8
+    mov     r5, r2, lsr #3
+    orr     r4, r4, r5, lsl #10     @ blue = y / 8
+    strh    r4, [r0], #2
+    add     r3, r3, #1
+    cmp     r3, #240
+    blt     loop_x
+    add     r2, r2, #1
+    cmp     r2, #160
+    blt     loop_y
+done:
+    b       done
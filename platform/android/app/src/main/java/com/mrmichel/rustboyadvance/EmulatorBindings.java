@@ -61,6 +61,15 @@ public class EmulatorBindings {
 
     public static native void stop(long ctx);
 
+    /**
+     * Connect the rumble of the cartridge, call before runMainLoop
+     *
+     * @param ctx
+     * @param rumble rumble instance
+     * @throws NativeBindingException
+     */
+    public static native void setRumble(long ctx, IRumble rumble) throws NativeBindingException;
+
 
     public static native int[] getFrameBuffer(long ctx);
 
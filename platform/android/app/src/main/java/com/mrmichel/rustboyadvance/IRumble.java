@@ -0,0 +1,5 @@
+package com.mrmichel.rustboyadvance;
+
+public interface IRumble {
+    void setRumble(boolean on);
+}
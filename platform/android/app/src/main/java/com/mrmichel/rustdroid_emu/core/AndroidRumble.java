@@ -0,0 +1,36 @@
+package com.mrmichel.rustdroid_emu.core;
+
+import android.content.Context;
+import android.os.Build;
+import android.os.VibrationEffect;
+import android.os.Vibrator;
+
+import com.mrmichel.rustboyadvance.IRumble;
+
+/**
+ * Plays the rumble motor of the cartridge on the device vibrator
+ */
+public class AndroidRumble implements IRumble {
+    /// The vibrator stops by itself after this long, the game turns the motor off long before
+    private static final long RUMBLE_DURATION_MS = 10000;
+
+    private Vibrator vibrator;
+
+    public AndroidRumble(Context context) {
+        this.vibrator = (Vibrator) context.getSystemService(Context.VIBRATOR_SERVICE);
+    }
+
+    @Override
+    public void setRumble(boolean on) {
+        if (vibrator == null || !vibrator.hasVibrator()) {
+            return;
+        }
+        if (!on) {
+            vibrator.cancel();
+        } else if (Build.VERSION.SDK_INT >= 26) {
+            vibrator.vibrate(VibrationEffect.createOneShot(RUMBLE_DURATION_MS, VibrationEffect.DEFAULT_AMPLITUDE));
+        } else {
+            vibrator.vibrate(RUMBLE_DURATION_MS);
+        }
+    }
+}
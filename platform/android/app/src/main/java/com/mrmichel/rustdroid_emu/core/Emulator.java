@@ -2,6 +2,7 @@ package com.mrmichel.rustdroid_emu.core;
 
 import com.mrmichel.rustboyadvance.EmulatorBindings;
 import com.mrmichel.rustboyadvance.IFrameRenderer;
+import com.mrmichel.rustboyadvance.IRumble;
 import com.mrmichel.rustboyadvance.Keypad;
 
 public class Emulator {
@@ -12,16 +13,19 @@ public class Emulator {
 
     private AndroidAudioPlayer audioPlayer;
     private IFrameRenderer frameRenderer;
-    public Emulator(IFrameRenderer frameRenderer, AndroidAudioPlayer audioPlayer) {
+    private IRumble rumble;
+    public Emulator(IFrameRenderer frameRenderer, AndroidAudioPlayer audioPlayer, IRumble rumble) {
         this.keypad = new Keypad();
         this.frameRenderer = frameRenderer;
         this.audioPlayer = audioPlayer;
+        this.rumble = rumble;
     }
 
-    public Emulator(long ctx, IFrameRenderer frameRenderer, AndroidAudioPlayer audioPlayer) {
+    public Emulator(long ctx, IFrameRenderer frameRenderer, AndroidAudioPlayer audioPlayer, IRumble rumble) {
         this.ctx = ctx;
         this.frameRenderer = frameRenderer;
         this.audioPlayer = audioPlayer;
+        this.rumble = rumble;
         this.keypad = new Keypad();
 
     }
@@ -75,10 +79,12 @@ public class Emulator {
 
     public synchronized void open(byte[] bios, byte[] rom, String saveName, boolean skipBios) throws EmulatorBindings.NativeBindingException {
         this.ctx = EmulatorBindings.openEmulator(bios, rom, this.frameRenderer, this.audioPlayer, this.keypad, saveName, skipBios);
+        EmulatorBindings.setRumble(this.ctx, this.rumble);
     }
 
     public synchronized void openSavedState(byte[] bios, byte[] rom, byte[] savedState) throws EmulatorBindings.NativeBindingException {
         this.ctx = EmulatorBindings.openSavedState(bios, rom, savedState, this.frameRenderer, this.audioPlayer, this.keypad);
+        EmulatorBindings.setRumble(this.ctx, this.rumble);
     }
 
     public synchronized void close() {
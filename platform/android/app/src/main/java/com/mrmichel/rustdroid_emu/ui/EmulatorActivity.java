@@ -26,6 +26,7 @@ import com.mrmichel.rustboyadvance.Keypad;
 import com.mrmichel.rustdroid_emu.R;
 import com.mrmichel.rustdroid_emu.Util;
 import com.mrmichel.rustdroid_emu.core.AndroidAudioPlayer;
+import com.mrmichel.rustdroid_emu.core.AndroidRumble;
 import com.mrmichel.rustdroid_emu.core.Emulator;
 import com.mrmichel.rustdroid_emu.core.RomManager;
 import com.mrmichel.rustdroid_emu.core.Snapshot;
@@ -325,7 +326,7 @@ public class EmulatorActivity extends AppCompatActivity implements View.OnClickL
         this.bios = getIntent().getByteArrayExtra("bios");
 
         this.screenView = findViewById(R.id.gba_view);
-        this.emulator = new Emulator(this.screenView, this.audioPlayer);
+        this.emulator = new Emulator(this.screenView, this.audioPlayer, new AndroidRumble(this));
 
         final String saveFilePath;
 
@@ -0,0 +1,64 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::rc::Rc;
+
+use rustboyadvance_core::prelude::*;
+use rustboyadvance_core::sound::interface::StereoSample;
+
+type DynError = Box<dyn std::error::Error>;
+
+/// Keeps every sample the emulator produces so it can be written out at exit
+pub struct AudioRecorder {
+    samples: Rc<RefCell<Vec<i16>>>,
+}
+
+impl AudioRecorder {
+    pub fn new() -> (Box<AudioRecorder>, Rc<RefCell<Vec<i16>>>) {
+        let samples = Rc::new(RefCell::new(Vec::new()));
+        (
+            Box::new(AudioRecorder {
+                samples: samples.clone(),
+            }),
+            samples,
+        )
+    }
+}
+
+impl AudioInterface for AudioRecorder {
+    fn push_sample(&mut self, sample: &StereoSample<i16>) {
+        self.samples.borrow_mut().extend_from_slice(sample);
+    }
+}
+
+pub fn write_png(path: &Path, frame_buffer: &[u32]) -> Result<(), DynError> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut data = Vec::with_capacity(frame_buffer.len() * 3);
+    for color in frame_buffer {
+        data.push((color >> 16) as u8);
+        data.push((color >> 8) as u8);
+        data.push(*color as u8);
+    }
+    encoder.write_header()?.write_image_data(&data)?;
+    Ok(())
+}
+
+pub fn write_wav(path: &Path, sample_rate: u32, samples: &[i16]) -> Result<(), DynError> {
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    for sample in samples {
+        writer.write_sample(*sample)?;
+    }
+    writer.finalize()?;
+    Ok(())
+}
@@ -0,0 +1,159 @@
+//! Scripted input for unattended runs.
+//!
+//! Every non empty line holds a frame number followed by the keys held down starting from that
+//! frame, until the next line takes effect. A line with only a frame number releases all keys.
+//! Everything after a '#' is a comment.
+//!
+//! ```text
+//! # wait for the intro, then press start for 5 frames
+//! 120 START
+//! 125
+//! 200 A RIGHT
+//! ```
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use rustboyadvance_core::keypad::{Keys, KEYINPUT_ALL_RELEASED};
+
+#[derive(Debug)]
+pub struct ParseError {
+    line: usize,
+    message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "input script line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn parse_key(name: &str) -> Option<Keys> {
+    let key = match name.to_ascii_uppercase().as_str() {
+        "A" => Keys::ButtonA,
+        "B" => Keys::ButtonB,
+        "SELECT" => Keys::Select,
+        "START" => Keys::Start,
+        "RIGHT" => Keys::Right,
+        "LEFT" => Keys::Left,
+        "UP" => Keys::Up,
+        "DOWN" => Keys::Down,
+        "R" => Keys::ButtonR,
+        "L" => Keys::ButtonL,
+        _ => return None,
+    };
+    Some(key)
+}
+
+pub struct InputScript {
+    /// (frame, KEYINPUT value) sorted by frame
+    entries: Vec<(usize, u16)>,
+    next: usize,
+}
+
+impl InputScript {
+    pub fn load(path: &Path) -> Result<InputScript, Box<dyn std::error::Error>> {
+        Ok(InputScript::parse(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn parse(script: &str) -> Result<InputScript, ParseError> {
+        let mut entries = Vec::new();
+        for (i, line) in script.lines().enumerate() {
+            let error = |message: String| ParseError {
+                line: i + 1,
+                message,
+            };
+            let line = line.split('#').next().unwrap();
+            let mut tokens = line.split_whitespace();
+            let frame = match tokens.next() {
+                Some(frame) => frame
+                    .parse::<usize>()
+                    .map_err(|_| error(format!("invalid frame number {:?}", frame)))?,
+                None => continue,
+            };
+            if let Some(&(prev, _)) = entries.last() {
+                if frame < prev {
+                    return Err(error(format!("frame {} comes after frame {}", frame, prev)));
+                }
+            }
+            let mut keyinput = KEYINPUT_ALL_RELEASED;
+            for name in tokens {
+                let key =
+                    parse_key(name).ok_or_else(|| error(format!("unknown key {:?}", name)))?;
+                keyinput &= !(1 << key as u16);
+            }
+            entries.push((frame, keyinput));
+        }
+        Ok(InputScript { entries, next: 0 })
+    }
+
+    /// Returns the new KEYINPUT value if the input changes at this frame
+    pub fn keyinput_at(&mut self, frame: usize) -> Option<u16> {
+        let mut keyinput = None;
+        while let Some(&(at, value)) = self.entries.get(self.next) {
+            if at > frame {
+                break;
+            }
+            keyinput = Some(value);
+            self.next += 1;
+        }
+        keyinput
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pressed(keys: &[Keys]) -> u16 {
+        keys.iter().fold(KEYINPUT_ALL_RELEASED, |keyinput, &key| {
+            keyinput & !(1 << key as u16)
+        })
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut script = InputScript::parse(
+            "# wait for the intro\n\
+             \n\
+             120 START   # press start\n\
+             125\n\
+             200 a right\n",
+        )
+        .unwrap();
+        assert_eq!(script.keyinput_at(0), None);
+        assert_eq!(script.keyinput_at(120), Some(pressed(&[Keys::Start])));
+        assert_eq!(script.keyinput_at(121), None);
+        // skipping over a line only keeps the last one
+        assert_eq!(
+            script.keyinput_at(300),
+            Some(pressed(&[Keys::ButtonA, Keys::Right]))
+        );
+        assert_eq!(script.keyinput_at(301), None);
+    }
+
+    #[test]
+    fn test_bad_key() {
+        let err = InputScript::parse("10 A\n20 B TURBO\n").err().unwrap();
+        assert_eq!(err.line, 2);
+        assert_eq!(
+            err.to_string(),
+            "input script line 2: unknown key \"TURBO\""
+        );
+    }
+
+    #[test]
+    fn test_bad_frame() {
+        let err = InputScript::parse("START 10\n").err().unwrap();
+        assert_eq!(err.line, 1);
+        assert!(err.message.contains("invalid frame number"));
+
+        assert!(InputScript::parse("-1 A\n").is_err());
+
+        let err = InputScript::parse("20 A\n10 B\n").err().unwrap();
+        assert_eq!(err.line, 2);
+        assert_eq!(err.message, "frame 10 comes after frame 20");
+    }
+}
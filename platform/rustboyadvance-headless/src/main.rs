@@ -0,0 +1,110 @@
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use structopt::StructOpt;
+
+mod artifacts;
+mod input_script;
+mod options;
+
+use rustboyadvance_core::hle;
+use rustboyadvance_core::prelude::*;
+
+use artifacts::{write_png, write_wav, AudioRecorder};
+use input_script::InputScript;
+use options::{MemoryCondition, Options};
+
+/// Exit code for runs where the --until condition was never met
+const EXIT_TIMEOUT: i32 = 2;
+
+type DynError = Box<dyn std::error::Error>;
+
+fn check_condition(gba: &mut GameBoyAdvance, condition: &MemoryCondition) -> bool {
+    let value = match condition.width {
+        8 => gba.debug_read_8(condition.addr) as u32,
+        16 => gba.debug_read_16(condition.addr) as u32,
+        _ => gba.debug_read_32(condition.addr),
+    };
+    value == condition.value
+}
+
+fn screenshot(gba: &GameBoyAdvance, path: &Path) -> Result<(), DynError> {
+    write_png(path, gba.get_frame_buffer())?;
+    println!("wrote {}", path.display());
+    Ok(())
+}
+
+fn run(opts: &Options) -> Result<bool, DynError> {
+    let bios = match read_bin_file(&opts.bios) {
+        Ok(bios) => bios.into_boxed_slice(),
+        Err(_) => {
+            eprintln!("missing bios file, using the built-in bios");
+            hle::builtin_bios()
+        }
+    };
+    let (audio_recorder, samples) = AudioRecorder::new();
+    let sample_rate = audio_recorder.get_sample_rate() as u32;
+    let mut gba = GameBoyAdvance::new(bios, opts.cartridge_from_opts()?, audio_recorder);
+    if opts.skip_bios {
+        gba.skip_bios();
+    }
+
+    let mut input_script = match &opts.input {
+        Some(path) => Some(InputScript::load(path)?),
+        None => None,
+    };
+
+    fs::create_dir_all(&opts.output)?;
+
+    let mut condition_met = false;
+    let mut frame = 0;
+    while frame < opts.frames {
+        if let Some(keyinput) = input_script.as_mut().and_then(|s| s.keyinput_at(frame)) {
+            *gba.get_key_state_mut() = keyinput;
+        }
+        gba.frame();
+        frame += 1;
+
+        if opts.screenshot_at.contains(&frame) {
+            screenshot(&gba, &opts.output.join(format!("frame-{}.png", frame)))?;
+        }
+        if let Some(condition) = &opts.until {
+            if check_condition(&mut gba, condition) {
+                condition_met = true;
+                break;
+            }
+        }
+    }
+    println!("stopped after {} frames", frame);
+
+    screenshot(&gba, &opts.output.join("screenshot.png"))?;
+    if opts.audio {
+        let path = opts.output.join("audio.wav");
+        write_wav(&path, sample_rate, &samples.borrow())?;
+        println!("wrote {}", path.display());
+    }
+    for region in &opts.dump {
+        let path = opts.output.join(format!("{}.bin", region.name));
+        let bytes = gba.debug_get_bytes(region.start..region.start + region.len);
+        write_bin_file(&path, &bytes)?;
+        println!("wrote {}", path.display());
+    }
+
+    Ok(opts.until.is_none() || condition_met)
+}
+
+fn main() {
+    let opts = Options::from_args();
+    match run(&opts) {
+        Ok(true) => {}
+        Ok(false) => {
+            eprintln!("timed out after {} frames", opts.frames);
+            process::exit(EXIT_TIMEOUT);
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use std::convert::TryFrom;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use rustboyadvance_core::{
+    cartridge::{BackupType, GamepakBuilder, RtcClock},
+    prelude::{Addr, Cartridge},
+};
+use structopt::StructOpt;
+
+const SAVE_TYPE_POSSIBLE_VALUES: &[&str] =
+    &["sram", "flash128k", "flash64k", "eeprom", "autodetect"];
+
+/// Run a rom without a window and collect artifacts once it is done.
+///
+/// Exits with 0 when the frame limit is reached or the --until condition was met,
+/// 2 when the --until condition was not met in time and 1 on any other error.
+#[derive(StructOpt, Debug)]
+#[structopt(name = "rustboyadvance-headless")]
+pub struct Options {
+    /// Rom file to emulate, may be a raw dump from a cartridge or a compiled ELF file
+    #[structopt(name = "ROM", parse(from_os_str))]
+    pub rom: PathBuf,
+
+    /// Bios file to use
+    #[structopt(long, parse(from_os_str), default_value = "gba_bios.bin")]
+    pub bios: PathBuf,
+
+    /// Skip running the bios boot animation and jump straight to the ROM
+    #[structopt(long)]
+    pub skip_bios: bool,
+
+    /// Override save type, useful for troublemaking games that fool the auto detection
+    #[structopt(long, default_value = "autodetect", possible_values = SAVE_TYPE_POSSIBLE_VALUES)]
+    pub save_type: BackupType,
+
+    /// Where the RTC takes the time from: realtime, offset:<seconds>, frozen:<date time> or
+    /// emulated:<date time>. The default emulated clock gives the same times on every run.
+    #[structopt(long, default_value = "emulated:2000-01-01T00:00:00")]
+    pub rtc_time: RtcClock,
+
+    /// Number of frames to run, when used with --until this is the timeout
+    #[structopt(short = "n", long, default_value = "600")]
+    pub frames: usize,
+
+    /// Stop once a memory location holds a value, ADDR[:WIDTH]=VALUE (e.g 0x03000000:8=0x1)
+    #[structopt(long)]
+    pub until: Option<MemoryCondition>,
+
+    /// Input script, every line holds a frame number followed by the keys held from that frame on
+    #[structopt(short, long, parse(from_os_str))]
+    pub input: Option<PathBuf>,
+
+    /// Directory to write the artifacts to
+    #[structopt(short, long, parse(from_os_str), default_value = ".")]
+    pub output: PathBuf,
+
+    /// Also write a screenshot at these frames
+    #[structopt(long)]
+    pub screenshot_at: Vec<usize>,
+
+    /// Record the audio output into audio.wav
+    #[structopt(long)]
+    pub audio: bool,
+
+    /// Dump memory at exit, either a region name (ewram, iwram, palram, vram, oam, io) or ADDR+LEN
+    #[structopt(long)]
+    pub dump: Vec<MemoryRegion>,
+}
+
+type DynError = Box<dyn std::error::Error>;
+
+impl Options {
+    pub fn cartridge_from_opts(&self) -> Result<Cartridge, DynError> {
+        Ok(GamepakBuilder::new()
+            .save_type(self.save_type)
+            .rtc_clock(self.rtc_time)
+            .file(&self.rom)
+            .without_backup_to_file()
+            .build()?)
+    }
+}
+
+fn parse_number(s: &str) -> Result<u32, String> {
+    let s = s.trim();
+    let result = if let Some(hex) = s.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16)
+    } else {
+        s.parse()
+    };
+    result.map_err(|e| format!("invalid number {:?}: {}", s, e))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryCondition {
+    pub addr: Addr,
+    pub width: u8,
+    pub value: u32,
+}
+
+impl FromStr for MemoryCondition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (location, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected ADDR[:WIDTH]=VALUE, got {:?}", s))?;
+        let (addr, width) = match location.split_once(':') {
+            Some((addr, width)) => (addr, parse_number(width)?),
+            None => (location, 32),
+        };
+        let width = u8::try_from(width)
+            .ok()
+            .filter(|width| [8, 16, 32].contains(width))
+            .ok_or_else(|| format!("invalid width {}, expected 8, 16 or 32", width))?;
+        Ok(MemoryCondition {
+            addr: parse_number(addr)?,
+            width,
+            value: parse_number(value)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MemoryRegion {
+    pub name: String,
+    pub start: Addr,
+    pub len: u32,
+}
+
+impl FromStr for MemoryRegion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, len) = match s {
+            "ewram" => (0x0200_0000, 0x4_0000),
+            "iwram" => (0x0300_0000, 0x8000),
+            "io" => (0x0400_0000, 0x400),
+            "palram" => (0x0500_0000, 0x400),
+            "vram" => (0x0600_0000, 0x1_8000),
+            "oam" => (0x0700_0000, 0x400),
+            _ => {
+                let (start, len) = s.split_once('+').ok_or_else(|| {
+                    format!("unknown region {:?}, expected a name or ADDR+LEN", s)
+                })?;
+                let start = parse_number(start)?;
+                let len = parse_number(len)?;
+                if start.checked_add(len).is_none() {
+                    return Err(format!(
+                        "the region 0x{:08x}+0x{:x} goes past the end of the address space",
+                        start, len
+                    ));
+                }
+                return Ok(MemoryRegion {
+                    name: format!("mem-{:08x}", start),
+                    start,
+                    len,
+                });
+            }
+        };
+        Ok(MemoryRegion {
+            name: s.to_string(),
+            start,
+            len,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_condition() {
+        let condition: MemoryCondition = "0x03000000:8=0x1".parse().unwrap();
+        assert_eq!(condition.addr, 0x0300_0000);
+        assert_eq!(condition.width, 8);
+        assert_eq!(condition.value, 1);
+        assert_eq!("0x10=5".parse::<MemoryCondition>().unwrap().width, 32);
+        // 264 doesn't fit in the width, it must not wrap around to 8
+        assert!("0x10:264=5".parse::<MemoryCondition>().is_err());
+        assert!("0x10:12=5".parse::<MemoryCondition>().is_err());
+    }
+
+    #[test]
+    fn test_parse_region() {
+        let region: MemoryRegion = "0x02000000+0x100".parse().unwrap();
+        assert_eq!((region.start, region.len), (0x0200_0000, 0x100));
+        assert!("0xffffff00+0xff".parse::<MemoryRegion>().is_ok());
+        assert!("0xffffff00+0x100".parse::<MemoryRegion>().is_err());
+    }
+}
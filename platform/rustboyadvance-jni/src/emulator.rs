@@ -9,7 +9,7 @@ use std::time::{Duration, Instant};
 use jni::objects::{GlobalRef, JMethodID, JObject, JString, JValue};
 use jni::signature;
 use jni::sys::{jboolean, jbyteArray, jintArray, jmethodID};
-use jni::JNIEnv;
+use jni::{JNIEnv, JavaVM};
 
 use crate::audio::{self, connector::AudioJNIConnector, thread::AudioThreadCommand};
 
@@ -105,6 +105,57 @@ impl Keypad {
     }
 }
 
+/// Forwards the rumble of the cartridge to the java side, which drives the vibrator
+struct Rumble {
+    jvm: JavaVM,
+    rumble_ref: GlobalRef,
+    mid_set_rumble: jmethodID,
+}
+
+impl Rumble {
+    fn new(env: &JNIEnv, rumble_obj: JObject) -> Result<Rumble, String> {
+        let jvm = env
+            .get_java_vm()
+            .map_err(|e| format!("failed to get the java vm, error: {:?}", e))?;
+        let rumble_ref = env
+            .new_global_ref(rumble_obj)
+            .map_err(|e| format!("failed to add new global ref, error: {:?}", e))?;
+        let rumble_klass = env
+            .get_object_class(rumble_ref.as_obj())
+            .map_err(|e| format!("failed to get rumble class, error: {:?}", e))?;
+        let mid_set_rumble = env
+            .get_method_id(rumble_klass, "setRumble", "(Z)V")
+            .map_err(|e| format!("failed to get methodID for setRumble, error: {:?}", e))?
+            .into_inner();
+
+        Ok(Rumble {
+            jvm,
+            rumble_ref,
+            mid_set_rumble,
+        })
+    }
+}
+
+impl RumbleInterface for Rumble {
+    fn set_rumble(&mut self, on: bool) {
+        // Called from the emulation thread, which is already attached to the jvm
+        let env = match self.jvm.get_env() {
+            Ok(env) => env,
+            Err(e) => {
+                error!("failed to get the jni env, error: {:?}", e);
+                return;
+            }
+        };
+        env.call_method_unchecked(
+            self.rumble_ref.as_obj(),
+            JMethodID::from(self.mid_set_rumble),
+            signature::JavaType::Primitive(signature::Primitive::Void),
+            &[JValue::from(on)],
+        )
+        .expect("failed to call setRumble");
+    }
+}
+
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum EmulationState {
     Initial,
@@ -243,6 +294,13 @@ impl EmulatorContext {
         })
     }
 
+    /// Connect the vibrator, for the games with a rumble motor
+    pub fn native_set_rumble(&mut self, env: &JNIEnv, rumble_obj: JObject) -> Result<(), String> {
+        let rumble = Rumble::new(env, rumble_obj)?;
+        self.gba.connect_rumble(Box::new(rumble));
+        Ok(())
+    }
+
     fn render_video(&mut self, env: &JNIEnv) {
         self.renderer.render_frame(env, self.gba.get_frame_buffer());
     }
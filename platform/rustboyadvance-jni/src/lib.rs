@@ -197,6 +197,19 @@ pub mod bindings {
         ctx.set_turbo(turbo != 0);
     }
 
+    #[no_mangle]
+    pub unsafe extern "C" fn Java_com_mrmichel_rustboyadvance_EmulatorBindings_setRumble(
+        env: JNIEnv,
+        _obj: JClass,
+        ctx: jlong,
+        rumble_obj: JObject,
+    ) {
+        let ctx = cast_ctx(ctx);
+        if let Err(msg) = ctx.native_set_rumble(&env, rumble_obj) {
+            env.throw_new(NATIVE_EXCEPTION_CLASS, msg).unwrap();
+        }
+    }
+
     #[no_mangle]
     pub unsafe extern "C" fn Java_com_mrmichel_rustboyadvance_EmulatorBindings_stop(
         _env: JNIEnv,
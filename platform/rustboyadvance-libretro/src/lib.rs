@@ -11,6 +11,7 @@ use libretro_backend::{
 use bit::BitIndex;
 use unsafe_unwrap::UnsafeUnwrap;
 
+use rustboyadvance_core::hle;
 use rustboyadvance_core::keypad::Keys as _GbaButton;
 use rustboyadvance_core::prelude::*;
 use rustboyadvance_utils::audio::SampleConsumer;
@@ -75,11 +76,15 @@ impl libretro_backend::Core for RustBoyAdvanceCore {
         let system_directory_path = Path::new(&system_directory);
 
         let bios_path = system_directory_path.join("gba_bios.bin");
-        if !bios_path.exists() {
-            error!("bios file missing, please place it in {:?}", bios_path);
-            return LoadGameResult::Failed(game_data);
-        }
-        let bios = read_bin_file(&bios_path);
+        let bios = if bios_path.exists() {
+            read_bin_file(&bios_path)
+        } else {
+            warn!(
+                "bios file missing, using the built-in bios. For better compatibility place it in {:?}",
+                bios_path
+            );
+            Ok(hle::builtin_bios().into_vec())
+        };
 
         if game_data.is_empty() {
             error!("game data is empty!");
@@ -135,6 +140,22 @@ impl libretro_backend::Core for RustBoyAdvanceCore {
         update_controllers!(A, B, Start, Select, Left, Up, Right, Down, L1, R1);
         drop(key_state);
 
+        // The backend doesn't expose the analog sticks, so the motion sensors are driven to their
+        // extremes by the spare shoulder buttons: L2/R2 turn and tilt left/right, L3/R3 tilt
+        // forward/back.
+        let axis = |negative, positive| match (
+            handle.is_joypad_button_pressed(joypad_port, negative),
+            handle.is_joypad_button_pressed(joypad_port, positive),
+        ) {
+            (true, false) => i16::MIN,
+            (false, true) => i16::MAX,
+            _ => 0,
+        };
+        let x = axis(JoypadButton::L2, JoypadButton::R2);
+        let y = axis(JoypadButton::L3, JoypadButton::R3);
+        gba.set_gyro_rotation(x);
+        gba.set_tilt(x, y);
+
         gba.frame();
 
         let framebuffer = gba.get_frame_buffer();
@@ -3,6 +3,7 @@ use sdl2::controller::Button;
 use sdl2::keyboard::Scancode;
 
 use rustboyadvance_core::keypad as gba_keypad;
+use rustboyadvance_core::prelude::GameBoyAdvance;
 
 use bit;
 use bit::BitIndex;
@@ -87,3 +88,28 @@ fn controller_button_to_keypad(button: Button) -> Option<gba_keypad::Keys> {
         _ => None,
     }
 }
+
+/// The right stick moves the cartridge, for the games with a gyro or tilt sensor
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CartridgeMotion {
+    x: i16,
+    y: i16,
+}
+
+impl CartridgeMotion {
+    /// Returns false if the axis doesn't belong to the right stick
+    pub fn on_axis_motion(&mut self, axis: Axis, val: i16) -> bool {
+        match axis {
+            Axis::RightX => self.x = val,
+            Axis::RightY => self.y = val,
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn apply(&self, gba: &mut GameBoyAdvance) {
+        // Pushing the stick right turns the cartridge clockwise
+        gba.set_gyro_rotation(self.x);
+        gba.set_tilt(self.x, self.y);
+    }
+}
@@ -1,5 +1,5 @@
 use sdl2::controller::Button;
-use sdl2::event::Event;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Scancode;
 use sdl2::{self};
 
@@ -7,9 +7,12 @@ use bytesize;
 use spin_sleep;
 use structopt::StructOpt;
 
+use std::cell::RefCell;
+use std::cmp;
 use std::fs;
 use std::io::Cursor;
 use std::path::Path;
+use std::rc::Rc;
 use std::time;
 
 #[macro_use]
@@ -20,27 +23,42 @@ use flexi_logger::*;
 mod audio;
 mod input;
 mod options;
+mod rumble;
 mod video;
+mod viewers;
 
+use rustboyadvance_core::cartridge::SOLAR_LIGHT_LEVELS;
+use rustboyadvance_core::hle;
+use rustboyadvance_core::keypad::KEYINPUT_ALL_RELEASED;
+use rustboyadvance_core::movie::{Movie, MoviePlayer, MovieRecorder};
 use rustboyadvance_core::prelude::*;
+use rustboyadvance_core::rewind::RewindBuffer;
 
 use rustboyadvance_utils::FpsCounter;
 
+use viewers::{ViewerKind, Viewers};
+
 const LOG_DIR: &str = ".logs";
 
-fn ask_download_bios() {
+fn suggest_download_bios() {
     const OPEN_SOURCE_BIOS_URL: &'static str =
         "https://github.com/Nebuleon/ReGBA/raw/master/bios/gba_bios.bin";
-    println!("Missing BIOS file. If you don't have the original GBA BIOS, you can download an open-source bios from {}", OPEN_SOURCE_BIOS_URL);
-    std::process::exit(0);
+    println!("Missing BIOS file, falling back to the built-in bios. If you don't have the original GBA BIOS, you can download an open-source bios from {}", OPEN_SOURCE_BIOS_URL);
+}
+
+fn save_movie(recorder: MovieRecorder, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let frames = recorder.frames_recorded();
+    recorder.finish().save(path)?;
+    info!("Saved movie to {:?} ({} frames)", path, frames);
+    Ok(())
 }
 
 fn load_bios(bios_path: &Path) -> Box<[u8]> {
     match read_bin_file(bios_path) {
         Ok(bios) => bios.into_boxed_slice(),
         _ => {
-            ask_download_bios();
-            unreachable!()
+            suggest_download_bios();
+            hle::builtin_bios()
         }
     }
 }
@@ -58,6 +76,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let opts = options::Options::from_args();
 
+    if let Some(path) = &opts.export_save {
+        opts.export_save(path)?;
+        info!("Exported the save to {:?}", path);
+        return Ok(());
+    }
+
     info!("Initializing SDL2 context");
     let sdl_context = sdl2::init().expect("failed to initialize sdl2");
 
@@ -70,7 +94,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .filter(|&id| controller_subsystem.is_game_controller(id))
         .collect::<Vec<u32>>();
 
-    let mut active_controller = match available_controllers.first() {
+    let active_controller = Rc::new(RefCell::new(match available_controllers.first() {
         Some(&id) => {
             let controller = controller_subsystem.open(id)?;
             info!("Found game controller: {}", controller.name());
@@ -80,9 +104,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             info!("No game controllers were found");
             None
         }
-    };
+    }));
 
     let mut renderer = video::init(&sdl_context)?;
+    let mut viewers = Viewers::new(sdl_context.video()?);
     let (audio_interface, mut _sdl_audio_device) = audio::create_audio_player(&sdl_context)?;
     let rom_name = opts.rom_name();
 
@@ -106,28 +131,61 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     //     normal_panic(panic_info);
     // }));
 
-    if opts.skip_bios {
+    let mut movie_recorder = None;
+    let mut movie_player = None;
+    if let Some(path) = &opts.play_movie {
+        info!("Playing movie {:?}", path);
+        movie_player = Some(MoviePlayer::start(Movie::load(path)?, &mut gba)?);
+    } else if opts.record_movie.is_some() {
+        // The movie takes care of the bios skip
+        movie_recorder = Some(MovieRecorder::power_on(&mut gba, opts.skip_bios)?);
+    } else if opts.skip_bios {
         println!("Skipping bios animation..");
         gba.skip_bios();
     }
 
+    if let Some(transport) = opts.link_cable_from_opts()? {
+        gba.connect_link_cable(transport);
+    }
+
+    let controller_rumble = rumble::ControllerRumble::new(active_controller.clone());
+    gba.connect_rumble(Box::new(controller_rumble.clone()));
+
     if opts.gdbserver {
         gba.start_gdbserver(opts.gdbserver_port);
     }
 
+    let mut solar_level = 0;
+    let mut cartridge_motion = input::CartridgeMotion::default();
     let mut vsync = true;
+    let mut rewinding = false;
+    let mut rewind_buffer = RewindBuffer::new(opts.rewind_seconds);
     let mut fps_counter = FpsCounter::default();
     const FRAME_TIME: time::Duration = time::Duration::new(0, 1_000_000_000u32 / 60);
     let mut event_pump = sdl_context.event_pump()?;
     'running: loop {
         let start_time = time::Instant::now();
         for event in event_pump.poll_iter() {
+            let event = match viewers.handle_event(event) {
+                Some(event) => event,
+                None => continue,
+            };
             match event {
                 Event::KeyDown {
                     scancode: Some(scancode),
                     ..
                 } => match scancode {
                     Scancode::Space => vsync = false,
+                    Scancode::Grave => rewinding = true,
+                    Scancode::Minus | Scancode::Equals if gba.has_solar_sensor() => {
+                        solar_level = if scancode == Scancode::Equals {
+                            cmp::min(solar_level + 1, SOLAR_LIGHT_LEVELS.len() - 1)
+                        } else {
+                            solar_level.saturating_sub(1)
+                        };
+                        info!("Solar sensor light level {}", solar_level);
+                        gba.set_solar_light(SOLAR_LIGHT_LEVELS[solar_level]);
+                    }
                     k => input::on_keyboard_key_down(gba.get_key_state_mut(), k),
                 },
                 Event::KeyUp {
@@ -144,9 +202,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         info!("ending debugger...")
                     }
                     Scancode::F2 => gba.start_gdbserver(opts.gdbserver_port),
+                    Scancode::F3 => viewers.toggle(ViewerKind::Tiles)?,
+                    Scancode::F4 => viewers.toggle(ViewerKind::Tilemap)?,
                     Scancode::F5 => {
                         info!("Saving state ...");
-                        let save = gba.save_state()?;
+                        let save = gba.save_state_with_thumbnail()?;
                         write_bin_file(&opts.savestate_path(), &save)?;
                         info!(
                             "Saved to {:?} ({})",
@@ -154,26 +214,79 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                             bytesize::ByteSize::b(save.len() as u64)
                         );
                     }
+                    Scancode::F6 => match movie_recorder.take() {
+                        Some(recorder) => {
+                            if let Err(e) = save_movie(recorder, &opts.movie_path()) {
+                                warn!("Failed to save the movie: {}", e);
+                            }
+                        }
+                        None if movie_player.is_none() => {
+                            match MovieRecorder::from_savestate(&mut gba) {
+                                Ok(recorder) => {
+                                    info!("Recording movie ...");
+                                    movie_recorder = Some(recorder);
+                                }
+                                Err(e) => warn!("Failed to start recording: {}", e),
+                            }
+                        }
+                        None => warn!("Can't record while a movie is playing"),
+                    },
+                    Scancode::F7 => {
+                        if movie_recorder.is_some() {
+                            warn!("Stop the recording first (F6)");
+                        } else if opts.movie_path().is_file() {
+                            info!("Playing movie {:?}", opts.movie_path());
+                            match Movie::load(&opts.movie_path())
+                                .and_then(|movie| MoviePlayer::start(movie, &mut gba))
+                            {
+                                Ok(player) => movie_player = Some(player),
+                                Err(e) => warn!("Failed to play the movie: {}", e),
+                            }
+                        } else {
+                            info!("Movie not recorded, please record one by pressing F6");
+                        }
+                    }
+                    Scancode::F8 => viewers.toggle(ViewerKind::Palette)?,
+                    Scancode::F9 if movie_recorder.is_some() || movie_player.is_some() => {
+                        warn!("Can't load a snapshot during a movie");
+                    }
                     Scancode::F9 => {
                         if opts.savestate_path().is_file() {
                             let save = read_bin_file(&opts.savestate_path())?;
                             info!("Restoring state from {:?}...", opts.savestate_path());
                             let (audio_interface, _sdl_audio_device_new) =
                                 audio::create_audio_player(&sdl_context)?;
-                            _sdl_audio_device = _sdl_audio_device_new;
                             let rom = opts.read_rom()?.into_boxed_slice();
-                            gba = Box::new(GameBoyAdvance::from_saved_state(
+                            let link_cable = gba.disconnect_link_cable();
+                            let rumble = gba.disconnect_rumble();
+                            match GameBoyAdvance::from_saved_state(
                                 &save,
                                 bios_bin.clone(),
                                 rom,
                                 audio_interface,
-                            )?);
-                            info!("Restored!");
+                            ) {
+                                Ok(restored) => {
+                                    *gba = restored;
+                                    gba.set_solar_light(SOLAR_LIGHT_LEVELS[solar_level]);
+                                    cartridge_motion.apply(&mut gba);
+                                    _sdl_audio_device = _sdl_audio_device_new;
+                                    info!("Restored!");
+                                }
+                                Err(e) => error!("Failed to restore state: {}", e),
+                            }
+                            if let Some(transport) = link_cable {
+                                gba.connect_link_cable(transport);
+                            }
+                            if let Some(rumble) = rumble {
+                                gba.connect_rumble(rumble);
+                            }
                         } else {
                             info!("Savestate not created, please create one by pressing F5");
                         }
                     }
+                    Scancode::F10 => viewers.toggle(ViewerKind::Oam)?,
                     Scancode::Space => vsync = true,
+                    Scancode::Grave => rewinding = false,
                     k => input::on_keyboard_key_up(gba.get_key_state_mut(), k),
                 },
                 Event::ControllerButtonDown { button, .. } => match button {
@@ -184,30 +297,45 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     input::on_controller_button_up(gba.get_key_state_mut(), button);
                 }
                 Event::ControllerAxisMotion { axis, value, .. } => {
-                    input::on_axis_motion(gba.get_key_state_mut(), axis, value);
+                    if cartridge_motion.on_axis_motion(axis, value) {
+                        cartridge_motion.apply(&mut gba);
+                    } else {
+                        input::on_axis_motion(gba.get_key_state_mut(), axis, value);
+                    }
                 }
                 Event::ControllerDeviceRemoved { which, .. } => {
-                    let removed = if let Some(active_controller) = &active_controller {
+                    let removed = if let Some(active_controller) = &*active_controller.borrow() {
                         active_controller.instance_id() == (which as i32)
                     } else {
                         false
                     };
                     if removed {
                         let name = active_controller
+                            .borrow_mut()
+                            .take()
                             .and_then(|controller| Some(controller.name()))
                             .unwrap();
                         info!("Removing game controller: {}", name);
-                        active_controller = None;
                     }
                 }
                 Event::ControllerDeviceAdded { which, .. } => {
-                    if active_controller.is_none() {
+                    if active_controller.borrow().is_none() {
                         let controller = controller_subsystem.open(which)?;
                         info!("Adding game controller: {}", controller.name());
-                        active_controller = Some(controller);
+                        *active_controller.borrow_mut() = Some(controller);
                     }
                 }
-                Event::Quit { .. } => break 'running,
+                // With viewers open, closing the main window doesn't quit by itself
+                Event::Quit { .. }
+                | Event::Window {
+                    win_event: WindowEvent::Close,
+                    ..
+                } => {
+                    if let Some(recorder) = movie_recorder.take() {
+                        save_movie(recorder, &opts.movie_path())?;
+                    }
+                    break 'running;
+                }
                 Event::DropFile {  .. } => {
                     todo!("impl DropFile again")
                 }
@@ -215,12 +343,33 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
 
-        if gba.is_debugger_attached() {
+        if let Some(player) = &mut movie_player {
+            if !player.play_frame(&mut gba) {
+                info!("Movie finished after {} frames", player.current_frame());
+                movie_player = None;
+                *gba.get_key_state_mut() = KEYINPUT_ALL_RELEASED;
+            }
+        }
+        if let Some(recorder) = &mut movie_recorder {
+            recorder.record_frame(&mut gba);
+        }
+
+        // Rewinding would desync a movie
+        let movie_active = movie_recorder.is_some() || movie_player.is_some();
+        if rewinding && !movie_active {
+            rewind_buffer.rewind(&mut gba)?;
+            // The light and motion come from the host, not the rewound state
+            gba.set_solar_light(SOLAR_LIGHT_LEVELS[solar_level]);
+            cartridge_motion.apply(&mut gba);
+        } else if gba.is_debugger_attached() {
             gba.debugger_run()
         } else {
             gba.frame();
+            rewind_buffer.push(&gba)?;
         }
+        controller_rumble.refresh();
         renderer.render(gba.get_frame_buffer());
+        viewers.render(&mut gba);
 
         if let Some(fps) = fps_counter.tick() {
             let title = format!("{} ({} fps)", rom_name, fps);
@@ -1,10 +1,12 @@
-use std::{path::PathBuf};
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
 
 use rustboyadvance_core::{
-    cartridge::{BackupType, GamepakBuilder},
+    cartridge::{BackupType, GamepakBuilder, RtcClock, SaveFormat},
     prelude::Cartridge,
+    sio::{DynSioTransport, TcpTransport},
 };
-use rustboyadvance_utils::read_bin_file;
 use structopt::StructOpt;
 
 const SAVE_TYPE_POSSIBLE_VALUES: &[&str] =
@@ -40,33 +42,142 @@ pub struct Options {
     #[structopt(long)]
     pub rtc: bool,
 
+    /// Where the RTC takes the time from: realtime, offset:<seconds>, frozen:<date time> or
+    /// emulated:<date time>, with date times like 2004-09-01T12:00:00. The emulated clock starts
+    /// at the given time and advances with the emulation. Without it the host clock is used,
+    /// shifted by the time the game set in the save file.
+    #[structopt(long)]
+    pub rtc_time: Option<RtcClock>,
+
+    /// Force emulation of the Boktai solar sensor, the light is set with the - and = keys
+    #[structopt(long)]
+    pub solar_sensor: bool,
+
+    /// Force emulation of the gyro sensor, the cartridge is turned with the right stick
+    #[structopt(long)]
+    pub gyro: bool,
+
+    /// Force emulation of the tilt sensor, the cartridge is tilted with the right stick
+    #[structopt(long)]
+    pub tilt: bool,
+
+    /// Force emulation of the rumble motor, played on the game controller
+    #[structopt(long)]
+    pub rumble: bool,
+
+    /// Apply an IPS, UPS or BPS patch to the rom, a patch next to the rom with the same name is
+    /// used by default
+    #[structopt(long, parse(from_os_str))]
+    pub patch: Option<PathBuf>,
+
+    /// Import a save from another emulator, a VBA savestate (.sgm) or a GameShark/Action Replay
+    /// (.sps, .gsv) into the save of the rom
+    #[structopt(long, parse(from_os_str))]
+    pub import_save: Option<PathBuf>,
+
+    /// Export the save of the rom to a file, in the format of its extension (.sav, .sps), and exit
+    #[structopt(long, parse(from_os_str), conflicts_with = "import-save")]
+    pub export_save: Option<PathBuf>,
+
     /// Override save type, useful for troublemaking games that fool the auto detection
     #[structopt(long, default_value = "autodetect", possible_values = SAVE_TYPE_POSSIBLE_VALUES)]
     pub save_type: BackupType,
+
+    /// Wait for another emulator to connect a link cable on this address (e.g 0.0.0.0:4567)
+    #[structopt(long, conflicts_with = "link-connect")]
+    pub link_listen: Option<String>,
+
+    /// Connect a link cable to another emulator listening on this address
+    #[structopt(long)]
+    pub link_connect: Option<String>,
+
+    /// Record an input movie from power-on into this file (F6 stops the recording)
+    #[structopt(long, parse(from_os_str), conflicts_with = "play-movie")]
+    pub record_movie: Option<PathBuf>,
+
+    /// Play back an input movie
+    #[structopt(long, parse(from_os_str))]
+    pub play_movie: Option<PathBuf>,
+
+    /// How many seconds of gameplay to keep for rewinding, 0 disables it
+    #[structopt(long, default_value = "10")]
+    pub rewind_seconds: usize,
 }
 
 type DynError = Box<dyn std::error::Error>;
 
 impl Options {
-    pub fn cartridge_from_opts(&self) -> Result<Cartridge, DynError> {
+    fn gamepak_builder(&self) -> GamepakBuilder {
         let mut builder = GamepakBuilder::new()
             .save_type(self.save_type)
             .file(&self.rom);
         if self.rtc {
             builder = builder.with_rtc();
         }
-        Ok(builder.build()?)
+        if let Some(clock) = self.rtc_time {
+            builder = builder.rtc_clock(clock);
+        }
+        if self.solar_sensor {
+            builder = builder.with_solar_sensor();
+        }
+        if self.gyro {
+            builder = builder.with_gyro();
+        }
+        if self.tilt {
+            builder = builder.with_tilt();
+        }
+        if self.rumble {
+            builder = builder.with_rumble();
+        }
+        if let Some(patch) = &self.patch {
+            builder = builder.patch(patch);
+        }
+        builder
+    }
+
+    pub fn cartridge_from_opts(&self) -> Result<Cartridge, DynError> {
+        let mut cartridge = self.gamepak_builder().build()?;
+        if let Some(path) = &self.import_save {
+            info!("Importing save {:?}", path);
+            let bytes = fs::read(path)?;
+            cartridge.import_save_as(&bytes, SaveFormat::detect_file(path, &bytes)?)?;
+        }
+        Ok(cartridge)
+    }
+
+    pub fn export_save(&self, path: &Path) -> Result<(), DynError> {
+        let format = SaveFormat::from_path(path)?;
+        let cartridge = self.gamepak_builder().build()?;
+        fs::write(path, cartridge.export_save(format)?)?;
+        Ok(())
+    }
+
+    pub fn link_cable_from_opts(&self) -> Result<Option<DynSioTransport>, DynError> {
+        Ok(match (&self.link_listen, &self.link_connect) {
+            (Some(addr), _) => Some(TcpTransport::listen(addr.as_str())?),
+            (_, Some(addr)) => Some(TcpTransport::connect(addr.as_str())?),
+            _ => None,
+        })
     }
 
     pub fn savestate_path(&self) -> PathBuf {
         self.rom.with_extension("savestate")
     }
 
+    pub fn movie_path(&self) -> PathBuf {
+        match &self.record_movie {
+            Some(path) => path.clone(),
+            None => self.rom.with_extension("movie"),
+        }
+    }
+
     pub fn rom_name(&self) -> &str {
         self.rom.file_name().unwrap().to_str().unwrap()
     }
 
-    pub fn read_rom(&self) -> Result<Vec<u8>, std::io::Error> {
-        read_bin_file(&self.rom)
+    /// The rom as the emulator sees it, unzipped and patched
+    pub fn read_rom(&self) -> Result<Vec<u8>, DynError> {
+        let cartridge = self.gamepak_builder().without_backup_to_file().build()?;
+        Ok(cartridge.get_rom_bytes().to_vec())
     }
 }
@@ -0,0 +1,51 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use sdl2::controller::GameController;
+
+use rustboyadvance_core::prelude::RumbleInterface;
+
+/// SDL stops the effect once the duration runs out, `refresh` keeps it going while the motor runs
+const RUMBLE_DURATION_MS: u32 = 100;
+
+pub type SharedController = Rc<RefCell<Option<GameController>>>;
+
+/// Plays the rumble of the cartridge on the active game controller.
+/// Clones share the motor state, so the main loop can keep one to refresh the effect.
+#[derive(Clone)]
+pub struct ControllerRumble {
+    controller: SharedController,
+    on: Rc<Cell<bool>>,
+}
+
+impl ControllerRumble {
+    pub fn new(controller: SharedController) -> ControllerRumble {
+        ControllerRumble {
+            controller,
+            on: Rc::new(Cell::new(false)),
+        }
+    }
+
+    /// Call every frame, re-arms the effect before it runs out while the motor is on
+    pub fn refresh(&self) {
+        if self.on.get() {
+            self.play(true);
+        }
+    }
+
+    fn play(&self, on: bool) {
+        if let Some(controller) = self.controller.borrow_mut().as_mut() {
+            let strength = if on { 0xffff } else { 0 };
+            if let Err(e) = controller.set_rumble(strength, strength, RUMBLE_DURATION_MS) {
+                warn!("Failed to rumble the game controller: {}", e);
+            }
+        }
+    }
+}
+
+impl RumbleInterface for ControllerRumble {
+    fn set_rumble(&mut self, on: bool) {
+        self.on.set(on);
+        self.play(on);
+    }
+}
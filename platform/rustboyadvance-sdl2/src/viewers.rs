@@ -0,0 +1,271 @@
+//! Debug windows for the tiles, tilemaps, palettes and objs, drawn with `gpu::viewer`
+//!
+//! Keys inside a viewer window:
+//! * Left/Right: previous/next char block, background or obj
+//! * Up/Down: previous/next palette bank (tiles)
+//! * B: switch between 4bpp and 8bpp (tiles)
+//! * P: switch between the background and obj palettes (tiles)
+use std::error::Error;
+
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Scancode;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, TextureCreator, WindowCanvas};
+use sdl2::video::WindowContext;
+use sdl2::VideoSubsystem;
+
+use rustboyadvance_core::gpu::viewer::{TilePalette, ViewerImage};
+use rustboyadvance_core::gpu::PixelFormat;
+use rustboyadvance_core::prelude::*;
+
+/// The oam viewer shows every obj in a 64x64 cell
+const OBJ_CELL: usize = 64;
+const OBJS_PER_ROW: usize = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ViewerKind {
+    Tiles,
+    Tilemap,
+    Palette,
+    Oam,
+}
+
+/// Shown when there is nothing to draw for the selection
+fn empty_image() -> ViewerImage {
+    ViewerImage {
+        width: 1,
+        height: 1,
+        pixels: vec![0],
+    }
+}
+
+struct ViewerWindow {
+    kind: ViewerKind,
+    canvas: WindowCanvas,
+    tc: TextureCreator<WindowContext>,
+    /// The char block, background or obj being looked at
+    selection: usize,
+    format: PixelFormat,
+    palette: TilePalette,
+}
+
+impl ViewerWindow {
+    fn id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    fn on_key(&mut self, scancode: Scancode) {
+        let count = match self.kind {
+            ViewerKind::Tiles => 6,
+            ViewerKind::Tilemap => 4,
+            ViewerKind::Palette => 1,
+            ViewerKind::Oam => 128,
+        };
+        let bank = match self.palette {
+            TilePalette::Bg(bank) | TilePalette::Obj(bank) => bank,
+        };
+        match scancode {
+            Scancode::Right => self.selection = (self.selection + 1) % count,
+            Scancode::Left => self.selection = (self.selection + count - 1) % count,
+            Scancode::Up => self.set_palette_bank((bank + 1) % 16),
+            Scancode::Down => self.set_palette_bank((bank + 15) % 16),
+            Scancode::B => {
+                self.format = match self.format {
+                    PixelFormat::BPP4 => PixelFormat::BPP8,
+                    PixelFormat::BPP8 => PixelFormat::BPP4,
+                }
+            }
+            Scancode::P => {
+                self.palette = match self.palette {
+                    TilePalette::Bg(bank) => TilePalette::Obj(bank),
+                    TilePalette::Obj(bank) => TilePalette::Bg(bank),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn set_palette_bank(&mut self, bank: u32) {
+        self.palette = match self.palette {
+            TilePalette::Bg(_) => TilePalette::Bg(bank),
+            TilePalette::Obj(_) => TilePalette::Obj(bank),
+        };
+    }
+
+    fn on_click(&mut self, x: i32, y: i32) {
+        if self.kind != ViewerKind::Oam || x < 0 || y < 0 {
+            return;
+        }
+        let index = (y as usize / OBJ_CELL) * OBJS_PER_ROW + x as usize / OBJ_CELL;
+        if index < 128 {
+            self.selection = index;
+        }
+    }
+
+    fn render(&mut self, gba: &mut GameBoyAdvance) -> Result<(), Box<dyn Error>> {
+        let gpu = gba.get_gpu_mut();
+        let (image, title) = match self.kind {
+            ViewerKind::Tiles => {
+                match gpu.tile_view(self.selection as u32, self.format, self.palette) {
+                    Some(image) => (
+                        image,
+                        format!(
+                            "Tiles - char block {} {:?} {:?}",
+                            self.selection, self.format, self.palette
+                        ),
+                    ),
+                    None => (
+                        empty_image(),
+                        format!("Tiles - there is no char block {}", self.selection),
+                    ),
+                }
+            }
+            ViewerKind::Tilemap => match gpu.tilemap_view(self.selection) {
+                Some(image) => (image, format!("Tilemap - BG{}", self.selection)),
+                None => (
+                    empty_image(),
+                    format!("Tilemap - BG{} is not a tiled background", self.selection),
+                ),
+            },
+            ViewerKind::Palette => (gpu.palette_view(), "Palettes".to_string()),
+            ViewerKind::Oam => {
+                let mut image = ViewerImage {
+                    width: OBJ_CELL * OBJS_PER_ROW,
+                    height: OBJ_CELL * 128 / OBJS_PER_ROW,
+                    pixels: vec![0; OBJ_CELL * OBJ_CELL * 128],
+                };
+                for index in 0..128 {
+                    let obj = gpu.obj_view(index);
+                    let cell_x = (index % OBJS_PER_ROW) * OBJ_CELL;
+                    let cell_y = (index / OBJS_PER_ROW) * OBJ_CELL;
+                    for y in 0..obj.height {
+                        let row = (cell_y + y) * image.width + cell_x;
+                        image.pixels[row..row + obj.width]
+                            .copy_from_slice(&obj.pixels[y * obj.width..(y + 1) * obj.width]);
+                    }
+                }
+                (image, gpu.obj_info(self.selection).to_string())
+            }
+        };
+
+        self.canvas.window_mut().set_title(&title)?;
+        self.canvas
+            .set_logical_size(image.width as u32, image.height as u32)?;
+        let mut texture = self.tc.create_texture_streaming(
+            PixelFormatEnum::ARGB8888,
+            image.width as u32,
+            image.height as u32,
+        )?;
+        texture.set_blend_mode(BlendMode::Blend);
+        texture.update(
+            None,
+            unsafe {
+                std::slice::from_raw_parts(
+                    image.pixels.as_ptr() as *const u8,
+                    image.pixels.len() * 4,
+                )
+            },
+            image.width * 4,
+        )?;
+        self.canvas.set_draw_color(Color::RGB(0x40, 0x40, 0x40));
+        self.canvas.clear();
+        self.canvas.copy(&texture, None, None)?;
+        if self.kind == ViewerKind::Oam {
+            self.canvas.set_draw_color(Color::RGB(0xff, 0xff, 0));
+            self.canvas.draw_rect(Rect::new(
+                ((self.selection % OBJS_PER_ROW) * OBJ_CELL) as i32,
+                ((self.selection / OBJS_PER_ROW) * OBJ_CELL) as i32,
+                OBJ_CELL as u32,
+                OBJ_CELL as u32,
+            ))?;
+        }
+        self.canvas.present();
+        Ok(())
+    }
+}
+
+pub struct Viewers {
+    video_subsystem: VideoSubsystem,
+    windows: Vec<ViewerWindow>,
+}
+
+impl Viewers {
+    pub fn new(video_subsystem: VideoSubsystem) -> Viewers {
+        Viewers {
+            video_subsystem,
+            windows: Vec::new(),
+        }
+    }
+
+    /// Open the viewer, or close it if it's already open
+    pub fn toggle(&mut self, kind: ViewerKind) -> Result<(), Box<dyn Error>> {
+        if let Some(i) = self.windows.iter().position(|w| w.kind == kind) {
+            self.windows.remove(i);
+            return Ok(());
+        }
+        let (width, height) = match kind {
+            ViewerKind::Tiles => (768, 384),
+            ViewerKind::Tilemap => (512, 512),
+            ViewerKind::Palette => (192, 384),
+            ViewerKind::Oam => (1024, 512),
+        };
+        let window = self
+            .video_subsystem
+            .window(&format!("{:?}", kind), width, height)
+            .resizable()
+            .build()?;
+        let canvas = window.into_canvas().build()?;
+        let tc = canvas.texture_creator();
+        self.windows.push(ViewerWindow {
+            kind,
+            canvas,
+            tc,
+            selection: 0,
+            format: PixelFormat::BPP4,
+            palette: TilePalette::Bg(0),
+        });
+        Ok(())
+    }
+
+    /// Handle the event if it's for one of the viewer windows, otherwise give it back
+    pub fn handle_event(&mut self, event: Event) -> Option<Event> {
+        let window_id = match event.get_window_id() {
+            Some(window_id) => window_id,
+            None => return Some(event),
+        };
+        let i = match self.windows.iter().position(|w| w.id() == window_id) {
+            Some(i) => i,
+            None => return Some(event),
+        };
+        match event {
+            Event::Window {
+                win_event: WindowEvent::Close,
+                ..
+            } => {
+                self.windows.remove(i);
+            }
+            Event::KeyDown {
+                scancode: Some(scancode),
+                ..
+            } => self.windows[i].on_key(scancode),
+            Event::MouseButtonDown {
+                mouse_btn: MouseButton::Left,
+                x,
+                y,
+                ..
+            } => self.windows[i].on_click(x, y),
+            _ => {}
+        }
+        None
+    }
+
+    pub fn render(&mut self, gba: &mut GameBoyAdvance) {
+        for window in &mut self.windows {
+            if let Err(e) = window.render(gba) {
+                error!("failed to draw the {:?} viewer: {}", window.kind, e);
+            }
+        }
+    }
+}